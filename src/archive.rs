@@ -0,0 +1,118 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+
+use crate::colorize;
+use crate::fs_utils::get_data_dir;
+use crate::update::{download, sha256_hex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    TarGz,
+    Tar,
+    Zip,
+}
+
+impl ArchiveKind {
+    // Guesses the format from the URL path, ignoring any query string
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if path.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn is_archive_url(source: &str) -> bool {
+    (source.starts_with("https://") || source.starts_with("http://")) && ArchiveKind::from_url(source).is_some()
+}
+
+// Each URL gets its own extraction directory, so switching sources never mixes trees
+pub fn archive_cache_dir(url: &str) -> Result<PathBuf> {
+    let key = &sha256_hex(url.as_bytes())[..16];
+    Ok(get_data_dir()?.join("sources").join(key))
+}
+
+pub fn extract_archive(data: &[u8], kind: ArchiveKind, output_dir: &Path) -> Result<()> {
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create directory {}", output_dir.display()))?;
+
+    match kind {
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(data)).unpack(output_dir)?,
+        ArchiveKind::Tar => tar::Archive::new(data).unpack(output_dir)?,
+        ArchiveKind::Zip => zip::ZipArchive::new(Cursor::new(data))?.extract(output_dir)?,
+    }
+
+    Ok(())
+}
+
+// Archives of a repository usually wrap everything in one directory, e.g. dotfiles-main/. A single dot
+// directory such as .config/ is part of the dotfiles, not a wrapper.
+fn archive_root(extracted_dir: &Path) -> Result<PathBuf> {
+    let entries = fs::read_dir(extracted_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect::<Vec<_>>();
+
+    match entries.as_slice() {
+        [single] if single.is_dir() && !single.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) => Ok(single.clone()),
+        _ => Ok(extracted_dir.to_path_buf()),
+    }
+}
+
+// Extracts into a fresh directory next to the cache and swaps it in, so a failed download leaves the
+// previous copy usable
+pub fn unpack_into_cache(data: &[u8], kind: ArchiveKind, cache_dir: &Path) -> Result<()> {
+    let staging_dir = cache_dir.with_extension("partial");
+    let previous_dir = cache_dir.with_extension("previous");
+
+    for dir in [&staging_dir, &previous_dir] {
+        if dir.exists() {
+            fs::remove_dir_all(dir)
+                .with_context(|| format!("Failed to remove {}", dir.display()))?;
+        }
+    }
+
+    extract_archive(data, kind, &staging_dir)
+        .with_context(|| format!("Failed to extract archive into {}", staging_dir.display()))?;
+    let root = archive_root(&staging_dir)?;
+
+    if cache_dir.exists() {
+        fs::rename(cache_dir, &previous_dir)
+            .with_context(|| format!("Failed to move {} aside", cache_dir.display()))?;
+    }
+    fs::rename(&root, cache_dir)
+        .with_context(|| format!("Failed to move extracted files to {}", cache_dir.display()))?;
+
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    if previous_dir.exists() {
+        fs::remove_dir_all(&previous_dir)?;
+    }
+
+    Ok(())
+}
+
+// Downloads the archive at `url` and refreshes its extracted copy under the data directory
+pub fn fetch_archive(url: &str) -> Result<PathBuf> {
+    let kind = ArchiveKind::from_url(url)
+        .ok_or_else(|| anyhow!("Unsupported archive type for {} (expected .tar.gz, .tgz, .tar or .zip)", url))?;
+    let cache_dir = archive_cache_dir(url)?;
+
+    println!("{} {}", colorize::info("Downloading source archive"), colorize::path(url));
+    let data = download(url)?;
+    unpack_into_cache(&data, kind, &cache_dir)?;
+    println!("{} {}", colorize::success("Extracted to"), colorize::path(cache_dir.display()));
+
+    Ok(cache_dir)
+}
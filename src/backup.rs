@@ -1,10 +1,177 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 use crate::colorize;
+use crate::config::Config;
+use crate::fs_utils::{copy_file_with_mode, get_data_dir, get_home_dir, write_file_atomic, FileLock, SensitivePaths, PRIVATE_FILE_MODE};
 
-pub fn backup_file(file_path: &Path, backup_dir: &Path, dry_run: bool) -> Result<()> {
+// When a backup was made: milliseconds since the epoch, and a counter for further backups of the same
+// file within the same millisecond. Backups used to be named after whole seconds, without a counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BackupVersion {
+    pub millis: u64,
+    pub counter: u32,
+}
+
+// Versions below this are whole seconds from the old naming scheme; as milliseconds they would predate 1973
+const LEGACY_SECONDS_LIMIT: u64 = 100_000_000_000;
+
+impl BackupVersion {
+    // Parses the suffix of a backup name: "<millis>", "<millis>-<counter>" or legacy "<seconds>"
+    pub fn parse(version: &str) -> Option<Self> {
+        let (timestamp, counter) = match version.split_once('-') {
+            Some((timestamp, counter)) => (timestamp, counter.parse().ok()?),
+            None => (version, 0),
+        };
+        if !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let timestamp = timestamp.parse::<u64>().ok()?;
+        let millis = if timestamp < LEGACY_SECONDS_LIMIT { timestamp * 1000 } else { timestamp };
+        Some(Self { millis, counter })
+    }
+
+    pub fn seconds(&self) -> u64 {
+        self.millis / 1000
+    }
+
+    pub fn date_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::from_timestamp_millis(self.millis as i64)
+    }
+}
+
+impl fmt::Display for BackupVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.millis)?;
+        if self.counter > 0 {
+            write!(f, "-{}", self.counter)?;
+        }
+        Ok(())
+    }
+}
+
+// Kept in the data directory so the backup directory only ever holds backups
+pub const BACKUP_INDEX_FILE: &str = "backup_index.yaml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRecord {
+    // Absolute path the file was backed up from, which its flattened backup name doesn't keep
+    pub original: PathBuf,
+    // A tombstone: the file was deleted by the tool right after this backup, rather than overwritten
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+// Metadata for backups, keyed by the absolute path of the backup file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupIndex {
+    #[serde(default)]
+    pub backups: BTreeMap<PathBuf, BackupRecord>,
+}
+
+// Serializes index updates between threads; the lock file next to the index does the same between processes
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+impl BackupIndex {
+    fn path() -> Result<PathBuf> {
+        Ok(get_data_dir()?.join(BACKUP_INDEX_FILE))
+    }
+
+    // Loads the index, changes it with `f` and saves it under the index lock, so backups made at the same time
+    // by other threads or processes can't drop each other's records
+    pub fn update<F>(f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self),
+    {
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let index_path = Self::path()?;
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let _lock = FileLock::acquire(&index_path.with_extension("lock"))?;
+
+        let mut index = Self::load()?;
+        f(&mut index);
+        index.save()
+    }
+
+    pub fn load() -> Result<Self> {
+        let index_path = Self::path()?;
+        if !index_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&index_path)
+            .with_context(|| format!("Failed to read backup index at {}", index_path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse backup index at {}", index_path.display()))
+    }
+
+    // Saves the index, dropping records of backups that have since been removed
+    fn save(&mut self) -> Result<()> {
+        self.backups.retain(|backup_path, _| backup_path.exists());
+
+        let index_path = Self::path()?;
+        let content = serde_yaml::to_string(self)
+            .with_context(|| "Failed to serialize backup index")?;
+        // Replaced in one step, so a load never sees half an index
+        write_file_atomic(&index_path, content.as_bytes(), None)
+            .with_context(|| format!("Failed to write backup index at {}", index_path.display()))
+    }
+
+    // Where a backup was taken from, when that was recorded
+    pub fn original_path(&self, backup_path: &Path) -> Option<&Path> {
+        let backup_path = std::path::absolute(backup_path).ok()?;
+        self.backups.get(&backup_path).map(|record| record.original.as_path())
+    }
+
+    // Whether a backup holds a file the tool deleted
+    pub fn is_tombstone(&self, backup_path: &Path) -> bool {
+        std::path::absolute(backup_path).ok()
+            .and_then(|backup_path| self.backups.get(&backup_path))
+            .is_some_and(|record| record.deleted)
+    }
+}
+
+// Splits a backup file name into the name of the file it was taken from and its version
+fn parse_backup_name(backup_name: &str) -> Option<(&str, BackupVersion)> {
+    let (name, version) = backup_name.rsplit_once('.')?;
+    Some((name, BackupVersion::parse(version)?))
+}
+
+// Where backups go, and the settings from the caller's config that apply to making them
+#[derive(Debug, Clone)]
+pub struct Backups {
+    pub dir: PathBuf,
+    keep_versions: Option<usize>,
+    sensitive: SensitivePaths,
+}
+
+impl Backups {
+    pub fn new(dir: PathBuf, config: &Config) -> Result<Self> {
+        Ok(Self { dir, keep_versions: config.keep_versions_per_file, sensitive: config.sensitive_paths()? })
+    }
+}
+
+pub fn backup_file(file_path: &Path, backups: &Backups, dry_run: bool) -> Result<()> {
+    save_backup(file_path, backups, dry_run, false)
+}
+
+// Backs up a file the tool is about to delete, recording a tombstone so `restore` can bring it back
+pub fn backup_deleted_file(file_path: &Path, backups: &Backups) -> Result<()> {
+    save_backup(file_path, backups, false, true)
+}
+
+fn save_backup(file_path: &Path, backups: &Backups, dry_run: bool, deleted: bool) -> Result<()> {
+    let backup_dir = &backups.dir;
     if !backup_dir.exists() && !dry_run {
         return Err(anyhow!("Backup directory {} does not exist", backup_dir.display()));
     }
@@ -13,18 +180,40 @@ pub fn backup_file(file_path: &Path, backup_dir: &Path, dry_run: bool) -> Result
         .ok_or_else(|| anyhow!("Could not get filename"))?
         .to_string_lossy();
     
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let backup_filename = format!("{}.{}", filename, timestamp);
-    let backup_path = backup_dir.join(&backup_filename);
+    if !dry_run && !file_path.exists() {
+        return Err(anyhow!("Source file {} does not exist", file_path.display()));
+    }
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    // Backups of the same file within one millisecond get a counter instead of replacing each other
+    let backup_path = (0..)
+        .map(|counter| backup_dir.join(format!("{}.{}", filename, BackupVersion { millis, counter })))
+        .find_map(|path| match claim_backup_path(&path, dry_run) {
+            Ok(true) => Some(Ok(path)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e).with_context(|| format!("Failed to create backup at {}", path.display()))),
+        })
+        .expect("some counter is free")?;
     
     if !dry_run {
-        if !file_path.exists() {
-            return Err(anyhow!("Source file {} does not exist", file_path.display()));
+        // Backups of sensitive files are private from the moment they have content
+        let mode = is_sensitive_file(&backups.sensitive, file_path).then_some(PRIVATE_FILE_MODE);
+        let copied = copy_file_with_mode(file_path, &backup_path, mode)
+            .with_context(|| format!("Failed to create backup at {}", backup_path.display()));
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&backup_path);
+            return Err(e);
+        }
+
+        let record = BackupRecord { original: std::path::absolute(file_path)?, deleted };
+        let backup_path = std::path::absolute(&backup_path)?;
+        BackupIndex::update(|index| {
+            index.backups.insert(backup_path, record);
+        })?;
+
+        if let Some(keep) = backups.keep_versions {
+            trim_versions(file_path, backup_dir, keep)?;
         }
-        
-        fs::copy(file_path, &backup_path)
-            .with_context(|| format!("Failed to create backup at {}", backup_path.display()))?;
-            
     } else {
         println!("  {} {}", 
             colorize::dry_run("[Dry run] Would create backup at"), 
@@ -34,21 +223,61 @@ pub fn backup_file(file_path: &Path, backup_dir: &Path, dry_run: bool) -> Result
     Ok(())
 }
 
-#[cfg(test)]
-pub fn find_backup_by_version(file_path: &str, version: &str, backup_dir: &Path) -> Result<PathBuf> {
-    let filename = Path::new(file_path).file_name()
-        .ok_or_else(|| anyhow!("Invalid file path"))?
-        .to_string_lossy();
-    
-    let backup_path = backup_dir.join(format!("{}.{}", filename, version));
-    
-    if backup_path.exists() {
-        Ok(backup_path)
-    } else {
-        Err(anyhow!("Backup version {} not found for {}", version, file_path))
+// Takes a backup name by creating an empty file there, so concurrent backups of the same file can't both pick
+// it; false when it is taken. A dry run only checks.
+fn claim_backup_path(backup_path: &Path, dry_run: bool) -> std::io::Result<bool> {
+    if dry_run {
+        return Ok(!backup_path.exists());
+    }
+
+    match fs::OpenOptions::new().write(true).create_new(true).open(backup_path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Whether a file in the home directory is one `sensitive` in the config lists
+fn is_sensitive_file(sensitive: &SensitivePaths, file_path: &Path) -> bool {
+    let Ok(home_dir) = get_home_dir() else {
+        return false;
+    };
+
+    match std::path::absolute(file_path) {
+        Ok(file_path) => file_path.strip_prefix(&home_dir).is_ok_and(|relative_path| sensitive.is_sensitive(relative_path)),
+        Err(_) => false,
     }
 }
 
+// Removes the oldest backups of a file beyond the newest `keep` versions, returning how many were removed.
+// Backups of other files with the same name are counted apart, like in find_latest_backup_of.
+pub fn trim_versions(file_path: &Path, backup_dir: &Path, keep: usize) -> Result<usize> {
+    let index = BackupIndex::load()?;
+    let file_path = std::path::absolute(file_path)?;
+    let versions = find_all_backup_versions(&file_path.to_string_lossy(), backup_dir)?.into_iter()
+        .filter(|(_, backup_path)| index.original_path(backup_path).is_none_or(|original| original == file_path))
+        .collect::<Vec<_>>();
+    let excess = versions.len().saturating_sub(keep.max(MIN_VERSIONS_PER_FILE));
+
+    for (_, path) in versions.iter().take(excess) {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove backup {}", path.display()))?;
+    }
+
+    Ok(excess)
+}
+
+pub fn find_backup_by_version(file_path: &str, version: &str, backup_dir: &Path) -> Result<PathBuf> {
+    let wanted = BackupVersion::parse(version)
+        .ok_or_else(|| anyhow!("'{}' is not a backup version", version))?;
+
+    find_all_backup_versions(file_path, backup_dir)?.into_iter()
+        .find(|(version, _)| *version == wanted)
+        .map(|(_, backup_path)| backup_path)
+        .ok_or_else(|| anyhow!("Backup version {} not found for {}", version, file_path))
+}
+
+#[cfg(test)]
 pub fn find_latest_backup(file_path: &str, backup_dir: &Path) -> Result<PathBuf> {
     let versions = find_all_backup_versions(file_path, backup_dir)?;
     
@@ -63,7 +292,20 @@ pub fn find_latest_backup(file_path: &str, backup_dir: &Path) -> Result<PathBuf>
     Ok(latest_path)
 }
 
-pub fn find_all_backup_versions(file_path: &str, backup_dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+// The newest backup taken from `target_path`. Backups of other files with the same name are passed
+// over, while backups from before locations were recorded are assumed to match.
+pub fn find_latest_backup_of(target_path: &Path, backup_dir: &Path) -> Result<PathBuf> {
+    let index = BackupIndex::load()?;
+    let target_path = std::path::absolute(target_path)?;
+
+    find_all_backup_versions(&target_path.to_string_lossy(), backup_dir)?.into_iter()
+        .filter(|(_, backup_path)| index.original_path(backup_path).is_none_or(|original| original == target_path))
+        .map(|(_, backup_path)| backup_path)
+        .next_back()
+        .ok_or_else(|| anyhow!("No backups found for {}", target_path.display()))
+}
+
+pub fn find_all_backup_versions(file_path: &str, backup_dir: &Path) -> Result<Vec<(BackupVersion, PathBuf)>> {
     let filename = Path::new(file_path).file_name()
         .ok_or_else(|| anyhow!("Invalid file path"))?
         .to_string_lossy();
@@ -84,21 +326,155 @@ pub fn find_all_backup_versions(file_path: &str, backup_dir: &Path) -> Result<Ve
         
         if let Some(backup_name) = path.file_name() {
             let backup_name = backup_name.to_string_lossy();
-            
-            if let Some(pos) = backup_name.rfind('.') {
-                let (name, ver) = backup_name.split_at(pos);
-                
-                if name == filename {
-                    let ver = &ver[1..];  
-                    if let Ok(timestamp) = ver.parse::<u64>() {
-                        versions.push((timestamp, path));
-                    }
-                }
+
+            if let Some((name, version)) = parse_backup_name(&backup_name)
+                && name == filename {
+                versions.push((version, path));
             }
         }
     }
     
-    versions.sort_by_key(|(timestamp, _)| *timestamp);
+    versions.sort_by_key(|(version, _)| *version);
     
     Ok(versions)
-} 
\ No newline at end of file
+} 
+// Every `<name>.<version>` backup in the backup directory, grouped by file name, oldest version first
+pub fn backup_versions_by_file(backup_dir: &Path) -> Result<BTreeMap<String, Vec<(BackupVersion, PathBuf)>>> {
+    let mut versions: BTreeMap<String, Vec<(BackupVersion, PathBuf)>> = BTreeMap::new();
+
+    if !backup_dir.exists() {
+        return Ok(versions);
+    }
+
+    for entry in fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let backup_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if let Some((name, version)) = parse_backup_name(&backup_name) {
+            versions.entry(name.to_string()).or_default().push((version, path));
+        }
+    }
+
+    for file_versions in versions.values_mut() {
+        file_versions.sort_by_key(|(version, _)| *version);
+    }
+
+    Ok(versions)
+}
+
+// Like backup_versions_by_file, but grouped by the path each backup was taken from, so files that share a name
+// in different directories are kept apart. Backups from before locations were recorded are grouped by name.
+fn backup_versions_by_original(backup_dir: &Path) -> Result<BTreeMap<PathBuf, Vec<(BackupVersion, PathBuf)>>> {
+    let index = BackupIndex::load()?;
+    let mut versions: BTreeMap<PathBuf, Vec<(BackupVersion, PathBuf)>> = BTreeMap::new();
+
+    for (name, file_versions) in backup_versions_by_file(backup_dir)? {
+        for (version, backup_path) in file_versions {
+            let original = index.original_path(&backup_path).map_or_else(|| PathBuf::from(&name), Path::to_path_buf);
+            versions.entry(original).or_default().push((version, backup_path));
+        }
+    }
+
+    Ok(versions)
+}
+
+// How many backup versions the backup directory holds and their total size in bytes
+pub fn backup_usage(backup_dir: &Path) -> Result<(usize, u64)> {
+    let backups = backup_versions_by_file(backup_dir)?.into_values().flatten().collect::<Vec<_>>();
+    let bytes = backups.iter()
+        .filter_map(|(_, path)| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    Ok((backups.len(), bytes))
+}
+
+// How long ago a backup was made, e.g. "3 hours ago"
+pub fn format_age(seconds: u64) -> String {
+    const UNITS: &[(u64, &str)] = &[(365 * 86400, "year"), (30 * 86400, "month"), (86400, "day"), (3600, "hour"), (60, "minute")];
+
+    for &(unit_seconds, unit) in UNITS {
+        let count = seconds / unit_seconds;
+        if count > 0 {
+            return format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" });
+        }
+    }
+
+    String::from("just now")
+}
+
+// Versions of each file, newest first, that trimming and quota pruning never remove
+pub const MIN_VERSIONS_PER_FILE: usize = 1;
+
+// Parses sizes like "500MB", "1.5 GiB" or "20000". Units are multiples of 1024, as for the sizes shown elsewhere.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let number = number.parse::<f64>()
+        .map_err(|_| anyhow!("invalid size '{}', expected a number followed by B, KB, MB or GB", size))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("invalid size '{}', expected a number followed by B, KB, MB or GB", size)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QuotaPruning {
+    pub removed: usize,
+    pub reclaimed: u64,
+    // Size of the backup directory afterwards; still above the quota when only protected versions are left
+    pub remaining: u64,
+}
+
+// Removes the oldest backup versions until the backup directory fits in `max_size` bytes, keeping the
+// newest MIN_VERSIONS_PER_FILE versions of every file
+pub fn prune_to_quota(backup_dir: &Path, max_size: u64) -> Result<QuotaPruning> {
+    let mut pruning = QuotaPruning::default();
+    if !backup_dir.exists() {
+        return Ok(pruning);
+    }
+
+    pruning.remaining = WalkDir::new(backup_dir).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    if pruning.remaining <= max_size {
+        return Ok(pruning);
+    }
+
+    let mut candidates = backup_versions_by_original(backup_dir)?.into_values()
+        .flat_map(|versions| {
+            let prunable = versions.len().saturating_sub(MIN_VERSIONS_PER_FILE);
+            versions.into_iter().take(prunable)
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(version, _)| *version);
+
+    for (_, path) in candidates {
+        if pruning.remaining <= max_size {
+            break;
+        }
+
+        let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove backup {}", path.display()))?;
+        pruning.removed += 1;
+        pruning.reclaimed += size;
+        pruning.remaining -= size;
+    }
+
+    Ok(pruning)
+}
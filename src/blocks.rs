@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::template;
+
+const MARKER: &str = "dotfiles-rust";
+
+fn default_comment() -> String {
+    String::from("#")
+}
+
+fn is_default_comment(comment: &str) -> bool {
+    comment == "#"
+}
+
+// A region inside a file the tool doesn't own, such as a `source ~/.aliases` line in an existing .bashrc.
+// Only the lines between the BEGIN and END markers are managed; the rest of the file is left alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockConfig {
+    // Home-relative path of the file holding the block
+    pub target: String,
+    // Tells several blocks in one file apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // The managed lines, given inline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    // Or read from this file in the source directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    // Line comment syntax of the target file, used for the markers
+    #[serde(default = "default_comment", skip_serializing_if = "is_default_comment")]
+    pub comment: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockState {
+    InSync,
+    Outdated,
+    Missing,
+}
+
+impl BlockConfig {
+    pub fn target_path(&self) -> &Path {
+        Path::new(self.target.trim_start_matches("~/"))
+    }
+
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", self.target_path().display(), name),
+            None => self.target_path().display().to_string(),
+        }
+    }
+
+    pub fn markers(&self) -> (String, String) {
+        let suffix = self.name.as_ref().map(|name| format!(" {}", name)).unwrap_or_default();
+        (format!("{} BEGIN {}{}", self.comment, MARKER, suffix), format!("{} END {}{}", self.comment, MARKER, suffix))
+    }
+
+    // The managed lines, rendered like a template so they can use the same variables
+    pub fn content(&self, source_dir: &Path, vars: &BTreeMap<String, String>) -> Result<String> {
+        let content = match (&self.content, &self.source) {
+            (Some(content), None) => content.clone(),
+            (None, Some(source)) => {
+                let source_path = source_dir.join(source);
+                fs::read_to_string(&source_path)
+                    .with_context(|| format!("Failed to read block source {}", source_path.display()))?
+            },
+            _ => return Err(anyhow!("Block for {} needs exactly one of `content` or `source`", self.target)),
+        };
+
+        template::render(&content, vars)
+            .with_context(|| format!("Failed to render the block for {}", self.label()))
+    }
+}
+
+// Byte range of the block, from the start of its BEGIN line to the end of its END line
+fn find_block(text: &str, begin: &str, end: &str) -> Option<Range<usize>> {
+    let mut offset = 0;
+    let mut start = None;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if start.is_none() && trimmed == begin {
+            start = Some(offset);
+        } else if let Some(start) = start
+            && trimmed == end {
+            return Some(start..offset + line.len());
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn render_block(block: &BlockConfig, content: &str) -> String {
+    let (begin, end) = block.markers();
+    let content = content.trim_end_matches('\n');
+
+    if content.is_empty() {
+        format!("{}\n{}\n", begin, end)
+    } else {
+        format!("{}\n{}\n{}\n", begin, content, end)
+    }
+}
+
+// The file with the block inserted, or replaced when it is already there
+pub fn apply_block(text: &str, block: &BlockConfig, content: &str) -> String {
+    let (begin, end) = block.markers();
+    let rendered = render_block(block, content);
+
+    match find_block(text, &begin, &end) {
+        Some(range) => format!("{}{}{}", &text[..range.start], rendered, &text[range.end..]),
+        None if text.is_empty() => rendered,
+        None if text.ends_with('\n') => format!("{}\n{}", text, rendered),
+        None => format!("{}\n\n{}", text, rendered),
+    }
+}
+
+// The file without the block, or None when it has no such block
+pub fn remove_block(text: &str, block: &BlockConfig) -> Option<String> {
+    let (begin, end) = block.markers();
+    let range = find_block(text, &begin, &end)?;
+
+    let before = &text[..range.start];
+    // Drop the blank line apply_block put in front of the block
+    let before = before.strip_suffix("\n\n").map(|rest| format!("{}\n", rest)).unwrap_or_else(|| before.to_string());
+    Some(format!("{}{}", before, &text[range.end..]))
+}
+
+pub fn block_state(text: Option<&str>, block: &BlockConfig, content: &str) -> BlockState {
+    let Some(text) = text else {
+        return BlockState::Missing;
+    };
+    let (begin, end) = block.markers();
+
+    match find_block(text, &begin, &end) {
+        Some(range) if text[range.clone()] == render_block(block, content) => BlockState::InSync,
+        Some(_) => BlockState::Outdated,
+        None => BlockState::Missing,
+    }
+}
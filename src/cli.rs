@@ -1,10 +1,42 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Parser, Subcommand, ValueHint};
+use clap_complete::{ArgValueCandidates, Shell};
+
+use crate::completion::{complete_backed_up_files, complete_managed_paths};
+use crate::export::ExportFormat;
+use crate::import::ImportFormat;
+use crate::shell_init::InitShell;
 
 #[derive(Parser)]
 #[command(author, version, about = "Manage dotfiles installation and backups")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Args,
+
+    /// Never send long output through $PAGER
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// Files to compare at once, e.g. for homes on NFS; overrides `jobs` in the config
+    #[arg(short = 'j', long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Plain ASCII output without colors or unicode symbols, for dumb terminals, logs and screen readers
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Answer every prompt by going ahead, for scripts and CI
+    #[arg(short = 'y', long, global = true, conflicts_with = "assume_no")]
+    pub assume_yes: bool,
+
+    /// Answer every prompt by leaving things as they are
+    #[arg(long, global = true)]
+    pub assume_no: bool,
+
+    /// Answer a single prompt by its key, e.g. --answers reset=yes or --answers email=me@example.com (repeatable)
+    #[arg(long = "answers", value_name = "KEY=VALUE", value_parser = crate::template::parse_assignment, global = true)]
+    pub answers: Vec<(String, String)>,
 }
 
 #[derive(Subcommand)]
@@ -16,16 +48,94 @@ pub enum Args {
         dry_run: bool,
         
         /// Force overwrite existing files
-        #[arg(short, long)]
+        #[arg(short, long, overrides_with = "no_force")]
         force: bool,
+
+        /// Don't force, even when the config's `defaults` does
+        #[arg(long)]
+        no_force: bool,
         
-        /// Create backups of existing files before overwriting
-        #[arg(short, long, default_value = "true")]
+        /// Create backups of existing files before overwriting (the default unless the config turns it off)
+        #[arg(short, long, overrides_with = "no_backup")]
         backup: bool,
+
+        /// Overwrite existing files without backing them up
+        #[arg(long)]
+        no_backup: bool,
         
         /// Display verbose output
         #[arg(short, long)]
         verbose: bool,
+
+        /// Back up and remove previously installed files whose source was deleted
+        #[arg(long)]
+        prune: bool,
+
+        /// Only install files at or below this path, e.g. .config/nvim (repeatable)
+        #[arg(long = "path", value_name = "PATH", value_hint = ValueHint::AnyPath, add = ArgValueCandidates::new(complete_managed_paths))]
+        paths: Vec<String>,
+
+        /// Skip files matching this glob, e.g. '.ssh' or '*.bak' (repeatable)
+        #[arg(long = "exclude", value_name = "GLOB")]
+        excludes: Vec<String>,
+
+        /// Only install files changed in the source repository since the last install
+        #[arg(long)]
+        changed_only: bool,
+
+        /// Only install files changed in the source repository since this commit or tag, skipping the rest of the tree
+        #[arg(long, value_name = "REF", conflicts_with_all = ["changed_only", "prune"])]
+        since: Option<String>,
+
+        /// Install this commit, tag or branch of the source repository, e.g. v1.2 or main~3, from a temporary
+        /// checkout that leaves the working copy alone
+        #[arg(long = "ref", value_name = "REF", conflicts_with_all = ["adopt", "skel"])]
+        git_ref: Option<String>,
+
+        /// Refuse to install unless the source tree matches a manifest signed by trusted_key
+        #[arg(long)]
+        require_signed: bool,
+
+        /// Set a template variable, e.g. --set email=me@example.com (repeatable)
+        #[arg(long = "set", value_name = "NAME=VALUE", value_parser = crate::template::parse_assignment)]
+        set_vars: Vec<(String, String)>,
+
+        /// Also write files that target paths outside your home directory, such as /etc/zsh/zshenv, with sudo,
+        /// showing the diff and asking before each one
+        #[arg(long, alias = "sudo")]
+        apply_system: bool,
+
+        /// Install into this directory instead of your home directory, e.g. /etc/skel or a mounted image
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath, conflicts_with_all = ["prune", "changed_only", "apply_system"])]
+        target: Option<PathBuf>,
+
+        /// Install into /etc/skel for new accounts (as root)
+        #[arg(long, conflicts_with_all = ["target", "prune", "changed_only", "apply_system"])]
+        skel: bool,
+
+        /// With --skel, also install into the home of every existing regular user
+        #[arg(long, requires = "skel")]
+        all_users: bool,
+
+        /// Overwrite read-only files too (with --force), keeping them read-only afterwards
+        #[arg(long)]
+        force_readonly: bool,
+
+        /// Copy installed files that differ from the source back into the source instead of overwriting them
+        #[arg(long, conflicts_with = "force")]
+        adopt: bool,
+
+        /// Also overwrite files edited since the last install (they are backed up first)
+        #[arg(long, conflicts_with = "adopt")]
+        force_local: bool,
+
+        /// Carry on when a file fails to install, then list every failure and exit with status 3
+        #[arg(long)]
+        keep_going: bool,
+
+        /// Print what happened to each file as JSON; other output goes to standard error
+        #[arg(long, conflicts_with = "skel")]
+        json: bool,
     },
     
     /// Uninstall dotfiles, restoring from backups when available
@@ -35,28 +145,60 @@ pub enum Args {
         dry_run: bool,
         
         /// Force removal even if files were modified
-        #[arg(short, long)]
+        #[arg(short, long, overrides_with = "no_force")]
         force: bool,
+
+        /// Don't force, even when the config's `defaults` does
+        #[arg(long)]
+        no_force: bool,
+
+        /// Keep the backups put back in place instead of deleting them
+        #[arg(long, overrides_with = "no_keep_backups")]
+        keep_backups: bool,
+
+        /// Delete the backups put back in place, even when the config's `defaults` keeps them
+        #[arg(long)]
+        no_keep_backups: bool,
         
         /// Display verbose output
         #[arg(short, long)]
         verbose: bool,
     },
     
-    /// Initialize configuration file with source directory
+    /// Initialize configuration file with source directory; without options, walks through the setup interactively
     Init {
-        /// Source directory containing dotfiles
-        #[arg(short, long, default_value = ".")]
-        source_dir: String,
+        /// Source directory containing dotfiles [default: .]
+        #[arg(short, long)]
+        source_dir: Option<String>,
+
+        /// Use an https tarball or zip as the source; it is downloaded on each install
+        #[arg(long, value_name = "URL", conflicts_with = "source_dir")]
+        from_url: Option<String>,
     },
     
     /// List available backups
+    #[command(visible_alias = "list")]
     Backups {
         /// Specific file to list backups for
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::FilePath, add = ArgValueCandidates::new(complete_backed_up_files))]
         file: Option<String>,
+
+        /// Print the content of this backup version of the file, or of the latest with `latest`
+        #[arg(long, value_name = "VERSION", requires = "file")]
+        show: Option<String>,
     },
     
+    /// Remove backups that are no longer needed
+    Prune {
+        /// Remove backups identical to the installed file or to what the source would install
+        #[arg(long, required = true)]
+        redundant: bool,
+
+        /// Show what would be removed without removing anything
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+
     /// Clear all backup files
     Reset {
         /// Skip confirmation prompt
@@ -64,10 +206,297 @@ pub enum Args {
         force: bool,
     },
 
+    /// Restore files from their backups
+    #[command(group(ArgGroup::new("named").args(["file", "files"]).multiple(true)))]
+    Restore {
+        /// File to restore; picked at a terminal, and every file with a backup otherwise
+        #[arg(value_hint = ValueHint::FilePath, add = ArgValueCandidates::new(complete_backed_up_files))]
+        file: Option<String>,
+
+        /// More files to restore together with it, as a comma-separated list or repeated; asks once for all of them
+        /// and puts every file back as it was if one can't be restored
+        #[arg(long = "file", visible_alias = "files", value_name = "FILE", value_delimiter = ',', value_hint = ValueHint::FilePath,
+            add = ArgValueCandidates::new(complete_backed_up_files))]
+        files: Vec<String>,
+
+        /// Backup version, as listed by `backups --file`, to restore instead of the latest
+        #[arg(long, requires = "named")]
+        version: Option<String>,
+
+        /// Show a diff between each backup and the current file and ask before overwriting it
+        #[arg(short, long)]
+        preview: bool,
+
+        /// Show what would be restored without changing anything
+        #[arg(short, long)]
+        dry_run: bool,
+
+        /// Also restore files outside your home directory, such as /etc/zsh/zshenv, with sudo, showing the diff
+        /// and asking before each one
+        #[arg(long)]
+        apply_system: bool,
+
+        /// Keep each backup after putting it back in place instead of deleting it
+        #[arg(long, overrides_with = "no_keep_backups")]
+        keep_backups: bool,
+
+        /// Delete each backup put back in place, even when the config's `defaults` keeps them
+        #[arg(long)]
+        no_keep_backups: bool,
+
+        /// Restore over files changed after their backup was taken without asking first
+        #[arg(long)]
+        overwrite_newer: bool,
+
+        /// Write the backup to stdout instead of putting it in place, e.g. to pipe it into a diff tool
+        #[arg(long, requires = "named", conflicts_with_all = ["preview", "dry_run", "apply_system"])]
+        stdout: bool,
+    },
+
     /// Show status of dotfiles
     Status {
         /// Display detailed file content differences
         #[arg(short, long)]
         verbose: bool,
+
+        /// Also list files in managed directories that are not in the source tree
+        #[arg(short, long)]
+        extras: bool,
+
+        /// Step through each modified or missing file and choose how to reconcile it
+        #[arg(long)]
+        fix: bool,
+
+        /// Only list files that are modified or not installed
+        #[arg(short, long)]
+        changed_only: bool,
+
+        /// Also write metrics for monitoring to this file: JSON when it ends in .json, otherwise a Prometheus textfile
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        metrics: Option<PathBuf>,
+    },
+
+    /// Check installed files against the source directory without changing anything
+    Verify {
+        /// Compare against a sha256sum-style file of home-relative paths instead of the source
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        checksums: Option<String>,
+    },
+
+    /// Open the source file behind an installed dotfile in $VISUAL/$EDITOR and reinstall it
+    Edit {
+        /// Installed path, e.g. ~/.config/nvim/init.vim or .bashrc; picked from the managed files when omitted
+        #[arg(value_hint = ValueHint::FilePath, add = ArgValueCandidates::new(complete_managed_paths))]
+        file: Option<String>,
+
+        /// Do not reinstall the file after the editor exits
+        #[arg(long)]
+        no_install: bool,
+    },
+
+    /// List every file that install would manage
+    Files {
+        /// Print the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check the source directory for problems before installing, e.g. from a pre-commit hook
+    Lint {
+        /// Print the problems as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how installed files differ from the source, using the configured difftool if installed
+    Diff {
+        /// Installed files or directories to compare (picked at a terminal, all managed files otherwise)
+        #[arg(value_hint = ValueHint::FilePath, add = ArgValueCandidates::new(complete_managed_paths))]
+        paths: Vec<String>,
+    },
+
+    /// Show the source file that an installed dotfile comes from
+    Which {
+        /// Installed path, or a source path when --reverse is given
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: String,
+
+        /// Look up where a source file is installed instead
+        #[arg(short, long)]
+        reverse: bool,
+    },
+
+    /// Print what a template renders to on this machine, without installing it
+    Render {
+        /// Installed path or source file, e.g. ~/.gitconfig or .gitconfig.tmpl
+        #[arg(value_hint = ValueHint::FilePath, add = ArgValueCandidates::new(complete_managed_paths))]
+        file: String,
+
+        /// Set a template variable for this render, e.g. --vars email=me@example.com (repeatable)
+        #[arg(long = "vars", alias = "set", value_name = "NAME=VALUE", value_parser = crate::template::parse_assignment)]
+        vars: Vec<(String, String)>,
+    },
+
+    /// Remove dangling symlinks in your home that point into the source directory
+    Clean {
+        /// Show which symlinks would be removed without removing them
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+
+    /// Commit all changes in the source repository and push them
+    Push {
+        /// Commit message (generated from the changed files by default)
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Show what would be committed without committing or pushing
+        #[arg(short, long)]
+        dry_run: bool,
     },
-} 
\ No newline at end of file
+
+    /// List every machine's last install, from the state files synced through the source repository
+    Fleet,
+
+    /// Convert a dotfiles tree managed by another tool into this tool's layout
+    Import {
+        /// Tool that manages the tree being imported
+        #[arg(long, value_enum)]
+        from: ImportFormat,
+
+        /// Directory to import from
+        #[arg(value_hint = ValueHint::DirPath)]
+        source: String,
+
+        /// Directory to write the converted dotfiles to
+        #[arg(short, long, value_hint = ValueHint::DirPath)]
+        output: String,
+
+        /// Perform a dry run without writing any files
+        #[arg(short, long)]
+        dry_run: bool,
+
+        /// Overwrite files that already exist in the output directory
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Write the managed dotfiles out in the layout another dotfile manager expects
+    Export {
+        /// Tool to export for
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+
+        /// Directory to write the exported tree to
+        #[arg(short, long, value_hint = ValueHint::DirPath)]
+        output: String,
+
+        /// Perform a dry run without writing any files
+        #[arg(short, long)]
+        dry_run: bool,
+
+        /// Overwrite files that already exist in the output directory
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Periodically update the source repository and install dotfiles
+    #[command(args_conflicts_with_subcommands = true)]
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
+
+        /// Seconds between runs (defaults to daemon_interval from the config, or one hour)
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Run a single update and install, then exit
+        #[arg(long)]
+        once: bool,
+
+        /// Overwrite modified files (existing files are backed up)
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Sign the source tree, or check it against its signed manifest
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestAction,
+    },
+
+    /// Inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Update dotfiles-rust to the latest GitHub release
+    SelfUpdate {
+        /// Only check whether a newer version is available
+        #[arg(short, long)]
+        check: bool,
+
+        /// Reinstall even if already on the latest version
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Generate shell completion scripts
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print a snippet for your shell's startup file with completions and aliases
+    ShellInit {
+        /// Shell to generate the snippet for
+        #[arg(value_enum)]
+        shell: InitShell,
+
+        /// Warn on shell start when installed files differ from the source
+        #[arg(long)]
+        check: bool,
+
+        /// Leave out the dfi/dfs/dfe/dfv aliases
+        #[arg(long)]
+        no_aliases: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Check the configuration file for unknown keys and invalid values
+    Validate,
+}
+
+#[derive(Subcommand)]
+pub enum ManifestAction {
+    /// Write checksums of every source file and sign them with this machine's key
+    Sign,
+    /// Check the source tree against its signed manifest
+    Verify {
+        /// Hex-encoded public key to trust instead of trusted_key from the config
+        #[arg(long)]
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DaemonAction {
+    /// Write a systemd user service and timer that run the daemon
+    InstallUnit {
+        /// Seconds between runs (defaults to daemon_interval from the config, or one hour)
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Overwrite modified files on each run (existing files are backed up)
+        #[arg(short, long)]
+        force: bool,
+
+        /// Print the unit files instead of writing them
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+}
@@ -1,5 +1,44 @@
 use colored::*;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+// --plain: no colors, unicode symbols or box drawing, for dumb terminals, logs and screen readers
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+    colored::control::set_override(!plain);
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+fn symbol(fancy: &'static str, plain: &'static str) -> &'static str {
+    if is_plain() { plain } else { fancy }
+}
+
+// Marks an item that is as it should be
+pub fn tick() -> &'static str {
+    symbol("✓", "ok")
+}
+
+// Marks an item with a problem
+pub fn cross() -> &'static str {
+    symbol("✗", "x")
+}
+
+pub fn arrow() -> &'static str {
+    symbol("→", "->")
+}
+
+// Connects an item to its parent in a tree; the last child gets a corner
+pub fn branch(last: bool) -> &'static str {
+    match last {
+        true => symbol("└──", "`--"),
+        false => symbol("├──", "|--"),
+    }
+}
 
 pub fn success<T: Display>(text: T) -> impl Display {
     text.to_string().green()
@@ -35,4 +74,14 @@ pub fn path<T: Display>(text: T) -> impl Display {
 
 pub fn version<T: Display>(text: T) -> impl Display {
     text.to_string().green().bold()
-}
\ No newline at end of file
+}
+// Diff lines; the emphasized parts are the words that changed within the line
+pub fn removed<T: Display>(text: T, emphasized: bool) -> impl Display {
+    let text = text.to_string().red();
+    if emphasized { text.reversed() } else { text }
+}
+
+pub fn added<T: Display>(text: T, emphasized: bool) -> impl Display {
+    let text = text.to_string().green();
+    if emphasized { text.reversed() } else { text }
+}
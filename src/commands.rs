@@ -1,35 +1,371 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
 use std::fs;
+use std::io::Write;
 use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
 
 use anyhow::{anyhow, Context, Result};
-use walkdir::WalkDir;
-use chrono;
+use clap::CommandFactory;
+use serde::Serialize;
+use clap_complete::Shell;
+use clap_complete::env::EnvCompleter;
+use walkdir::{DirEntry, WalkDir};
 use std::path::PathBuf;
 
-use crate::fs_utils::{get_home_dir, get_backup_dir};
-use crate::backup::{backup_file, find_latest_backup, find_all_backup_versions};
-use crate::config::read_config;
+use crate::fs_utils::{get_home_dir, get_backup_dir, get_data_dir, as_home_owner, normalize_path, ensure_parent_dirs, DirModes, copy_file, copy_file_with_mode, mode_permissions, write_file_atomic, file_mode, with_executable_bits, ensure_executable, is_on_path, io_options, parallel_map, PRIVATE_FILE_MODE};
+use crate::cli::Cli;
+use crate::backup::{backup_deleted_file, backup_file, backup_usage, backup_versions_by_file, Backups, find_backup_by_version, find_latest_backup_of, find_all_backup_versions, BackupIndex, BackupRecord, BackupVersion, format_age, parse_size, prune_to_quota};
+use crate::config::{read_config, read_config_for_source, get_config_path, check_config, Config, Layout, REPO_CONFIG_FILE};
 use crate::colorize;
+use crate::git;
+use crate::prompt;
+use crate::pager;
+use crate::archive::fetch_archive;
+use crate::script::{run_hook, SCRIPTS_DIR};
+use crate::privileged::{escalated_write, is_privileged, DEFAULT_ESCALATION_COMMAND};
+use crate::dir_config::{apply_mode, DirConfigs, DIR_CONFIG_FILE};
+use crate::manifest::Manifest;
+use crate::metrics::{write_metrics, Metrics};
+use crate::fleet::{record_install, STATE_DIR};
+use crate::signing::{self, SIGNATURE_FILE, SIGNED_MANIFEST_FILE};
+use crate::skel::UserHome;
+use crate::fragments;
+use crate::patch::{is_patch, read_patch, PatchState};
+use crate::template::{self, expected_content, is_generated, write_rendered};
+use crate::table::{Cell, Column, Style, Table};
+use crate::suggest::closest_match;
+use crate::summary::{DryRunSummary, PlannedAction};
+use crate::report::{InstallOutcome, InstallReport};
+use crate::diff::{diff_stat, format_size, same_file, same_file_content, unified_diff, CompareMode, DiffStat};
+use crate::difftool::{run_difftool, DiffPair, Difftool};
+use crate::matcher::PathMatcher;
+use crate::blocks::{apply_block, block_state, remove_block, BlockConfig, BlockState};
+
+// Repository metadata and tool files that are never installed, matched with the same rules as user patterns
+const BLACKLIST: &[&str] = &[
+    ".git", ".github", ".gitmodules", "/.gitignore", "/.gitattributes",
+    ".dotfiles-rustrc.yaml", REPO_CONFIG_FILE, DIR_CONFIG_FILE, SCRIPTS_DIR,
+    "README.md", "node_modules", ".DS_Store", "SHA256SUMS", STATE_DIR, SIGNED_MANIFEST_FILE, SIGNATURE_FILE,
+];
+
+pub fn is_blacklisted(relative_path: &Path) -> bool {
+    static MATCHER: OnceLock<PathMatcher> = OnceLock::new();
+
+    MATCHER.get_or_init(|| PathMatcher::new(BLACKLIST).expect("blacklist patterns are valid"))
+        .is_match(relative_path)
+}
 
-const BLACKLIST: &[&str] = &[".git", ".dotfiles-rustrc.yaml", "README.md", "node_modules", ".DS_Store"];
+pub fn build_exclude_set(patterns: &[String]) -> Result<PathMatcher> {
+    PathMatcher::new(patterns).with_context(|| "Invalid exclude pattern")
+}
 
-pub fn install_dotfiles(dry_run: bool, force: bool, backup: bool, verbose: bool) -> Result<()> {
-    let config = read_config()?;
+pub fn is_excluded(excludes: &PathMatcher, relative_path: &Path) -> bool {
+    excludes.is_match(relative_path)
+}
+
+// Entries below the source directory, in file name order within each directory so output and manifests are
+// the same on every filesystem. A directory for which `prune` returns true (given its source-relative path)
+// is still returned but not descended into, so trees such as node_modules or .git are never read.
+// With `follow_links`, symlinked directories are walked too; one leading back to a directory it is in is skipped.
+pub fn source_entries<'a, F>(source_dir: &'a Path, follow_links: bool, prune: F) -> impl Iterator<Item = DirEntry> + 'a
+where
+    F: Fn(&Path) -> bool + 'a,
+{
+    let mut walker = WalkDir::new(source_dir).min_depth(1).follow_links(follow_links).sort_by_file_name().into_iter();
+
+    std::iter::from_fn(move || loop {
+        let entry = match walker.next()? {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                    println!("  {} {} (a symlink loop back to {})",
+                        colorize::warning("Skipping:"),
+                        colorize::path(path.display()),
+                        colorize::path(ancestor.display()));
+                }
+                continue;
+            },
+        };
+
+        if entry.file_type().is_dir() && entry.path().strip_prefix(source_dir).is_ok_and(&prune) {
+            walker.skip_current_dir();
+        }
+        return Some(entry);
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManagedFile {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub relative_path: PathBuf,
+    // Permissions forced by a per-directory config, or by `sensitive` in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(skip_serializing_if = "CompareMode::is_content")]
+    pub compare: CompareMode,
+}
+
+// Every file install would touch, after the blacklist, layout and directory configs are applied, sorted by target
+pub fn managed_files(source_dir: &Path, layout: Layout, home_dir: &Path) -> Result<Vec<ManagedFile>> {
+    let mut files = source_files(source_dir, layout, home_dir)?;
+    files.retain(|file| !is_patch(&file.source));
+    Ok(files)
+}
+
+// Patches in the source tree, with the files they apply to as their targets
+pub fn patch_files(source_dir: &Path, layout: Layout, home_dir: &Path) -> Result<Vec<ManagedFile>> {
+    let mut files = source_files(source_dir, layout, home_dir)?;
+    files.retain(|file| is_patch(&file.source));
+    Ok(files)
+}
+
+fn source_files(source_dir: &Path, layout: Layout, home_dir: &Path) -> Result<Vec<ManagedFile>> {
+    let dirs = DirConfigs::load(source_dir)?;
+    let mut files = Vec::new();
+
+    for entry in source_entries(source_dir, dirs.follows_symlinks(), |path| is_blacklisted(path) || dirs.is_ignored(path) || dirs.is_assembled(path))
+    {
+        let source_relative_path = entry.path().strip_prefix(source_dir)?;
+
+        if is_blacklisted(source_relative_path) || dirs.is_ignored(source_relative_path) {
+            continue;
+        }
+        let assembled = dirs.is_assembled(source_relative_path);
+        if !entry.file_type().is_file() && !assembled {
+            continue;
+        }
+
+        if let Some(relative_path) = dirs.target_for(layout, source_relative_path) {
+            let mut mode = dirs.mode_for(source_relative_path);
+            // The directory's own mode says nothing about the file assembled from it
+            if assembled && mode.is_none() {
+                mode = fragments::permissions_source(entry.path()).and_then(|path| file_mode(&path));
+            }
+
+            files.push(ManagedFile {
+                source: entry.path().to_path_buf(),
+                target: home_dir.join(&relative_path),
+                relative_path,
+                mode,
+                compare: dirs.compare_for(source_relative_path),
+            });
+        }
+    }
+
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+    Ok(files)
+}
+
+// Managed files minus the config's ignore patterns, with files in bin directories expected to be executable
+pub fn configured_files(config: &Config, home_dir: &Path) -> Result<Vec<ManagedFile>> {
+    let ignore = build_exclude_set(&config.ignore)?;
+    let bin_dirs = config.bin_dirs();
+    let sensitive = config.sensitive_paths()?;
+    let mut files = managed_files(Path::new(&config.source_dir), config.layout, home_dir)?;
+
+    files.retain(|file| !is_excluded(&ignore, &file.relative_path));
+
+    for file in &mut files {
+        if sensitive.is_sensitive(&file.relative_path) {
+            file.mode = Some(PRIVATE_FILE_MODE);
+        } else if bin_dirs.iter().any(|dir| file.relative_path.starts_with(dir)) {
+            file.mode = file.mode.or_else(|| file_mode(&file.source)).map(with_executable_bits);
+        }
+    }
+
+    Ok(files)
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    pub dry_run: bool,
+    pub force: bool,
+    pub backup: bool,
+    pub verbose: bool,
+    // Remove previously installed files whose source has been deleted
+    pub prune: bool,
+    // Only install files at or below these home-relative paths; empty means everything
+    pub paths: Vec<String>,
+    // Glob patterns for home-relative paths to leave alone, on top of the blacklist
+    pub excludes: Vec<String>,
+    // Only install files changed in the source repository since the last recorded install
+    pub changed_only: bool,
+    // Only install files changed in the source repository since this git revision, walking just their paths
+    pub since: Option<String>,
+    // Refuse to install unless the source tree matches its signed manifest; also enabled by require_signed
+    pub require_signed: bool,
+    // Account the files are installed for when it isn't the current user, for the built-in template variables
+    pub user: Option<UserHome>,
+    // Template variables given on the command line; saved for later runs
+    pub set_vars: Vec<(String, String)>,
+    // Write targets outside the home directory through the escalation command, showing the diff and confirming each file
+    pub apply_system: bool,
+    // Directory to install into instead of the home directory; nothing is recorded in the manifest
+    pub target: Option<PathBuf>,
+    // Overwrite read-only targets, keeping them read-only; also enabled by force_readonly in the config
+    pub force_readonly: bool,
+    // Copy targets that differ from their source back into the source instead of overwriting them
+    pub adopt: bool,
+    // Overwrite targets edited since the last install, which --force leaves alone
+    pub force_local: bool,
+    // Carry on past files that fail to install, reporting them all at the end
+    pub keep_going: bool,
+    // Install this commit, tag or branch of the source repository from a temporary checkout instead of the working copy
+    pub git_ref: Option<String>,
+}
+
+// HEAD for clean_install, pointing out the uncommitted changes it leaves out, or None to install the directory as
+// it is when it isn't a git repository
+fn clean_install_ref(source_dir: &Path) -> Option<&'static str> {
+    if !git::is_git_repo(source_dir) {
+        println!("{}", colorize::warning("Source directory is not a git repository; installing it as it is despite clean_install"));
+        return None;
+    }
+
+    match git::uncommitted_changes(source_dir) {
+        Ok(changes) if !changes.is_empty() => println!("{} {} uncommitted change(s) in the source directory are not installed",
+            colorize::info("Note:"), colorize::highlight(changes.len())),
+        _ => {},
+    }
+    Some("HEAD")
+}
+
+// Source-relative paths changed since the commit of the last install, or None when that can't be determined
+fn changed_source_files(source_dir: &Path, manifest: &Manifest) -> Option<HashSet<PathBuf>> {
+    if !git::is_git_repo(source_dir) {
+        println!("{}", colorize::warning("Source directory is not a git repository; installing all files"));
+        return None;
+    }
+
+    let Some(commit) = &manifest.commit else {
+        println!("{}", colorize::warning("No previous install commit recorded; installing all files"));
+        return None;
+    };
+
+    match git::changed_files_since(source_dir, commit) {
+        Ok(changed) => {
+            println!("{} {}", colorize::info("Installing files changed since"), colorize::highlight(git::short_commit(commit)));
+            Some(changed.into_iter().collect())
+        },
+        Err(e) => {
+            println!("{} {}; installing all files", colorize::warning("Could not list changed files:"), e);
+            None
+        }
+    }
+}
+
+// Source-relative paths changed since `since`, a commit, tag or other git revision
+fn source_files_changed_since(source_dir: &Path, since: &str) -> Result<HashSet<PathBuf>> {
+    if !git::is_git_repo(source_dir) {
+        return Err(anyhow!("--since needs the source directory '{}' to be a git repository", source_dir.display()));
+    }
+
+    let changed = git::changed_files_since(source_dir, since)
+        .with_context(|| format!("Could not list files changed since {}", since))?;
+    println!("{} {}", colorize::info("Installing files changed since"), colorize::highlight(since));
+    Ok(changed.into_iter().collect())
+}
+
+// Prunes the oldest backup versions once the backup directory outgrows backup_max_size
+fn enforce_backup_quota(config: &Config, backup_dir: &Path) -> Result<()> {
+    let Some(max_size) = &config.backup_max_size else {
+        return Ok(());
+    };
+    let max_size = parse_size(max_size).context("Invalid backup_max_size")?;
+    let pruning = prune_to_quota(backup_dir, max_size)?;
+
+    if pruning.removed > 0 {
+        println!("{} pruned {} old backup version(s), reclaiming {}",
+            colorize::warning(format!("Backups exceeded the {} quota:", format_size(max_size))),
+            colorize::highlight(pruning.removed),
+            colorize::highlight(format_size(pruning.reclaimed)));
+    }
+    if pruning.remaining > max_size {
+        println!("{} backups still take {}; the latest version of each file is always kept",
+            colorize::warning("Warning:"),
+            colorize::highlight(format_size(pruning.remaining)));
+    }
+
+    Ok(())
+}
+
+// With more than one job, every installed file that isn't generated is compared with its source up front,
+// spread over the jobs, instead of one at a time during the install. Keyed by target, with the source compared.
+fn compare_plain_files(config: &Config, target_root: &Path) -> Result<HashMap<PathBuf, (PathBuf, bool)>> {
+    if io_options().jobs <= 1 {
+        return Ok(HashMap::new());
+    }
+
+    let files = configured_files(config, target_root)?.into_iter()
+        .filter(|file| !is_generated(&file.source) && file.source.is_file() && file.target.is_file())
+        .collect::<Vec<_>>();
+    let identical = parallel_map(&files, |file| same_file(&file.source, &file.target, file.compare).unwrap_or(false));
+
+    Ok(files.into_iter().zip(identical)
+        .map(|(file, identical)| (file.target, (file.source, identical)))
+        .collect())
+}
+
+// Installs the source tree and reports what happened to each file. With --keep-going, files that failed
+// are in the report rather than an error.
+pub fn install_dotfiles(options: &InstallOptions) -> Result<InstallReport> {
+    let &InstallOptions { dry_run, force, backup, verbose, prune, ref paths, ref excludes, changed_only, ref since, require_signed, ref user, ref set_vars, apply_system, ref target, force_readonly, adopt, force_local, keep_going, ref git_ref } = options;
+    let mut config = read_config()?;
+    // Archive sources are re-downloaded on every install, and their repository settings read from the new copy;
+    // a dry run reuses the cached copy when there is one
+    if let Some(url) = config.source_url.clone()
+        && !(dry_run && Path::new(&config.source_dir).exists()) {
+        fetch_archive(&url)?;
+        config = read_config()?;
+    }
+    let repo_dir = PathBuf::from(&config.source_dir);
+    let git_ref = match git_ref {
+        Some(git_ref) => Some(git_ref.as_str()),
+        None if config.clean_install => clean_install_ref(&repo_dir),
+        None => None,
+    };
+    // Kept until the install is over, when the checkout is removed again
+    let checkout = match git_ref {
+        Some(git_ref) => Some(git::Worktree::add(&repo_dir, git_ref, &get_data_dir()?.join(format!("checkout-{}", std::process::id())))?),
+        None => None,
+    };
+    if let Some(checkout) = &checkout {
+        println!("{} {}", colorize::info("Installing from"), colorize::highlight(git_ref.unwrap_or_default()));
+        // The working copy's repository settings may be uncommitted, so the checkout's own are used
+        config = read_config_for_source(checkout.dir())?;
+    }
     let source_dir = &config.source_dir;
 
     let home_dir = get_home_dir()?;
+    // Paths on the command line name files as they are in the home directory, wherever they are installed
+    let target_root = target.clone().unwrap_or_else(|| home_dir.clone());
     let source_dir = Path::new(source_dir);
-    let backup_dir = get_backup_dir()?;
+    let backups = Backups::new(get_backup_dir()?, &config)?;
+    let force_readonly = force_readonly || config.force_readonly;
 
     if !source_dir.exists() {
         return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
     }
 
+    if let Some(target) = target
+        && !target.is_dir() {
+        return Err(anyhow!("Target directory '{}' does not exist", target.display()));
+    }
+
+    // Checked before any hook runs, since hooks are code from the source tree too
+    if require_signed || config.require_signed {
+        signing::ensure_signed(source_dir, &config)?;
+    }
+
     if verbose {
         println!("{} {} to {}",
             colorize::info("Installing dotfiles from"),
             colorize::path(source_dir.display()),
-            colorize::path(home_dir.strip_prefix(&get_home_dir()?)?.display()));
+            colorize::path(target_root.display()));
         if dry_run {
             println!("{}", colorize::dry_run("Dry run mode: no files will be copied"));
         }
@@ -37,22 +373,48 @@ pub fn install_dotfiles(dry_run: bool, force: bool, backup: bool, verbose: bool)
         println!("{}", colorize::header("Installing dotfiles..."));
     }
 
-    for entry in WalkDir::new(source_dir)
-        .min_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let source_path = entry.path();
+    run_hook(&config, "pre-install", dry_run)?;
 
-        if !source_path.is_file() {
-            continue;
-        }
+    let selected_paths = paths.iter()
+        .map(|path| home_relative_path(path, &home_dir))
+        .collect::<Result<Vec<_>>>()?;
+    let excludes = build_exclude_set(&[excludes.as_slice(), config.ignore.as_slice()].concat())?;
+    let frozen = build_exclude_set(&config.frozen)?;
+    let is_selected = |target_relative_path: &Path| {
+        selected_paths.is_empty() || selected_paths.iter().any(|path| target_relative_path.starts_with(path))
+    };
 
+    // The manifest tracks the home directory only, so installs elsewhere start from and keep an empty one
+    let mut manifest = if target.is_some() { Manifest::default() } else { Manifest::load()? };
+    let mut managed = HashSet::new();
+    let mut summary = DryRunSummary::new();
+    let changed = match since {
+        Some(since) => Some(source_files_changed_since(source_dir, since)?),
+        None if changed_only => changed_source_files(source_dir, &manifest),
+        None => None,
+    };
+    let mut vars = template::template_vars_for(&config, set_vars, user.as_ref(), !dry_run)?;
+    let dirs = DirConfigs::load(source_dir)?;
+    let bin_dirs = config.bin_dirs();
+    let escalation = config.escalation_command.clone().unwrap_or_else(|| String::from(DEFAULT_ESCALATION_COMMAND));
+    let mut used_bin_dirs = BTreeSet::new();
+    let mut answers: BTreeMap<String, String> = set_vars.iter().cloned().collect();
+    let mut report = InstallReport::new(dry_run);
+    let compared = compare_plain_files(&config, &target_root)?;
+    let mut failed = FailedFiles { keep_going, ..Default::default() };
+    let dir_modes = config.dir_modes(&target_root);
+    let sensitive = config.sensitive_paths()?;
+
+    // With --since only directories holding a changed path are walked; --changed-only walks everything so
+    // the files it skips still count as managed for --prune
+    let outside_changes = |path: &Path| since.is_some()
+        && changed.as_ref().is_some_and(|changed| !changed.iter().any(|changed_path| changed_path.starts_with(path)));
+
+    for entry in source_entries(source_dir, dirs.follows_symlinks(), |path| is_blacklisted(path) || dirs.is_ignored(path) || dirs.is_assembled(path) || outside_changes(path)) {
+        let source_path = entry.path();
         let relative_path = source_path.strip_prefix(source_dir)?;
 
-        let should_skip = BLACKLIST.iter().any(|pattern| {
-            relative_path.to_string_lossy().contains(pattern)
-        });
+        let should_skip = is_blacklisted(relative_path);
 
         if should_skip {
             if verbose {
@@ -61,133 +423,1272 @@ pub fn install_dotfiles(dry_run: bool, force: bool, backup: bool, verbose: bool)
             continue;
         }
 
-        let target_path = home_dir.join(relative_path);
+        if dirs.is_ignored(relative_path) {
+            if verbose {
+                println!("  {} {}", colorize::warning("Skipping path ignored by its directory config:"), colorize::path(relative_path.display()));
+            }
+            continue;
+        }
+
+        // Patches are applied once every file is in place
+        if (!source_path.is_file() && !dirs.is_assembled(relative_path)) || is_patch(relative_path) {
+            continue;
+        }
+
+        let Some(target_relative_path) = dirs.target_for(config.layout, relative_path) else {
+            continue;
+        };
+        let target_relative_path = target_relative_path.as_path();
+        if !is_selected(target_relative_path) {
+            continue;
+        }
+        if is_excluded(&excludes, target_relative_path) {
+            if verbose {
+                println!("  {} {}", colorize::warning("Skipping excluded path:"), colorize::path(target_relative_path.display()));
+            }
+            continue;
+        }
+        let target_path = target_root.join(target_relative_path);
+        managed.insert(target_relative_path.to_path_buf());
+
+        // An assembled file changes with any of its fragments
+        if let Some(changed) = &changed
+            && !changed.iter().any(|path| path.starts_with(relative_path)) {
+            continue;
+        }
+
+        let privileged = is_privileged(&target_path, &target_root);
+        if privileged && target.is_some() {
+            report.skip(relative_path, &target_path, InstallOutcome::Skipped, "outside the home directory, not installed into the target");
+            summary.add_file(PlannedAction::Skip, source_path);
+            continue;
+        }
+        if privileged && !apply_system {
+            report.skip(relative_path, &target_path, InstallOutcome::Skipped, "outside the home directory, use --apply-system");
+            summary.add_file(PlannedAction::Skip, source_path);
+            continue;
+        }
 
         if verbose {
             println!("  {} {}", colorize::info("Processing:"), colorize::path(source_path.display()));
             println!("    {} {}", colorize::info("Relative path:"), colorize::path(relative_path.display()));
-            println!("    {} {}", colorize::info("Target path:"), colorize::path(target_path.strip_prefix(&get_home_dir()?)?.display()));
+            println!("    {} {}", colorize::info("Target path:"), colorize::path(target_relative_path.display()));
         }
 
-        if let Some(parent) = target_path.parent() {
-            if !parent.exists() && !dry_run {
-                fs::create_dir_all(parent)
-                    .with_context(|| format!("Failed to create directory {}", parent.display()))?;
-            }
+        if !privileged && failed.check(relative_path, &target_path, ensure_parent_dirs(&target_path, &dir_modes, dry_run))?.is_none() {
+            continue;
         }
 
         if verbose {
             println!("{} => {}",
                 colorize::path(source_path.display()),
-                colorize::path(target_path.strip_prefix(&get_home_dir()?)?.display()));
+                colorize::path(target_relative_path.display()));
         }
 
-        if target_path.exists() {
-            let files_identical = match (fs::read(&source_path), fs::read(&target_path)) {
-                (Ok(source_content), Ok(target_content)) => source_content == target_content,
-                _ => false
+        let rendered = if is_generated(source_path) {
+            let content = render_for_install(source_path, relative_path, &mut vars, &mut answers);
+            let Some(content) = failed.check(relative_path, &target_path, content)? else {
+                continue;
+            };
+            Some(content)
+        } else {
+            None
+        };
+
+        if privileged {
+            let installed = install_privileged(&PrivilegedInstall {
+                source_path,
+                target_path: &target_path,
+                rendered: rendered.as_deref(),
+                mode: dirs.mode_for(relative_path),
+                escalation: &escalation,
+                backups: &backups,
+            }, options, &mut summary);
+            let Some((outcome, reason)) = failed.check(relative_path, &target_path, installed)? else {
+                continue;
+            };
+            if !dry_run && matches!(outcome, InstallOutcome::Copied | InstallOutcome::SkippedIdentical) {
+                manifest.record(target_relative_path, relative_path, &target_path);
+            }
+            let entry = report.add(relative_path, &target_path, outcome);
+            entry.reason = reason.map(str::to_string);
+            continue;
+        }
+
+        if sensitive.is_sensitive(target_relative_path) && source_path.is_file() && file_mode(source_path).is_some_and(|mode| mode & 0o004 != 0) {
+            println!("  {} {} is world-readable in the source directory; it is installed as readable by you only",
+                colorize::warning("Warning:"), colorize::path(relative_path.display()));
+        }
+
+        // Read-only targets were locked on purpose, so they keep their mode when they are overwritten
+        let read_only_permissions = fs::metadata(&target_path).ok()
+            .map(|metadata| metadata.permissions())
+            .filter(|permissions| permissions.readonly());
+
+        let target_existed = target_path.exists();
+        if target_existed {
+            let files_identical = match compared.get(&target_path) {
+                Some((compared_source, identical)) if rendered.is_none() && compared_source == source_path => *identical,
+                _ => match &rendered {
+                    Some(content) => fs::read(&target_path).is_ok_and(|target_content| *content == target_content),
+                    None => same_file(source_path, &target_path, dirs.compare_for(relative_path)).unwrap_or(false),
+                },
             };
 
             if files_identical {
-                if verbose {
-                    println!("  {}", colorize::info("Skipping (files are identical)"));
-                    println!("  {} {}", colorize::info("Unchanged:"), colorize::path(relative_path.display()));
+                // A sensitive file installed before it was listed, or loosened since, is tightened all the same
+                if !dry_run && failed.check(relative_path, &target_path, sensitive.restrict(&target_root, &target_path))?.is_none() {
+                    continue;
+                }
+                report.add(relative_path, &target_path, InstallOutcome::SkippedIdentical);
+                manifest.record(target_relative_path, relative_path, &target_path);
+                summary.add_file(PlannedAction::Skip, source_path);
+                continue;
+            }
+
+            if is_excluded(&frozen, target_relative_path) {
+                summary.add_file(PlannedAction::Skip, source_path);
+                report.skip(relative_path, &target_path, InstallOutcome::SkippedConflict, "differs from the source, but it is frozen in the config");
+                continue;
+            }
+            if adopt {
+                if rendered.is_some() {
+                    summary.add_file(PlannedAction::Skip, source_path);
+                    report.skip(relative_path, &target_path, InstallOutcome::Skipped, "generated from a template or fragments; edit those instead");
+                    continue;
                 }
+                summary.add_file(PlannedAction::Adopt, &target_path);
+                if !dry_run {
+                    let copied = copy_file(&target_path, source_path)
+                        .with_context(|| format!("Failed to copy {} to {}", target_path.display(), source_path.display()));
+                    if failed.check(relative_path, &target_path, copied)?.is_none() {
+                        continue;
+                    }
+                    manifest.record(target_relative_path, relative_path, &target_path);
+                }
+                report.add(relative_path, &target_path, InstallOutcome::Adopted);
+                continue;
+            }
+            match manifest.is_locally_modified(target_relative_path, &target_path) {
+                // Edited since the last install: overwriting would lose work, so --force isn't enough
+                Some(true) if !force_local => {
+                    summary.add_file(PlannedAction::Skip, source_path);
+                    report.skip(relative_path, &target_path, InstallOutcome::SkippedConflict, "changed since the last install; use --force-local to overwrite it or --adopt to keep it");
+                    continue;
+                },
+                // Still exactly what the last install wrote, so it is only an older version of the source
+                Some(false) => {},
+                _ if !force && !force_local => {
+                    summary.add_file(PlannedAction::Skip, source_path);
+                    report.skip(relative_path, &target_path, InstallOutcome::SkippedConflict, "already exists, use --force to overwrite");
+                    continue;
+                },
+                _ => {},
+            }
+            if read_only_permissions.is_some() && !force_readonly {
+                summary.add_file(PlannedAction::Skip, source_path);
+                report.skip(relative_path, &target_path, InstallOutcome::SkippedConflict, "read-only, use --force-readonly to overwrite it");
+                continue;
+            }
+            if backup {
+                summary.add_file(PlannedAction::BackUp, &target_path);
+                // A file that couldn't be backed up is left as it is rather than overwritten
+                if failed.check(relative_path, &target_path, backup_file(&target_path, &backups, dry_run))?.is_none() {
+                    continue;
+                }
+            }
+            summary.add_file(PlannedAction::Overwrite, source_path);
+        } else {
+            summary.add_file(PlannedAction::Copy, source_path);
+        }
+
+        if !dry_run {
+            let bin_dir = bin_dirs.iter().find(|dir| target_relative_path.starts_with(dir));
+            let written = write_target(source_path, &target_path, rendered.as_deref(), dirs.mode_for(relative_path), read_only_permissions, bin_dir.is_some())
+                .and_then(|()| sensitive.restrict(&target_root, &target_path));
+            if failed.check(relative_path, &target_path, written)?.is_none() {
+                continue;
+            }
+            if let Some(bin_dir) = bin_dir {
+                used_bin_dirs.insert(bin_dir.clone());
+            }
+            manifest.record(target_relative_path, relative_path, &target_path);
+            report.bytes_written += fs::metadata(&target_path).map(|metadata| metadata.len()).unwrap_or(0);
+        }
+        report.add(relative_path, &target_path, InstallOutcome::Copied).backed_up = backup && target_existed;
+    }
+
+    for block in config.blocks.iter().filter(|block| is_selected(block.target_path()) && !is_excluded(&excludes, block.target_path())) {
+        failed.check(block.target_path(), &target_root.join(block.target_path()), block.content(source_dir, &vars)
+            .and_then(|content| install_block(block, &content, &target_root, &dir_modes, options, &backups, &mut summary)))?;
+    }
+
+    for patch in patch_files(source_dir, config.layout, &target_root)? {
+        if is_selected(&patch.relative_path) && !is_excluded(&excludes, &patch.relative_path) {
+            managed.insert(patch.relative_path.clone());
+            failed.check(&patch.relative_path, &patch.target, install_patch(&patch, &target_root, options, &backups, &mut summary))?;
+        }
+    }
+
+    let adopted_count = report.count(InstallOutcome::Adopted);
+    if !dry_run && adopted_count > 0 && git::is_git_repo(source_dir) {
+        println!("{} {} file(s) adopted; review them with {}",
+            colorize::info("Note:"),
+            colorize::highlight(adopted_count),
+            colorize::highlight(format!("git -C {} diff", source_dir.display())));
+    }
+
+    for (path, selected_path) in paths.iter().zip(&selected_paths) {
+        if !managed.iter().any(|target| target.starts_with(selected_path)) {
+            println!("  {} {}", colorize::warning("No managed files match:"), colorize::path(path));
+        }
+    }
+
+    if prune {
+        // A partial install only knows about the selected paths, so only prune within them
+        let stale = manifest.stale_entries(&managed).into_iter()
+            .filter(|target| is_selected(target) && !is_excluded(&excludes, target) && !is_excluded(&frozen, target))
+            .collect();
+        prune_stale_files(&mut manifest, stale, &home_dir, &backups, dry_run, force, &mut summary)?;
+    }
+
+    if dry_run {
+        report.summary = Some(summary);
+    } else {
+        // Partial installs, including --since ones, leave other files as they were, so they don't move the recorded
+        // commit; files the config ignores are never installed, so they don't make an install partial
+        if paths.is_empty() && options.excludes.is_empty() && since.is_none() && failed.failures.is_empty() && git::is_git_repo(source_dir) {
+            manifest.commit = git::head_commit(source_dir).ok();
+        }
+        if failed.failures.is_empty() {
+            manifest.installed_at = Some(chrono::Utc::now().timestamp());
+        }
+        if target.is_none() {
+            manifest.save()?;
+            if config.sync_state {
+                record_install(&repo_dir, manifest.commit.as_deref(), manifest.files.len())?;
+            }
+        }
+        enforce_backup_quota(&config, &backups.dir)?;
+
+        if !answers.is_empty() {
+            let mut data = template::load_template_data()?;
+            data.extend(answers);
+            template::save_template_data(&data)?;
+        }
+    }
+
+    run_hook(&config, "post-install", dry_run)?;
+
+    for bin_dir in used_bin_dirs {
+        let bin_dir = home_dir.join(bin_dir);
+        if target.is_none() && !is_on_path(&bin_dir) {
+            println!("{} {} {}",
+                colorize::warning("Installed executables to"),
+                colorize::path(bin_dir.display()),
+                colorize::warning("which is not on your PATH"));
+        }
+    }
+
+    for (source, target, e) in failed.failures {
+        report.skip(&source, &target, InstallOutcome::Failed, &format!("{:#}", e));
+    }
+
+    Ok(report)
+}
+
+// Installs and prints the report, failing with PartialFailure when --keep-going set files aside
+pub fn install_and_report(options: &InstallOptions) -> Result<()> {
+    let report = install_dotfiles(options)?;
+    report.print(options.verbose);
+    report.check()
+}
+
+// Prints the report as JSON, with everything else the install prints sent to standard error
+pub fn install_as_json(options: &InstallOptions) -> Result<()> {
+    let report = {
+        let _redirect = pager::stdout_to_stderr();
+        install_dotfiles(options)?
+    };
+    let output = serde_json::to_string_pretty(&report)
+        .with_context(|| "Failed to serialize the install report")?;
+    println!("{}", output);
+    report.check()
+}
+
+// Errors set aside by --keep-going, with the source-relative path and the target that failed
+#[derive(Debug, Default)]
+struct FailedFiles {
+    keep_going: bool,
+    failures: Vec<(PathBuf, PathBuf, anyhow::Error)>,
+}
+
+impl FailedFiles {
+    // Passes an error on, or with --keep-going sets it aside and returns None so the caller can move on to the next file
+    fn check<T>(&mut self, source: &Path, target: &Path, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.keep_going => {
+                self.failures.push((source.to_path_buf(), target.to_path_buf(), e));
+                Ok(None)
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// What install --keep-going returns when some files failed; main exits with PARTIAL_FAILURE_EXIT_CODE for it
+#[derive(Debug)]
+pub struct PartialFailure {
+    pub failed: usize,
+}
+
+pub const PARTIAL_FAILURE_EXIT_CODE: u8 = 3;
+
+impl std::fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} file(s) failed to install; the rest were installed", self.failed)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+// Renders a template or assembled file, asking for variables it needs that have no value yet
+fn render_for_install(source_path: &Path, relative_path: &Path, vars: &mut BTreeMap<String, String>, answers: &mut BTreeMap<String, String>) -> Result<Vec<u8>> {
+    for name in template::missing_variables(source_path, vars)? {
+        let value = template::prompt_for_variable(&name)
+            .with_context(|| format!("Failed to render {}", relative_path.display()))?;
+        answers.insert(name.clone(), value.clone());
+        vars.insert(name, value);
+    }
+
+    expected_content(source_path, vars)
+}
+
+// Writes one target of an install with the mode it should have, set before the content is written so a
+// sensitive file is never readable by others
+fn write_target(source_path: &Path, target_path: &Path, rendered: Option<&[u8]>, mode: Option<u32>, read_only_permissions: Option<fs::Permissions>, executable: bool) -> Result<()> {
+    match (rendered, mode_permissions(mode)) {
+        (Some(content), Some(permissions)) => {
+            write_file_atomic(target_path, content, Some(permissions))
+                .with_context(|| format!("Failed to write {}", target_path.display()))?;
+        },
+        (Some(content), None) => template::write_rendered(source_path, target_path, content)?,
+        (None, _) => {
+            copy_file_with_mode(source_path, target_path, mode)
+                .with_context(|| format!("Failed to copy {} to {}", source_path.display(), target_path.display()))?;
+        },
+    }
+    if let Some(permissions) = read_only_permissions {
+        as_home_owner(target_path, || fs::set_permissions(target_path, permissions))
+            .with_context(|| format!("Failed to restore the read-only mode of {}", target_path.display()))?;
+    }
+    if executable {
+        ensure_executable(target_path)?;
+    }
+
+    Ok(())
+}
+
+// Inserts or updates a managed block, leaving the rest of the file as it is
+fn install_block(block: &BlockConfig, content: &str, target_root: &Path, dir_modes: &DirModes, options: &InstallOptions, backups: &Backups, summary: &mut DryRunSummary) -> Result<()> {
+    let target_path = target_root.join(block.target_path());
+    let existing = match fs::read_to_string(&target_path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", target_path.display())),
+    };
+
+    if block_state(existing.as_deref(), block, content) == BlockState::InSync {
+        if options.verbose {
+            println!("  {} {}", colorize::info("Block unchanged:"), colorize::path(block.label()));
+        }
+        summary.add_file(PlannedAction::Skip, &target_path);
+        return Ok(());
+    }
+
+    let updated = apply_block(existing.as_deref().unwrap_or_default(), block, content);
+    if options.dry_run {
+        summary.add(if existing.is_some() { PlannedAction::Overwrite } else { PlannedAction::Copy }, updated.len() as u64);
+        println!("  {} {}", colorize::dry_run("[Dry run] Would update block in:"), colorize::path(block.label()));
+        return Ok(());
+    }
+
+    if existing.is_some() {
+        if options.backup {
+            backup_file(&target_path, backups, false)?;
+        }
+    } else {
+        ensure_parent_dirs(&target_path, dir_modes, false)?;
+    }
+    write_file_atomic(&target_path, updated.as_bytes(), None)
+        .with_context(|| format!("Failed to write {}", target_path.display()))?;
+    println!("  {} {}", colorize::success("Updated block in:"), colorize::path(block.label()));
+
+    Ok(())
+}
+
+// Applies a patch on top of a file owned by other software. The file is left alone when it is missing,
+// already patched, or has changed so much that the patch no longer fits.
+fn install_patch(patch: &ManagedFile, target_root: &Path, options: &InstallOptions, backups: &Backups, summary: &mut DryRunSummary) -> Result<()> {
+    let relative_path = patch.relative_path.display();
+
+    if is_privileged(&patch.target, target_root) {
+        println!("  {} {} (patches are only applied inside the home directory)", colorize::warning("Skipped:"), colorize::path(relative_path));
+        summary.add_file(PlannedAction::Skip, &patch.source);
+        return Ok(());
+    }
+    let Ok(text) = fs::read_to_string(&patch.target) else {
+        println!("  {} {} (there is no file to patch)", colorize::warning("Skipped:"), colorize::path(relative_path));
+        summary.add_file(PlannedAction::Skip, &patch.source);
+        return Ok(());
+    };
+
+    let parsed = read_patch(&patch.source)?;
+    match parsed.state(&text) {
+        PatchState::Applied => {
+            if options.verbose {
+                println!("  {} {}", colorize::info("Patch already applied:"), colorize::path(relative_path));
+            }
+            summary.add_file(PlannedAction::Skip, &patch.source);
+        },
+        PatchState::Conflict => {
+            println!("  {} {} (the file has changed; update the patch)", colorize::error("Patch does not apply:"), colorize::path(relative_path));
+            summary.add_file(PlannedAction::Skip, &patch.source);
+        },
+        PatchState::Unapplied if options.dry_run => {
+            println!("  {} {}", colorize::dry_run("[Dry run] Would patch:"), colorize::path(relative_path));
+            summary.add_file(PlannedAction::Overwrite, &patch.source);
+        },
+        PatchState::Unapplied => {
+            let patched = parsed.apply(&text)?;
+            if options.backup {
+                backup_file(&patch.target, backups, false)?;
+            }
+            write_file_atomic(&patch.target, patched.as_bytes(), None)
+                .with_context(|| format!("Failed to write {}", patch.target.display()))?;
+            println!("  {} {}", colorize::success("Patched:"), colorize::path(relative_path));
+        },
+    }
+
+    Ok(())
+}
+
+struct PrivilegedInstall<'a> {
+    source_path: &'a Path,
+    target_path: &'a Path,
+    rendered: Option<&'a [u8]>,
+    mode: Option<u32>,
+    escalation: &'a str,
+    backups: &'a Backups,
+}
+
+// Installs one file outside the home directory. Only files whose content would change are written, and
+// each change is shown as a diff before asking for the escalated write.
+fn install_privileged(file: &PrivilegedInstall, options: &InstallOptions, summary: &mut DryRunSummary) -> Result<(InstallOutcome, Option<&'static str>)> {
+    let &PrivilegedInstall { source_path, target_path, rendered, mode, escalation, backups } = file;
+    let exists = target_path.exists();
+    let content = match rendered {
+        Some(content) => content.to_vec(),
+        None => fs::read(source_path)
+            .with_context(|| format!("Failed to read {}", source_path.display()))?,
+    };
+    let current = fs::read(target_path).ok();
+
+    if current.as_ref() == Some(&content) {
+        summary.add_file(PlannedAction::Skip, source_path);
+        return Ok((InstallOutcome::SkippedIdentical, None));
+    }
+
+    println!("{} {}", colorize::header("System file"), colorize::path(target_path.display()));
+    match &current {
+        Some(current) => print_content_diff(target_path, current, source_path, &content),
+        None if exists => println!("    {}", colorize::warning("(the current file can't be read, so it can't be compared)")),
+        None => println!("    {}", colorize::info("(new file)")),
+    }
+
+    let action = if exists { PlannedAction::Overwrite } else { PlannedAction::Copy };
+    if options.dry_run {
+        if exists && options.backup {
+            summary.add_file(PlannedAction::BackUp, target_path);
+        }
+        summary.add_file(action, source_path);
+        return Ok((InstallOutcome::Copied, None));
+    }
+
+    if !prompt::confirm("sudo", &format!("Write {} as root using '{}'?", target_path.display(), escalation), false)? {
+        return Ok((InstallOutcome::Skipped, Some("not confirmed")));
+    }
+
+    if exists && options.backup {
+        backup_file(target_path, backups, false)?;
+    }
+    escalated_write(escalation, source_path, rendered, target_path, mode)?;
+
+    Ok((InstallOutcome::Copied, None))
+}
+
+fn prune_stale_files(manifest: &mut Manifest, stale: Vec<PathBuf>, home_dir: &Path, backups: &Backups, dry_run: bool, force: bool, summary: &mut DryRunSummary) -> Result<()> {
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    println!("{}", colorize::header("Installed files no longer in the source directory:"));
+    for relative_path in &stale {
+        println!("  {}", colorize::path(relative_path.display()));
+    }
+
+    if dry_run {
+        println!("{}", colorize::dry_run("[Dry run] Would back up and remove the files above"));
+        for relative_path in &stale {
+            let target_path = home_dir.join(relative_path);
+            if target_path.is_file() {
+                summary.add_file(PlannedAction::BackUp, &target_path);
+                summary.add_file(PlannedAction::Remove, &target_path);
+            }
+        }
+        return Ok(());
+    }
+
+    if !force && !prompt::confirm("prune", "Back up and remove these files from your home directory?", false)? {
+        println!("{}", colorize::warning("Pruning cancelled."));
+        return Ok(());
+    }
+
+    for relative_path in stale {
+        let target_path = home_dir.join(&relative_path);
+
+        if target_path.is_file() {
+            backup_deleted_file(&target_path, backups)?;
+            fs::remove_file(&target_path)
+                .with_context(|| format!("Failed to remove file {}", target_path.display()))?;
+            println!("  {} {}", colorize::success("Pruned:"), colorize::path(relative_path.display()));
+        }
+
+        manifest.remove(&relative_path);
+    }
+
+    Ok(())
+}
+
+pub fn list_backups(file: Option<&str>) -> Result<()> {
+    let backup_dir: PathBuf = get_backup_dir()?;
+
+    if !backup_dir.exists() {
+        println!("{}", colorize::warning("No backups found"));
+        return Ok(());
+    }
+
+    if let Some(file_path) = file {
+        let versions = find_all_backup_versions(file_path, &backup_dir)?;
+
+        if versions.is_empty() {
+            let hint = backed_up_file_hint(file_path, &get_home_dir()?, &backup_dir);
+            println!("{} {}{}", colorize::warning("No backups found for"), colorize::path(file_path), hint);
+        } else {
+            let home_dir = get_home_dir()?;
+            let manifest = Manifest::load()?;
+            let index = BackupIndex::load()?;
+            let lookup = SourceLookup::load(&home_dir);
+            let name = Path::new(file_path).file_name().unwrap_or_default().to_string_lossy();
+
+            println!("{} {}:", colorize::header("Backup versions for"), colorize::path(file_path));
+            let mut table = Table::new(vec![Column::left("Version"), Column::left("Backup").shrink(), Column::left("Date"), Column::left("")]);
+            for (version, path) in versions {
+                let date_time = version.date_time()
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+                    .unwrap_or_else(|| version.to_string());
+                let target = backup_target(&name, &path, &index, &manifest, &home_dir);
+                let note = lookup.redundancy(&path, target.as_deref())
+                    .map(|redundancy| format!("[{}]", redundancy.describe()))
+                    .unwrap_or_default();
+
+                table.row(vec![
+                    Cell::new(Style::Version, version),
+                    Cell::new(Style::Path, path.strip_prefix(&backup_dir)?.display()),
+                    Cell::new(Style::Info, date_time),
+                    Cell::new(Style::Success, note),
+                ]);
+            }
+            table.print();
+        }
+    } else {
+        print_backup_tree(&backup_dir)?;
+    }
+
+    Ok(())
+}
+
+// The backup of `file` at `version`, or its latest backup without a version or with `latest`
+fn find_backup(file: &str, version: Option<&str>, home_dir: &Path, backup_dir: &Path) -> Result<PathBuf> {
+    let relative_path = home_relative_path(file, home_dir)?;
+
+    match version {
+        Some(version) if version != "latest" => find_backup_by_version(&relative_path.to_string_lossy(), version, backup_dir),
+        _ => find_latest_backup_of(&home_dir.join(&relative_path), backup_dir)
+            .map_err(|err| anyhow!("{}{}", err, backed_up_file_hint(file, home_dir, backup_dir))),
+    }
+}
+
+pub fn backup_content(file: &str, version: &str) -> Result<Vec<u8>> {
+    let backup_path = find_backup(file, Some(version), &get_home_dir()?, &get_backup_dir()?)?;
+    fs::read(&backup_path)
+        .with_context(|| format!("Failed to read backup file {}", backup_path.display()))
+}
+
+// Prints one backup of a file as it is, to look at an old version without restoring it
+pub fn show_backup(file: &str, version: &str) -> Result<()> {
+    let content = backup_content(file, version)?;
+    std::io::stdout().write_all(&content)
+        .with_context(|| "Failed to write the backup to stdout")
+}
+
+// The installed file that backups named `name` were taken from: the one manifest entry with that file
+// name, or a file of that name directly in the home directory
+fn backed_up_target(name: &str, manifest: &Manifest, home_dir: &Path) -> Option<PathBuf> {
+    let mut matches = manifest.files.keys()
+        .filter(|target| target.file_name().is_some_and(|file_name| file_name == name));
+
+    match (matches.next(), matches.next()) {
+        (Some(target), None) => Some(home_dir.join(target)),
+        (None, _) if home_dir.join(name).is_file() => Some(home_dir.join(name)),
+        _ => None,
+    }
+}
+
+// Home-relative paths of the files in the home directory that have backups, as far as the index and the
+// backup names tell; empty when neither can be read
+pub fn backed_up_files(home_dir: &Path, backup_dir: &Path) -> BTreeSet<PathBuf> {
+    let index = BackupIndex::load().unwrap_or_default();
+    let manifest = Manifest::load().unwrap_or_default();
+    let backed_up_names = backup_versions_by_file(backup_dir).unwrap_or_default().into_keys()
+        .filter_map(|name| backed_up_target(&name, &manifest, home_dir));
+
+    index.backups.iter()
+        .filter(|(backup_path, _)| backup_path.exists())
+        .map(|(_, record)| record.original.clone())
+        .chain(backed_up_names)
+        .filter_map(|path| path.strip_prefix(home_dir).ok().map(Path::to_path_buf))
+        .collect()
+}
+
+// " (did you mean `~/.bashrc`?)" naming the backed-up or managed file closest to `file`, for when it has no
+// backups; empty when nothing is close enough to be a typo
+fn backed_up_file_hint(file: &str, home_dir: &Path, backup_dir: &Path) -> String {
+    let manifest = Manifest::load().unwrap_or_default();
+    let candidates = backed_up_files(home_dir, backup_dir).into_iter()
+        .chain(manifest.files.into_keys())
+        .map(|path| format!("~/{}", path.display()))
+        .collect::<BTreeSet<_>>();
+    let wanted = match home_relative_path(file, home_dir) {
+        Ok(relative_path) => format!("~/{}", relative_path.display()),
+        Err(_) => file.to_string(),
+    };
+
+    closest_match(&wanted, candidates.iter().map(String::as_str))
+        .filter(|candidate| *candidate != wanted)
+        .map(|candidate| format!(" (did you mean `{}`?)", candidate))
+        .unwrap_or_default()
+}
+
+// What a backup duplicates, making it safe to drop
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Redundancy {
+    // The file installed at its location now
+    Installed,
+    // What the source directory would install there
+    Source,
+}
+
+impl Redundancy {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Installed => "matches installed",
+            Self::Source => "matches source",
+        }
+    }
+}
+
+// Source file for each installed location, with the template variables to render them, for telling
+// whether a backup holds what the source would install. Empty when the source can't be read.
+struct SourceLookup {
+    sources: BTreeMap<PathBuf, PathBuf>,
+    vars: BTreeMap<String, String>,
+}
+
+impl SourceLookup {
+    fn load(home_dir: &Path) -> Self {
+        let Ok(config) = read_config() else {
+            return Self { sources: BTreeMap::new(), vars: BTreeMap::new() };
+        };
+        let sources = configured_files(&config, home_dir).unwrap_or_default().into_iter()
+            .map(|file| (file.target, file.source))
+            .collect();
+        let vars = template::template_vars(&config, &[]).unwrap_or_default();
+        Self { sources, vars }
+    }
+
+    fn redundancy(&self, backup_path: &Path, target: Option<&Path>) -> Option<Redundancy> {
+        let target = target?;
+        if target.is_file() && same_file_content(backup_path, target).unwrap_or(false) {
+            return Some(Redundancy::Installed);
+        }
+
+        let source = self.sources.get(target)?;
+        let expected = expected_content(source, &self.vars).ok()?;
+        (fs::read(backup_path).ok()? == expected).then_some(Redundancy::Source)
+    }
+}
+
+// Where a backup was taken from: recorded with it, or for older backups worked out from its file name
+fn backup_target(name: &str, backup_path: &Path, index: &BackupIndex, manifest: &Manifest, home_dir: &Path) -> Option<PathBuf> {
+    index.original_path(backup_path).map(Path::to_path_buf)
+        .or_else(|| backed_up_target(name, manifest, home_dir))
+}
+
+// Backups grouped by the file they were taken from, one line per version with its age, size and
+// whether it holds what is installed now or what the source would install
+fn print_backup_tree(backup_dir: &Path) -> Result<()> {
+    let home_dir = get_home_dir()?;
+    let manifest = Manifest::load()?;
+    let backup_index = BackupIndex::load()?;
+    let lookup = SourceLookup::load(&home_dir);
+    let versions = backup_versions_by_file(backup_dir)?;
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+
+    if versions.is_empty() {
+        println!("{}", colorize::warning("No backups found"));
+        return Ok(());
+    }
+
+    let version_count: usize = versions.values().map(Vec::len).sum();
+    println!("{} {} file(s), {} version(s)",
+        colorize::header("Backups:"),
+        colorize::highlight(versions.len()),
+        colorize::highlight(version_count));
+
+    let mut table = Table::new(vec![Column::left(""), Column::left(""), Column::right(""), Column::right(""), Column::left("")]);
+    for (name, file_versions) in &versions {
+        let recorded = file_versions.last().and_then(|(_, path)| backup_index.original_path(path)).map(Path::to_path_buf);
+        let target = recorded.or_else(|| backed_up_target(name, &manifest, &home_dir));
+        let label = match &target {
+            Some(target) if target.starts_with(&home_dir) => format!("~/{}", target.strip_prefix(&home_dir)?.display()),
+            Some(target) => target.display().to_string(),
+            None => name.clone(),
+        };
+        table.section(Cell::new(Style::Path, label));
+
+        for (index, (version, path)) in file_versions.iter().enumerate() {
+            let branch = colorize::branch(index + 1 == file_versions.len());
+            let date_time = version.date_time()
+                .map(|dt| dt.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| version.to_string());
+            let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+            let version_target = backup_target(name, path, &backup_index, &manifest, &home_dir);
+            let note = if let Some(redundancy) = lookup.redundancy(path, version_target.as_deref()) {
+                Cell::new(Style::Success, redundancy.describe())
+            } else if backup_index.is_tombstone(path) {
+                Cell::new(Style::Warning, "deleted")
+            } else {
+                Cell::from("")
+            };
+
+            table.row(vec![
+                branch.into(),
+                Cell::new(Style::Version, date_time),
+                Cell::new(Style::Info, format_age(now.saturating_sub(version.seconds()))),
+                format_size(size).into(),
+                note,
+            ]);
+        }
+    }
+    table.print();
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestoreChoice {
+    Restore,
+    Skip,
+    Quit,
+}
+
+fn prompt_restore(target_path: &Path) -> Result<RestoreChoice> {
+    let choice = prompt::choose("restore", &format!("Restore {}?", target_path.display()), &["restore", "skip", "quit"], 1)?;
+    Ok([RestoreChoice::Restore, RestoreChoice::Skip, RestoreChoice::Quit][choice])
+}
+
+// The latest backup of every file whose location is known: recorded with the backup, or for older
+// backups worked out from the file name
+fn latest_backups(backup_dir: &Path, home_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let manifest = Manifest::load()?;
+    let index = BackupIndex::load()?;
+    let mut restores = Vec::new();
+
+    for (name, versions) in backup_versions_by_file(backup_dir)? {
+        // Files sharing a name, such as .config/fish/config.fish and .config/foo/config.fish, are kept apart
+        let mut latest_by_original = BTreeMap::new();
+        let mut latest_unrecorded = None;
+        for (_, backup_path) in versions {
+            match index.original_path(&backup_path) {
+                Some(original) => {
+                    latest_by_original.insert(original.to_path_buf(), backup_path);
+                },
+                None => latest_unrecorded = Some(backup_path),
+            }
+        }
+
+        if !latest_by_original.is_empty() {
+            restores.extend(latest_by_original);
+            continue;
+        }
+        let Some(backup_path) = latest_unrecorded else {
+            continue;
+        };
+
+        match backed_up_target(&name, &manifest, home_dir) {
+            Some(target_path) => restores.push((target_path, backup_path)),
+            None => println!("  {} {} (can't tell where it was installed; restore it by path)",
+                colorize::warning("Skipped:"),
+                colorize::path(&name)),
+        }
+    }
+
+    Ok(restores)
+}
+
+// A file picked at the terminal when a command is run without one, as `~/`-prefixed path. `all` is offered
+// first for commands that work on every file without one, and None means going on with every file.
+fn pick_file(question: &str, all: Option<&str>, files: impl IntoIterator<Item = PathBuf>) -> Result<Option<String>> {
+    let files = files.into_iter().map(|path| format!("~/{}", path.display())).collect::<Vec<_>>();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let offset = usize::from(all.is_some());
+    let items = all.map(str::to_string).into_iter().chain(files).collect::<Vec<_>>();
+    Ok(prompt::pick(question, &items)?
+        .filter(|&index| index >= offset)
+        .map(|index| items[index].clone()))
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RestoreOptions {
+    // Files restored together; empty means every file with a backup
+    pub files: Vec<String>,
+    // Backup version of the one named file to restore instead of the latest
+    pub version: Option<String>,
+    pub preview: bool,
+    pub dry_run: bool,
+    pub apply_system: bool,
+    // Leave each backup in place after restoring it instead of deleting it
+    pub keep_backups: bool,
+    // Restore over files changed after their backup was taken without asking
+    pub overwrite_newer: bool,
+    // Of every file with a backup, only those restored to these paths, as ticked in the checklist
+    pub selected: Option<BTreeSet<PathBuf>>,
+}
+
+// Restores the named files, or at a terminal without any, the files ticked in a checklist of every file with a backup
+pub fn restore_backups(options: &RestoreOptions) -> Result<()> {
+    let selected = match options.files.as_slice() {
+        [] => pick_restores(&get_home_dir()?, &get_backup_dir()?)?,
+        _ => None,
+    };
+    if selected.as_ref().is_some_and(BTreeSet::is_empty) {
+        println!("{}", colorize::warning("Nothing selected to restore."));
+        return Ok(());
+    }
+    restore_backups_with(&RestoreOptions { selected, ..options.clone() }, prompt_restore)
+}
+
+// The checklist: each file whose latest backup differs from it, with the backup's age and the size of the change.
+// None when there is no terminal to show it on.
+fn pick_restores(home_dir: &Path, backup_dir: &Path) -> Result<Option<BTreeSet<PathBuf>>> {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let mut choices = Vec::new();
+    for (target_path, backup_path) in latest_backups(backup_dir, home_dir)? {
+        let backup_content = fs::read(&backup_path)
+            .with_context(|| format!("Failed to read backup file {}", backup_path.display()))?;
+        let change = match fs::read(&target_path) {
+            Ok(current) if current == backup_content => continue,
+            Ok(current) => diff_stat(&target_path, &current, &backup_content).to_string(),
+            Err(_) => String::from("no current file"),
+        };
+        let age = backup_path.extension()
+            .and_then(|version| BackupVersion::parse(&version.to_string_lossy()))
+            .map(|version| format_age(now.saturating_sub(version.seconds())))
+            .unwrap_or_default();
+        choices.push((home_display_path(&target_path, home_dir), age, change, target_path));
+    }
+
+    let path_width = choices.iter().map(|(path, ..)| path.chars().count()).max().unwrap_or(0);
+    let age_width = choices.iter().map(|(_, age, ..)| age.chars().count()).max().unwrap_or(0);
+    let items = choices.iter()
+        .map(|(path, age, change, _)| format!("{:<path_width$}  {:<age_width$}  {}", path, age, change))
+        .collect::<Vec<_>>();
+
+    Ok(prompt::checklist("Files to restore", &items)?
+        .map(|picked| picked.into_iter().map(|index| choices[index].3.clone()).collect()))
+}
+
+// Puts backups back in place of the current files, backing those up first so a restore can be undone.
+// With `preview`, each change is shown and `choose` decides whether it goes ahead. Backups of files outside
+// the home directory are only restored with `apply_system`, through the escalation command after showing the diff.
+// Named `files` are restored together: every backup is looked up first, several files are confirmed at once,
+// and when one can't be restored the ones before it are put back as they were.
+pub fn restore_backups_with<F>(options: &RestoreOptions, mut choose: F) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<RestoreChoice>,
+{
+    let RestoreOptions { ref files, ref version, preview, dry_run, apply_system, keep_backups, overwrite_newer, ref selected } = *options;
+    let config = read_config()?;
+    let home_dir = get_home_dir()?;
+    let backups = Backups::new(get_backup_dir()?, &config)?;
+    let dir_modes = config.dir_modes(&home_dir);
+    let escalation = config.escalation_command.clone().unwrap_or_else(|| String::from(DEFAULT_ESCALATION_COMMAND));
+
+    if version.is_some() && files.len() > 1 {
+        return Err(anyhow!("--version picks the backup of a single file, but {} files were given", files.len()));
+    }
+    let restores = match files.as_slice() {
+        [] => latest_backups(&backups.dir, &home_dir)?.into_iter()
+            .filter(|(target_path, _)| selected.as_ref().is_none_or(|selected| selected.contains(target_path)))
+            .collect(),
+        files => files.iter()
+            .map(|file| Ok((home_dir.join(home_relative_path(file, &home_dir)?), find_backup(file, version.as_deref(), &home_dir, &backups.dir)?)))
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    let backup_index = BackupIndex::load()?;
+    let manifest = Manifest::load()?;
+    let planned_restores = plan_restores(restores, &backup_index);
+    let mut summary = DryRunSummary::new();
+    let mut restored_count = 0;
+    let mut skipped_count = 0;
+    let mut undo_log = Vec::new();
+
+    if files.len() > 1 && !preview && !dry_run && !confirm_restores(&planned_restores, &home_dir)? {
+        println!("{}", colorize::warning("Restore cancelled."));
+        return Ok(());
+    }
+
+    for planned in planned_restores {
+        let PlannedRestore { target_path, backup_path, action } = &planned;
+        let display_path = home_display_path(target_path, &home_dir);
+
+        if *action == RestoreAction::Unchanged {
+            println!("  {} {} (matches the backup)", colorize::info("Unchanged:"), colorize::path(&display_path));
+            summary.add_file(PlannedAction::Skip, target_path);
+            continue;
+        }
+
+        let privileged = is_privileged(target_path, &home_dir);
+        if privileged && !apply_system {
+            println!("  {} {} (outside the home directory, use --apply-system)", colorize::warning("Skipped:"), colorize::path(&display_path));
+            summary.add_file(PlannedAction::Skip, target_path);
+            skipped_count += 1;
+            continue;
+        }
+
+        // System files are always shown, since they are confirmed one by one anyway
+        if preview || privileged {
+            println!("{} {}", colorize::header("restore"), colorize::path(&display_path));
+            let backup_content = fs::read(backup_path)
+                .with_context(|| format!("Failed to read backup file {}", backup_path.display()))?;
+            match fs::read(target_path) {
+                Ok(current) => print_content_diff(target_path, &current, backup_path, &backup_content),
+                Err(_) => println!("    {}", colorize::info("(no current file; the backup will be copied into place)")),
+            }
+        }
+        if preview {
+            match choose(target_path)? {
+                RestoreChoice::Restore => {},
+                RestoreChoice::Skip => {
+                    summary.add_file(PlannedAction::Skip, target_path);
+                    skipped_count += 1;
+                    continue;
+                },
+                RestoreChoice::Quit => break,
+            }
+        }
+
+        // Restoring over edits made since the backup was taken is easy to do by accident, so it is asked about
+        // separately unless the diff was just shown
+        if *action == RestoreAction::Replace && !privileged && !preview && !overwrite_newer
+            && has_newer_edits(target_path, backup_path, &home_dir, &manifest) {
+            if dry_run {
+                println!("  {} {} (changed after the backup was taken; a restore asks first)", colorize::warning("Newer:"), colorize::path(&display_path));
+            } else if !prompt::confirm("overwrite-newer", &format!("{} was changed after this backup was taken. Overwrite it anyway?", display_path), false)? {
+                println!("  {} {} (changed after the backup was taken, use --overwrite-newer)", colorize::warning("Skipped:"), colorize::path(&display_path));
+                summary.add_file(PlannedAction::Skip, target_path);
+                skipped_count += 1;
                 continue;
             }
+        }
+
+        if *action == RestoreAction::Replace {
+            summary.add_file(PlannedAction::BackUp, target_path);
+        }
+        summary.add_file(PlannedAction::Restore, backup_path);
+
+        if dry_run {
+            print_planned_restore(&planned, &display_path, &home_dir);
+            continue;
+        }
+
+        if privileged {
+            if !prompt::confirm("sudo", &format!("Write {} as root using '{}'?", target_path.display(), escalation), false)? {
+                println!("  {} {} (not confirmed)", colorize::warning("Skipped:"), colorize::path(&display_path));
+                skipped_count += 1;
+                continue;
+            }
+            apply_privileged_restore(&planned, &backups, &escalation, keep_backups)?;
+        } else if files.is_empty() {
+            apply_restore(&planned, &backups, &dir_modes, keep_backups)?;
+        } else {
+            let undo = RestoreUndo::capture(&planned, &backups.dir)?;
+            if let Err(e) = apply_restore(&planned, &backups, &dir_modes, keep_backups) {
+                undo_log.push(undo);
+                let undone = undo_log.len() - 1;
+                for undo in undo_log.iter().rev() {
+                    undo.apply(&backups.dir)?;
+                }
+                return Err(e.context(format!("Failed to restore {}; the {} file(s) restored before it were put back as they were", display_path, undone)));
+            }
+            undo_log.push(undo);
+        }
+        let verb = if matches!(action, RestoreAction::Recreate { resurrect: true, .. }) { "Resurrected:" } else { "Restored:" };
+        println!("  {} {}", colorize::success(verb), colorize::path(&display_path));
+        restored_count += 1;
+    }
+
+    if dry_run {
+        summary.print();
+        return Ok(());
+    }
+
+    if restored_count > 0 {
+        enforce_backup_quota(&config, &backups.dir)?;
+    }
+
+    println!("\n{}", colorize::header("Summary:"));
+    println!("  {} {}", colorize::success("Files restored:"), colorize::highlight(restored_count));
+    if skipped_count > 0 {
+        println!("  {} {}", colorize::warning("Files skipped:"), colorize::highlight(skipped_count));
+    }
+
+    Ok(())
+}
+
+// Whether the current file was changed after the backup was taken, other than by install writing what the
+// manifest records, so restoring the backup would throw those changes away
+fn has_newer_edits(target_path: &Path, backup_path: &Path, home_dir: &Path, manifest: &Manifest) -> bool {
+    let Some(taken) = backup_path.extension().and_then(|version| BackupVersion::parse(&version.to_string_lossy())) else {
+        return false;
+    };
+    let changed_after = fs::metadata(target_path).and_then(|metadata| metadata.modified()).ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_some_and(|modified| modified.as_millis() as u64 > taken.millis);
+    let as_installed = target_path.strip_prefix(home_dir).ok()
+        .and_then(|relative_path| manifest.is_locally_modified(relative_path, target_path)) == Some(false);
+
+    changed_after && !as_installed
+}
+
+// What restore does to one file. It is worked out before anything changes, so a dry run shows every step a
+// real run would take.
+#[derive(Debug, Clone, PartialEq)]
+enum RestoreAction {
+    // The current file already matches the backup
+    Unchanged,
+    // The current file is backed up, so the restore can be undone, then replaced with the backup
+    Replace,
+    // There is no current file, so the backup is put in place, under `create_dirs` when its directory is gone.
+    // A file the tool deleted is resurrected from its tombstone.
+    Recreate { resurrect: bool, create_dirs: Option<PathBuf> },
+}
 
-            if !force {
-                if verbose {
-                    println!("  {}", colorize::warning("Skipping (already exists but different, use --force to overwrite)"));
-                } else {
-                    println!("  {} {} (already exists, use --force to overwrite)",
-                        colorize::warning("Skipped:"),
-                        colorize::path(relative_path.display()));
-                }
-                continue;
-            } else if backup {
-                backup_file(&target_path, &backup_dir, dry_run)?;
-            }
-        }
+#[derive(Debug, Clone, PartialEq)]
+struct PlannedRestore {
+    target_path: PathBuf,
+    backup_path: PathBuf,
+    action: RestoreAction,
+}
 
-        if !dry_run {
-            fs::copy(source_path, &target_path)
-                .with_context(|| format!("Failed to copy {} to {}", source_path.display(), target_path.display()))?;
-            if verbose {
-                println!("  {}", colorize::success("Copied successfully"));
-            } else {
-                println!("  {} {}", colorize::success("Copied:"), colorize::path(relative_path.display()));
-            }
-        } else {
-            if verbose {
-                println!("  {} {}",
-                    colorize::dry_run("[Dry run] Would copy to"),
-                    colorize::path(target_path.strip_prefix(&get_home_dir()?)?.display()));
+fn plan_restores(restores: Vec<(PathBuf, PathBuf)>, backup_index: &BackupIndex) -> Vec<PlannedRestore> {
+    restores.into_iter()
+        .map(|(target_path, backup_path)| {
+            let action = if target_path.is_file() && same_file_content(&backup_path, &target_path).unwrap_or(false) {
+                RestoreAction::Unchanged
+            } else if target_path.exists() {
+                RestoreAction::Replace
             } else {
-                println!("  {} {}",
-                    colorize::dry_run("[Dry run] Would copy:"),
-                    colorize::path(relative_path.display()));
+                // The outermost directory that is missing, which is the one a dry run reports
+                let create_dirs = target_path.ancestors().skip(1)
+                    .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+                    .last()
+                    .map(Path::to_path_buf);
+                RestoreAction::Recreate { resurrect: backup_index.is_tombstone(&backup_path), create_dirs }
+            };
+            PlannedRestore { target_path, backup_path, action }
+        })
+        .collect()
+}
+
+fn home_display_path(path: &Path, home_dir: &Path) -> String {
+    path.strip_prefix(home_dir)
+        .map(|relative_path| format!("~/{}", relative_path.display()))
+        .unwrap_or_else(|_| path.display().to_string())
+}
+
+fn print_planned_restore(planned: &PlannedRestore, display_path: &str, home_dir: &Path) {
+    match &planned.action {
+        RestoreAction::Unchanged => {},
+        RestoreAction::Replace => {
+            println!("  {} {}", colorize::dry_run("[Dry run] Would back up:"), colorize::path(display_path));
+            println!("  {} {}", colorize::dry_run("[Dry run] Would restore:"), colorize::path(display_path));
+        },
+        RestoreAction::Recreate { resurrect, create_dirs } => {
+            if let Some(dir) = create_dirs {
+                println!("  {} {}", colorize::dry_run("[Dry run] Would create directory:"), colorize::path(home_display_path(dir, home_dir)));
             }
-        }
+            let verb = if *resurrect { "[Dry run] Would resurrect:" } else { "[Dry run] Would restore:" };
+            println!("  {} {}", colorize::dry_run(verb), colorize::path(display_path));
+        },
+    }
+}
+
+fn apply_restore(planned: &PlannedRestore, backups: &Backups, dir_modes: &DirModes, keep_backup: bool) -> Result<()> {
+    let PlannedRestore { target_path, backup_path, action } = planned;
+    if *action == RestoreAction::Unchanged {
+        return Ok(());
     }
 
-    if verbose {
-        println!("{}", colorize::success("Dotfiles installation complete!"));
-        println!("{}", colorize::info("You can now run 'restore' to revert to original files at any time."));
+    // Read up front, since backing up the current file may trim old versions, this one among them
+    let backup_content = fs::read(backup_path)
+        .with_context(|| format!("Failed to read backup file {}", backup_path.display()))?;
+    let backup_permissions = fs::metadata(backup_path).ok().map(|metadata| metadata.permissions());
+
+    if *action == RestoreAction::Replace {
+        backup_file(target_path, backups, false)?;
     } else {
-        println!("{}", colorize::success("Installation complete."));
-        println!("{}", colorize::info("You can now run 'restore' to revert to original files at any time."));
+        ensure_parent_dirs(target_path, dir_modes, false)?;
+    }
+
+    write_file_atomic(target_path, &backup_content, backup_permissions)
+        .with_context(|| format!("Failed to restore backup {} to {}", backup_path.display(), target_path.display()))?;
+    if !keep_backup {
+        remove_restored_backup(backup_path)?;
     }
     Ok(())
 }
 
-pub fn list_backups(file: Option<&str>) -> Result<()> {
-    let backup_dir: PathBuf = get_backup_dir()?;
+// Deletes a backup once it is back in place, so a restore that fails partway never loses it. Backing up the
+// replaced file may already have trimmed it away.
+fn remove_restored_backup(backup_path: &Path) -> Result<()> {
+    match fs::remove_file(backup_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)
+            .with_context(|| format!("Failed to delete backup file {}", backup_path.display())),
+        _ => Ok(()),
+    }
+}
 
-    if !backup_dir.exists() {
-        println!("{}", colorize::warning("No backups found"));
-        return Ok(());
+// Lists the restores that change something and asks once whether to go ahead with all of them
+fn confirm_restores(planned_restores: &[PlannedRestore], home_dir: &Path) -> Result<bool> {
+    let changes = planned_restores.iter()
+        .filter(|planned| planned.action != RestoreAction::Unchanged)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return Ok(true);
     }
 
-    if let Some(file_path) = file {
-        let versions = find_all_backup_versions(file_path, &backup_dir)?;
+    println!("{}", colorize::header("Files to restore:"));
+    for planned in &changes {
+        println!("  {}", colorize::path(home_display_path(&planned.target_path, home_dir)));
+    }
+    prompt::confirm("restore-files", &format!("Restore these {} files from their backups?", changes.len()), false)
+}
 
-        if versions.is_empty() {
-            println!("{} {}", colorize::warning("No backups found for"), colorize::path(file_path));
-        } else {
-            println!("{} {}:", colorize::header("Backup versions for"), colorize::path(file_path));
-            for (version, path) in versions {
-                let date_time = chrono::DateTime::<chrono::Utc>::from_timestamp(version as i64, 0)
-                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
-                    .unwrap_or_else(|| version.to_string());
+// A file and its backups as they were before a restore of named files, so it can be undone when a later file fails
+struct RestoreUndo {
+    target_path: PathBuf,
+    // None when there was no file
+    target: Option<(Vec<u8>, fs::Permissions)>,
+    backup_path: PathBuf,
+    backup: (Vec<u8>, fs::Permissions),
+    // The backup's index record, which is dropped once the restore removes the backup
+    record: Option<BackupRecord>,
+    // Every backup with the file's name, so the one the restore takes of the replaced file can be told apart
+    backups_before: BTreeSet<PathBuf>,
+}
 
-                println!("  {} - {} ({})",
-                    colorize::version(version),
-                    colorize::path(path.strip_prefix(&get_backup_dir()?)?.display()),
-                    colorize::info(date_time));
-            }
+impl RestoreUndo {
+    fn capture(planned: &PlannedRestore, backup_dir: &Path) -> Result<Self> {
+        let read = |path: &Path| -> Result<(Vec<u8>, fs::Permissions)> {
+            let content = fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok((content, fs::metadata(path)?.permissions()))
+        };
+
+        Ok(Self {
+            target_path: planned.target_path.clone(),
+            target: planned.target_path.is_file().then(|| read(&planned.target_path)).transpose()?,
+            backup_path: planned.backup_path.clone(),
+            backup: read(&planned.backup_path)?,
+            record: BackupIndex::load()?.backups.get(&std::path::absolute(&planned.backup_path)?).cloned(),
+            backups_before: Self::backups_of(&planned.target_path, backup_dir)?,
+        })
+    }
+
+    fn backups_of(target_path: &Path, backup_dir: &Path) -> Result<BTreeSet<PathBuf>> {
+        Ok(find_all_backup_versions(&target_path.to_string_lossy(), backup_dir)?.into_iter()
+            .map(|(_, backup_path)| backup_path)
+            .collect())
+    }
+
+    fn apply(&self, backup_dir: &Path) -> Result<()> {
+        for added in Self::backups_of(&self.target_path, backup_dir)?.difference(&self.backups_before) {
+            fs::remove_file(added)
+                .with_context(|| format!("Failed to remove backup {}", added.display()))?;
         }
-    } else {
-        println!("{}", colorize::header("All backup files:"));
-        let mut found = false;
 
-        for entry in WalkDir::new(&backup_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
-            let path = entry.path();
-            if let Some(_) = path.file_name() {
-                println!("  {}", colorize::path(path.strip_prefix(&backup_dir).unwrap_or(path).display()));
-                found = true;
-            }
+        let (content, permissions) = &self.backup;
+        write_file_atomic(&self.backup_path, content, Some(permissions.clone()))
+            .with_context(|| format!("Failed to put back backup {}", self.backup_path.display()))?;
+        if let Some(record) = &self.record {
+            let backup_path = std::path::absolute(&self.backup_path)?;
+            BackupIndex::update(|index| {
+                index.backups.insert(backup_path, record.clone());
+            })?;
         }
 
-        if !found {
-            println!("{}", colorize::warning("No backups found"));
+        match &self.target {
+            Some((content, permissions)) => write_file_atomic(&self.target_path, content, Some(permissions.clone()))
+                .with_context(|| format!("Failed to put back {}", self.target_path.display())),
+            None if self.target_path.is_file() => fs::remove_file(&self.target_path)
+                .with_context(|| format!("Failed to remove {}", self.target_path.display())),
+            None => Ok(()),
         }
     }
+}
+
+// Like apply_restore, but the backup is written as root; the current file is still backed up as the user
+fn apply_privileged_restore(planned: &PlannedRestore, backups: &Backups, escalation: &str, keep_backup: bool) -> Result<()> {
+    let PlannedRestore { target_path, backup_path, action } = planned;
+
+    let backup_content = fs::read(backup_path)
+        .with_context(|| format!("Failed to read backup file {}", backup_path.display()))?;
 
+    if *action == RestoreAction::Replace {
+        backup_file(target_path, backups, false)?;
+    }
+    escalated_write(escalation, backup_path, Some(&backup_content), target_path, None)
+        .with_context(|| format!("Failed to restore backup {} to {}", backup_path.display(), target_path.display()))?;
+    if !keep_backup {
+        remove_restored_backup(backup_path)?;
+    }
     Ok(())
 }
 
@@ -216,13 +1717,8 @@ pub fn clear_backups(force: bool) -> Result<()> {
         println!("{} {}",
             colorize::warning("Warning: This will permanently delete all backup files in"),
             colorize::path(display_path));
-        println!("{}", colorize::warning("Are you sure you want to continue? (yes/no)"));
 
-        let mut confirmation = String::new();
-        std::io::stdin().read_line(&mut confirmation)?;
-        let confirmation = confirmation.trim().to_lowercase();
-
-        if confirmation != "yes" {
+        if !prompt::confirm("reset", "Are you sure you want to continue?", false)? {
             println!("{}", colorize::warning("Backup clearing cancelled."));
             return Ok(());
         }
@@ -243,7 +1739,377 @@ pub fn clear_backups(force: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn status_dotfiles(verbose: bool) -> Result<()> {
+// Removes backups identical to the installed file at their location or to what the source would install
+// there, since either can already bring that content back
+pub fn prune_redundant_backups(dry_run: bool) -> Result<()> {
+    let backup_dir = get_backup_dir()?;
+    if !backup_dir.exists() {
+        println!("{}", colorize::warning("No backups found"));
+        return Ok(());
+    }
+
+    let home_dir = get_home_dir()?;
+    let manifest = Manifest::load()?;
+    let index = BackupIndex::load()?;
+    let lookup = SourceLookup::load(&home_dir);
+
+    let mut removed_count = 0;
+    let mut reclaimed = 0;
+
+    for (name, versions) in backup_versions_by_file(&backup_dir)? {
+        for (_, backup_path) in versions {
+            let target = backup_target(&name, &backup_path, &index, &manifest, &home_dir);
+            let Some(redundancy) = lookup.redundancy(&backup_path, target.as_deref()) else {
+                continue;
+            };
+
+            let display_path = backup_path.strip_prefix(&backup_dir).unwrap_or(&backup_path).display().to_string();
+            let size = fs::metadata(&backup_path).map(|metadata| metadata.len()).unwrap_or(0);
+            if dry_run {
+                println!("  {} {} ({})", colorize::dry_run("[Dry run] Would remove:"), colorize::path(display_path), redundancy.describe());
+            } else {
+                fs::remove_file(&backup_path)
+                    .with_context(|| format!("Failed to remove backup {}", backup_path.display()))?;
+                println!("  {} {} ({})", colorize::success("Removed:"), colorize::path(display_path), redundancy.describe());
+            }
+            removed_count += 1;
+            reclaimed += size;
+        }
+    }
+
+    if removed_count == 0 {
+        println!("{}", colorize::success("No redundant backups found"));
+    } else {
+        let verb = if dry_run { "Redundant backups found:" } else { "Redundant backups removed:" };
+        println!("\n{} {} ({})", colorize::info(verb), colorize::highlight(removed_count), format_size(reclaimed));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct StatusOptions {
+    pub verbose: bool,
+    // Also report unmanaged files inside directories that hold managed files
+    pub extras: bool,
+    // Interactively reconcile each modified or missing file
+    pub fix: bool,
+    // Leave out files that match the source
+    pub changed_only: bool,
+    // Also write the counts, last install time and backup usage here for monitoring
+    pub metrics: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileState {
+    Installed,
+    Modified,
+    Missing,
+}
+
+// Totals printed at the end of status, e.g. "142 ok, 3 modified, 1 missing, 2 extra"
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StatusCounts {
+    pub ok: usize,
+    pub modified: usize,
+    pub missing: usize,
+    // Unmanaged files in directories that hold managed files
+    pub extra: usize,
+}
+
+impl std::fmt::Display for StatusCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ok, {} modified, {} missing, {} extra", self.ok, self.modified, self.missing, self.extra)
+    }
+}
+
+// Status lists files under the top-level directory they install into; files directly in home (or directly
+// in / for targets outside it) get the empty group
+pub fn status_group(relative_path: &Path) -> PathBuf {
+    let mut group = PathBuf::new();
+
+    for component in relative_path.parent().into_iter().flat_map(Path::components) {
+        group.push(component);
+        if matches!(component, std::path::Component::Normal(_)) {
+            return group;
+        }
+    }
+
+    PathBuf::new()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixAction {
+    Reinstall,
+    Adopt,
+    ShowDiff,
+    Ignore,
+    Quit,
+}
+
+fn prompt_fix_action(file: &ManagedFile, missing: bool) -> Result<FixAction> {
+    let actions: &[(&str, FixAction)] = if missing {
+        &[("reinstall", FixAction::Reinstall), ("ignore", FixAction::Ignore), ("quit", FixAction::Quit)]
+    } else {
+        &[("reinstall", FixAction::Reinstall), ("adopt", FixAction::Adopt), ("diff", FixAction::ShowDiff), ("ignore", FixAction::Ignore), ("quit", FixAction::Quit)]
+    };
+    let choices = actions.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let ignore = choices.iter().position(|name| *name == "ignore").unwrap_or_default();
+
+    let choice = prompt::choose("fix", &format!("What to do with {}?", file.relative_path.display()), &choices, ignore)?;
+    Ok(actions[choice].1)
+}
+
+// Whether an installed file holds what its source installs as. Plain files are compared by size and hash
+// rather than read into memory, which matters for large binaries, or only by metadata when configured.
+// Home-relative paths of managed files that are missing or differ from what the source installs
+pub fn drifted_files(config: &Config, home_dir: &Path) -> Result<BTreeSet<PathBuf>> {
+    let vars = template::template_vars(config, &[])?;
+
+    Ok(configured_files(config, home_dir)?.into_iter()
+        .filter(|file| !file.target.exists() || !matches_source(file, &vars))
+        .map(|file| file.relative_path)
+        .collect())
+}
+
+fn matches_source(file: &ManagedFile, vars: &BTreeMap<String, String>) -> bool {
+    if is_generated(&file.source) {
+        return expected_content(&file.source, vars).ok() == fs::read(&file.target).ok();
+    }
+
+    same_file(&file.source, &file.target, file.compare).unwrap_or(false)
+}
+
+// Prints a unified diff from the content the source installs as to the installed file, indented under its entry
+fn print_unified_diff(source_path: &Path, target_path: &Path, vars: &BTreeMap<String, String>) -> Result<()> {
+    let source_content = expected_content(source_path, vars)?;
+    let target_content = fs::read(target_path)
+        .with_context(|| format!("Failed to read {}", target_path.display()))?;
+
+    print_content_diff(source_path, &source_content, target_path, &target_content);
+    Ok(())
+}
+
+fn print_content_diff(old_path: &Path, old: &[u8], new_path: &Path, new: &[u8]) {
+    let stat = diff_stat(old_path, old, new);
+    if matches!(stat, DiffStat::Binary { .. }) {
+        println!("    {}", colorize::info(stat));
+        return;
+    }
+
+    let old_label = old_path.display().to_string();
+    let new_label = new_path.display().to_string();
+    let diff = unified_diff(&String::from_utf8_lossy(old), &String::from_utf8_lossy(new), &old_label, &new_label);
+    for line in diff.lines() {
+        println!("    {}", line);
+    }
+}
+
+// Shows how installed files differ from their sources, in the configured difftool when there is one
+fn show_differences(files: &[&ManagedFile], vars: &BTreeMap<String, String>, difftool: Option<&Difftool>) -> Result<()> {
+    let Some(difftool) = difftool else {
+        for file in files {
+            if files.len() > 1 {
+                println!("{} {}", colorize::header("diff"), colorize::path(file.relative_path.display()));
+            }
+            print_unified_diff(&file.source, &file.target, vars)?;
+        }
+        return Ok(());
+    };
+
+    let expected = files.iter()
+        .map(|file| expected_content(&file.source, vars))
+        .collect::<Result<Vec<_>>>()?;
+    let pairs = files.iter().zip(&expected)
+        .map(|(file, expected)| DiffPair {
+            relative_path: &file.relative_path,
+            expected,
+            target: &file.target,
+        })
+        .collect::<Vec<_>>();
+
+    run_difftool(difftool, &pairs)
+}
+
+// Walks every managed file that is missing or differs from its source and applies the chosen action
+pub fn fix_differences_with<F>(files: &[ManagedFile], source_dir: &Path, backups: &Backups, dir_modes: &DirModes, vars: &BTreeMap<String, String>, difftool: Option<&Difftool>, mut choose: F) -> Result<()>
+where
+    F: FnMut(&ManagedFile, bool) -> Result<FixAction>,
+{
+    let mut manifest = Manifest::load()?;
+    let mut fixed = 0;
+
+    'files: for file in files {
+        let missing = !file.target.exists();
+
+        if !missing && matches_source(file, vars) {
+            continue;
+        }
+
+        if missing {
+            println!("\n{} {}", colorize::error("Not installed:"), colorize::path(file.relative_path.display()));
+        } else {
+            println!("\n{} {}", colorize::warning("Modified:"), colorize::path(file.relative_path.display()));
+        }
+
+        loop {
+            match choose(file, missing)? {
+                FixAction::Reinstall => {
+                    if !missing {
+                        backup_file(&file.target, backups, false)?;
+                    }
+                    ensure_parent_dirs(&file.target, dir_modes, false)?;
+                    if is_generated(&file.source) {
+                        write_rendered(&file.source, &file.target, &expected_content(&file.source, vars)?)?;
+                    } else {
+                        copy_file(&file.source, &file.target)
+                            .with_context(|| format!("Failed to copy {} to {}", file.source.display(), file.target.display()))?;
+                    }
+                    apply_mode(&file.target, file.mode)?;
+                    manifest.record(&file.relative_path, file.source.strip_prefix(source_dir)?, &file.target);
+                    println!("  {} {}", colorize::success("Reinstalled:"), colorize::path(file.relative_path.display()));
+                    fixed += 1;
+                },
+                FixAction::Adopt if is_generated(&file.source) => {
+                    println!("  {} {} is generated from a template or fragments; edit {} instead",
+                        colorize::warning("Cannot adopt:"),
+                        colorize::path(file.relative_path.display()),
+                        colorize::path(file.source.display()));
+                    continue;
+                },
+                FixAction::Adopt => {
+                    copy_file(&file.target, &file.source)
+                        .with_context(|| format!("Failed to copy {} to {}", file.target.display(), file.source.display()))?;
+                    println!("  {} {}", colorize::success("Adopted into source:"), colorize::path(file.source.display()));
+                    fixed += 1;
+                },
+                FixAction::ShowDiff => {
+                    show_differences(&[file], vars, difftool)?;
+                    continue;
+                },
+                FixAction::Ignore => {},
+                FixAction::Quit => break 'files,
+            }
+            break;
+        }
+    }
+
+    manifest.save()?;
+    println!("\n{} {}", colorize::info("Files fixed:"), colorize::highlight(fixed));
+
+    Ok(())
+}
+
+// Home-relative paths inside managed directories that don't come from the source tree.
+// Unknown subdirectories are reported as a whole rather than walked.
+pub fn find_extra_files(files: &[ManagedFile], home_dir: &Path) -> Result<Vec<PathBuf>> {
+    let managed: HashSet<&Path> = files.iter().map(|file| file.relative_path.as_path()).collect();
+    let known_dirs: HashSet<&Path> = files.iter()
+        .flat_map(|file| file.relative_path.ancestors().skip(1))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    // Only directories that directly contain managed files, so shared roots like .config aren't scanned
+    let managed_dirs: BTreeSet<&Path> = files.iter()
+        .filter_map(|file| file.relative_path.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+
+    let mut extras = Vec::new();
+
+    for dir in managed_dirs {
+        let Ok(entries) = fs::read_dir(home_dir.join(dir)) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let relative_path = dir.join(entry.file_name());
+
+            if is_blacklisted(&relative_path) {
+                continue;
+            }
+
+            let is_extra = if entry.path().is_dir() {
+                !known_dirs.contains(relative_path.as_path())
+            } else {
+                !managed.contains(relative_path.as_path())
+            };
+
+            if is_extra {
+                extras.push(relative_path);
+            }
+        }
+    }
+
+    extras.sort();
+
+    Ok(extras)
+}
+
+// Summarises how an installed file differs from its source, e.g. "(+3 -1 lines, changed 2024-05-01 12:30)"
+fn modification_details(source_path: &Path, target_path: &Path, vars: &BTreeMap<String, String>) -> String {
+    let mut details = Vec::new();
+
+    if let (Ok(source_content), Ok(target_content)) = (expected_content(source_path, vars), fs::read(target_path)) {
+        details.push(diff_stat(source_path, &source_content, &target_content).to_string());
+    }
+
+    if let Ok(modified) = fs::metadata(target_path).and_then(|metadata| metadata.modified()) {
+        let modified: chrono::DateTime<chrono::Local> = modified.into();
+        details.push(format!("changed {}", modified.format("%Y-%m-%d %H:%M")));
+    }
+
+    format!("({})", details.join(", "))
+}
+
+fn print_repo_status(source_dir: &Path) {
+    println!("\n{}", colorize::header("Repository:"));
+
+    let status = match git::repo_status(source_dir) {
+        Ok(status) => status,
+        Err(e) => {
+            println!("  {} {}", colorize::warning("Could not read git status:"), e);
+            return;
+        }
+    };
+
+    println!("  {} {}", colorize::info("Branch:"), colorize::highlight(&status.branch));
+
+    if let Ok(Some(commit)) = Manifest::load().map(|manifest| manifest.commit) {
+        match git::commits_since(source_dir, &commit) {
+            Ok(0) => println!("  {} {}", colorize::success("Home matches repo at"), colorize::highlight(git::short_commit(&commit))),
+            Ok(newer) => println!("  {} {}, {} {}",
+                colorize::warning("Home matches repo at"),
+                colorize::highlight(git::short_commit(&commit)),
+                colorize::warning("repo has newer commits:"),
+                colorize::highlight(newer)),
+            Err(_) => println!("  {} {} {}",
+                colorize::warning("Last installed commit"),
+                colorize::highlight(git::short_commit(&commit)),
+                colorize::warning("is not in the current history")),
+        }
+    }
+
+    match status.ahead_behind {
+        Some((0, 0)) => println!("  {}", colorize::success("Up to date with upstream (as of last fetch)")),
+        Some((ahead, behind)) => println!("  {} {} ahead, {} behind (as of last fetch)",
+            colorize::warning("Upstream:"),
+            colorize::highlight(ahead),
+            colorize::highlight(behind)),
+        None => println!("  {}", colorize::info("No upstream branch configured")),
+    }
+
+    if status.uncommitted.is_empty() {
+        println!("  {}", colorize::success("No uncommitted changes"));
+    } else {
+        println!("  {} {}", colorize::warning("Uncommitted changes:"), colorize::highlight(status.uncommitted.len()));
+        for path in &status.uncommitted {
+            println!("    {}", colorize::path(path));
+        }
+    }
+}
+
+pub fn status_dotfiles(options: &StatusOptions) -> Result<()> {
+    let &StatusOptions { verbose, extras, fix, changed_only, ref metrics } = options;
     let config = read_config()?;
     let source_dir = Path::new(&config.source_dir);
     let home_dir = get_home_dir()?;
@@ -257,138 +2123,178 @@ pub fn status_dotfiles(verbose: bool) -> Result<()> {
         colorize::info(format!("(source: {})", source_dir.display()))
     );
 
-    let mut total_count = 0;
-    let mut installed_count = 0;
-    let mut modified_count = 0;
-    let mut missing_count = 0;
+    let vars = template::template_vars(&config, &[])?;
+    let files = configured_files(&config, &home_dir)?;
+    let extra_files = find_extra_files(&files, &home_dir)?;
+    let mut counts = StatusCounts { extra: extra_files.len(), ..Default::default() };
 
-    for entry in fs::read_dir(source_dir)?
-        .filter_map(|e| e.ok())
-    {
-        let source_path = entry.path();
-        let relative_path = source_path.strip_prefix(source_dir)?;
+    let mut groups: BTreeMap<PathBuf, Vec<&ManagedFile>> = BTreeMap::new();
+    for file in &files {
+        groups.entry(status_group(&file.relative_path)).or_default().push(file);
+    }
+    let in_sync = files.iter()
+        .zip(parallel_map(&files, |file| file.target.exists() && matches_source(file, &vars)))
+        .map(|(file, in_sync)| (file.target.as_path(), in_sync))
+        .collect::<HashMap<_, _>>();
+
+    for (group, group_files) in &groups {
+        let mut table = Table::new(vec![Column::left(""), Column::left("").shrink(), Column::left(""), Column::left("")]);
+        let mut modified_files = Vec::new();
+
+        for file in group_files {
+            let state = if !file.target.exists() {
+                counts.missing += 1;
+                FileState::Missing
+            } else if in_sync[file.target.as_path()] {
+                counts.ok += 1;
+                FileState::Installed
+            } else {
+                counts.modified += 1;
+                FileState::Modified
+            };
+
+            if changed_only && state == FileState::Installed {
+                continue;
+            }
 
-        let should_skip = BLACKLIST.iter().any(|pattern| {
-            relative_path.to_string_lossy().contains(pattern)
-        });
+            let relative_path = Cell::new(Style::Path, file.relative_path.display());
+            match state {
+                FileState::Installed => table.row(vec![Cell::new(Style::Success, colorize::tick()), relative_path]),
+                FileState::Missing => table.row(vec![Cell::new(Style::Error, colorize::cross()), relative_path, Cell::new(Style::Error, "Not installed")]),
+                FileState::Modified => {
+                    table.row(vec![
+                        Cell::new(Style::Warning, "!"),
+                        relative_path,
+                        Cell::new(Style::Warning, "Modified"),
+                        Cell::new(Style::Info, modification_details(&file.source, &file.target, &vars)),
+                    ]);
+                    modified_files.push(file);
+                },
+            }
+        }
 
-        if should_skip {
+        if table.is_empty() {
             continue;
         }
+        let name = if group.as_os_str().is_empty() { String::from("~") } else { format!("{}/", group.display()) };
+        println!("\n{}", colorize::header(name));
+        table.print();
+
+        // Diffs follow the group's table so they don't break up its columns
+        for file in modified_files.iter().filter(|_| verbose) {
+            println!("\n  {} {}", colorize::warning("Differences in"), colorize::path(file.relative_path.display()));
+            if let Err(err) = print_unified_diff(&file.source, &file.target, &vars) {
+                println!("    {} {:#}", colorize::warning("Cannot show differences:"), err);
+            }
+        }
+    }
 
-        total_count += 1;
-        let target_path = home_dir.join(relative_path);
+    let mut blocks = Table::new(vec![Column::left(""), Column::left("").shrink(), Column::left("")]);
+    for block in &config.blocks {
+        let content = block.content(source_dir, &vars)?;
+        let text = fs::read_to_string(home_dir.join(block.target_path())).ok();
+        let state = block_state(text.as_deref(), block, &content);
 
-        if source_path.is_file() {
-            if !target_path.exists() {
-                println!("  {} {} {}", 
-                    colorize::error("✗"), 
-                    colorize::path(relative_path.display()),
-                    colorize::error("Not installed")
-                );
-                missing_count += 1;
-            } else {
-                let files_identical = match (fs::read(&source_path), fs::read(&target_path)) {
-                    (Ok(source_content), Ok(target_content)) => source_content == target_content,
-                    _ => false
-                };
-
-                if files_identical {
-                    println!("  {} {} {}", 
-                        colorize::success("✓"), 
-                        colorize::path(relative_path.display()),
-                        colorize::success("Installed")
-                    );
-                    installed_count += 1;
-                } else {
-                    modified_count += 1;
-                    
-                    println!("  {} {} {}", 
-                        colorize::warning("!"), 
-                        colorize::path(relative_path.display()),
-                        colorize::warning("Modified")
-                    );
-                    
-                    if verbose {
-                        if let (Ok(source_content), Ok(target_content)) = (
-                            fs::read_to_string(&source_path),
-                            fs::read_to_string(&target_path)
-                        ) {
-                            let source_lines: Vec<&str> = source_content.lines().collect();
-                            let target_lines: Vec<&str> = target_content.lines().collect();
-                            
-                            println!("    {} {} lines, {} {} lines", 
-                                colorize::info("Source:"), 
-                                source_lines.len(),
-                                colorize::info("Target:"), 
-                                target_lines.len()
-                            );
-                            
-                            let mut diff_count = 0;
-                            let max_diffs = 3;
-                            let max_line_len = 60;
-                            
-                            for i in 0..std::cmp::min(source_lines.len(), target_lines.len()) {
-                                if source_lines[i] != target_lines[i] && diff_count < max_diffs {
-                                    diff_count += 1;
-                                    
-                                    let source_snippet = if source_lines[i].len() > max_line_len {
-                                        format!("{}...", &source_lines[i][0..max_line_len])
-                                    } else {
-                                        source_lines[i].to_string()
-                                    };
-                                    
-                                    let target_snippet = if target_lines[i].len() > max_line_len {
-                                        format!("{}...", &target_lines[i][0..max_line_len])
-                                    } else {
-                                        target_lines[i].to_string()
-                                    };
-                                    
-                                    println!("    Line {}: ", i + 1);
-                                    println!("      Source: {}", source_snippet);
-                                    println!("      Target: {}", target_snippet);
-                                }
-                            }
-                        }
-                        println!();
-                    }
-                }
-            }
-        } else if source_path.is_dir() {
-             if target_path.exists() && target_path.is_dir() {
-                 println!("  {} {} {}", 
-                     colorize::success("✓"), 
-                     colorize::path(relative_path.display()),
-                     colorize::success("Installed")
-                 );
-                 installed_count += 1;
-             } else {
-                 println!("  {} {} {}", 
-                     colorize::error("✗"), 
-                     colorize::path(relative_path.display()),
-                     colorize::error("Not installed")
-                 );
-                 missing_count += 1;
-             }
+        match state {
+            BlockState::InSync => counts.ok += 1,
+            BlockState::Outdated => counts.modified += 1,
+            BlockState::Missing => counts.missing += 1,
+        }
+        if changed_only && state == BlockState::InSync {
+            continue;
+        }
+
+        let label = Cell::new(Style::Path, block.label());
+        match state {
+            BlockState::InSync => blocks.row(vec![Cell::new(Style::Success, colorize::tick()), label]),
+            BlockState::Outdated => blocks.row(vec![Cell::new(Style::Warning, "!"), label, Cell::new(Style::Warning, "Block differs from the config")]),
+            BlockState::Missing => blocks.row(vec![Cell::new(Style::Error, colorize::cross()), label, Cell::new(Style::Error, "Block not installed")]),
         }
     }
+    if !blocks.is_empty() {
+        println!("\n{}", colorize::header("Managed blocks"));
+        blocks.print();
+    }
 
-    println!("\n{}", colorize::header("Summary:"));
-    println!("  {} {}", colorize::info("Total files and directories:"), colorize::highlight(total_count));
-    println!("  {} {}", colorize::success("Installed:"), colorize::highlight(installed_count));
-    println!("  {} {}", colorize::warning("Modified:"), colorize::highlight(modified_count));
-    println!("  {} {}", colorize::error("Not installed:"), colorize::highlight(missing_count));
+    let ignore = build_exclude_set(&config.ignore)?;
+    let mut patches = Table::new(vec![Column::left(""), Column::left("").shrink(), Column::left("")]);
+    for patch in patch_files(source_dir, config.layout, &home_dir)? {
+        if is_excluded(&ignore, &patch.relative_path) {
+            continue;
+        }
+        // None when there is no file to patch
+        let state = match fs::read_to_string(&patch.target) {
+            Ok(text) => Some(read_patch(&patch.source)?.state(&text)),
+            Err(_) => None,
+        };
+
+        match state {
+            Some(PatchState::Applied) => counts.ok += 1,
+            Some(PatchState::Conflict) => counts.modified += 1,
+            Some(PatchState::Unapplied) | None => counts.missing += 1,
+        }
+        if changed_only && state == Some(PatchState::Applied) {
+            continue;
+        }
+
+        let relative_path = Cell::new(Style::Path, patch.relative_path.display());
+        match state {
+            Some(PatchState::Applied) => patches.row(vec![Cell::new(Style::Success, colorize::tick()), relative_path]),
+            Some(PatchState::Unapplied) => patches.row(vec![Cell::new(Style::Error, colorize::cross()), relative_path, Cell::new(Style::Error, "Patch not applied")]),
+            Some(PatchState::Conflict) => patches.row(vec![Cell::new(Style::Warning, "!"), relative_path, Cell::new(Style::Warning, "Patch does not apply")]),
+            None => patches.row(vec![Cell::new(Style::Error, colorize::cross()), relative_path, Cell::new(Style::Error, "No file to patch")]),
+        }
+    }
+    if !patches.is_empty() {
+        println!("\n{}", colorize::header("Patched files"));
+        patches.print();
+    }
+
+    if changed_only && counts.modified == 0 && counts.missing == 0 {
+        println!("\n{}", colorize::success("All managed files are installed and unchanged."));
+    }
+
+    if git::is_git_repo(source_dir) {
+        print_repo_status(source_dir);
+    }
+
+    if extras {
+        println!("\n{}", colorize::header("Unmanaged files in managed directories:"));
+        if extra_files.is_empty() {
+            println!("  {}", colorize::success("None"));
+        }
+        for extra in &extra_files {
+            let suffix = if home_dir.join(extra).is_dir() { "/" } else { "" };
+            println!("  {} {}{}", colorize::info("?"), colorize::path(extra.display()), suffix);
+        }
+    }
+
+    println!("\n{} {}", colorize::header("Summary:"), counts);
+
+    if let Some(path) = metrics {
+        let metrics = Metrics::new(&counts, Manifest::load()?.installed_at, backup_usage(&get_backup_dir()?)?);
+        write_metrics(path, &metrics)?;
+        println!("{} {}", colorize::info("Wrote metrics to"), colorize::path(path.display()));
+    }
+
+    if fix {
+        let backups = Backups::new(get_backup_dir()?, &config)?;
+        fix_differences_with(&files, source_dir, &backups, &config.dir_modes(&home_dir), &vars, Difftool::from_config(&config).as_ref(), prompt_fix_action)?;
+        enforce_backup_quota(&config, &backups.dir)?;
+    }
 
     Ok(())
 }
 
-pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<()> {
+pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool, keep_backups: bool) -> Result<()> {
     let config = read_config()?;
     let source_dir = &config.source_dir;
 
     let home_dir = get_home_dir()?;
     let source_dir = Path::new(source_dir);
-    let backup_dir = get_backup_dir()?;
+    let backups = Backups::new(get_backup_dir()?, &config)?;
+    let vars = template::template_vars(&config, &[])?;
+    let dirs = DirConfigs::load(source_dir)?;
 
     if !source_dir.exists() {
         return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
@@ -405,26 +2311,20 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
         println!("{}", colorize::header("Uninstalling dotfiles..."));
     }
 
-    let mut success_count = 0;
-    let mut restored_count = 0;
-    let mut skipped_count = 0;
-
-    for entry in WalkDir::new(source_dir)
-        .min_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let source_path = entry.path();
-
-        if !source_path.is_file() {
-            continue;
-        }
-
+    // Files the config ignores, on this machine too, were never installed, so they aren't removed either
+    let ignore = build_exclude_set(&config.ignore)?;
+    let backup_index = BackupIndex::load()?;
+    let mut manifest = Manifest::load()?;
+    let mut summary = DryRunSummary::new();
+    let mut success_count = 0;
+    let mut restored_count = 0;
+    let mut skipped_count = 0;
+
+    for entry in source_entries(source_dir, dirs.follows_symlinks(), |path| is_blacklisted(path) || dirs.is_ignored(path) || dirs.is_assembled(path)) {
+        let source_path = entry.path();
         let relative_path = source_path.strip_prefix(source_dir)?;
 
-        let should_skip = BLACKLIST.iter().any(|pattern| {
-            relative_path.to_string_lossy().contains(pattern)
-        });
+        let should_skip = is_blacklisted(relative_path);
 
         if should_skip {
             if verbose {
@@ -434,8 +2334,21 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
             continue;
         }
 
-        let target_path = home_dir.join(relative_path);
-        let rel_path_str = relative_path.to_string_lossy();
+        if dirs.is_ignored(relative_path) || (!source_path.is_file() && !dirs.is_assembled(relative_path)) || is_patch(relative_path) {
+            continue;
+        }
+
+        let Some(target_relative_path) = dirs.target_for(config.layout, relative_path) else {
+            continue;
+        };
+        let target_relative_path = target_relative_path.as_path();
+        if is_excluded(&ignore, target_relative_path) {
+            if verbose {
+                println!("  {} {}", colorize::warning("Skipping ignored path:"), colorize::path(target_relative_path.display()));
+            }
+            continue;
+        }
+        let target_path = home_dir.join(target_relative_path);
 
         if verbose {
             println!("  {} {}", colorize::info("Processing:"), colorize::path(relative_path.display()));
@@ -452,11 +2365,12 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
                     colorize::path(relative_path.display()));
             }
             skipped_count += 1;
+            summary.add(PlannedAction::Skip, 0);
             continue;
         }
 
         // Check if the target is identical to the source
-        let files_identical = match (fs::read(&source_path), fs::read(&target_path)) {
+        let files_identical = match (expected_content(source_path, &vars), fs::read(&target_path)) {
             (Ok(source_content), Ok(target_content)) => source_content == target_content,
             _ => false
         };
@@ -472,12 +2386,16 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
                     colorize::path(relative_path.display()));
             }
             skipped_count += 1;
+            summary.add_file(PlannedAction::Skip, &target_path);
             continue;
         }
 
-        // Try to find a backup to restore
-        match find_latest_backup(&rel_path_str, &backup_dir) {
-            Ok(backup_path) => {
+        // Try to find a backup to restore. A tombstone is a file an earlier uninstall or prune removed, not
+        // what was there before the install.
+        match find_latest_backup_of(&target_path, &backups.dir).ok().filter(|backup_path| !backup_index.is_tombstone(backup_path)) {
+            Some(backup_path) => {
+                summary.add_file(PlannedAction::Restore, &backup_path);
+
                 if verbose {
                     println!("  {} {} with backup",
                         colorize::info("Replacing"),
@@ -489,24 +2407,42 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
                 }
 
                 if !dry_run {
-                    fs::copy(&backup_path, &target_path)
+                    let backup_content = fs::read(&backup_path)
+                        .with_context(|| format!("Failed to read backup file {}", backup_path.display()))?;
+                    let backup_permissions = fs::metadata(&backup_path).ok().map(|metadata| metadata.permissions());
+
+                    // Save the file being replaced so a mistaken restore can itself be undone
+                    backup_file(&target_path, &backups, false)?;
+
+                    write_file_atomic(&target_path, &backup_content, backup_permissions)
                         .with_context(|| format!("Failed to restore backup {} to {}", 
                             backup_path.display(), target_path.display()))?;
+                    // Only once it is back in place, so a failed write leaves the backup to try again
+                    if !keep_backups {
+                        remove_restored_backup(&backup_path)?;
+                    }
                     restored_count += 1;
-
-                    fs::remove_file(&backup_path)
-                        .with_context(|| format!("Failed to delete backup file {}", backup_path.display()))?;
+                    manifest.remove(target_relative_path);
                         
                     if verbose {
-                        println!("  {}", colorize::success("Backup restored and cleaned up"));
+                        println!("  {}", colorize::success("Backup restored; replaced file backed up"));
+                    }
+                } else {
+                    summary.add_file(PlannedAction::BackUp, &target_path);
+                    if verbose {
+                        println!("  {} {}",
+                            colorize::dry_run("[Dry run] Would restore from backup:"),
+                            colorize::path(backup_path.strip_prefix(&backups.dir)?.display()));
+                        if let (Ok(current), Ok(backup)) = (fs::read(&target_path), fs::read(&backup_path)) {
+                            print_content_diff(&target_path, &current, &backup_path, &backup);
+                        }
                     }
-                } else if verbose {
-                    println!("  {} {}",
-                        colorize::dry_run("[Dry run] Would restore from backup:"),
-                        colorize::path(backup_path.strip_prefix(&backup_dir)?.display()));
                 }
             },
-            Err(_) => {
+            None => {
+                summary.add_file(PlannedAction::BackUp, &target_path);
+                summary.add_file(PlannedAction::Remove, &target_path);
+
                 if verbose {
                     println!("  {} {}", 
                         colorize::info("Removing"),
@@ -518,12 +2454,15 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
                 }
 
                 if !dry_run {
+                    // Nothing to put back, so the file is kept as a tombstone that `restore` can resurrect
+                    backup_deleted_file(&target_path, &backups)?;
                     fs::remove_file(&target_path)
                         .with_context(|| format!("Failed to remove file {}", target_path.display()))?;
+                    manifest.remove(target_relative_path);
                     success_count += 1;
                     
                     if verbose {
-                        println!("  {}", colorize::success("Removed successfully"));
+                        println!("  {}", colorize::success("Backed up and removed"));
                     }
                 } else if verbose {
                     println!("  {} {}",
@@ -534,9 +2473,55 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
         }
     }
 
+    // Files holding managed blocks aren't ours, so only the blocks come out
+    for block in &config.blocks {
+        let target_path = home_dir.join(block.target_path());
+        let Some(updated) = fs::read_to_string(&target_path).ok().and_then(|text| remove_block(&text, block)) else {
+            continue;
+        };
+
+        if dry_run {
+            summary.add_file(PlannedAction::Overwrite, &target_path);
+            println!("  {} {}", colorize::dry_run("[Dry run] Would remove block from:"), colorize::path(block.label()));
+        } else {
+            backup_file(&target_path, &backups, false)?;
+            write_file_atomic(&target_path, updated.as_bytes(), None)
+                .with_context(|| format!("Failed to write {}", target_path.display()))?;
+            println!("  {} {}", colorize::success("Removed block from:"), colorize::path(block.label()));
+            success_count += 1;
+        }
+    }
+
+    // Patched files aren't ours either, so the patch is reversed and the rest of the file kept
+    for patch in patch_files(source_dir, config.layout, &home_dir)? {
+        let Ok(text) = fs::read_to_string(&patch.target) else {
+            continue;
+        };
+        let parsed = read_patch(&patch.source)?;
+        if parsed.state(&text) != PatchState::Applied {
+            continue;
+        }
+
+        if dry_run {
+            summary.add_file(PlannedAction::Overwrite, &patch.target);
+            println!("  {} {}", colorize::dry_run("[Dry run] Would reverse the patch on:"), colorize::path(patch.relative_path.display()));
+        } else {
+            let original = parsed.reversed().apply(&text)?;
+            backup_file(&patch.target, &backups, false)?;
+            write_file_atomic(&patch.target, original.as_bytes(), None)
+                .with_context(|| format!("Failed to write {}", patch.target.display()))?;
+            println!("  {} {}", colorize::success("Reversed patch on:"), colorize::path(patch.relative_path.display()));
+            success_count += 1;
+        }
+    }
+
     if dry_run {
         println!("{}", colorize::dry_run("Dry run - no files were actually modified"));
+        summary.print();
     } else {
+        manifest.save()?;
+        enforce_backup_quota(&config, &backups.dir)?;
+
         println!("\n{}", colorize::header("Summary:"));
         if restored_count > 0 {
             println!("  {} {}", 
@@ -557,4 +2542,487 @@ pub fn uninstall_dotfiles(dry_run: bool, force: bool, verbose: bool) -> Result<(
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Turns a user-supplied target (absolute, ~/..., or relative to the current directory or home) into a path relative to home
+pub fn home_relative_path(target: &str, home_dir: &Path) -> Result<PathBuf> {
+    let path = match target.strip_prefix("~/") {
+        Some(rest) => home_dir.join(rest),
+        None => PathBuf::from(target),
+    };
+
+    let path = if path.is_relative() {
+        match env::current_dir() {
+            Ok(cwd) if cwd.starts_with(home_dir) && cwd != home_dir && cwd.join(&path).exists() => cwd.join(&path),
+            _ => return Ok(path),
+        }
+    } else {
+        path
+    };
+
+    path.strip_prefix(home_dir)
+        .map(Path::to_path_buf)
+        .map_err(|_| anyhow!("{} is not inside the home directory {}", path.display(), home_dir.display()))
+}
+
+// The source file that installs to a home-relative path, after layout, templates and directory configs
+pub fn find_source_path(source_dir: &Path, layout: Layout, home_relative: &Path) -> Result<Option<PathBuf>> {
+    Ok(managed_files(source_dir, layout, Path::new(""))?
+        .into_iter()
+        .find(|file| file.relative_path == home_relative)
+        .map(|file| file.source))
+}
+
+fn editor_command() -> String {
+    ["VISUAL", "EDITOR"].iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+fn open_in_editor(editor: &str, path: &Path) -> Result<()> {
+    // Editors are often configured with arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next()
+        .ok_or_else(|| anyhow!("Editor command is empty"))?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to launch editor '{}'", editor))?;
+
+    if !status.success() {
+        return Err(anyhow!("Editor '{}' exited with {}", editor, status));
+    }
+
+    Ok(())
+}
+
+// A managed file picked at the terminal, for `edit` and `diff` run without one
+pub fn pick_managed_file(question: &str, all: Option<&str>) -> Result<Option<String>> {
+    let config = read_config()?;
+    // The command itself reports a missing source directory
+    if !Path::new(&config.source_dir).exists() {
+        return Ok(None);
+    }
+    let files = configured_files(&config, &get_home_dir()?)?.into_iter().map(|file| file.relative_path);
+    pick_file(question, all, files)
+}
+
+pub fn edit_dotfile(file: Option<&str>, no_install: bool) -> Result<()> {
+    let picked = match file {
+        Some(file) => file.to_string(),
+        None => pick_managed_file("File to edit", None)?
+            .ok_or_else(|| anyhow!("No file to edit: name one, or run at a terminal to pick one"))?,
+    };
+    edit_dotfile_with(&picked, &editor_command(), !no_install)
+}
+
+pub fn edit_dotfile_with(file: &str, editor: &str, reinstall: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    let relative_path = home_relative_path(file, &home_dir)?;
+    let source_path = find_source_path(source_dir, config.layout, &relative_path)?
+        .ok_or_else(|| anyhow!("No source file in {} manages {}", source_dir.display(), relative_path.display()))?;
+    let target_path = home_dir.join(&relative_path);
+
+    if source_path.is_dir() {
+        return Err(anyhow!("{} is assembled from the fragments in {}; edit one of those instead",
+            relative_path.display(), source_path.display()));
+    }
+
+    println!("{} {}", colorize::info("Editing"), colorize::path(source_path.display()));
+    open_in_editor(editor, &source_path)?;
+
+    if !reinstall {
+        return Ok(());
+    }
+
+    let vars = template::template_vars(&config, &[])?;
+    let files_identical = match (expected_content(&source_path, &vars), fs::read(&target_path)) {
+        (Ok(source_content), Ok(target_content)) => source_content == target_content,
+        _ => false
+    };
+
+    if files_identical {
+        println!("  {} {}", colorize::info("Unchanged:"), colorize::path(relative_path.display()));
+        return Ok(());
+    }
+
+    if target_path.exists() {
+        let backups = Backups::new(get_backup_dir()?, &config)?;
+        backup_file(&target_path, &backups, false)?;
+        enforce_backup_quota(&config, &backups.dir)?;
+    } else {
+        ensure_parent_dirs(&target_path, &config.dir_modes(&home_dir), false)?;
+    }
+
+    if is_generated(&source_path) {
+        write_rendered(&source_path, &target_path, &expected_content(&source_path, &vars)?)?;
+    } else {
+        copy_file(&source_path, &target_path)
+            .with_context(|| format!("Failed to copy {} to {}", source_path.display(), target_path.display()))?;
+    }
+    println!("  {} {}", colorize::success("Installed:"), colorize::path(relative_path.display()));
+
+    Ok(())
+}
+
+pub fn list_managed_files(json: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let files = configured_files(&config, &home_dir)?;
+
+    if json {
+        let output = serde_json::to_string_pretty(&files)
+            .with_context(|| "Failed to serialize managed files")?;
+        println!("{}", output);
+        return Ok(());
+    }
+
+    println!("{} {}",
+        colorize::header("Managed files"),
+        colorize::info(format!("(source: {})", source_dir.display())));
+
+    let mut table = Table::new(vec![Column::left("").shrink(), Column::left("")]);
+    for file in &files {
+        let source_relative_path = file.source.strip_prefix(source_dir)?;
+        let origin = if source_relative_path == file.relative_path {
+            String::new()
+        } else {
+            format!("(from {})", source_relative_path.display())
+        };
+
+        table.row(vec![Cell::new(Style::Path, file.relative_path.display()), Cell::new(Style::Info, origin)]);
+    }
+    table.print();
+
+    println!("\n{} {}", colorize::info("Total managed files:"), colorize::highlight(files.len()));
+
+    Ok(())
+}
+
+// Shows the differences between installed files and the source, for every managed file or only the given paths
+pub fn diff_dotfiles(paths: &[String]) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let mut files = configured_files(&config, &home_dir)?;
+    if !paths.is_empty() {
+        let wanted = paths.iter()
+            .map(|path| home_relative_path(path, &home_dir))
+            .collect::<Result<Vec<_>>>()?;
+
+        for path in &wanted {
+            if !files.iter().any(|file| file.relative_path.starts_with(path)) {
+                return Err(anyhow!("{} is not managed by the source directory {}", path.display(), source_dir.display()));
+            }
+        }
+        files.retain(|file| wanted.iter().any(|path| file.relative_path.starts_with(path)));
+    }
+
+    let vars = template::template_vars(&config, &[])?;
+    let mut changed = Vec::new();
+
+    for file in &files {
+        if !file.target.exists() {
+            println!("{} {}", colorize::error("Not installed:"), colorize::path(file.relative_path.display()));
+        } else if !matches_source(file, &vars) {
+            changed.push(file);
+        }
+    }
+
+    if changed.is_empty() {
+        println!("{}", colorize::success("No differences."));
+        return Ok(());
+    }
+
+    show_differences(&changed, &vars, Difftool::from_config(&config).as_ref())
+}
+
+pub fn which_dotfile(path: &str, reverse: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    if reverse {
+        let target_path = find_target_path(source_dir, config.layout, &home_dir, Path::new(path))?;
+        println!("{}", target_path.display());
+
+        if !target_path.exists() {
+            println!("{}", colorize::warning("(not installed)"));
+        }
+        return Ok(());
+    }
+
+    let relative_path = home_relative_path(path, &home_dir)?;
+    let source_path = find_source_path(source_dir, config.layout, &relative_path)?
+        .filter(|source_path| source_path.strip_prefix(source_dir).is_ok_and(|p| !is_blacklisted(p)))
+        .ok_or_else(|| anyhow!("{} is not managed by the source directory {}", relative_path.display(), source_dir.display()))?;
+
+    println!("{}", source_path.display());
+
+    if config.layout == Layout::Stow
+        && let Some(package) = source_path.strip_prefix(source_dir)?.iter().next() {
+        println!("{} {}", colorize::info("package:"), package.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+// The bytes a file renders to on this machine with `overrides` on top of its template variables. `file` is an
+// installed path, or a source file relative to the current directory or the source directory.
+pub fn rendered_dotfile(file: &str, overrides: &[(String, String)]) -> Result<Vec<u8>> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    let installed = match home_relative_path(file, &home_dir) {
+        Ok(relative_path) => find_source_path(source_dir, config.layout, &relative_path)?,
+        Err(_) => None,
+    };
+    let source_path = match installed {
+        Some(source_path) => source_path,
+        None => {
+            let canonical_source_dir = source_dir.canonicalize()
+                .with_context(|| format!("Source directory '{}' does not exist", source_dir.display()))?;
+            [env::current_dir().map(|cwd| cwd.join(file)).unwrap_or_else(|_| PathBuf::from(file)), source_dir.join(file)].iter()
+                .filter_map(|candidate| candidate.canonicalize().ok())
+                .find(|candidate| candidate.starts_with(&canonical_source_dir))
+                .ok_or_else(|| anyhow!("{} is neither managed by nor a file in the source directory {}", file, source_dir.display()))?
+        },
+    };
+
+    let vars = template::template_vars(&config, overrides)?;
+    expected_content(&source_path, &vars)
+}
+
+pub fn render_dotfile(file: &str, overrides: &[(String, String)]) -> Result<()> {
+    let content = rendered_dotfile(file, overrides)?;
+    std::io::stdout().write_all(&content)
+        .with_context(|| "Failed to write the rendered file")
+}
+
+// Resolves a source file (absolute, or relative to the current directory or the source directory) to its install location
+pub fn find_target_path(source_dir: &Path, layout: Layout, home_dir: &Path, source_file: &Path) -> Result<PathBuf> {
+    let candidates = [
+        env::current_dir().map(|cwd| cwd.join(source_file)).unwrap_or_else(|_| source_file.to_path_buf()),
+        source_dir.join(source_file),
+    ];
+    let source_path = candidates.iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| anyhow!("Source file {} does not exist", source_file.display()))?
+        .canonicalize()?;
+
+    let canonical_source_dir = source_dir.canonicalize()
+        .with_context(|| format!("Source directory '{}' does not exist", source_dir.display()))?;
+    let relative_path = source_path.strip_prefix(&canonical_source_dir)
+        .map_err(|_| anyhow!("{} is not inside the source directory {}", source_path.display(), source_dir.display()))?;
+
+    if is_blacklisted(relative_path) {
+        return Err(anyhow!("{} is excluded from installation", relative_path.display()));
+    }
+
+    let dirs = DirConfigs::load(source_dir)?;
+    if dirs.is_ignored(relative_path) {
+        return Err(anyhow!("{} is ignored by its directory config", relative_path.display()));
+    }
+
+    let target_relative_path = dirs.target_for(layout, relative_path)
+        .ok_or_else(|| anyhow!("{} is not inside a stow package", relative_path.display()))?;
+
+    Ok(home_dir.join(target_relative_path))
+}
+
+#[derive(Debug)]
+pub struct BrokenSymlink {
+    pub link: PathBuf,
+    pub target: PathBuf,
+}
+
+// Dangling symlinks under home whose target lies inside the source directory
+pub fn find_broken_symlinks(home_dir: &Path, source_dir: &Path) -> Result<Vec<BrokenSymlink>> {
+    let source_dir = source_dir.canonicalize()
+        .unwrap_or_else(|_| normalize_path(source_dir));
+    let mut broken = Vec::new();
+
+    for entry in WalkDir::new(home_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            // Never descend into the source tree itself or large directories that cannot hold dotfiles
+            !(e.file_type().is_dir() && (e.path() == source_dir || is_blacklisted(Path::new(e.file_name()))))
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path_is_symlink())
+    {
+        let link = entry.path();
+
+        if link.exists() {
+            continue;
+        }
+
+        let target = fs::read_link(link)
+            .with_context(|| format!("Failed to read symlink {}", link.display()))?;
+        let parent = link.parent().unwrap_or(home_dir);
+        let parent = parent.canonicalize().unwrap_or_else(|_| parent.to_path_buf());
+        let resolved = normalize_path(&parent.join(&target));
+
+        if resolved.starts_with(&source_dir) {
+            broken.push(BrokenSymlink {
+                link: link.to_path_buf(),
+                target: resolved,
+            });
+        }
+    }
+
+    broken.sort_by(|a, b| a.link.cmp(&b.link));
+
+    Ok(broken)
+}
+
+pub fn clean_broken_symlinks(dry_run: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    if dry_run {
+        println!("{}", colorize::dry_run("Dry run mode - no symlinks will be removed"));
+    }
+
+    let broken = find_broken_symlinks(&home_dir, source_dir)?;
+
+    if broken.is_empty() {
+        println!("{}", colorize::success("No broken symlinks found"));
+        return Ok(());
+    }
+
+    for symlink in &broken {
+        let display_path = symlink.link.strip_prefix(&home_dir).unwrap_or(&symlink.link);
+
+        if dry_run {
+            println!("  {} {} {}",
+                colorize::dry_run("[Dry run] Would remove:"),
+                colorize::path(display_path.display()),
+                colorize::info(format!("-> {}", symlink.target.display())));
+            continue;
+        }
+
+        fs::remove_file(&symlink.link)
+            .with_context(|| format!("Failed to remove symlink {}", symlink.link.display()))?;
+        println!("  {} {} {}",
+            colorize::success("Removed:"),
+            colorize::path(display_path.display()),
+            colorize::info(format!("-> {}", symlink.target.display())));
+    }
+
+    let verb = if dry_run { "Broken symlinks found:" } else { "Broken symlinks removed:" };
+    println!("\n{} {}", colorize::info(verb), colorize::highlight(broken.len()));
+
+    Ok(())
+}
+
+pub fn push_source(message: Option<&str>, dry_run: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+
+    if !git::is_git_repo(source_dir) {
+        return Err(anyhow!("Source directory '{}' is not a git repository", source_dir.display()));
+    }
+
+    let changes = git::uncommitted_changes(source_dir)?;
+
+    if changes.is_empty() {
+        println!("{}", colorize::info("No uncommitted changes in the source directory"));
+    } else {
+        let message = message.map(String::from)
+            .unwrap_or_else(|| git::generate_commit_message(&changes));
+
+        println!("{} {}", colorize::header("Committing"), colorize::info(format!("\"{}\"", message)));
+        for path in &changes {
+            println!("  {}", colorize::path(path));
+        }
+
+        if dry_run {
+            println!("{}", colorize::dry_run("[Dry run] Would commit and push the changes above"));
+            return Ok(());
+        }
+
+        git::commit_all(source_dir, &message)?;
+    }
+
+    if dry_run {
+        println!("{}", colorize::dry_run("[Dry run] Would push to the upstream branch"));
+        return Ok(());
+    }
+
+    git::push(source_dir)?;
+    println!("{}", colorize::success("Pushed source changes."));
+
+    Ok(())
+}
+
+pub fn validate_config() -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        println!("{} {} {}",
+            colorize::warning("No configuration file found at"),
+            colorize::path(config_path.display()),
+            colorize::info("(defaults will be used)"));
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read config file at {}", config_path.display()))?;
+    let issues = check_config(&content);
+
+    if issues.is_empty() {
+        println!("{} {}", colorize::success("Configuration is valid:"), colorize::path(config_path.display()));
+        return Ok(());
+    }
+
+    println!("{} {}", colorize::error("Configuration problems in"), colorize::path(config_path.display()));
+    for issue in &issues {
+        println!("  {} {}", colorize::error(colorize::cross()), issue);
+    }
+
+    Err(anyhow!("Found {} problem(s) in {}", issues.len(), config_path.display()))
+}
+
+// Scripts that call back into the binary for completions, so restore and install can offer backed-up and
+// managed paths. Shells without dynamic completion get a static script.
+pub fn generate_completions(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    let bin_name = command.get_name().to_string();
+    let mut stdout = std::io::stdout();
+
+    let completer: &dyn EnvCompleter = match shell {
+        Shell::Bash => &clap_complete::env::Bash,
+        Shell::Zsh => &clap_complete::env::Zsh,
+        Shell::Fish => &clap_complete::env::Fish,
+        Shell::Elvish => &clap_complete::env::Elvish,
+        Shell::PowerShell => &clap_complete::env::Powershell,
+        _ => {
+            clap_complete::generate(shell, &mut command, bin_name, &mut stdout);
+            return Ok(());
+        },
+    };
+    completer.write_registration("COMPLETE", &bin_name, &bin_name, &bin_name, &mut stdout)
+        .with_context(|| "Failed to write the completion script")
+}
@@ -0,0 +1,40 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use clap_complete::CompletionCandidate;
+
+use crate::commands::{backed_up_files, configured_files};
+use crate::config::{read_config, Config};
+use crate::fs_utils::{get_backup_dir, get_home_dir};
+
+// Home-relative paths of the managed files and of the directories holding them
+pub fn managed_paths(config: &Config, home_dir: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut paths = BTreeSet::new();
+    for file in configured_files(config, home_dir)? {
+        paths.extend(file.relative_path.ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(Path::to_path_buf));
+    }
+
+    Ok(paths)
+}
+
+fn candidates(paths: BTreeSet<PathBuf>) -> Vec<CompletionCandidate> {
+    paths.into_iter().map(|path| CompletionCandidate::new(path.into_os_string())).collect()
+}
+
+// Completes `restore`, `backups --file` and the like. Completion has nowhere to report errors, so it offers nothing instead.
+pub fn complete_backed_up_files() -> Vec<CompletionCandidate> {
+    let (Ok(home_dir), Ok(backup_dir)) = (get_home_dir(), get_backup_dir()) else {
+        return Vec::new();
+    };
+    candidates(backed_up_files(&home_dir, &backup_dir))
+}
+
+// Completes `install --path`, `edit` and `diff` from what the source directory manages
+pub fn complete_managed_paths() -> Vec<CompletionCandidate> {
+    let (Ok(config), Ok(home_dir)) = (read_config(), get_home_dir()) else {
+        return Vec::new();
+    };
+    candidates(managed_paths(&config, &home_dir).unwrap_or_default())
+}
@@ -1,29 +1,862 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
-use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::fs_utils::get_home_dir;
+use crate::archive::{archive_cache_dir, is_archive_url};
+use crate::backup::parse_size;
+use crate::blocks::BlockConfig;
+use crate::script::{HookConfig, HOOKS};
+use crate::dir_config::parse_mode;
+use crate::fs_utils::{get_home_dir, DirModes, IoOptions, SensitivePaths, DEFAULT_SENSITIVE};
+use crate::matcher::PathMatcher;
+use crate::signing::parse_public_key;
+use crate::suggest::closest_match;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const CONFIG_VERSION: u32 = 1;
+
+// Settings committed at the root of the source directory, versioned with the dotfiles themselves
+pub const REPO_CONFIG_FILE: &str = ".dotfiles-rust.yaml";
+
+const CONFIG_KEYS: &[&str] = &["version", "source_dir", "layout", "daemon_interval", "ignore", "template_vars", "machines", "include", "bin_dirs", "escalation_command", "difftool", "difftool_mode", "backup_max_size", "force_readonly", "frozen", "blocks", "sync_state", "notifications", "webhook_url", "webhook_command", "keep_versions_per_file", "respect_gitignore", "trusted_key", "require_signed", "jobs", "copy_buffer_size", "fsync", "max_depth", "skip_hidden_dirs", "follow_symlinks", "dir_mode", "dir_modes", "sensitive", "hooks", "defaults", "clean_install"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    // Files written before versioning was introduced have no version key and are treated as 0
+    #[serde(default)]
+    pub version: u32,
     pub source_dir: String,
+    #[serde(default, skip_serializing_if = "Layout::is_plain")]
+    pub layout: Layout,
+    // Seconds between daemon runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon_interval: Option<u64>,
+    // Glob patterns for home-relative paths that are never installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    // Glob patterns for home-relative paths that install never overwrites, even with --force
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frozen: Vec<String>,
+    // Marked regions managed inside files the tool doesn't otherwise own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<BlockConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_vars: BTreeMap<String, String>,
+    // Home-relative directories, besides .local/bin, whose files are made executable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bin_dirs: Vec<String>,
+    // Runs privileged writes for targets outside the home directory; defaults to sudo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation_command: Option<String>,
+    // External diff program, e.g. "delta" or "meld"; the built-in diff is used when unset or not installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difftool: Option<String>,
+    #[serde(default, skip_serializing_if = "DifftoolMode::is_file")]
+    pub difftool_mode: DifftoolMode,
+    // Size such as "500MB" above which the oldest backup versions are pruned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_max_size: Option<String>,
+    // Backup versions kept per file; older ones are removed as soon as a new backup is made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_versions_per_file: Option<usize>,
+    // Let install overwrite read-only files (restoring their mode afterwards) without --force-readonly
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub force_readonly: bool,
+    // Record each install in .state/<hostname>.yaml in the source directory, for `fleet`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sync_state: bool,
+    // Send a desktop notification when the daemon installs changes or finds files that differ from the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notifications: bool,
+    // URL posted a JSON report after every install, restore and daemon run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    // Shell command run with the same JSON report on standard input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_command: Option<String>,
+    // Skip source files matched by the source repository's .gitignore files
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub respect_gitignore: bool,
+    // Hex-encoded ed25519 public key that `manifest verify` and require_signed check the source tree against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trusted_key: Option<String>,
+    // Refuse to install unless the source tree matches a manifest signed by trusted_key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_signed: bool,
+    // Files compared at once by install and status; --jobs overrides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    // Size such as "1MB" of the buffer used for copies the filesystem can't clone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy_buffer_size: Option<String>,
+    // Flush every written file to disk before it replaces the old one, for homes on NFS or SD cards
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fsync: bool,
+    // Deepest path, in components below the source directory, that is installed; deeper trees aren't walked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    // Don't descend into hidden directories below the top level of the source tree (or of each stow package)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_hidden_dirs: bool,
+    // Walk symlinked directories in the source tree as if their contents were there
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_symlinks: bool,
+    // Install the last commit of the source repository from a temporary checkout, never the working copy's edits
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clean_install: bool,
+    // Octal mode such as "750" for directories created in the home directory; the umask decides without it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<String>,
+    // Octal modes by home-relative directory, e.g. `.ssh: "700"`, also given to new directories below it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dir_modes: BTreeMap<String, String>,
+    // Glob patterns for home-relative paths installed as 600 in 700 directories, and backed up as 600
+    #[serde(default = "default_sensitive", skip_serializing_if = "is_default_sensitive")]
+    pub sensitive: Vec<String>,
+    // Timeout, working directory and environment of each hook, by hook name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hooks: BTreeMap<String, HookConfig>,
+    // Values for command-line flags that aren't given, e.g. `keep_backups: true` instead of passing --keep-backups
+    #[serde(default, skip_serializing_if = "FlagDefaults::is_unset")]
+    pub defaults: FlagDefaults,
+    // Per-hostname settings merged over the rest of the config when running on that machine
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub machines: BTreeMap<String, MachineConfig>,
+    // Further config files merged over this one, in order; missing files are skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Set by read_config when source_dir is an archive URL; source_dir then points at the extracted copy
+    #[serde(skip)]
+    pub source_url: Option<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             source_dir: String::from("."),
+            layout: Layout::default(),
+            daemon_interval: None,
+            ignore: Vec::new(),
+            frozen: Vec::new(),
+            blocks: Vec::new(),
+            template_vars: BTreeMap::new(),
+            bin_dirs: Vec::new(),
+            escalation_command: None,
+            difftool: None,
+            difftool_mode: DifftoolMode::default(),
+            backup_max_size: None,
+            keep_versions_per_file: None,
+            force_readonly: false,
+            sync_state: false,
+            notifications: false,
+            webhook_url: None,
+            webhook_command: None,
+            respect_gitignore: false,
+            trusted_key: None,
+            require_signed: false,
+            jobs: None,
+            copy_buffer_size: None,
+            fsync: false,
+            max_depth: None,
+            skip_hidden_dirs: false,
+            follow_symlinks: false,
+            clean_install: false,
+            dir_mode: None,
+            dir_modes: BTreeMap::new(),
+            sensitive: default_sensitive(),
+            hooks: BTreeMap::new(),
+            defaults: FlagDefaults::default(),
+            machines: BTreeMap::new(),
+            include: Vec::new(),
+            source_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagDefaults {
+    // Leave backups in place when restore and uninstall put them back, instead of deleting them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_backups: bool,
+    // Whether install backs files up before overwriting them; it does unless this is false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<bool>,
+    // --force for install and uninstall
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub force: bool,
+    // --verbose for install, uninstall and status
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verbose: bool,
+}
+
+impl FlagDefaults {
+    fn is_unset(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon_interval: Option<u64>,
+    // Added to the base ignore list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    // Added to the base frozen list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frozen: Vec<String>,
+    // Override base variables with the same name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_vars: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+    // Used unless the local config sets a layout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    // Combined with the local ignore list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    // Defaults for template variables; the local config wins for names defined in both
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_vars: BTreeMap<String, String>,
+    // Applied before the blocks in the local config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<BlockConfig>,
+    // Turns on respect_gitignore for everyone using the repository
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub respect_gitignore: bool,
+    // Used unless the local config sets a max_depth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    // Turn on skip_hidden_dirs and follow_symlinks for everyone using the repository
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_hidden_dirs: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_symlinks: bool,
+}
+
+pub const DEFAULT_BIN_DIR: &str = ".local/bin";
+
+fn default_sensitive() -> Vec<String> {
+    DEFAULT_SENSITIVE.iter().map(|pattern| pattern.to_string()).collect()
+}
+
+fn is_default_sensitive(sensitive: &[String]) -> bool {
+    sensitive == DEFAULT_SENSITIVE
+}
+
+impl Config {
+    pub fn io_options(&self, jobs: Option<usize>) -> IoOptions {
+        IoOptions {
+            jobs: jobs.or(self.jobs).unwrap_or(1).max(1),
+            copy_buffer_size: self.copy_buffer_size.as_deref()
+                .and_then(|size| parse_size(size).ok())
+                .and_then(|size| usize::try_from(size).ok())
+                .filter(|size| *size > 0),
+            fsync: self.fsync,
+        }
+    }
+
+    // Modes for directories created below `root`, the home directory or the install target; invalid
+    // modes are left out, since `config validate` reports them
+    pub fn dir_modes(&self, root: &Path) -> DirModes {
+        let paths = self.dir_modes.iter()
+            .filter_map(|(path, mode)| Some((PathBuf::from(path.trim_matches('/')), parse_mode(mode).ok()?)))
+            .collect();
+        DirModes::new(root, self.dir_mode.as_deref().and_then(|mode| parse_mode(mode).ok()), paths)
+    }
+
+    pub fn sensitive_paths(&self) -> Result<SensitivePaths> {
+        SensitivePaths::new(&self.sensitive)
+            .with_context(|| "Invalid pattern in sensitive")
+    }
+
+    pub fn bin_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(DEFAULT_BIN_DIR)
+            .chain(self.bin_dirs.iter().map(|dir| dir.trim_start_matches("~/")))
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    // Merges repository settings under the local ones
+    pub fn apply_repo_config(&mut self, repo: RepoConfig) {
+        if let Some(layout) = repo.layout
+            && self.layout.is_plain() {
+            self.layout = layout;
+        }
+
+        let mut ignore = repo.ignore;
+        ignore.append(&mut self.ignore);
+        self.ignore = ignore;
+
+        let mut template_vars = repo.template_vars;
+        template_vars.append(&mut self.template_vars);
+        self.template_vars = template_vars;
+
+        let mut blocks = repo.blocks;
+        blocks.append(&mut self.blocks);
+        self.blocks = blocks;
+
+        self.respect_gitignore |= repo.respect_gitignore;
+        self.max_depth = self.max_depth.or(repo.max_depth);
+        self.skip_hidden_dirs |= repo.skip_hidden_dirs;
+        self.follow_symlinks |= repo.follow_symlinks;
+    }
+
+    // Merges the section for `hostname`, if any, over the base settings
+    pub fn apply_machine_overrides(&mut self, hostname: &str) {
+        let Some(machine) = self.machines.get(hostname).cloned() else {
+            return;
+        };
+
+        if let Some(source_dir) = machine.source_dir {
+            self.source_dir = source_dir;
+        }
+        if let Some(layout) = machine.layout {
+            self.layout = layout;
+        }
+        if machine.daemon_interval.is_some() {
+            self.daemon_interval = machine.daemon_interval;
+        }
+        self.ignore.extend(machine.ignore);
+        self.frozen.extend(machine.frozen);
+        self.template_vars.extend(machine.template_vars);
+    }
+}
+
+pub fn current_hostname() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    // Paths in the source directory mirror paths in the home directory
+    #[default]
+    Plain,
+    // GNU stow style: each top-level directory is a package whose contents mirror the home directory
+    Stow,
+}
+
+impl Layout {
+    fn is_plain(&self) -> bool {
+        *self == Layout::Plain
+    }
+
+    // Maps a path relative to the source directory to the path it installs to, relative to home.
+    // Returns None for paths that are not installed, such as loose files next to stow packages.
+    pub fn target_relative_path<'a>(&self, relative_path: &'a Path) -> Option<&'a Path> {
+        match self {
+            Layout::Plain => Some(relative_path),
+            Layout::Stow => {
+                let mut components = relative_path.components();
+                components.next()?;
+                let package_relative = components.as_path();
+
+                if package_relative.as_os_str().is_empty() {
+                    None
+                } else {
+                    Some(package_relative)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DifftoolMode {
+    // Run the difftool once per changed file
+    #[default]
+    File,
+    // Run it once on two directories holding every changed file
+    Dir,
+}
+
+impl DifftoolMode {
+    fn is_file(&self) -> bool {
+        *self == DifftoolMode::File
+    }
+}
+
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut serde_yaml::Mapping) -> Result<()>,
+}
+
+// Each entry upgrades a config from `from` to `from + 1`; append a new one whenever CONFIG_VERSION is bumped
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "add explicit config version",
+        apply: migrate_v0_to_v1,
+    },
+];
+
+fn migrate_v0_to_v1(_config: &mut serde_yaml::Mapping) -> Result<()> {
+    // Unversioned configs share the v1 layout; only the version stamp is added
+    Ok(())
+}
+
+pub fn config_version(value: &serde_yaml::Value) -> Result<u32> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version.as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow!("`version` must be a non-negative integer, found {:?}", version)),
+    }
+}
+
+pub fn migrate_config(value: serde_yaml::Value) -> Result<(serde_yaml::Value, Vec<&'static str>)> {
+    let mut version = config_version(&value)?;
+
+    if version > CONFIG_VERSION {
+        return Err(anyhow!("Config version {} is newer than the supported version {}; please upgrade dotfiles-rust",
+            version, CONFIG_VERSION));
+    }
+
+    let mut mapping = match value {
+        serde_yaml::Value::Mapping(mapping) => mapping,
+        serde_yaml::Value::Null => serde_yaml::Mapping::new(),
+        _ => return Err(anyhow!("Expected a mapping of configuration keys")),
+    };
+    let mut applied = Vec::new();
+
+    while version < CONFIG_VERSION {
+        let migration = MIGRATIONS.iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| anyhow!("No migration registered for config version {}", version))?;
+
+        (migration.apply)(&mut mapping)
+            .with_context(|| format!("Failed to migrate config from version {}: {}", version, migration.description))?;
+
+        version += 1;
+        mapping.insert(serde_yaml::Value::from("version"), serde_yaml::Value::from(version));
+        applied.push(migration.description);
+    }
+
+    Ok((serde_yaml::Value::Mapping(mapping), applied))
+}
+
+// Runs the migration pipeline and re-validates the result, so a broken migration can never be written back
+fn upgrade_config(value: serde_yaml::Value) -> Result<(Config, Vec<&'static str>)> {
+    let (migrated, applied) = migrate_config(value)?;
+
+    let migrated_yaml = serde_yaml::to_string(&migrated)
+        .with_context(|| "Failed to serialize migrated config")?;
+    let config = parse_config(&migrated_yaml)
+        .with_context(|| "Migrated config failed validation")?;
+
+    Ok((config, applied))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub key_path: Option<String>,
+    pub line: Option<usize>,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key_path) = &self.key_path {
+            write!(f, "`{}`: ", key_path)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl ConfigIssue {
+    fn from_yaml_error(error: &serde_yaml::Error) -> Self {
+        // serde_yaml appends the location to its messages; it is reported separately here
+        let message = error.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(pos) => message[..pos].to_string(),
+            None => message,
+        };
+
+        Self {
+            key_path: None,
+            line: error.location().map(|location| location.line()),
+            message,
+            suggestion: None,
         }
     }
 }
 
+fn find_key_line(content: &str, key: &str) -> Option<usize> {
+    content.lines()
+        .position(|line| {
+            line.strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'))
+        })
+        .map(|index| index + 1)
+}
+
+// The first line mentioning `item` from the top-level `key` on, or the key's own line when none does
+fn find_item_line(content: &str, key: &str, item: &str) -> Option<usize> {
+    let key_line = find_key_line(content, key)?;
+    Some(content.lines().enumerate().skip(key_line - 1)
+        .find(|(_, line)| line.contains(item))
+        .map_or(key_line, |(index, _)| index + 1))
+}
+
+pub fn check_config(content: &str) -> Vec<ConfigIssue> {
+    let value = match serde_yaml::from_str::<serde_yaml::Value>(content) {
+        Ok(value) => value,
+        Err(e) => return vec![ConfigIssue::from_yaml_error(&e)],
+    };
+
+    let mut issues = Vec::new();
+
+    match &value {
+        serde_yaml::Value::Mapping(mapping) => {
+            for key in mapping.keys() {
+                let key = match key.as_str() {
+                    Some(key) => key,
+                    None => {
+                        issues.push(ConfigIssue {
+                            key_path: None,
+                            line: None,
+                            message: format!("keys must be strings, found {:?}", key),
+                            suggestion: None,
+                        });
+                        continue;
+                    }
+                };
+
+                if !CONFIG_KEYS.contains(&key) {
+                    issues.push(ConfigIssue {
+                        key_path: Some(key.to_string()),
+                        line: find_key_line(content, key),
+                        message: String::from("unknown key"),
+                        suggestion: closest_match(key, CONFIG_KEYS.iter().copied()).map(String::from),
+                    });
+                }
+            }
+        },
+        _ => {
+            issues.push(ConfigIssue {
+                key_path: None,
+                line: None,
+                message: String::from("expected a mapping of configuration keys"),
+                suggestion: None,
+            });
+            return issues;
+        }
+    }
+
+    match serde_yaml::from_str::<Config>(content) {
+        Ok(config) => {
+            if let Some(Err(e)) = config.backup_max_size.as_deref().map(parse_size) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("backup_max_size")),
+                    line: find_key_line(content, "backup_max_size"),
+                    message: e.to_string(),
+                    suggestion: None,
+                });
+            }
+            if let Some(Err(e)) = config.trusted_key.as_deref().map(parse_public_key) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("trusted_key")),
+                    line: find_key_line(content, "trusted_key"),
+                    message: e.to_string(),
+                    suggestion: None,
+                });
+            }
+            if let Some(Err(e)) = config.copy_buffer_size.as_deref().map(parse_size) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("copy_buffer_size")),
+                    line: find_key_line(content, "copy_buffer_size"),
+                    message: e.to_string(),
+                    suggestion: None,
+                });
+            }
+            if let Some(Err(e)) = config.dir_mode.as_deref().map(parse_mode) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("dir_mode")),
+                    line: find_key_line(content, "dir_mode"),
+                    message: e.to_string(),
+                    suggestion: None,
+                });
+            }
+            for (path, mode) in &config.dir_modes {
+                if let Err(e) = parse_mode(mode) {
+                    issues.push(ConfigIssue {
+                        key_path: Some(format!("dir_modes.{}", path)),
+                        line: find_key_line(content, "dir_modes"),
+                        message: e.to_string(),
+                        suggestion: None,
+                    });
+                }
+            }
+            if let Err(e) = SensitivePaths::new(&config.sensitive) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("sensitive")),
+                    line: find_key_line(content, "sensitive"),
+                    message: format!("{:#}", e),
+                    suggestion: None,
+                });
+            }
+            let machine_patterns = config.machines.iter()
+                .flat_map(|(host, machine)| [(format!("machines.{}.ignore", host), &machine.ignore), (format!("machines.{}.frozen", host), &machine.frozen)]);
+            for (key_path, patterns) in [(String::from("ignore"), &config.ignore), (String::from("frozen"), &config.frozen)].into_iter().chain(machine_patterns) {
+                for pattern in patterns {
+                    if let Err(e) = PathMatcher::new(std::slice::from_ref(pattern)) {
+                        let key = key_path.split('.').next().unwrap_or(&key_path);
+                        issues.push(ConfigIssue {
+                            line: find_item_line(content, key, pattern),
+                            key_path: Some(key_path.clone()),
+                            message: format!("{:#}", e),
+                            suggestion: None,
+                        });
+                    }
+                }
+            }
+            for (name, hook) in &config.hooks {
+                let message = if !HOOKS.contains(&name.as_str()) {
+                    format!("unknown hook, expected one of {}", HOOKS.join(", "))
+                } else if hook.timeout == Some(0) {
+                    String::from("timeout must be at least 1 second")
+                } else {
+                    continue;
+                };
+                issues.push(ConfigIssue {
+                    key_path: Some(format!("hooks.{}", name)),
+                    line: find_key_line(content, "hooks"),
+                    message,
+                    suggestion: None,
+                });
+            }
+            if let Some(url) = &config.webhook_url
+                && !url.starts_with("http://") && !url.starts_with("https://") {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("webhook_url")),
+                    line: find_key_line(content, "webhook_url"),
+                    message: format!("'{}' is not an http(s) URL", url),
+                    suggestion: None,
+                });
+            }
+            if config.max_depth == Some(0) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("max_depth")),
+                    line: find_key_line(content, "max_depth"),
+                    message: String::from("must be at least 1"),
+                    suggestion: None,
+                });
+            }
+            if config.jobs == Some(0) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("jobs")),
+                    line: find_key_line(content, "jobs"),
+                    message: String::from("must be at least 1"),
+                    suggestion: None,
+                });
+            }
+            if config.keep_versions_per_file == Some(0) {
+                issues.push(ConfigIssue {
+                    key_path: Some(String::from("keep_versions_per_file")),
+                    line: find_key_line(content, "keep_versions_per_file"),
+                    message: String::from("must keep at least one version"),
+                    suggestion: None,
+                });
+            }
+        },
+        Err(e) => issues.push(ConfigIssue::from_yaml_error(&e)),
+    }
+
+    issues
+}
+
+pub fn parse_config(content: &str) -> Result<Config> {
+    let issues = check_config(content);
+
+    if !issues.is_empty() {
+        let details = issues.iter()
+            .map(|issue| format!("  {}", issue))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(anyhow!("{} problem(s) found:\n{}", issues.len(), details));
+    }
+
+    let config: Config = serde_yaml::from_str(content)?;
+    Ok(config)
+}
+
 pub fn get_config_path() -> Result<PathBuf> {
     let home_dir = get_home_dir()?;
     Ok(home_dir.join(".dotfiles-rustrc.yaml"))
 }
 
+// The config as seen on this machine, with its `machines` section and the source directory's
+// repository settings applied. Archive URLs resolve to their extracted copy.
 pub fn read_config() -> Result<Config> {
+    let mut config = read_base_config()?;
+    config.apply_machine_overrides(&current_hostname());
+
+    if is_archive_url(&config.source_dir) {
+        let cache_dir = archive_cache_dir(&config.source_dir)?;
+        let url = std::mem::replace(&mut config.source_dir, cache_dir.to_string_lossy().into_owned());
+        config.source_url = Some(url);
+    }
+
+    if let Some(repo_config) = read_repo_config(Path::new(&config.source_dir))? {
+        config.apply_repo_config(repo_config);
+    }
+
+    Ok(config)
+}
+
+// The config for installing from `source_dir` instead of the configured source directory, such as a checkout
+// of another commit, with the repository settings read from there rather than from the working copy
+pub fn read_config_for_source(source_dir: &Path) -> Result<Config> {
+    let mut config = read_base_config()?;
+    config.apply_machine_overrides(&current_hostname());
+    config.source_dir = source_dir.to_string_lossy().into_owned();
+
+    if let Some(repo_config) = read_repo_config(source_dir)? {
+        config.apply_repo_config(repo_config);
+    }
+
+    Ok(config)
+}
+
+// I/O options for this run, before a command reads the rest of the config; defaults when there is no valid config
+pub fn read_io_options(jobs: Option<usize>) -> IoOptions {
+    read_base_config()
+        .map(|config| config.io_options(jobs))
+        .unwrap_or_else(|_| IoOptions { jobs: jobs.unwrap_or(1).max(1), ..Default::default() })
+}
+
+pub fn read_repo_config(source_dir: &Path) -> Result<Option<RepoConfig>> {
+    let repo_config_path = source_dir.join(REPO_CONFIG_FILE);
+
+    if !repo_config_path.is_file() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&repo_config_path)
+        .with_context(|| format!("Failed to read repository config at {}", repo_config_path.display()))?;
+    let repo_config = serde_yaml::from_str::<Option<RepoConfig>>(&content)
+        .with_context(|| format!("Invalid repository config at {}", repo_config_path.display()))?;
+
+    Ok(Some(repo_config.unwrap_or_default()))
+}
+
+// Flag defaults from the config, or none when there is no config yet
+pub fn read_flag_defaults() -> FlagDefaults {
+    read_base_config()
+        .map(|config| config.defaults)
+        .unwrap_or_default()
+}
+
+// The config file with its includes merged in, before machine overrides are applied
+fn read_base_config() -> Result<Config> {
+    let config = read_main_config()?;
+
+    if config.include.is_empty() {
+        return Ok(config);
+    }
+
+    let config_path = get_config_path()?;
+    let merged = resolve_includes(&config_path, &mut Vec::new())?;
+    let merged_yaml = serde_yaml::to_string(&merged)
+        .with_context(|| "Failed to serialize merged config")?;
+
+    parse_config(&merged_yaml)
+        .with_context(|| format!("Invalid config after merging the includes of {}", config_path.display()))
+}
+
+// Mappings are merged key by key and sequences are concatenated; anything else in `overlay` replaces `base`
+pub fn deep_merge(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (serde_yaml::Value::Sequence(base), serde_yaml::Value::Sequence(overlay)) => base.extend(overlay),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn include_path(include: &str, including_file: &Path) -> Result<PathBuf> {
+    let path = match include.strip_prefix("~/") {
+        Some(rest) => get_home_dir()?.join(rest),
+        None => PathBuf::from(include),
+    };
+
+    Ok(match including_file.parent() {
+        Some(parent) if path.is_relative() => parent.join(path),
+        _ => path,
+    })
+}
+
+// Loads a config file and merges its includes over it, depth first
+fn resolve_includes(path: &Path, stack: &mut Vec<PathBuf>) -> Result<serde_yaml::Value> {
+    let canonical = path.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    if stack.contains(&canonical) {
+        return Err(anyhow!("Config include cycle: {} includes itself", path.display()));
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file at {}", path.display()))?;
+    let mut value = serde_yaml::from_str::<serde_yaml::Value>(&content)
+        .with_context(|| format!("Invalid YAML in {}", path.display()))?;
+    if value.is_null() {
+        value = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+    }
+
+    let includes = match value.as_mapping_mut().and_then(|mapping| mapping.remove("include")) {
+        Some(includes) => serde_yaml::from_value::<Vec<String>>(includes)
+            .with_context(|| format!("`include` in {} must be a list of paths", path.display()))?,
+        None => Vec::new(),
+    };
+
+    stack.push(canonical);
+    for include in includes {
+        let included_path = include_path(&include, path)?;
+        if !included_path.is_file() {
+            continue;
+        }
+
+        let included = resolve_includes(&included_path, stack)?;
+        deep_merge(&mut value, included);
+    }
+    stack.pop();
+
+    Ok(value)
+}
+
+// The config file as written, before includes and machine overrides are applied
+fn read_main_config() -> Result<Config> {
     let config_path = get_config_path()?;
     
     if !config_path.exists() {
@@ -34,9 +867,10 @@ pub fn read_config() -> Result<Config> {
             let config_content = fs::read_to_string(&old_config_path)
                 .with_context(|| format!("Failed to read old config file at {}", old_config_path.display()))?;
             
-            match serde_json::from_str::<Config>(&config_content) {
-                Ok(config) => {
+            match serde_json::from_str::<serde_yaml::Value>(&config_content) {
+                Ok(value) => {
                     println!("Converting old JSON config to YAML format...");
+                    let (config, _) = upgrade_config(value)?;
                     // Write the config in the new YAML format
                     write_config(&config)?;
                     println!("Old config file has been converted to YAML format at {}", config_path.display());
@@ -57,8 +891,28 @@ pub fn read_config() -> Result<Config> {
     let config_content = fs::read_to_string(&config_path)
         .with_context(|| format!("Failed to read config file at {}", config_path.display()))?;
     
-    let config: Config = serde_yaml::from_str(&config_content)
-        .with_context(|| format!("Failed to parse YAML config file at {}", config_path.display()))?;
+    if let Ok(value) = serde_yaml::from_str::<serde_yaml::Value>(&config_content) {
+        let version = config_version(&value)
+            .with_context(|| format!("Invalid config file at {}", config_path.display()))?;
+        
+        if version != CONFIG_VERSION {
+            let (config, applied) = upgrade_config(value)
+                .with_context(|| format!("Failed to upgrade config file at {}", config_path.display()))?;
+            
+            // Keep the pre-migration file around in case the upgrade needs to be undone
+            let backup_path = config_path.with_extension(format!("yaml.v{}", version));
+            fs::copy(&config_path, &backup_path)
+                .with_context(|| format!("Failed to back up config file to {}", backup_path.display()))?;
+            write_config(&config)?;
+            
+            println!("Upgraded config from version {} to {} ({}), previous file saved as {}",
+                version, CONFIG_VERSION, applied.join(", "), backup_path.display());
+            return Ok(config);
+        }
+    }
+    
+    let config = parse_config(&config_content)
+        .with_context(|| format!("Invalid config file at {}", config_path.display()))?;
     
     Ok(config)
 }
@@ -84,6 +938,7 @@ pub fn write_config(config: &Config) -> Result<()> {
 pub fn initialize_config(source_dir: &str) -> Result<()> {
     let config = Config {
         source_dir: source_dir.to_string(),
+        ..Default::default()
     };
     
     write_config(&config)
@@ -0,0 +1,157 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+
+use crate::colorize;
+use crate::commands::{drifted_files, install_and_report, InstallOptions};
+use crate::config::read_config;
+use crate::fs_utils::get_home_dir;
+use crate::git;
+use crate::notification::notify_drift;
+use crate::webhook::report_run;
+
+pub const DEFAULT_INTERVAL: u64 = 3600;
+const UNIT_NAME: &str = "dotfiles-rust";
+
+pub fn resolve_interval(interval: Option<u64>) -> Result<u64> {
+    let interval = match interval {
+        Some(interval) => interval,
+        None => read_config()?.daemon_interval.unwrap_or(DEFAULT_INTERVAL),
+    };
+
+    if interval == 0 {
+        return Err(anyhow!("Daemon interval must be at least one second"));
+    }
+
+    Ok(interval)
+}
+
+pub fn run_once(force: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+
+    if git::is_git_repo(source_dir) {
+        println!("{} {}", colorize::info("Updating source repository"), colorize::path(source_dir.display()));
+        let output = git::pull(source_dir)?;
+        if !output.is_empty() {
+            println!("  {}", output);
+        }
+    }
+
+    let options = InstallOptions { force, backup: true, ..Default::default() };
+    if !config.notifications {
+        return install_and_report(&options);
+    }
+
+    // Read again, since the pull may have changed the repository's config
+    let config = read_config()?;
+    let home_dir = get_home_dir()?;
+    let before = drifted_files(&config, &home_dir)?;
+    let installed = install_and_report(&options);
+    notify_drift(&before, &drifted_files(&config, &home_dir)?)?;
+
+    installed
+}
+
+pub fn run_daemon(interval: Option<u64>, once: bool, force: bool) -> Result<()> {
+    if once {
+        let result = run_once(force);
+        report_run("daemon", &result);
+        return result;
+    }
+
+    let interval = resolve_interval(interval)?;
+    println!("{} {}s", colorize::header("Starting dotfiles daemon, interval:"), colorize::highlight(interval));
+
+    loop {
+        let started = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        println!("{} {}", colorize::info("Sync started at"), colorize::highlight(started));
+
+        // A failed run must not stop the daemon; the next tick retries
+        let result = run_once(force);
+        report_run("daemon", &result);
+        if let Err(e) = result {
+            println!("{} {:#}", colorize::error("Sync failed:"), e);
+        }
+
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+// Quotes a word of a systemd command line: backslashes and quotes are escaped inside the double quotes,
+// and `%` and `$` are doubled so systemd doesn't expand them as specifiers or variables
+fn systemd_quote(word: &str) -> String {
+    let escaped = word.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+pub fn render_service_unit(exe_path: &Path, force: bool) -> String {
+    let force_flag = if force { " --force" } else { "" };
+
+    format!("[Unit]
+Description=Sync dotfiles with dotfiles-rust
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+ExecStart={} daemon --once{}
+", systemd_quote(&exe_path.to_string_lossy()), force_flag)
+}
+
+pub fn render_timer_unit(interval: u64) -> String {
+    format!("[Unit]
+Description=Periodically sync dotfiles with dotfiles-rust
+
+[Timer]
+OnBootSec=2min
+OnUnitActiveSec={}s
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+", interval)
+}
+
+pub fn install_unit(interval: Option<u64>, force: bool, dry_run: bool) -> Result<()> {
+    let interval = resolve_interval(interval)?;
+    let exe_path = env::current_exe()
+        .with_context(|| "Failed to locate the running executable")?;
+    let unit_dir = get_home_dir()?.join(".config/systemd/user");
+
+    let units = [
+        (format!("{}.service", UNIT_NAME), render_service_unit(&exe_path, force)),
+        (format!("{}.timer", UNIT_NAME), render_timer_unit(interval)),
+    ];
+
+    for (name, content) in &units {
+        let unit_path = unit_dir.join(name);
+
+        if dry_run {
+            println!("{} {}", colorize::dry_run("[Dry run] Would write"), colorize::path(unit_path.display()));
+            println!("{}", content);
+            continue;
+        }
+
+        fs::create_dir_all(&unit_dir)
+            .with_context(|| format!("Failed to create directory {}", unit_dir.display()))?;
+        fs::write(&unit_path, content)
+            .with_context(|| format!("Failed to write unit file {}", unit_path.display()))?;
+        println!("{} {}", colorize::success("Wrote"), colorize::path(unit_path.display()));
+    }
+
+    if !dry_run {
+        println!("{}", colorize::info("Enable the timer with:"));
+        println!("  systemctl --user daemon-reload");
+        println!("  systemctl --user enable --now {}.timer", UNIT_NAME);
+    }
+
+    Ok(())
+}
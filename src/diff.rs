@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
+
+use crate::colorize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffStat {
+    // Lines present only in the installed file, and lines present only in the source
+    Text { added: usize, removed: usize },
+    Binary { old_size: u64, new_size: u64 },
+}
+
+// Formats that are never worth a line diff, even when a file happens to contain no null bytes
+const BINARY_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "icns", "webp", "tiff", "pdf",
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "jar",
+    "ttf", "otf", "woff", "woff2", "mp3", "ogg", "wav", "mp4",
+    "db", "sqlite", "so", "dylib", "dll", "exe", "o", "a", "class", "pyc",
+];
+
+// 1536 -> "1.5 KiB", 14336 -> "14 KiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if size < 10.0 {
+        format!("{:.1} {}", size, UNITS[unit]).replace(".0 ", " ")
+    } else {
+        format!("{:.0} {}", size, UNITS[unit])
+    }
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffStat::Text { added, removed } => write!(f, "+{} -{} lines", added, removed),
+            DiffStat::Binary { old_size, new_size } => write!(f, "binary file differs ({} {} {})", format_size(*old_size), colorize::arrow(), format_size(*new_size)),
+        }
+    }
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content.contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+pub fn has_binary_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| BINARY_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+// Whether a file should be compared byte for byte instead of line by line
+pub fn is_binary_file(path: &Path, content: &[u8]) -> bool {
+    has_binary_extension(path) || is_binary(content)
+}
+
+fn file_digest(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+// Hex sha256 of a file's content
+pub fn file_hash(path: &Path) -> io::Result<String> {
+    Ok(file_digest(path)?.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Files at least this large are compared through memory maps, chunk by chunk
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+const MMAP_CHUNK_SIZE: usize = 1024 * 1024;
+
+// How an installed file is checked against its source
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode {
+    #[default]
+    Content,
+    // Only size and modification time, for huge assets such as fonts that are never edited in place
+    MetadataOnly,
+}
+
+impl CompareMode {
+    pub fn is_content(&self) -> bool {
+        *self == CompareMode::Content
+    }
+}
+
+// Compares two files by size and then by hash, without holding either in memory
+pub fn same_file_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let size = fs::metadata(a)?.len();
+    if size != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    if size >= MMAP_THRESHOLD {
+        return same_mapped_content(a, b);
+    }
+
+    Ok(file_digest(a)? == file_digest(b)?)
+}
+
+// Hashes both files a chunk at a time through memory maps, stopping at the first chunk that differs
+fn same_mapped_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a, b) = (File::open(a)?, File::open(b)?);
+    // Safety: the maps are only read. A file truncated by another process while it is compared can raise
+    // SIGBUS, the same risk `cmp` and other mmap-based tools take.
+    let (a, b) = unsafe { (Mmap::map(&a)?, Mmap::map(&b)?) };
+
+    Ok(a.len() == b.len()
+        && a.chunks(MMAP_CHUNK_SIZE).zip(b.chunks(MMAP_CHUNK_SIZE)).all(|(a, b)| Sha256::digest(a) == Sha256::digest(b)))
+}
+
+// Whether `target` looks like an unchanged copy of `source` from its size and modification time: the
+// same size, and written no earlier than the source last changed
+pub fn same_file_metadata(source: &Path, target: &Path) -> io::Result<bool> {
+    let (source, target) = (fs::metadata(source)?, fs::metadata(target)?);
+    Ok(source.len() == target.len() && target.modified()? >= source.modified()?)
+}
+
+pub fn same_file(source: &Path, target: &Path, compare: CompareMode) -> io::Result<bool> {
+    match compare {
+        CompareMode::Content => same_file_content(source, target),
+        CompareMode::MetadataOnly => same_file_metadata(source, target),
+    }
+}
+
+// Lines only in the source and lines only in the target, in file order. Lines are matched by
+// occurrence rather than by running a full diff, so moved lines count as unchanged.
+pub fn line_changes<'a>(source: &'a str, target: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
+    fn unmatched<'a>(lines: &'a str, other: &'a str) -> Vec<&'a str> {
+        let mut available: HashMap<&str, usize> = HashMap::new();
+        for line in other.lines() {
+            *available.entry(line).or_default() += 1;
+        }
+
+        lines.lines()
+            .filter(|line| match available.get_mut(line) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                },
+                _ => true,
+            })
+            .collect()
+    }
+
+    (unmatched(source, target), unmatched(target, source))
+}
+
+pub fn diff_stat(path: &Path, source: &[u8], target: &[u8]) -> DiffStat {
+    if is_binary_file(path, source) || is_binary(target) {
+        return DiffStat::Binary { old_size: source.len() as u64, new_size: target.len() as u64 };
+    }
+
+    let source_text = String::from_utf8_lossy(source);
+    let target_text = String::from_utf8_lossy(target);
+    let (removed, added) = line_changes(&source_text, &target_text);
+
+    DiffStat::Text { added: added.len(), removed: removed.len() }
+}
+
+pub const DIFF_CONTEXT_LINES: usize = 3;
+
+// A unified diff from `old` to `new` with the changed words inside each line highlighted, so no external
+// diff program is needed
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let mut output = String::new();
+    if old == new {
+        return output;
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+
+    let _ = writeln!(output, "{}", colorize::removed(format!("--- {}", old_label), false));
+    let _ = writeln!(output, "{}", colorize::added(format!("+++ {}", new_label), false));
+
+    for hunk in diff.unified_diff().context_radius(DIFF_CONTEXT_LINES).iter_hunks() {
+        let _ = writeln!(output, "{}", colorize::info(hunk.header()));
+
+        for op in hunk.ops() {
+            for change in diff.iter_inline_changes(op) {
+                let mut line = String::new();
+                for (emphasized, value) in change.iter_strings_lossy() {
+                    let value = value.trim_end_matches(['\r', '\n']);
+                    let _ = match change.tag() {
+                        ChangeTag::Delete => write!(line, "{}", colorize::removed(value, emphasized)),
+                        ChangeTag::Insert => write!(line, "{}", colorize::added(value, emphasized)),
+                        ChangeTag::Equal => write!(line, "{}", value),
+                    };
+                }
+
+                let _ = match change.tag() {
+                    ChangeTag::Delete => writeln!(output, "{}{}", colorize::removed("-", false), line),
+                    ChangeTag::Insert => writeln!(output, "{}{}", colorize::added("+", false), line),
+                    ChangeTag::Equal => writeln!(output, " {}", line),
+                };
+                if change.missing_newline() {
+                    let _ = writeln!(output, "\\ No newline at end of file");
+                }
+            }
+        }
+    }
+
+    output
+}
@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+
+use crate::colorize;
+use crate::config::{Config, DifftoolMode};
+use crate::fs_utils::get_data_dir;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difftool {
+    pub command: String,
+    pub mode: DifftoolMode,
+}
+
+impl Difftool {
+    // The configured difftool, or None to use the built-in diff. A tool that isn't installed is reported
+    // once and skipped rather than failing the command.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let command = config.difftool.as_deref()?.trim();
+        if command.is_empty() {
+            return None;
+        }
+
+        if !is_available(command) {
+            println!("{} {} {}",
+                colorize::warning("Difftool"),
+                colorize::path(command),
+                colorize::warning("is not installed; using the built-in diff"));
+            return None;
+        }
+
+        Some(Self { command: command.to_string(), mode: config.difftool_mode })
+    }
+}
+
+// A file to compare: what the source installs as, and the installed copy
+pub struct DiffPair<'a> {
+    pub relative_path: &'a Path,
+    pub expected: &'a [u8],
+    pub target: &'a Path,
+}
+
+fn split_command(command: &str) -> Result<(&str, Vec<&str>)> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next()
+        .ok_or_else(|| anyhow!("Difftool command is empty"))?;
+    Ok((program, parts.collect()))
+}
+
+// Whether the program a command starts with can be run, either as a path or from PATH
+pub fn is_available(command: &str) -> bool {
+    let Ok((program, _)) = split_command(command) else {
+        return false;
+    };
+
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(program).is_file();
+    }
+
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    let extensions: &[&str] = if cfg!(windows) { &["", ".exe", ".cmd", ".bat"] } else { &[""] };
+
+    env::split_paths(&path).any(|dir| extensions.iter().any(|extension| dir.join(format!("{}{}", program, extension)).is_file()))
+}
+
+fn run_tool(command: &str, expected: &Path, target: &Path) -> Result<()> {
+    let (program, args) = split_command(command)?;
+
+    // Diff programs exit non-zero when the inputs differ, so only a failure to launch is an error
+    Command::new(program)
+        .args(args)
+        .arg(expected)
+        .arg(target)
+        .status()
+        .with_context(|| format!("Failed to launch difftool '{}'", command))?;
+
+    Ok(())
+}
+
+fn staging_dir() -> Result<PathBuf> {
+    let dir = get_data_dir()?.join("difftool");
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    Ok(dir)
+}
+
+fn stage(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Runs the external difftool over the given files. The source side is staged to a temporary copy, since
+// templates only exist rendered in memory.
+pub fn run_difftool(difftool: &Difftool, pairs: &[DiffPair]) -> Result<()> {
+    let command = difftool.command.as_str();
+    let staging = staging_dir()?;
+
+    let result = match difftool.mode {
+        DifftoolMode::File => pairs.iter().try_for_each(|pair| {
+            println!("{} {}", colorize::header("diff"), colorize::path(pair.relative_path.display()));
+            let expected = staging.join("source").join(pair.relative_path);
+            stage(&expected, pair.expected)?;
+            run_tool(command, &expected, pair.target)
+        }),
+        DifftoolMode::Dir => (|| {
+            for pair in pairs {
+                stage(&staging.join("source").join(pair.relative_path), pair.expected)?;
+                let installed = fs::read(pair.target).unwrap_or_default();
+                stage(&staging.join("installed").join(pair.relative_path), &installed)?;
+            }
+            run_tool(command, &staging.join("source"), &staging.join("installed"))
+        })(),
+    };
+
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
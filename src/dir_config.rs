@@ -0,0 +1,233 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::commands::{build_exclude_set, is_blacklisted, is_excluded, source_entries};
+use crate::config::{read_config, Config, Layout};
+use crate::diff::CompareMode;
+use crate::gitignore::GitIgnore;
+use crate::matcher::PathMatcher;
+use crate::template::target_file_path;
+
+// Placed in a subdirectory of the source tree to change how that subtree installs
+pub const DIR_CONFIG_FILE: &str = ".dotfiles.yaml";
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirConfig {
+    // Octal permissions for every file below the directory, e.g. "755"
+    #[serde(default)]
+    pub mode: Option<String>,
+    // Home-relative directory the subtree installs into instead of its own path
+    #[serde(default)]
+    pub target: Option<String>,
+    // Glob patterns relative to the directory
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    // Install the directory as one file, concatenating the files in it in name order
+    #[serde(default)]
+    pub assemble: bool,
+    // How installed files below the directory are checked against the source
+    #[serde(default)]
+    pub compare: Option<CompareMode>,
+}
+
+#[derive(Debug)]
+struct DirOverride {
+    // Directory holding the config, relative to the source directory
+    dir: PathBuf,
+    mode: Option<u32>,
+    target: Option<PathBuf>,
+    ignore: PathMatcher,
+    assemble: bool,
+    compare: Option<CompareMode>,
+}
+
+// How far the source tree is walked, from the config
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct WalkLimits {
+    max_depth: Option<usize>,
+    skip_hidden_dirs: bool,
+    // Leading path components that may be hidden directories anyway: .config at the top, or inside a stow package
+    top_levels: usize,
+    follow_symlinks: bool,
+}
+
+impl WalkLimits {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            max_depth: config.max_depth,
+            skip_hidden_dirs: config.skip_hidden_dirs,
+            top_levels: if config.layout == Layout::Stow { 2 } else { 1 },
+            follow_symlinks: config.follow_symlinks,
+        }
+    }
+}
+
+// Every per-directory config in a source tree, parents before their subdirectories
+#[derive(Debug, Default)]
+pub struct DirConfigs {
+    source_dir: PathBuf,
+    overrides: Vec<DirOverride>,
+    // The repository's .gitignore rules, when the config asks for them
+    gitignore: Option<GitIgnore>,
+    limits: WalkLimits,
+}
+
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    let digits = mode.trim().trim_start_matches("0o");
+
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(anyhow!("'{}' is not an octal file mode", mode)),
+    }
+}
+
+impl DirConfigs {
+    pub fn load(source_dir: &Path) -> Result<Self> {
+        let config = read_config().ok();
+        let limits = config.as_ref().map(WalkLimits::from_config).unwrap_or_default();
+        let mut overrides = Vec::new();
+
+        for entry in source_entries(source_dir, limits.follow_symlinks, is_blacklisted)
+            .filter(|e| e.depth() >= 2 && e.file_type().is_file() && e.file_name() == DIR_CONFIG_FILE)
+        {
+            let config_path = entry.path();
+            let content = fs::read_to_string(config_path)
+                .with_context(|| format!("Failed to read {}", config_path.display()))?;
+            let config = serde_yaml::from_str::<Option<DirConfig>>(&content)
+                .with_context(|| format!("Invalid directory config at {}", config_path.display()))?
+                .unwrap_or_default();
+
+            let dir = config_path.parent()
+                .and_then(|parent| parent.strip_prefix(source_dir).ok())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+
+            overrides.push(DirOverride {
+                dir,
+                mode: config.mode.as_deref().map(parse_mode).transpose()
+                    .with_context(|| format!("Invalid mode in {}", config_path.display()))?,
+                target: config.target.map(PathBuf::from),
+                ignore: build_exclude_set(&config.ignore)
+                    .with_context(|| format!("Invalid ignore pattern in {}", config_path.display()))?,
+                assemble: config.assemble,
+                compare: config.compare,
+            });
+        }
+
+        overrides.sort_by_key(|dir_override| dir_override.dir.components().count());
+
+        let gitignore = config.is_some_and(|config| config.respect_gitignore)
+            .then(|| GitIgnore::load(source_dir));
+
+        Ok(Self { source_dir: source_dir.to_path_buf(), overrides, gitignore, limits })
+    }
+
+    pub fn follows_symlinks(&self) -> bool {
+        self.limits.follow_symlinks
+    }
+
+    // Whether a source-relative path lies beyond max_depth or inside a hidden directory skip_hidden_dirs leaves out
+    fn is_beyond_limits(&self, source_relative_path: &Path) -> bool {
+        let components = source_relative_path.components().collect::<Vec<_>>();
+        if self.limits.max_depth.is_some_and(|max_depth| components.len() > max_depth) {
+            return true;
+        }
+        if !self.limits.skip_hidden_dirs {
+            return false;
+        }
+
+        let is_hidden = |component: &Component| component.as_os_str().to_string_lossy().starts_with('.');
+        let Some((last, parents)) = components.split_last() else {
+            return false;
+        };
+        parents.iter().skip(self.limits.top_levels).any(is_hidden)
+            || (components.len() > self.limits.top_levels && is_hidden(last) && self.source_dir.join(source_relative_path).is_dir())
+    }
+
+    // Overrides that apply to a source-relative path, outermost first
+    fn applying_to<'a>(&'a self, source_relative_path: &'a Path) -> impl Iterator<Item = &'a DirOverride> + 'a {
+        self.overrides.iter().filter(move |dir_override| source_relative_path.starts_with(&dir_override.dir))
+    }
+
+    pub fn is_ignored(&self, source_relative_path: &Path) -> bool {
+        self.is_beyond_limits(source_relative_path)
+            || self.gitignore.as_ref().is_some_and(|gitignore| gitignore.is_ignored(source_relative_path))
+            || self.applying_to(source_relative_path).any(|dir_override| {
+                source_relative_path.strip_prefix(&dir_override.dir)
+                    .is_ok_and(|inner| is_excluded(&dir_override.ignore, inner))
+            })
+    }
+
+    // Whether a source-relative directory is installed as one file assembled from its fragments
+    pub fn is_assembled(&self, source_relative_path: &Path) -> bool {
+        self.overrides.iter().any(|dir_override| dir_override.assemble && dir_override.dir == source_relative_path)
+    }
+
+    // The assembled directory a source-relative path is a fragment of, if any
+    fn assembled_dir<'a>(&'a self, source_relative_path: &'a Path) -> Option<&'a Path> {
+        self.applying_to(source_relative_path)
+            .find(|dir_override| dir_override.assemble)
+            .map(|dir_override| dir_override.dir.as_path())
+    }
+
+    pub fn mode_for(&self, source_relative_path: &Path) -> Option<u32> {
+        self.applying_to(source_relative_path).filter_map(|dir_override| dir_override.mode).last()
+    }
+
+    pub fn compare_for(&self, source_relative_path: &Path) -> CompareMode {
+        self.applying_to(source_relative_path).filter_map(|dir_override| dir_override.compare).last().unwrap_or_default()
+    }
+
+    // Where a source file installs to, relative to home. The innermost `target` wins; without one the
+    // layout decides. Fragments install to the file their directory is assembled into, which is the
+    // directory's own target without a `.d` suffix, e.g. .zshrc.d -> .zshrc.
+    pub fn target_for(&self, layout: Layout, source_relative_path: &Path) -> Option<PathBuf> {
+        if let Some(dir) = self.assembled_dir(source_relative_path) {
+            let target = self.remapped_target(layout, dir)?;
+            return Some(match target.extension() {
+                Some(extension) if extension == "d" => target.with_extension(""),
+                _ => target,
+            });
+        }
+
+        self.remapped_target(layout, source_relative_path).map(|target| target_file_path(&target))
+    }
+
+    fn remapped_target(&self, layout: Layout, source_relative_path: &Path) -> Option<PathBuf> {
+        let remapped = self.applying_to(source_relative_path)
+            .filter(|dir_override| dir_override.target.is_some())
+            .last()
+            .and_then(|dir_override| {
+                let inner = source_relative_path.strip_prefix(&dir_override.dir).ok()?;
+                let target = dir_override.target.as_ref()?;
+                // Joining an empty path would leave a trailing separator
+                Some(if inner.as_os_str().is_empty() { target.clone() } else { target.join(inner) })
+            });
+
+        match remapped {
+            Some(target) => Some(target),
+            None => layout.target_relative_path(source_relative_path).map(Path::to_path_buf),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn apply_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use crate::fs_utils::as_home_owner;
+
+    if let Some(mode) = mode {
+        as_home_owner(path, || fs::set_permissions(path, fs::Permissions::from_mode(mode)))
+            .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply_mode(_path: &Path, _mode: Option<u32>) -> Result<()> {
+    Ok(())
+}
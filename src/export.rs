@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+
+use crate::colorize;
+use crate::commands::{configured_files, ManagedFile};
+use crate::config::read_config;
+use crate::dir_config::apply_mode;
+use crate::fs_utils::{file_mode, get_home_dir};
+use crate::template::{self, expected_content, is_template};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// A chezmoi source directory (dot_, executable_ and private_ prefixes)
+    Chezmoi,
+    /// A GNU stow directory with one package per application
+    Stow,
+    /// A plain tree with a dotbot install.conf.yaml linking every file
+    Dotbot,
+}
+
+// Directories that only group applications, so their subdirectories make better stow package names
+const STOW_GROUPING_DIRS: &[&str] = &[".config", ".local", ".local/share"];
+
+// Encodes a home-relative path the way chezmoi names source files
+pub fn chezmoi_path(relative_path: &Path, mode: Option<u32>) -> PathBuf {
+    let mut components = relative_path.iter().map(|c| c.to_string_lossy().to_string()).collect::<Vec<_>>();
+    let last = components.len().saturating_sub(1);
+
+    for (index, component) in components.iter_mut().enumerate() {
+        let mut name = match component.strip_prefix('.') {
+            Some(rest) => format!("dot_{}", rest),
+            None => component.clone(),
+        };
+
+        if index == last
+            && let Some(mode) = mode {
+            if mode & 0o111 != 0 {
+                name = format!("executable_{}", name);
+            }
+            if mode & 0o077 == 0 {
+                name = format!("private_{}", name);
+            }
+        }
+        *component = name;
+    }
+
+    components.iter().collect()
+}
+
+// The stow package a file goes into: its top-level name without the dot, or the application directory
+// for files under grouping directories like .config, e.g. .config/nvim/init.lua -> nvim
+pub fn stow_package(relative_path: &Path) -> String {
+    let grouping = STOW_GROUPING_DIRS.iter()
+        .filter(|dir| relative_path.parent().is_some_and(|parent| parent.starts_with(dir) && parent != Path::new(dir)))
+        .max_by_key(|dir| dir.len());
+
+    let name = match grouping {
+        Some(dir) => relative_path.strip_prefix(dir).ok()
+            .and_then(|rest| rest.iter().next())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        None => relative_path.iter().next()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+
+    name.trim_start_matches('.').to_string()
+}
+
+fn dotbot_config(files: &[&ManagedFile]) -> String {
+    let mut config = String::from("- defaults:\n    link:\n      create: true\n      relink: true\n\n- clean: ['~']\n\n- link:\n");
+
+    for file in files {
+        let path = file.relative_path.to_string_lossy().replace('\'', "''");
+        config.push_str(&format!("    '~/{}': '{}'\n", path, path));
+    }
+
+    config
+}
+
+// Where a managed file goes in the exported tree
+fn export_path(format: ExportFormat, file: &ManagedFile, mode: Option<u32>) -> PathBuf {
+    match format {
+        ExportFormat::Chezmoi => chezmoi_path(&file.relative_path, mode),
+        ExportFormat::Stow => Path::new(&stow_package(&file.relative_path)).join(&file.relative_path),
+        ExportFormat::Dotbot => file.relative_path.clone(),
+    }
+}
+
+pub fn export_dotfiles(format: ExportFormat, output: &Path, dry_run: bool, force: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    let home_dir = get_home_dir()?;
+
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    println!("{} {} {} {}",
+        colorize::header("Exporting"),
+        colorize::path(source_dir.display()),
+        colorize::header("to"),
+        colorize::path(output.display()));
+
+    let vars = template::template_vars(&config, &[])?;
+    let files = configured_files(&config, &home_dir)?;
+    let mut exported = Vec::new();
+    let mut skipped_count = 0;
+    let mut rendered_count = 0;
+
+    for file in &files {
+        // Other managers only install into the home directory
+        if file.relative_path.components().any(|c| matches!(c, Component::RootDir | Component::Prefix(_))) {
+            println!("  {} {} (outside the home directory)", colorize::warning("Skipped:"), colorize::path(file.relative_path.display()));
+            skipped_count += 1;
+            continue;
+        }
+
+        let mode = file.mode.or_else(|| file_mode(&file.source));
+        let target = export_path(format, file, mode);
+        let output_path = output.join(&target);
+
+        if output_path.exists() && !force {
+            println!("  {} {} (already exists, use --force to overwrite)",
+                colorize::warning("Skipped:"),
+                colorize::path(target.display()));
+            skipped_count += 1;
+            continue;
+        }
+
+        let content = expected_content(&file.source, &vars)?;
+        if is_template(&file.source) {
+            rendered_count += 1;
+        }
+
+        if dry_run {
+            println!("  {} {} => {}",
+                colorize::dry_run("[Dry run] Would export"),
+                colorize::path(file.relative_path.display()),
+                colorize::path(target.display()));
+        } else {
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+            }
+            fs::write(&output_path, content)
+                .with_context(|| format!("Failed to write {}", output_path.display()))?;
+            apply_mode(&output_path, mode)?;
+            println!("  {} {} => {}",
+                colorize::success("Exported:"),
+                colorize::path(file.relative_path.display()),
+                colorize::path(target.display()));
+        }
+        exported.push(file);
+    }
+
+    if format == ExportFormat::Dotbot {
+        let config_path = output.join("install.conf.yaml");
+        if config_path.exists() && !force {
+            println!("  {} install.conf.yaml (already exists, use --force to overwrite)", colorize::warning("Skipped:"));
+        } else if dry_run {
+            println!("  {} install.conf.yaml", colorize::dry_run("[Dry run] Would write"));
+        } else {
+            fs::create_dir_all(output)
+                .with_context(|| format!("Failed to create directory {}", output.display()))?;
+            fs::write(&config_path, dotbot_config(&exported))
+                .with_context(|| format!("Failed to write {}", config_path.display()))?;
+            println!("  {} install.conf.yaml", colorize::success("Wrote:"));
+        }
+    }
+
+    println!("\n{}", colorize::header("Summary:"));
+    println!("  {} {}", colorize::success("Files exported:"), colorize::highlight(exported.len()));
+    println!("  {} {}", colorize::warning("Files skipped:"), colorize::highlight(skipped_count));
+    if rendered_count > 0 {
+        println!("  {} {} template(s) were rendered with this machine's values",
+            colorize::info("Note:"),
+            colorize::highlight(rendered_count));
+    }
+    if dry_run {
+        println!("{}", colorize::dry_run("Dry run - no files were written"));
+    }
+
+    Ok(())
+}
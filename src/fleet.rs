@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::backup::format_age;
+use crate::colorize;
+use crate::config::{current_hostname, read_config};
+use crate::git;
+
+// Directory in the source repository holding one state file per machine
+pub const STATE_DIR: &str = ".state";
+
+// What a machine last installed, committed with the dotfiles so every machine can see the others
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachineState {
+    pub hostname: String,
+    // Unix time of the last install
+    pub installed_at: i64,
+    // Source commit of the last complete install
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    // Number of installs on this machine so far
+    pub generation: u64,
+    // Files recorded in the manifest after the install
+    #[serde(default)]
+    pub files: usize,
+}
+
+pub fn state_path(source_dir: &Path, hostname: &str) -> PathBuf {
+    source_dir.join(STATE_DIR).join(format!("{}.yaml", hostname))
+}
+
+fn read_state(path: &Path) -> Result<MachineState> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read machine state at {}", path.display()))?;
+    serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse machine state at {}", path.display()))
+}
+
+// Writes this machine's state file, counting up the generation from the previous one
+pub fn record_install(source_dir: &Path, commit: Option<&str>, files: usize) -> Result<()> {
+    let hostname = current_hostname();
+    let path = state_path(source_dir, &hostname);
+    let generation = read_state(&path).map(|state| state.generation).unwrap_or(0) + 1;
+
+    let state = MachineState {
+        hostname,
+        installed_at: chrono::Utc::now().timestamp(),
+        commit: commit.map(String::from),
+        generation,
+        files,
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let content = serde_yaml::to_string(&state)
+        .with_context(|| "Failed to serialize machine state")?;
+    fs::write(&path, content)
+        .with_context(|| format!("Failed to write machine state at {}", path.display()))?;
+
+    Ok(())
+}
+
+// Every machine's state in the source directory, most recently installed first. Unreadable files are
+// reported and skipped so one bad file doesn't hide the rest.
+pub fn load_states(source_dir: &Path) -> Result<Vec<MachineState>> {
+    let state_dir = source_dir.join(STATE_DIR);
+    if !state_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut states = Vec::new();
+    for entry in fs::read_dir(&state_dir)
+        .with_context(|| format!("Failed to read {}", state_dir.display()))?
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "yaml"))
+    {
+        match read_state(&entry.path()) {
+            Ok(state) => states.push(state),
+            Err(e) => println!("{} {:#}", colorize::warning("Skipping:"), e),
+        }
+    }
+
+    states.sort_by(|a, b| b.installed_at.cmp(&a.installed_at).then_with(|| a.hostname.cmp(&b.hostname)));
+    Ok(states)
+}
+
+// How a machine's last install compares with the source repository's HEAD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncStatus {
+    UpToDate,
+    Behind,
+    // The machine recorded no commit, or the source directory isn't a git repository
+    Unknown,
+}
+
+pub fn sync_status(state: &MachineState, head: Option<&str>) -> SyncStatus {
+    match (state.commit.as_deref(), head) {
+        (Some(commit), Some(head)) if commit == head => SyncStatus::UpToDate,
+        (Some(_), Some(_)) => SyncStatus::Behind,
+        _ => SyncStatus::Unknown,
+    }
+}
+
+pub fn show_fleet() -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let states = load_states(source_dir)?;
+    if states.is_empty() {
+        println!("{}", colorize::info("No machine state recorded yet; set `sync_state: true` in the config on each machine"));
+        return Ok(());
+    }
+
+    let head = if git::is_git_repo(source_dir) { git::head_commit(source_dir).ok() } else { None };
+    let hostname = current_hostname();
+    let now = chrono::Utc::now().timestamp();
+    let width = states.iter().map(|state| state.hostname.len()).max().unwrap_or(0);
+
+    println!("{}", colorize::header("Machines:"));
+    for state in &states {
+        let age = format_age(now.saturating_sub(state.installed_at).max(0) as u64);
+        let commit = state.commit.as_deref().map(git::short_commit).unwrap_or("-");
+        let sync = match sync_status(state, head.as_deref()) {
+            SyncStatus::UpToDate => colorize::success("up to date").to_string(),
+            SyncStatus::Behind => colorize::warning("behind").to_string(),
+            SyncStatus::Unknown => colorize::info("unknown").to_string(),
+        };
+        let marker = if state.hostname == hostname { " (this machine)" } else { "" };
+
+        println!("  {}  {:<14}  {:<7}  gen {:<4}  {}{}",
+            colorize::highlight(format!("{:<width$}", state.hostname, width = width)),
+            age,
+            commit,
+            state.generation,
+            sync,
+            marker);
+    }
+
+    Ok(())
+}
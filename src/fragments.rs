@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+use crate::template::{self, expected_content, is_template};
+
+// The files an assembled directory is built from, in the order they are joined: by name, so numeric
+// prefixes such as 10-env.zsh and 20-aliases.zsh set the order. Hidden files, including the directory
+// config, and subdirectories are left out.
+pub fn fragment_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read fragments in {}", dir.display()))?
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    Ok(paths)
+}
+
+// The fragments joined into one file, with templates rendered. Every fragment starts on a new line.
+pub fn assemble(dir: &Path, vars: &BTreeMap<String, String>) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+
+    for path in fragment_paths(dir)? {
+        if !content.is_empty() && !content.ends_with(b"\n") {
+            content.push(b'\n');
+        }
+        content.extend(expected_content(&path, vars)?);
+    }
+
+    Ok(content)
+}
+
+// Variables used by template fragments that `vars` doesn't define, in order of first use
+pub fn undefined_variables(dir: &Path, vars: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+
+    for path in fragment_paths(dir)?.into_iter().filter(|path| is_template(path)) {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read template {}", path.display()))?;
+        for name in template::undefined_variables(&content, vars) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+// The file whose permissions the assembled file gets: the first fragment
+pub fn permissions_source(dir: &Path) -> Option<PathBuf> {
+    fragment_paths(dir).ok()?.into_iter().next()
+}
@@ -1,25 +1,78 @@
 use std::env;
 use std::fs;
-use std::path::{PathBuf};
+use std::path::{Component, Path, PathBuf};
 use anyhow::{anyhow, Context, Result};
 use std::thread_local;
-use std::sync::Mutex;
+use std::io::{BufReader, BufWriter, Write};
+use std::sync::{Mutex, RwLock};
 use std::sync::LazyLock;
-
-#[cfg(test)]
-use std::path::{Path};
+use crate::dir_config::apply_mode;
+use crate::matcher::PathMatcher;
 
 #[cfg(test)]
 use std::sync::atomic::{AtomicU64, Ordering};
 
 thread_local! {
-    static TEST_HOME_DIR: std::cell::RefCell<Option<PathBuf>> = std::cell::RefCell::new(None);
-    static TEST_BACKUP_DIR: std::cell::RefCell<Option<PathBuf>> = std::cell::RefCell::new(None);
-    static TEST_ID: std::cell::RefCell<Option<u64>> = std::cell::RefCell::new(None);
+    static TEST_HOME_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+    static TEST_BACKUP_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+    static TEST_ID: std::cell::RefCell<Option<u64>> = const { std::cell::RefCell::new(None) };
+    static HOME_OWNER: std::cell::RefCell<Option<HomeOwner>> = const { std::cell::RefCell::new(None) };
 }
 
 static HOME_ENV_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
 
+// How files are read and written, from the config and --jobs; shared with worker threads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoOptions {
+    // Files compared at once
+    pub jobs: usize,
+    // Buffer for copies that can't be cloned; without one the OS copies in-kernel where it can
+    pub copy_buffer_size: Option<usize>,
+    // Flush each written file to disk before it replaces the old one
+    pub fsync: bool,
+}
+
+impl Default for IoOptions {
+    fn default() -> Self {
+        DEFAULT_IO_OPTIONS
+    }
+}
+
+const DEFAULT_IO_OPTIONS: IoOptions = IoOptions { jobs: 1, copy_buffer_size: None, fsync: false };
+
+static IO_OPTIONS: RwLock<IoOptions> = RwLock::new(DEFAULT_IO_OPTIONS);
+
+pub fn set_io_options(options: IoOptions) {
+    *IO_OPTIONS.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = options;
+}
+
+pub fn io_options() -> IoOptions {
+    *IO_OPTIONS.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// `f` applied to every item, spread over `jobs` threads; results keep the order of the items
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = io_options().jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(jobs);
+    std::thread::scope(|scope| {
+        let workers = items.chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    })
+}
+
 #[cfg(test)]
 static NEXT_TEST_ID: AtomicU64 = AtomicU64::new(1);
 
@@ -74,6 +127,27 @@ pub fn get_home_dir() -> Result<PathBuf> {
     Ok(home_path)
 }
 
+pub fn get_data_dir() -> Result<PathBuf> {
+    Ok(get_home_dir()?.join(".local/share/dotfiles-rust"))
+}
+
+// Resolves `.` and `..` components without touching the filesystem, for paths that may not exist
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
 pub fn get_backup_dir() -> Result<PathBuf> {
     let test_backup = TEST_BACKUP_DIR.with(|dir| dir.borrow().clone());
     
@@ -93,13 +167,431 @@ pub fn get_backup_dir() -> Result<PathBuf> {
     Ok(backup_dir)
 }
 
-#[cfg(test)]
-pub fn ensure_parent_dirs(path: &Path, dry_run: bool) -> Result<()> {
+pub fn ensure_parent_dirs(path: &Path, modes: &DirModes, dry_run: bool) -> Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.exists() && !dry_run {
+        create_dirs(parent, modes)?;
+    }
+    Ok(())
+}
+
+// Modes for the directories the tool creates below the home directory (or install target), from dir_mode
+// and dir_modes in the config. Without either, new directories get the default from the umask.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirModes {
+    root: PathBuf,
+    default: Option<u32>,
+    // Root-relative directories whose mode also goes to new directories below them; the innermost wins
+    paths: Vec<(PathBuf, u32)>,
+}
+
+impl DirModes {
+    pub fn new(root: &Path, default: Option<u32>, paths: Vec<(PathBuf, u32)>) -> Self {
+        Self { root: root.to_path_buf(), default, paths }
+    }
+
+    pub fn mode_for(&self, dir: &Path) -> Option<u32> {
+        let relative_dir = dir.strip_prefix(&self.root).ok()?;
+        self.paths.iter()
+            .filter(|(path, _)| relative_dir.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map(|(_, mode)| *mode)
+            .or(self.default)
+    }
+}
+
+pub const DEFAULT_SENSITIVE: &[&str] = &[".ssh/**", ".gnupg/**", ".netrc"];
+
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
+
+// Home-relative paths, from `sensitive` in the config, whose installed copies and backups are only
+// readable by their owner whatever the permissions in the source
+#[derive(Debug, Clone)]
+pub struct SensitivePaths {
+    matcher: PathMatcher,
+}
+
+impl SensitivePaths {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        Ok(Self { matcher: PathMatcher::new(patterns)? })
+    }
+
+    pub fn is_sensitive(&self, relative_path: &Path) -> bool {
+        self.matcher.is_match(relative_path)
+    }
+
+    // A directory is private when anything in it would be, as ~/.ssh is for `.ssh/**`
+    fn is_sensitive_dir(&self, relative_dir: &Path) -> bool {
+        !relative_dir.as_os_str().is_empty() && self.matcher.is_match(&relative_dir.join("*"))
+    }
+
+    // Gives a sensitive file below `root` mode 600 (400 if it is read-only), and 700 to the directories holding
+    // it that are sensitive too; `root` itself is left alone
+    pub fn restrict(&self, root: &Path, path: &Path) -> Result<()> {
+        let Ok(relative_path) = path.strip_prefix(root) else {
+            return Ok(());
+        };
+        if !self.is_sensitive(relative_path) {
+            return Ok(());
+        }
+
+        if let Some(mode) = file_mode(path) {
+            let private_mode = if mode & 0o200 == 0 { 0o400 } else { PRIVATE_FILE_MODE };
+            if mode != private_mode {
+                apply_mode(path, Some(private_mode))?;
+            }
+        }
+        for dir in relative_path.ancestors().skip(1).filter(|dir| self.is_sensitive_dir(dir)) {
+            if file_mode(&root.join(dir)).is_some_and(|mode| mode != PRIVATE_DIR_MODE) {
+                apply_mode(&root.join(dir), Some(PRIVATE_DIR_MODE))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Creates `dir` and its missing parents, each with its mode from `modes`; directories that exist are left alone
+pub fn create_dirs(dir: &Path, modes: &DirModes) -> Result<()> {
+    let missing = dir.ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect::<Vec<_>>();
+
+    for new_dir in missing.into_iter().rev() {
+        match as_home_owner(new_dir, || create_dir_with_mode(new_dir, modes.mode_for(new_dir))) {
+            Ok(()) => {},
+            // Created by something else in the meantime
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && new_dir.is_dir() => {},
+            Err(e) => return Err(e).with_context(|| format!("Failed to create directory {}", new_dir.display())),
+        }
+    }
+
+    Ok(())
+}
+
+// The mode is given at creation, so a private directory is never briefly readable, and set again afterwards
+// since the umask may have taken bits away
+#[cfg(unix)]
+fn create_dir_with_mode(dir: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let Some(mode) = mode else {
+        return fs::create_dir(dir);
+    };
+    fs::DirBuilder::new().mode(mode).create(dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn create_dir_with_mode(dir: &Path, _mode: Option<u32>) -> std::io::Result<()> {
+    fs::create_dir(dir)
+}
+
+// The account a home directory belongs to when it isn't the one running the tool, as for install --skel --all-users
+#[derive(Debug, Clone, PartialEq)]
+struct HomeOwner {
+    uid: u32,
+    gid: u32,
+    home: PathBuf,
+}
+
+// While it is held, this thread changes files below `home` with the uid and gid of the account it belongs to,
+// so symlinks planted in the home can't lead root anywhere the account couldn't write itself. New files and
+// directories are owned by the account from the start.
+pub struct WritingAs {
+    _private: (),
+}
+
+impl WritingAs {
+    pub fn owner(uid: u32, gid: u32, home: &Path) -> Self {
+        HOME_OWNER.with(|owner| *owner.borrow_mut() = Some(HomeOwner { uid, gid, home: home.to_path_buf() }));
+        Self { _private: () }
+    }
+}
+
+impl Drop for WritingAs {
+    fn drop(&mut self) {
+        HOME_OWNER.with(|owner| *owner.borrow_mut() = None);
+    }
+}
+
+fn home_owner_of(path: &Path) -> Option<HomeOwner> {
+    HOME_OWNER.with(|owner| owner.borrow().clone().filter(|owner| path.starts_with(&owner.home)))
+}
+
+// Makes a change to `path` as the owner of the home it is in (see WritingAs), or as whoever runs the tool
+pub fn as_home_owner<T, F>(path: &Path, change: F) -> std::io::Result<T>
+where
+    F: FnOnce() -> std::io::Result<T>,
+{
+    match home_owner_of(path) {
+        Some(owner) => {
+            let _identity = FsIdentity::assume(owner.uid, owner.gid)?;
+            change()
+        },
+        None => change(),
+    }
+}
+
+// The ids file access is checked against. setfsuid, setfsgid and the raw setgroups call only change the calling
+// thread, unlike seteuid, which glibc applies to the whole process.
+#[cfg(target_os = "linux")]
+struct FsIdentity {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+#[cfg(target_os = "linux")]
+impl FsIdentity {
+    fn assume(uid: u32, gid: u32) -> std::io::Result<Self> {
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut groups = vec![0; count as usize];
+        if unsafe { libc::getgroups(count, groups.as_mut_ptr()) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if unsafe { libc::syscall(libc::SYS_setgroups, 1, &gid as *const libc::gid_t) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // Both calls return the previous id whether or not they succeed, so a second call shows the outcome
+        let previous = Self {
+            gid: unsafe { libc::setfsgid(gid) } as libc::gid_t,
+            uid: unsafe { libc::setfsuid(uid) } as libc::uid_t,
+            groups,
+        };
+        if unsafe { libc::setfsgid(gid) } as u32 != gid || unsafe { libc::setfsuid(uid) } as u32 != uid {
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("Failed to switch to uid {} and gid {}", uid, gid)));
+        }
+        Ok(previous)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for FsIdentity {
+    fn drop(&mut self) {
+        unsafe {
+            libc::setfsuid(self.uid);
+            libc::setfsgid(self.gid);
+            libc::syscall(libc::SYS_setgroups, self.groups.len(), self.groups.as_ptr());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct FsIdentity;
+
+#[cfg(not(target_os = "linux"))]
+impl FsIdentity {
+    fn assume(_uid: u32, _gid: u32) -> std::io::Result<Self> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Writing as another user is only supported on Linux"))
+    }
+}
+
+// An exclusive advisory lock on a file, held until it is dropped; other processes taking the same lock wait for it
+pub struct FileLock {
+    _file: fs::File,
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+        // Closing the file releases the lock
+        file.lock()
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+        Ok(Self { _file: file })
+    }
+}
+
+// Temporary file next to `target`, on the same filesystem so it can be renamed over it
+fn temp_path_for(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.dotfiles-rust-{}.tmp", name, std::process::id()))
+}
+
+// The file a write to `target` replaces: the file a symlink points to, so links are written through
+fn replacement_path(target: &Path) -> PathBuf {
+    match target.symlink_metadata() {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf()),
+        _ => target.to_path_buf(),
+    }
+}
+
+// Writes the new content to a temporary file with `write` and renames it over `target`, so readers
+// never see a half-written file and a crash leaves either the old or the new one. The temporary file
+// is created with `permissions`, before any content is in it, so a private file is never readable by others.
+fn replace_file<F>(target: &Path, permissions: Option<fs::Permissions>, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut fs::File) -> std::io::Result<()>,
+{
+    as_home_owner(target, || replace_file_now(target, permissions, write))
+}
+
+fn replace_file_now<F>(target: &Path, permissions: Option<fs::Permissions>, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut fs::File) -> std::io::Result<()>,
+{
+    let target = replacement_path(target);
+    let temp_path = temp_path_for(&target);
+    let _ = fs::remove_file(&temp_path);
+
+    let result = create_temp_file(&temp_path, permissions.as_ref())
+        .and_then(|mut file| write(&mut file))
+        // The umask may have taken bits off when it was created
+        .and_then(|_| permissions.map_or(Ok(()), |permissions| fs::set_permissions(&temp_path, permissions)))
+        .and_then(|_| sync_file(&temp_path))
+        .and_then(|_| fs::rename(&temp_path, &target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn create_temp_file(path: &Path, permissions: Option<&fs::Permissions>) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(permissions.map_or(0o666, |permissions| permissions.mode() & 0o7777))
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_temp_file(path: &Path, _permissions: Option<&fs::Permissions>) -> std::io::Result<fs::File> {
+    fs::OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+// With `fsync` set, flushes a written file to disk; its directory is flushed too so a rename over it is kept
+fn sync_file(path: &Path) -> std::io::Result<()> {
+    if !io_options().fsync {
+        return Ok(());
+    }
+
+    fs::File::open(path)?.sync_all()?;
+    #[cfg(unix)]
     if let Some(parent) = path.parent() {
-        if !parent.exists() && !dry_run {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Clones `source` into `target` copy-on-write (FICLONE) when the filesystem supports it
+#[cfg(target_os = "linux")]
+fn clone_into(source: &fs::File, target: &fs::File) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_into(_source: &fs::File, _target: &fs::File) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+// Copies a file, with `mode` instead of the source's permissions when given. Files are cloned copy-on-write
+// on Linux when the filesystem supports it. Existing targets are replaced atomically.
+pub fn copy_file_with_mode(source: &Path, target: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    let permissions = match mode_permissions(mode) {
+        Some(permissions) => permissions,
+        None => fs::metadata(source)?.permissions(),
+    };
+    // The owner of another user's home may not be able to read the source, so it is opened first
+    let mut source = fs::File::open(source)?;
+
+    replace_file(target, Some(permissions), |temp_file| {
+        if clone_into(&source, temp_file).is_ok() {
+            return Ok(());
         }
+        match io_options().copy_buffer_size {
+            Some(buffer_size) => {
+                let mut reader = BufReader::with_capacity(buffer_size, &mut source);
+                let mut writer = BufWriter::with_capacity(buffer_size, temp_file);
+                std::io::copy(&mut reader, &mut writer)?;
+                writer.into_inner().map_err(|e| e.into_error())?;
+            },
+            None => {
+                std::io::copy(&mut source, temp_file)?;
+            },
+        }
+        Ok(())
+    })
+}
+
+// Copies a file with its permissions
+pub fn copy_file(source: &Path, target: &Path) -> std::io::Result<()> {
+    copy_file_with_mode(source, target, None)
+}
+
+// Atomically replaces `target` with `content`. Without `permissions` the replaced file keeps its own.
+pub fn write_file_atomic(target: &Path, content: &[u8], permissions: Option<fs::Permissions>) -> std::io::Result<()> {
+    let permissions = permissions.or_else(|| fs::metadata(target).ok().map(|metadata| metadata.permissions()));
+
+    replace_file(target, permissions, |temp_file| temp_file.write_all(content))
+}
+
+// Permissions with the given Unix mode, or None where modes don't apply
+#[cfg(unix)]
+pub fn mode_permissions(mode: Option<u32>) -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    mode.map(fs::Permissions::from_mode)
+}
+
+#[cfg(not(unix))]
+pub fn mode_permissions(_mode: Option<u32>) -> Option<fs::Permissions> {
+    None
+}
+
+// Unix permission bits, or None where they don't apply
+#[cfg(unix)]
+pub fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).ok().map(|metadata| metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+pub fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+// Adds execute permission wherever read permission is granted, e.g. 644 becomes 755
+pub fn with_executable_bits(mode: u32) -> u32 {
+    mode | ((mode & 0o444) >> 2)
+}
+
+#[cfg(unix)]
+pub fn ensure_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .with_context(|| format!("Failed to read permissions of {}", path.display()))?
+        .permissions()
+        .mode();
+    if with_executable_bits(mode) != mode {
+        as_home_owner(path, || fs::set_permissions(path, fs::Permissions::from_mode(with_executable_bits(mode))))
+            .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
     }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn ensure_executable(_path: &Path) -> Result<()> {
     Ok(())
-} 
\ No newline at end of file
+}
+
+pub fn is_on_path(dir: &Path) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+    env::split_paths(&path).any(|entry| entry == dir || entry.canonicalize().is_ok_and(|entry| entry == canonical))
+}
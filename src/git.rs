@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+
+pub fn is_git_repo(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+pub fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run git {} in {}", args.join(" "), dir.display()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git {} failed in {}: {}", args.join(" "), dir.display(), stderr.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+pub fn pull(dir: &Path) -> Result<String> {
+    run_git(dir, &["pull", "--ff-only"])
+}
+
+pub fn clone(url: &str, destination: &Path) -> Result<()> {
+    let status = Command::new("git")
+        .arg("clone")
+        .arg(url)
+        .arg(destination)
+        .status()
+        .with_context(|| format!("Failed to run git clone {}", url))?;
+
+    if !status.success() {
+        return Err(anyhow!("git clone {} into {} failed with {}", url, destination.display(), status));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoStatus {
+    pub branch: String,
+    // Paths from `git status --porcelain`, relative to the repository root
+    pub uncommitted: Vec<String>,
+    // Commits ahead of and behind the upstream branch, as of the last fetch
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+pub fn current_branch(dir: &Path) -> Result<String> {
+    run_git(dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+pub fn uncommitted_changes(dir: &Path) -> Result<Vec<String>> {
+    let output = run_git(dir, &["status", "--porcelain"])?;
+
+    Ok(output.lines()
+        .filter_map(|line| line.get(3..))
+        .map(String::from)
+        .collect())
+}
+
+// Returns None when the branch has no upstream configured
+pub fn ahead_behind(dir: &Path) -> Result<Option<(usize, usize)>> {
+    if run_git(dir, &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{upstream}"]).is_err() {
+        return Ok(None);
+    }
+
+    let output = run_git(dir, &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"])?;
+    let mut counts = output.split_whitespace().map(|count| count.parse::<usize>());
+
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some((ahead, behind))),
+        _ => Err(anyhow!("Unexpected output from git rev-list: {}", output)),
+    }
+}
+
+pub fn repo_status(dir: &Path) -> Result<RepoStatus> {
+    Ok(RepoStatus {
+        branch: current_branch(dir)?,
+        uncommitted: uncommitted_changes(dir)?,
+        ahead_behind: ahead_behind(dir)?,
+    })
+}
+
+pub fn head_commit(dir: &Path) -> Result<String> {
+    run_git(dir, &["rev-parse", "HEAD"])
+}
+
+pub fn short_commit(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+// Number of commits reachable from HEAD but not from `commit`
+pub fn commits_since(dir: &Path, commit: &str) -> Result<usize> {
+    let range = format!("{}..HEAD", commit);
+    let output = run_git(dir, &["rev-list", "--count", &range])?;
+
+    output.trim().parse()
+        .with_context(|| format!("Unexpected output from git rev-list: {}", output))
+}
+
+// Files under `dir` that differ from `commit`, including uncommitted and untracked ones, relative to `dir`
+pub fn changed_files_since(dir: &Path, commit: &str) -> Result<Vec<PathBuf>> {
+    let changed = run_git(dir, &["diff", "--name-only", "--relative", commit])?;
+    let untracked = run_git(dir, &["ls-files", "--others", "--exclude-standard"])?;
+
+    Ok(changed.lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+// Summarises changed paths for an automatic commit, e.g. "Update .bashrc, .vimrc and 2 other files"
+pub fn generate_commit_message(paths: &[String]) -> String {
+    const LISTED: usize = 3;
+
+    let names: Vec<&str> = paths.iter()
+        .map(|path| path.rsplit(" -> ").next().unwrap_or(path).trim_matches('"'))
+        .collect();
+
+    match names.len() {
+        0 => String::from("Update dotfiles"),
+        count if count <= LISTED => format!("Update {}", names.join(", ")),
+        count => {
+            let others = count - (LISTED - 1);
+            format!("Update {} and {} other files", names[..LISTED - 1].join(", "), others)
+        }
+    }
+}
+
+pub fn commit_all(dir: &Path, message: &str) -> Result<String> {
+    run_git(dir, &["add", "-A"])?;
+    run_git(dir, &["commit", "-q", "-m", message])
+}
+
+pub fn push(dir: &Path) -> Result<String> {
+    run_git(dir, &["push", "-q"])
+}
+
+// A detached checkout of one commit of the repository holding `dir`, leaving its working copy alone. It is
+// removed again when dropped.
+pub struct Worktree {
+    repo: PathBuf,
+    root: PathBuf,
+    // Where `dir` is inside the checkout
+    dir: PathBuf,
+}
+
+impl Worktree {
+    // Checks `revision` (a commit, tag, branch or expression like main~3) out at `root`
+    pub fn add(dir: &Path, revision: &str, root: &Path) -> Result<Self> {
+        let prefix = run_git(dir, &["rev-parse", "--show-prefix"])
+            .map_err(|_| anyhow!("{} is not in a git repository", dir.display()))?;
+        if revision.starts_with('-') {
+            return Err(anyhow!("'{}' is not a commit, tag or branch", revision));
+        }
+        let commit = run_git(dir, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", revision)])
+            .map_err(|_| anyhow!("'{}' is not a commit, tag or branch in {}", revision, dir.display()))?;
+
+        // A checkout left behind by an interrupted run is cleared out first
+        if root.exists() {
+            fs::remove_dir_all(root)
+                .with_context(|| format!("Failed to remove {}", root.display()))?;
+        }
+        run_git(dir, &["worktree", "prune"])?;
+        run_git(dir, &["worktree", "add", "-q", "--detach", &root.to_string_lossy(), &commit])?;
+
+        Ok(Self { repo: dir.to_path_buf(), root: root.to_path_buf(), dir: root.join(prefix) })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let _ = run_git(&self.repo, &["worktree", "remove", "--force", &self.root.to_string_lossy()]);
+    }
+}
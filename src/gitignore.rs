@@ -0,0 +1,42 @@
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::Gitignore;
+use walkdir::WalkDir;
+
+const GITIGNORE_FILE: &str = ".gitignore";
+
+// The rules of every .gitignore in a source tree, so build artifacts and caches kept in the repository
+// are never installed
+#[derive(Debug, Default)]
+pub struct GitIgnore {
+    source_dir: PathBuf,
+    // One matcher per .gitignore, deepest directory first since its rules win over its parents'
+    matchers: Vec<Gitignore>,
+}
+
+impl GitIgnore {
+    pub fn load(source_dir: &Path) -> Self {
+        let mut matchers = WalkDir::new(source_dir).sort_by_file_name().into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .flatten()
+            .filter(|entry| entry.file_type().is_file() && entry.file_name() == GITIGNORE_FILE)
+            // Lines git can't parse either are skipped, as git does
+            .map(|entry| Gitignore::new(entry.path()).0)
+            .collect::<Vec<_>>();
+
+        matchers.sort_by_key(|matcher| Reverse(matcher.path().components().count()));
+
+        Self { source_dir: source_dir.to_path_buf(), matchers }
+    }
+
+    pub fn is_ignored(&self, source_relative_path: &Path) -> bool {
+        let path = self.source_dir.join(source_relative_path);
+        let is_dir = path.is_dir();
+
+        self.matchers.iter()
+            .filter(|matcher| path.starts_with(matcher.path()))
+            .map(|matcher| matcher.matched_path_or_any_parents(&path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
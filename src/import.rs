@@ -0,0 +1,830 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde_yaml::Value;
+use walkdir::WalkDir;
+
+use crate::colorize;
+use crate::config::{get_config_path, write_config, Config};
+use crate::dir_config::DIR_CONFIG_FILE;
+use crate::fs_utils::{copy_file, get_home_dir, mode_permissions, normalize_path, PRIVATE_DIR_MODE};
+use crate::script::{script_path, SCRIPTS_DIR};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportFormat {
+    /// A chezmoi source directory (dot_ prefixes, .chezmoiignore)
+    Chezmoi,
+    /// A GNU stow directory with one package directory per application
+    Stow,
+    /// A dotbot install.conf.yaml, or the directory containing it
+    Dotbot,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ChezmoiAttributes {
+    pub executable: bool,
+    pub private: bool,
+    pub template: bool,
+}
+
+// Prefixes describing behavior this tool has no equivalent for
+const CHEZMOI_UNSUPPORTED_PREFIXES: &[(&str, &str)] = &[
+    ("run_", "script"),
+    ("modify_", "modify script"),
+    ("symlink_", "symlink"),
+    ("remove_", "removal entry"),
+    ("encrypted_", "encrypted file"),
+];
+
+pub fn parse_chezmoi_name(name: &str) -> std::result::Result<(String, ChezmoiAttributes), String> {
+    let mut attributes = ChezmoiAttributes::default();
+    let mut rest = name;
+
+    for (prefix, kind) in CHEZMOI_UNSUPPORTED_PREFIXES {
+        if rest.starts_with(prefix) {
+            return Err(format!("chezmoi {} is not supported", kind));
+        }
+    }
+
+    loop {
+        if let Some(stripped) = rest.strip_prefix("literal_") {
+            rest = stripped;
+            break;
+        } else if let Some(stripped) = rest.strip_prefix("private_") {
+            attributes.private = true;
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("executable_") {
+            attributes.executable = true;
+            rest = stripped;
+        } else if let Some(stripped) = ["create_", "readonly_", "empty_", "exact_", "external_"]
+            .iter()
+            .find_map(|prefix| rest.strip_prefix(prefix))
+        {
+            rest = stripped;
+        } else {
+            if let Some(stripped) = rest.strip_prefix("dot_") {
+                return finish_chezmoi_name(format!(".{}", stripped), attributes);
+            }
+            break;
+        }
+    }
+
+    finish_chezmoi_name(rest.to_string(), attributes)
+}
+
+fn finish_chezmoi_name(name: String, mut attributes: ChezmoiAttributes) -> std::result::Result<(String, ChezmoiAttributes), String> {
+    let name = if let Some(stripped) = name.strip_suffix(".literal") {
+        stripped.to_string()
+    } else if let Some(stripped) = name.strip_suffix(".tmpl") {
+        attributes.template = true;
+        stripped.to_string()
+    } else {
+        name
+    };
+
+    if name.is_empty() {
+        return Err(String::from("empty name after removing chezmoi attributes"));
+    }
+
+    Ok((name, attributes))
+}
+
+pub struct ChezmoiIgnore {
+    ignored: GlobSet,
+    included: GlobSet,
+    pub templated_lines: usize,
+}
+
+impl ChezmoiIgnore {
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut ignored = GlobSetBuilder::new();
+        let mut included = GlobSetBuilder::new();
+        let mut templated_lines = 0;
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Template conditionals can't be evaluated here, so those lines are reported instead
+            if line.contains("{{") {
+                templated_lines += 1;
+                continue;
+            }
+
+            let (builder, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (&mut included, pattern),
+                None => (&mut ignored, line),
+            };
+            builder.add(Glob::new(pattern.trim_end_matches('/'))
+                .with_context(|| format!("Invalid pattern '{}' in .chezmoiignore", pattern))?);
+        }
+
+        Ok(Self {
+            ignored: ignored.build()?,
+            included: included.build()?,
+            templated_lines,
+        })
+    }
+
+    pub fn is_ignored(&self, target: &Path) -> bool {
+        // A pattern matching a directory ignores everything below it
+        let ignored = target.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.ignored.is_match(ancestor));
+
+        ignored && !self.included.is_match(target)
+    }
+}
+
+fn find_chezmoi_root(source: &Path) -> Result<PathBuf> {
+    let root_file = source.join(".chezmoiroot");
+
+    if root_file.exists() {
+        let root = fs::read_to_string(&root_file)
+            .with_context(|| format!("Failed to read {}", root_file.display()))?;
+        return Ok(source.join(root.trim()));
+    }
+
+    Ok(source.to_path_buf())
+}
+
+#[cfg(unix)]
+fn apply_chezmoi_mode(path: &Path, attributes: &ChezmoiAttributes) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = match (attributes.executable, attributes.private) {
+        (true, true) => 0o700,
+        (true, false) => 0o755,
+        (false, true) => 0o600,
+        (false, false) => return Ok(()),
+    };
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn apply_chezmoi_mode(_path: &Path, _attributes: &ChezmoiAttributes) -> Result<()> {
+    Ok(())
+}
+
+// Imports a chezmoi source directory, returning the home-relative directories chezmoi keeps private
+fn import_chezmoi(source: &Path, output: &Path, dry_run: bool, force: bool) -> Result<BTreeSet<PathBuf>> {
+    let root = find_chezmoi_root(source)?;
+
+    let ignore_path = root.join(".chezmoiignore");
+    let ignore = if ignore_path.exists() {
+        let content = fs::read_to_string(&ignore_path)
+            .with_context(|| format!("Failed to read {}", ignore_path.display()))?;
+        Some(ChezmoiIgnore::parse(&content)?)
+    } else {
+        None
+    };
+
+    if let Some(ignore) = &ignore
+        && ignore.templated_lines > 0 {
+        println!("  {} {} templated line(s) in .chezmoiignore were not evaluated",
+            colorize::warning("Warning:"),
+            colorize::highlight(ignore.templated_lines));
+    }
+
+    let mut imported_count = 0;
+    let mut ignored_count = 0;
+    let mut skipped_count = 0;
+    let mut private_dirs = BTreeSet::new();
+
+    for entry in WalkDir::new(&root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            // chezmoi's own metadata (.chezmoiignore, .chezmoiscripts, ...) and VCS data are not dotfiles
+            let name = e.file_name().to_string_lossy();
+            !name.starts_with(".chezmoi") && name != ".git"
+        })
+        .filter_map(|e| e.ok())
+    {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative_path = entry.path().strip_prefix(&root)?;
+        let mut target = PathBuf::new();
+        let mut attributes = ChezmoiAttributes::default();
+        // Directories on the way to the file with private_ in their names, like private_dot_ssh/
+        let mut file_private_dirs = Vec::new();
+        let mut unsupported = None;
+
+        for component in relative_path.iter() {
+            match parse_chezmoi_name(&component.to_string_lossy()) {
+                Ok((name, component_attributes)) => {
+                    if attributes.private {
+                        file_private_dirs.push(target.clone());
+                    }
+                    target.push(name);
+                    attributes = component_attributes;
+                },
+                Err(reason) => {
+                    unsupported = Some(reason);
+                    break;
+                }
+            }
+        }
+
+        if let Some(reason) = unsupported {
+            println!("  {} {} ({})", colorize::warning("Skipped:"), colorize::path(relative_path.display()), reason);
+            skipped_count += 1;
+            continue;
+        }
+
+        if attributes.template {
+            println!("  {} {} (templates must be converted by hand)",
+                colorize::warning("Skipped:"),
+                colorize::path(relative_path.display()));
+            skipped_count += 1;
+            continue;
+        }
+
+        if ignore.as_ref().is_some_and(|ignore| ignore.is_ignored(&target)) {
+            println!("  {} {}", colorize::info("Ignored:"), colorize::path(target.display()));
+            ignored_count += 1;
+            continue;
+        }
+
+        let output_path = output.join(&target);
+
+        if output_path.exists() && !force {
+            println!("  {} {} (already exists, use --force to overwrite)",
+                colorize::warning("Skipped:"),
+                colorize::path(target.display()));
+            skipped_count += 1;
+            continue;
+        }
+
+        if dry_run {
+            println!("  {} {} => {}",
+                colorize::dry_run("[Dry run] Would import"),
+                colorize::path(relative_path.display()),
+                colorize::path(target.display()));
+        } else {
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+            }
+            copy_file(entry.path(), &output_path)
+                .with_context(|| format!("Failed to copy {} to {}", entry.path().display(), output_path.display()))?;
+            apply_chezmoi_mode(&output_path, &attributes)?;
+
+            println!("  {} {} => {}",
+                colorize::success("Imported:"),
+                colorize::path(relative_path.display()),
+                colorize::path(target.display()));
+        }
+        private_dirs.extend(file_private_dirs);
+        imported_count += 1;
+    }
+
+    if !dry_run {
+        for dir in &private_dirs {
+            let output_dir = output.join(dir);
+            if let Some(permissions) = mode_permissions(Some(PRIVATE_DIR_MODE)) {
+                fs::set_permissions(&output_dir, permissions)
+                    .with_context(|| format!("Failed to set permissions on {}", output_dir.display()))?;
+            }
+        }
+    }
+
+    println!("\n{}", colorize::header("Summary:"));
+    println!("  {} {}", colorize::success("Files imported:"), colorize::highlight(imported_count));
+    println!("  {} {}", colorize::info("Files ignored:"), colorize::highlight(ignored_count));
+    println!("  {} {}", colorize::warning("Files skipped:"), colorize::highlight(skipped_count));
+
+    Ok(private_dirs)
+}
+
+// Files stow never links, following its default ignore list
+fn is_stow_ignored(package_relative_path: &Path) -> bool {
+    let file_name = package_relative_path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let at_package_root = package_relative_path.components().count() == 1;
+
+    matches!(file_name.as_str(), ".stow-local-ignore" | ".gitignore" | ".gitmodules" | ".cvsignore")
+        || package_relative_path.components().any(|c| matches!(c.as_os_str().to_str(), Some(".git" | ".hg" | ".svn" | "CVS" | "RCS")))
+        || file_name.ends_with('~')
+        || (at_package_root && ["README", "LICENSE", "COPYING"].iter().any(|name| file_name.starts_with(name)))
+}
+
+fn import_stow(source: &Path, output: &Path, dry_run: bool, force: bool) -> Result<()> {
+    let mut imported_count = 0;
+    let mut skipped_count = 0;
+    let mut imported_targets: Vec<PathBuf> = Vec::new();
+
+    let mut packages = fs::read_dir(source)
+        .with_context(|| format!("Failed to read {}", source.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')))
+        .collect::<Vec<_>>();
+    packages.sort();
+
+    for package in packages {
+        let package_name = package.file_name().unwrap_or_default().to_string_lossy().to_string();
+        println!("{} {}", colorize::info("Package:"), colorize::highlight(&package_name));
+
+        for entry in WalkDir::new(&package)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let target = entry.path().strip_prefix(&package)?.to_path_buf();
+
+            if is_stow_ignored(&target) {
+                continue;
+            }
+
+            // Two packages providing the same file would conflict under stow as well
+            if imported_targets.contains(&target) {
+                println!("  {} {} (already provided by another package)",
+                    colorize::warning("Skipped:"),
+                    colorize::path(target.display()));
+                skipped_count += 1;
+                continue;
+            }
+
+            let output_path = output.join(&target);
+
+            if output_path.exists() && !force {
+                println!("  {} {} (already exists, use --force to overwrite)",
+                    colorize::warning("Skipped:"),
+                    colorize::path(target.display()));
+                skipped_count += 1;
+                continue;
+            }
+
+            if dry_run {
+                println!("  {} {}", colorize::dry_run("[Dry run] Would import"), colorize::path(target.display()));
+            } else {
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+                }
+                copy_file(entry.path(), &output_path)
+                    .with_context(|| format!("Failed to copy {} to {}", entry.path().display(), output_path.display()))?;
+                println!("  {} {}", colorize::success("Imported:"), colorize::path(target.display()));
+            }
+
+            imported_targets.push(target);
+            imported_count += 1;
+        }
+    }
+
+    println!("\n{}", colorize::header("Summary:"));
+    println!("  {} {}", colorize::success("Files imported:"), colorize::highlight(imported_count));
+    println!("  {} {}", colorize::warning("Files skipped:"), colorize::highlight(skipped_count));
+    println!("{}", colorize::info("Tip: set 'layout: stow' in the config to install from the stow directory without converting it."));
+
+    Ok(())
+}
+
+// Config file names dotbot's install script looks for, in order
+const DOTBOT_CONFIG_FILES: &[&str] = &["install.conf.yaml", "install.conf.yml", "install.conf.json"];
+// Links to absolute paths outside home are imported below this directory, which is mapped back to / by a
+// directory config
+const DOTBOT_ROOT_DIR: &str = "root";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotbotLink {
+    // Link location as written in the config, e.g. ~/.vimrc
+    pub target: String,
+    // File or directory relative to the config's directory; a glob pattern when `glob` is set
+    pub path: String,
+    pub glob: bool,
+    // Shell condition from `if:`, which can't be evaluated at import time
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotbotCommand {
+    pub command: String,
+    pub description: Option<String>,
+}
+
+// The directives of a dotbot config that have an equivalent here
+#[derive(Debug, Default, PartialEq)]
+pub struct DotbotConfig {
+    pub links: Vec<DotbotLink>,
+    // Directories to create, with an optional octal mode
+    pub create: Vec<(String, Option<u32>)>,
+    pub shell: Vec<DotbotCommand>,
+    // Directives with no equivalent, such as clean or plugin directives
+    pub unsupported: Vec<String>,
+}
+
+fn yaml_str(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// Dotbot writes modes as YAML integers, which read 0700 as decimal 700; both spellings mean octal here
+fn yaml_mode(value: &Value) -> Option<u32> {
+    let digits = yaml_str(value)?;
+    u32::from_str_radix(digits.trim_start_matches("0o"), 8).ok()
+}
+
+fn parse_dotbot_link(target: &str, value: &Value, defaults: &Value) -> Result<DotbotLink> {
+    let option = |name: &str| value.get(name).or_else(|| defaults.get(name));
+
+    // A link without a path uses the target's file name without its leading dot, e.g. ~/.vimrc -> vimrc
+    let inferred_path = || {
+        let name = Path::new(target).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        name.trim_start_matches('.').to_string()
+    };
+
+    let path = match value {
+        Value::Null => inferred_path(),
+        Value::String(path) => path.clone(),
+        Value::Mapping(_) => value.get("path").and_then(yaml_str).unwrap_or_else(inferred_path),
+        _ => return Err(anyhow!("link for '{}' must be a path or a mapping", target)),
+    };
+
+    Ok(DotbotLink {
+        target: target.to_string(),
+        path,
+        glob: option("glob").and_then(Value::as_bool).unwrap_or(false),
+        condition: option("if").and_then(yaml_str),
+    })
+}
+
+pub fn parse_dotbot_config(content: &str) -> Result<DotbotConfig> {
+    let tasks = serde_yaml::from_str::<Option<Vec<Value>>>(content)
+        .with_context(|| "Expected a list of dotbot tasks")?
+        .unwrap_or_default();
+    let mut config = DotbotConfig::default();
+    let mut link_defaults = Value::Null;
+
+    for task in &tasks {
+        let Value::Mapping(directives) = task else {
+            return Err(anyhow!("Each dotbot task must be a mapping of directives"));
+        };
+
+        for (directive, value) in directives {
+            let directive = yaml_str(directive).unwrap_or_default();
+
+            match (directive.as_str(), value) {
+                ("defaults", _) => {
+                    link_defaults = value.get("link").cloned().unwrap_or(Value::Null);
+                },
+                ("link", Value::Mapping(links)) => {
+                    for (target, link) in links {
+                        let target = yaml_str(target).ok_or_else(|| anyhow!("link targets must be strings"))?;
+                        config.links.push(parse_dotbot_link(&target, link, &link_defaults)?);
+                    }
+                },
+                ("create", Value::Sequence(paths)) => {
+                    config.create.extend(paths.iter().filter_map(yaml_str).map(|path| (path, None)));
+                },
+                ("create", Value::Mapping(paths)) => {
+                    for (path, options) in paths {
+                        let Some(path) = yaml_str(path) else {
+                            continue;
+                        };
+                        config.create.push((path, options.get("mode").and_then(yaml_mode)));
+                    }
+                },
+                ("shell", Value::Sequence(commands)) => {
+                    for command in commands {
+                        // A command is a string, [command, description] or a mapping with command/description
+                        let (command, description) = match command {
+                            Value::Sequence(parts) => (parts.first().and_then(yaml_str), parts.get(1).and_then(yaml_str)),
+                            Value::Mapping(_) => (command.get("command").and_then(yaml_str), command.get("description").and_then(yaml_str)),
+                            other => (yaml_str(other), None),
+                        };
+                        if let Some(command) = command {
+                            config.shell.push(DotbotCommand { command, description });
+                        }
+                    }
+                },
+                (other, _) => config.unsupported.push(other.to_string()),
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+fn rhai_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn shell_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("\"$HOME\"/{}", shell_quote(rest)),
+        None => shell_quote(path),
+    }
+}
+
+// A post-install hook doing the work of dotbot's create and shell directives. Commands run from the source
+// directory, like dotbot runs them from the config's directory, and a failing command fails the hook.
+pub fn dotbot_hook_script(config: &DotbotConfig) -> Option<String> {
+    if config.create.is_empty() && config.shell.is_empty() {
+        return None;
+    }
+
+    let mut commands = Vec::new();
+    for (path, mode) in &config.create {
+        let mut command = format!("mkdir -p {}", shell_path(path));
+        if let Some(mode) = mode {
+            command.push_str(&format!(" && chmod {:o} {}", mode, shell_path(path)));
+        }
+        commands.push((command, None));
+    }
+    commands.extend(config.shell.iter().map(|shell| (shell.command.clone(), shell.description.clone())));
+
+    // The source directory is quoted for the shell when the hook runs, since only then is it known
+    let mut script = String::from("// Converted from dotbot's create and shell directives\nlet source_dir = config().source_dir;\nsource_dir.replace(\"'\", \"'\\\\''\");\n");
+    for (command, description) in commands {
+        script.push('\n');
+        if let Some(description) = description {
+            script.push_str(&format!("// {}\n", description));
+        }
+        script.push_str(&format!("let result = run(\"cd '\" + source_dir + \"' && \" + {});\n", rhai_string(&command)));
+        script.push_str(&format!("if result.code != 0 {{ throw {} + \" failed: \" + result.stderr; }}\n", rhai_string(&command)));
+    }
+
+    Some(script)
+}
+
+fn find_dotbot_config(source: &Path) -> Result<PathBuf> {
+    if source.is_file() {
+        return Ok(source.to_path_buf());
+    }
+
+    DOTBOT_CONFIG_FILES.iter()
+        .map(|name| source.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("No {} found in {}", DOTBOT_CONFIG_FILES.join(" or "), source.display()))
+}
+
+// Where a link target lands in the output tree: home-relative, or below the root mapping for absolute paths
+fn dotbot_output_target(target: &str, home_dir: &Path) -> Result<PathBuf> {
+    let path = Path::new(target);
+    let relative_path = match target.strip_prefix("~/") {
+        Some(rest) => Path::new(rest),
+        None if path.is_absolute() => {
+            let path = normalize_path(path);
+            return Ok(match path.strip_prefix(home_dir) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => Path::new(DOTBOT_ROOT_DIR).join(path.strip_prefix("/").unwrap_or(&path)),
+            });
+        },
+        None => path,
+    };
+
+    // Relative targets are relative to the home directory, and `..` may not lead out of it
+    let mut normalized = PathBuf::new();
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir if !normalized.pop() => return Err(anyhow!("it is outside the home directory")),
+            _ => {},
+        }
+    }
+    Ok(normalized)
+}
+
+// The files a link brings in, as (file in the dotbot repo, path relative to the link target)
+fn dotbot_link_files(base_dir: &Path, link: &DotbotLink) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let files_below = |source: &Path, prefix: &Path| -> Vec<(PathBuf, PathBuf)> {
+        if source.is_file() {
+            return vec![(source.to_path_buf(), prefix.to_path_buf())];
+        }
+        WalkDir::new(source)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| Some((e.path().to_path_buf(), prefix.join(e.path().strip_prefix(source).ok()?))))
+            .collect()
+    };
+
+    if !link.glob {
+        return Ok(files_below(&base_dir.join(&link.path), Path::new("")));
+    }
+
+    // With glob, the target is a directory and every match is linked into it by name
+    let pattern = GlobBuilder::new(&link.path)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob '{}'", link.path))?
+        .compile_matcher();
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(base_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+        .filter_map(|e| e.ok())
+    {
+        let relative_path = entry.path().strip_prefix(base_dir)?;
+        if pattern.is_match(relative_path) {
+            files.extend(files_below(entry.path(), Path::new(&entry.file_name())));
+        }
+    }
+
+    Ok(files)
+}
+
+fn write_import_file(output_path: &Path, content: Option<&Path>, text: &str) -> Result<()> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    match content {
+        Some(source) => copy_file(source, output_path)
+            .with_context(|| format!("Failed to copy {} to {}", source.display(), output_path.display())),
+        None => fs::write(output_path, text)
+            .with_context(|| format!("Failed to write {}", output_path.display())),
+    }
+}
+
+fn import_dotbot(source: &Path, output: &Path, dry_run: bool, force: bool) -> Result<()> {
+    let config_path = find_dotbot_config(source)?;
+    let base_dir = config_path.parent().unwrap_or(Path::new("."));
+    let content = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let config = parse_dotbot_config(&content)
+        .with_context(|| format!("Invalid dotbot config {}", config_path.display()))?;
+    let home_dir = get_home_dir()?;
+
+    let mut imported_count = 0;
+    let mut skipped_count = 0;
+    let mut outside_home = false;
+
+    for link in &config.links {
+        if let Some(condition) = &link.condition {
+            println!("  {} {} is imported unconditionally (dotbot condition '{}' is not evaluated)",
+                colorize::warning("Warning:"),
+                colorize::path(&link.target),
+                condition);
+        }
+
+        let files = dotbot_link_files(base_dir, link)?;
+        if files.is_empty() {
+            println!("  {} {} (nothing matches {})", colorize::warning("Skipped:"), colorize::path(&link.target), link.path);
+            skipped_count += 1;
+            continue;
+        }
+
+        let target_dir = match dotbot_output_target(&link.target, &home_dir) {
+            Ok(target_dir) => target_dir,
+            Err(e) => {
+                println!("  {} {} ({})", colorize::warning("Skipped:"), colorize::path(&link.target), e);
+                skipped_count += 1;
+                continue;
+            },
+        };
+        outside_home |= target_dir.starts_with(DOTBOT_ROOT_DIR) && Path::new(&link.target).is_absolute();
+
+        for (file, inner) in files {
+            let target = if inner.as_os_str().is_empty() { target_dir.clone() } else { target_dir.join(&inner) };
+            let output_path = output.join(&target);
+            let relative_source = file.strip_prefix(base_dir).unwrap_or(&file);
+
+            if output_path.exists() && !force {
+                println!("  {} {} (already exists, use --force to overwrite)",
+                    colorize::warning("Skipped:"),
+                    colorize::path(target.display()));
+                skipped_count += 1;
+                continue;
+            }
+
+            if dry_run {
+                println!("  {} {} => {}",
+                    colorize::dry_run("[Dry run] Would import"),
+                    colorize::path(relative_source.display()),
+                    colorize::path(target.display()));
+            } else {
+                write_import_file(&output_path, Some(&file), "")?;
+                println!("  {} {} => {}",
+                    colorize::success("Imported:"),
+                    colorize::path(relative_source.display()),
+                    colorize::path(target.display()));
+            }
+            imported_count += 1;
+        }
+    }
+
+    let mut generated = Vec::new();
+    if outside_home {
+        generated.push((output.join(DOTBOT_ROOT_DIR).join(DIR_CONFIG_FILE), String::from("# Links to absolute paths, installed with install --apply-system\ntarget: /\n")));
+    }
+    if let Some(script) = dotbot_hook_script(&config) {
+        generated.push((script_path(output, "post-install"), script));
+    }
+
+    for (path, text) in generated {
+        let display_path = path.strip_prefix(output).unwrap_or(&path).to_path_buf();
+
+        if path.exists() && !force {
+            println!("  {} {} (already exists, use --force to overwrite)", colorize::warning("Skipped:"), colorize::path(display_path.display()));
+        } else if dry_run {
+            println!("  {} {}", colorize::dry_run("[Dry run] Would write"), colorize::path(display_path.display()));
+        } else {
+            write_import_file(&path, None, &text)?;
+            println!("  {} {}", colorize::success("Wrote:"), colorize::path(display_path.display()));
+        }
+    }
+
+    for directive in &config.unsupported {
+        let hint = if directive == "clean" { " (run 'dotfiles-rust clean' to remove dangling symlinks)" } else { "" };
+        println!("  {} dotbot directive '{}' has no equivalent and was skipped{}", colorize::warning("Warning:"), directive, hint);
+    }
+
+    println!("\n{}", colorize::header("Summary:"));
+    println!("  {} {}", colorize::success("Files imported:"), colorize::highlight(imported_count));
+    println!("  {} {}", colorize::warning("Files skipped:"), colorize::highlight(skipped_count));
+    if !config.create.is_empty() || !config.shell.is_empty() {
+        println!("  {} {}/post-install.rhai", colorize::info("Create and shell directives run from"), SCRIPTS_DIR);
+    }
+
+    Ok(())
+}
+
+pub fn import_dotfiles(format: ImportFormat, source: &Path, output: &Path, dry_run: bool, force: bool) -> Result<()> {
+    // Dotbot is pointed at its config file; the other formats at a directory
+    let source_is_config = format == ImportFormat::Dotbot && source.is_file();
+    if !source.is_dir() && !source_is_config {
+        return Err(anyhow!("Import source '{}' is not a directory", source.display()));
+    }
+
+    println!("{} {} {} {}",
+        colorize::header("Importing"),
+        colorize::path(source.display()),
+        colorize::header("into"),
+        colorize::path(output.display()));
+
+    let private_dirs = match format {
+        ImportFormat::Chezmoi => import_chezmoi(source, output, dry_run, force)?,
+        ImportFormat::Stow => {
+            import_stow(source, output, dry_run, force)?;
+            BTreeSet::new()
+        },
+        ImportFormat::Dotbot => {
+            import_dotbot(source, output, dry_run, force)?;
+            BTreeSet::new()
+        },
+    };
+    // Private directories are created with mode 700 on install, as chezmoi would
+    let dir_modes = private_dirs.iter()
+        .map(|dir| (dir.to_string_lossy().to_string(), format!("{:o}", PRIVATE_DIR_MODE)))
+        .collect::<BTreeMap<_, _>>();
+
+    if dry_run {
+        println!("{}", colorize::dry_run("Dry run - no files were written"));
+        return Ok(());
+    }
+
+    if !get_config_path()?.exists() {
+        let output_dir = output.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", output.display()))?;
+        write_config(&Config {
+            source_dir: output_dir.to_string_lossy().to_string(),
+            dir_modes,
+            ..Default::default()
+        })?;
+        println!("{} {}", colorize::success("Configuration file created with source directory"), colorize::path(output_dir.display()));
+    } else {
+        println!("{} dotfiles-rust init --source-dir {}",
+            colorize::info("To install from the imported tree, run:"),
+            output.display());
+        if !dir_modes.is_empty() {
+            println!("{}", colorize::info("Add the private directories to dir_modes in your config:"));
+            println!("dir_modes:");
+            for (dir, mode) in &dir_modes {
+                println!("  {}: \"{}\"", dir, mode);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::colorize;
+use crate::commands::{build_exclude_set, is_excluded, managed_files};
+use crate::config::{read_config, Config};
+use crate::template::{self, is_template};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LintIssue {
+    // The source file can't be read, or a template isn't text
+    Unreadable { error: String },
+    UndefinedVariables { names: Vec<String> },
+    // More than one source file installs to the same place
+    SameTarget { target: PathBuf, sources: Vec<PathBuf> },
+    // The source installs a file where another source needs a directory
+    TargetInsideFile { target: PathBuf, file: PathBuf },
+    UnusedIgnore { pattern: String },
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintIssue::Unreadable { error } => write!(f, "can't be read: {}", error),
+            LintIssue::UndefinedVariables { names } => write!(f, "undefined template variable(s): {}", names.join(", ")),
+            LintIssue::SameTarget { target, sources } => {
+                let sources = sources.iter().map(|source| source.display().to_string()).collect::<Vec<_>>();
+                write!(f, "~/{} is installed from each of {}", target.display(), sources.join(", "))
+            },
+            LintIssue::TargetInsideFile { target, file } => {
+                write!(f, "~/{} is inside ~/{}, which another source installs as a file", target.display(), file.display())
+            },
+            LintIssue::UnusedIgnore { pattern } => write!(f, "ignore pattern '{}' matches no managed file", pattern),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintProblem {
+    // Source-relative path of the file with the problem; the config for unused ignore patterns
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub issue: LintIssue,
+}
+
+// Problems that would make an install fail or do something other than intended, in source path order
+pub fn lint_source(config: &Config, vars: &BTreeMap<String, String>) -> Result<Vec<LintProblem>> {
+    let source_dir = Path::new(&config.source_dir);
+    let mut files = managed_files(source_dir, config.layout, Path::new(""))?;
+    let mut problems = Vec::new();
+
+    for pattern in &config.ignore {
+        let matcher = build_exclude_set(std::slice::from_ref(pattern))?;
+        if !files.iter().any(|file| is_excluded(&matcher, &file.relative_path)) {
+            problems.push(LintProblem { path: PathBuf::from("config"), issue: LintIssue::UnusedIgnore { pattern: pattern.clone() } });
+        }
+    }
+    let ignore = build_exclude_set(&config.ignore)?;
+    files.retain(|file| !is_excluded(&ignore, &file.relative_path));
+
+    let mut sources_by_target = BTreeMap::<&Path, Vec<PathBuf>>::new();
+    for file in &files {
+        sources_by_target.entry(&file.relative_path).or_default()
+            .push(file.source.strip_prefix(source_dir)?.to_path_buf());
+    }
+    let targets = sources_by_target.keys().copied().collect::<BTreeSet<_>>();
+
+    for file in &files {
+        let path = file.source.strip_prefix(source_dir)?.to_path_buf();
+        let sources = &sources_by_target[file.relative_path.as_path()];
+        if sources.len() > 1 && sources[0] == path {
+            problems.push(LintProblem { path: path.clone(), issue: LintIssue::SameTarget { target: file.relative_path.clone(), sources: sources.clone() } });
+        }
+        if let Some(parent) = file.relative_path.ancestors().skip(1).find(|ancestor| targets.contains(ancestor)) {
+            problems.push(LintProblem { path: path.clone(), issue: LintIssue::TargetInsideFile { target: file.relative_path.clone(), file: parent.to_path_buf() } });
+        }
+
+        if let Some(issue) = content_issue(&file.source, vars) {
+            problems.push(LintProblem { path, issue });
+        }
+    }
+
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(problems)
+}
+
+// Whether a source file can be read and, for templates and fragment directories, rendered with `vars`
+fn content_issue(source_path: &Path, vars: &BTreeMap<String, String>) -> Option<LintIssue> {
+    if !source_path.is_dir() && !is_template(source_path) {
+        return fs::File::open(source_path).err()
+            .map(|e| LintIssue::Unreadable { error: e.to_string() });
+    }
+
+    match template::missing_variables(source_path, vars) {
+        Ok(names) if names.is_empty() => None,
+        Ok(names) => Some(LintIssue::UndefinedVariables { names }),
+        Err(e) => Some(LintIssue::Unreadable { error: format!("{:#}", e) }),
+    }
+}
+
+pub fn lint(json: bool) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let vars = template::template_vars(&config, &[])?;
+    let problems = lint_source(&config, &vars)?;
+
+    if json {
+        let output = serde_json::to_string_pretty(&problems)
+            .with_context(|| "Failed to serialize lint problems")?;
+        println!("{}", output);
+    } else if problems.is_empty() {
+        println!("{} {}", colorize::success("No problems found in"), colorize::path(source_dir.display()));
+    } else {
+        println!("{} {}", colorize::header("Problems in"), colorize::path(source_dir.display()));
+        for problem in &problems {
+            println!("  {} {}: {}", colorize::error(colorize::cross()), colorize::path(problem.path.display()), problem.issue);
+        }
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        count => Err(anyhow!("Lint found {} problem(s)", count)),
+    }
+}
@@ -1,44 +1,241 @@
 mod cli;
 mod fs_utils;
 mod backup;
+mod blocks;
 mod commands;
+mod completion;
 mod config;
 mod colorize;
+mod update;
+mod suggest;
+mod table;
+mod git;
+mod daemon;
+mod notification;
+mod webhook;
+mod import;
+mod export;
+mod manifest;
+mod metrics;
+mod matcher;
+mod summary;
+mod report;
+mod verify;
+mod lint;
+mod diff;
+mod difftool;
+mod template;
+mod fragments;
+mod patch;
+mod fleet;
+mod signing;
+mod skel;
+mod archive;
+mod dir_config;
+mod gitignore;
+mod script;
+mod shell_init;
+mod privileged;
+mod pager;
+mod prompt;
+mod wizard;
 #[cfg(test)]
 mod tests;
 
-use anyhow::Result;
-use clap::Parser;
-use crate::cli::{Cli, Args};
-use crate::commands::{list_backups, clear_backups, uninstall_dotfiles};
-use crate::config::initialize_config;
-use colored;
-
-fn main() -> Result<()> {
-    colored::control::set_override(true);
-    let cli = Cli::parse();
-    
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Result};
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use crate::cli::{Cli, Args, ConfigAction, DaemonAction, ManifestAction};
+use crate::commands::{list_backups, clear_backups, uninstall_dotfiles, InstallOptions, PartialFailure, RestoreOptions, StatusOptions, PARTIAL_FAILURE_EXIT_CODE};
+use crate::config::{initialize_config, FlagDefaults};
+use crate::prompt::AnswerMode;
+
+// Commands whose output can run to hundreds of lines. Install is only paged when verbose and when it
+// won't stop to ask for confirmation.
+fn uses_pager(command: &Args) -> bool {
+    match command {
+        Args::Install { verbose, apply_system, prune, force, json, .. } => *verbose && !*json && !*apply_system && (!*prune || *force),
+        Args::Status { fix, .. } => !*fix,
+        Args::Files { .. } | Args::Diff { .. } | Args::Backups { .. } => true,
+        _ => false,
+    }
+}
+
+// Flags that weren't given on the command line, either way, take their value from `defaults` in the config
+fn apply_flag_defaults(command: &mut Args, defaults: &FlagDefaults) {
+    let resolve = |flag: &mut bool, negated: bool, default: bool| *flag = *flag || (!negated && default);
+
+    match command {
+        Args::Install { force, no_force, backup, no_backup, verbose, .. } => {
+            resolve(force, *no_force, defaults.force);
+            resolve(backup, *no_backup, defaults.backup.unwrap_or(true));
+            resolve(verbose, false, defaults.verbose);
+        },
+        Args::Uninstall { force, no_force, verbose, keep_backups, no_keep_backups, .. } => {
+            resolve(force, *no_force, defaults.force);
+            resolve(verbose, false, defaults.verbose);
+            resolve(keep_backups, *no_keep_backups, defaults.keep_backups);
+        },
+        Args::Restore { keep_backups, no_keep_backups, .. } => resolve(keep_backups, *no_keep_backups, defaults.keep_backups),
+        Args::Status { verbose, .. } => resolve(verbose, false, defaults.verbose),
+        _ => {},
+    }
+}
+
+fn main() -> ExitCode {
+    // The shell calls back into the binary with COMPLETE set to ask for completions
+    CompleteEnv::with_factory(Cli::command).complete();
+
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            // Some files were installed, which scripts may want to tell apart from nothing happening
+            if e.is::<PartialFailure>() {
+                ExitCode::from(PARTIAL_FAILURE_EXIT_CODE)
+            } else {
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+fn run() -> Result<()> {
+    let mut cli = Cli::parse();
+    colorize::set_plain(cli.plain);
+    fs_utils::set_io_options(config::read_io_options(cli.jobs.map(usize::from)));
+    let answer_mode = if cli.assume_yes { AnswerMode::AssumeYes } else if cli.assume_no { AnswerMode::AssumeNo } else { AnswerMode::Ask };
+    prompt::set_answers(answer_mode, &cli.answers);
+
+    apply_flag_defaults(&mut cli.command, &config::read_flag_defaults());
+
+    // The file to compare is picked before the pager takes over the terminal
+    if let Args::Diff { paths } = &mut cli.command && paths.is_empty() {
+        paths.extend(commands::pick_managed_file("File to compare", Some("Every managed file"))?);
+    }
+    let _pager = pager::start_pager(!cli.no_pager && uses_pager(&cli.command));
+
     match cli.command {
-        Args::Install { dry_run, force, backup, verbose } => {
-            commands::install_dotfiles(dry_run, force, backup, verbose)
+        Args::Install { dry_run, force, backup, verbose, prune, paths, excludes, changed_only, since, git_ref, require_signed, set_vars, apply_system, target, skel, all_users, force_readonly, adopt, force_local, keep_going, json, .. } => {
+            let options = InstallOptions { dry_run, force, backup, verbose, prune, paths, excludes, changed_only, since, git_ref, require_signed, set_vars, apply_system, target, force_readonly, adopt, force_local, keep_going, ..Default::default() };
+            let result = if skel {
+                skel::install_skel(&options, all_users)
+            } else if json {
+                commands::install_as_json(&options)
+            } else {
+                commands::install_and_report(&options)
+            };
+            if !dry_run {
+                webhook::report_run("install", &result);
+            }
+            result
         },
-        Args::Uninstall { dry_run, force, verbose } => {
-            uninstall_dotfiles(dry_run, force, verbose)
+        Args::Uninstall { dry_run, force, verbose, keep_backups, .. } => {
+            uninstall_dotfiles(dry_run, force, verbose, keep_backups)
         },
-        Args::Init { source_dir } => {
+        Args::Init { source_dir, from_url } => {
+            if let Some(url) = &from_url
+                && !archive::is_archive_url(url) {
+                return Err(anyhow!("{} is not an http(s) URL ending in .tar.gz, .tgz, .tar or .zip", url));
+            }
+            // Bare `init` at a terminal walks through the setup instead of assuming the current directory
+            if source_dir.is_none() && from_url.is_none()
+                && std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+                return wizard::run_wizard();
+            }
+            let source_dir = from_url.or(source_dir).unwrap_or_else(|| String::from("."));
             println!("{} {}", colorize::info("Initializing config with source directory:"), colorize::path(&source_dir));
             initialize_config(&source_dir)?;
             println!("{} {}", colorize::success("Configuration file created at"), colorize::path("~/.dotfiles-rustrc.yaml"));
             Ok(())
         },
-        Args::Backups { file } => {
-            list_backups(file.as_deref())
+        Args::Backups { file, show } => match (file, show) {
+            (Some(file), Some(version)) => commands::show_backup(&file, &version),
+            (file, _) => list_backups(file.as_deref()),
+        },
+        Args::Prune { redundant: _, dry_run } => {
+            commands::prune_redundant_backups(dry_run)
         },
         Args::Reset { force } => {
             clear_backups(force)
         },
-        Args::Status { verbose } => {
-            commands::status_dotfiles(verbose)
+        Args::Restore { file, files, version, stdout: true, .. } => match file.into_iter().chain(files).collect::<Vec<_>>().as_slice() {
+            [file] => commands::show_backup(file, version.as_deref().unwrap_or("latest")),
+            _ => Err(anyhow!("--stdout writes a single backup, so it takes one file")),
+        },
+        Args::Restore { file, files, version, preview, dry_run, apply_system, keep_backups, overwrite_newer, stdout: false, .. } => {
+            let files = file.into_iter().chain(files).collect();
+            let result = commands::restore_backups(&RestoreOptions { files, version, preview, dry_run, apply_system, keep_backups, overwrite_newer, ..Default::default() });
+            if !dry_run {
+                webhook::report_run("restore", &result);
+            }
+            result
+        },
+        Args::Status { verbose, extras, fix, changed_only, metrics } => {
+            commands::status_dotfiles(&StatusOptions { verbose, extras, fix, changed_only, metrics })
+        },
+        Args::Verify { checksums } => {
+            verify::verify_dotfiles(checksums.as_deref().map(Path::new))
+        },
+        Args::Edit { file, no_install } => {
+            commands::edit_dotfile(file.as_deref(), no_install)
+        },
+        Args::Files { json } => {
+            commands::list_managed_files(json)
+        },
+        Args::Lint { json } => {
+            lint::lint(json)
+        },
+        Args::Diff { paths } => {
+            commands::diff_dotfiles(&paths)
+        },
+        Args::Which { path, reverse } => {
+            commands::which_dotfile(&path, reverse)
+        },
+        Args::Render { file, vars } => {
+            commands::render_dotfile(&file, &vars)
+        },
+        Args::Clean { dry_run } => {
+            commands::clean_broken_symlinks(dry_run)
+        },
+        Args::Push { message, dry_run } => {
+            commands::push_source(message.as_deref(), dry_run)
+        },
+        Args::Fleet => {
+            fleet::show_fleet()
+        },
+        Args::Import { from, source, output, dry_run, force } => {
+            import::import_dotfiles(from, Path::new(&source), Path::new(&output), dry_run, force)
+        },
+        Args::Export { format, output, dry_run, force } => {
+            export::export_dotfiles(format, Path::new(&output), dry_run, force)
+        },
+        Args::Daemon { action, interval, once, force } => match action {
+            Some(DaemonAction::InstallUnit { interval, force, dry_run }) => {
+                daemon::install_unit(interval, force, dry_run)
+            },
+            None => daemon::run_daemon(interval, once, force),
+        },
+        Args::Manifest { action } => match action {
+            ManifestAction::Sign => signing::sign_source(),
+            ManifestAction::Verify { key } => signing::verify_source(key.as_deref()),
+        },
+        Args::Config { action } => match action {
+            ConfigAction::Validate => commands::validate_config(),
+        },
+        Args::SelfUpdate { check, force } => {
+            update::self_update(check, force)
+        },
+        Args::Completions { shell } => {
+            commands::generate_completions(shell)
+        },
+        Args::ShellInit { shell, check, no_aliases } => {
+            print!("{}", shell_init::render_shell_init(shell, check, !no_aliases));
+            Ok(())
         }
     }
 } 
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::diff::file_hash;
+use crate::fs_utils::get_data_dir;
+
+// Records which files in the home directory were put there by install, so they can be cleaned up later
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    // Source repository commit of the last complete install, when the source is a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    // Unix time the last install finished without failures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<i64>,
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    // Path of the source file, relative to the source directory
+    pub source: PathBuf,
+    pub installed_at: i64,
+    // sha256 of the file as installed, to tell local edits apart from an older version of the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+pub fn get_manifest_path() -> Result<PathBuf> {
+    Ok(get_data_dir()?.join("manifest.yaml"))
+}
+
+impl Manifest {
+    pub fn load() -> Result<Self> {
+        let manifest_path = get_manifest_path()?;
+
+        if !manifest_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read manifest at {}", manifest_path.display()))?;
+        let manifest = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest at {}", manifest_path.display()))?;
+
+        Ok(manifest)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let manifest_path = get_manifest_path()?;
+
+        if let Some(parent) = manifest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let content = serde_yaml::to_string(self)
+            .with_context(|| "Failed to serialize manifest")?;
+        fs::write(&manifest_path, content)
+            .with_context(|| format!("Failed to write manifest at {}", manifest_path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn record(&mut self, target_relative_path: &Path, source_relative_path: &Path, target_path: &Path) {
+        self.files.insert(target_relative_path.to_path_buf(), ManifestEntry {
+            source: source_relative_path.to_path_buf(),
+            installed_at: chrono::Utc::now().timestamp(),
+            hash: file_hash(target_path).ok(),
+        });
+    }
+
+    // Whether the installed file was edited since install wrote it. None when that isn't known, for
+    // files installed before hashes were recorded or not installed by this tool at all.
+    pub fn is_locally_modified(&self, target_relative_path: &Path, target_path: &Path) -> Option<bool> {
+        let recorded = self.files.get(target_relative_path)?.hash.as_deref()?;
+        Some(file_hash(target_path).ok().as_deref() != Some(recorded))
+    }
+
+    pub fn remove(&mut self, target_relative_path: &Path) {
+        self.files.remove(target_relative_path);
+    }
+
+    // Installed files whose source no longer exists
+    pub fn stale_entries(&self, managed: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        self.files.keys()
+            .filter(|target| !managed.contains(*target))
+            .cloned()
+            .collect()
+    }
+}
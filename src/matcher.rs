@@ -0,0 +1,49 @@
+use std::path::{Component, Path};
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+// Matches relative paths against gitignore-style glob patterns, used for the built-in blacklist and for
+// user ignore/exclude patterns alike:
+// - a pattern without a slash, such as `README.md` or `*.swp`, matches a file or directory name at any depth
+// - a pattern with a slash, such as `.config/*/cache` or `/.gitignore`, matches from the root of the tree
+// - a trailing slash is ignored, and a matching directory matches everything below it
+#[derive(Debug, Clone)]
+pub struct PathMatcher {
+    names: GlobSet,
+    anchored: GlobSet,
+}
+
+impl PathMatcher {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut anchored = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let trimmed = pattern.trim_end_matches('/');
+            let (builder, glob) = if trimmed.contains('/') {
+                (&mut anchored, trimmed.trim_start_matches('/'))
+            } else {
+                (&mut names, trimmed)
+            };
+
+            builder.add(GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid pattern '{}'", pattern))?);
+        }
+
+        Ok(Self { names: names.build()?, anchored: anchored.build()? })
+    }
+
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        let name_matches = relative_path.components().any(|component| match component {
+            Component::Normal(name) => self.names.is_match(name),
+            _ => false,
+        });
+
+        name_matches || relative_path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.anchored.is_match(ancestor))
+    }
+}
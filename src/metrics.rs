@@ -0,0 +1,82 @@
+use std::fmt::Write;
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::commands::StatusCounts;
+use crate::fs_utils::write_file_atomic;
+
+// Health of the dotfiles on this machine, for monitoring. Blocks and patches count as managed files.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    pub managed_files: usize,
+    pub ok_files: usize,
+    pub modified_files: usize,
+    pub missing_files: usize,
+    pub extra_files: usize,
+    // Unix time the last install finished without failures
+    pub last_install_timestamp: Option<i64>,
+    pub backup_files: usize,
+    pub backup_bytes: u64,
+}
+
+impl Metrics {
+    pub fn new(counts: &StatusCounts, last_install_timestamp: Option<i64>, (backup_files, backup_bytes): (usize, u64)) -> Self {
+        Self {
+            managed_files: counts.ok + counts.modified + counts.missing,
+            ok_files: counts.ok,
+            modified_files: counts.modified,
+            missing_files: counts.missing,
+            extra_files: counts.extra,
+            last_install_timestamp,
+            backup_files,
+            backup_bytes,
+        }
+    }
+}
+
+fn write_metric(output: &mut String, name: &str, help: &str, samples: &[(&str, String)]) {
+    let _ = writeln!(output, "# HELP dotfiles_rust_{} {}", name, help);
+    let _ = writeln!(output, "# TYPE dotfiles_rust_{} gauge", name);
+    for (labels, value) in samples {
+        let _ = writeln!(output, "dotfiles_rust_{}{} {}", name, labels, value);
+    }
+}
+
+// The metrics in the Prometheus text format read by node_exporter's textfile collector
+pub fn render_prometheus(metrics: &Metrics) -> String {
+    let mut output = String::new();
+
+    write_metric(&mut output, "managed_files", "Files, blocks and patches managed by dotfiles-rust.",
+        &[("", metrics.managed_files.to_string())]);
+    write_metric(&mut output, "files", "Managed files by state.", &[
+        ("{state=\"ok\"}", metrics.ok_files.to_string()),
+        ("{state=\"modified\"}", metrics.modified_files.to_string()),
+        ("{state=\"missing\"}", metrics.missing_files.to_string()),
+    ]);
+    write_metric(&mut output, "drifted_files", "Managed files that are modified or missing.",
+        &[("", (metrics.modified_files + metrics.missing_files).to_string())]);
+    write_metric(&mut output, "extra_files", "Unmanaged files in directories that hold managed files.",
+        &[("", metrics.extra_files.to_string())]);
+    if let Some(timestamp) = metrics.last_install_timestamp {
+        write_metric(&mut output, "last_install_timestamp_seconds", "When the last install finished without failures.",
+            &[("", timestamp.to_string())]);
+    }
+    write_metric(&mut output, "backup_files", "Backup versions kept.", &[("", metrics.backup_files.to_string())]);
+    write_metric(&mut output, "backup_bytes", "Size of the kept backups.", &[("", metrics.backup_bytes.to_string())]);
+
+    output
+}
+
+// Writes the metrics to `path` as JSON when it ends in .json, otherwise as a Prometheus textfile. The file is
+// replaced in one step, so a collector never reads half of it.
+pub fn write_metrics(path: &Path, metrics: &Metrics) -> Result<()> {
+    let content = if path.extension().is_some_and(|extension| extension == "json") {
+        serde_json::to_string_pretty(metrics).with_context(|| "Failed to serialize metrics")? + "\n"
+    } else {
+        render_prometheus(metrics)
+    };
+
+    write_file_atomic(path, content.as_bytes(), None)
+        .with_context(|| format!("Failed to write metrics to {}", path.display()))
+}
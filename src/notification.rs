@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use notify_rust::Notification;
+
+use crate::colorize;
+use crate::fs_utils::{get_data_dir, write_file_atomic};
+
+const APP_NAME: &str = "dotfiles-rust";
+
+// Drifted files the last notification was about, so the same drift isn't reported on every run
+const NOTIFIED_DRIFT_FILE: &str = "notified_drift.yaml";
+
+// Files named in a notification before the rest are only counted
+const LISTED_FILES: usize = 5;
+
+fn list_files<'a>(files: impl ExactSizeIterator<Item = &'a PathBuf>) -> String {
+    let count = files.len();
+    let mut names = files.take(LISTED_FILES).map(|path| path.display().to_string()).collect::<Vec<_>>();
+    if count > LISTED_FILES {
+        names.push(format!("and {} more", count - LISTED_FILES));
+    }
+    names.join(", ")
+}
+
+// Title and body telling of one daemon run: files its install brought back in line with the source, and
+// files that differ from the source and weren't reported last time. None when there is nothing new.
+pub fn drift_message(applied: &BTreeSet<PathBuf>, drifted: &BTreeSet<PathBuf>, notified: &BTreeSet<PathBuf>) -> Option<(String, String)> {
+    let new_drift = drifted.difference(notified).collect::<BTreeSet<_>>();
+    let mut lines = Vec::new();
+
+    if !applied.is_empty() {
+        lines.push(format!("Installed {} file(s): {}", applied.len(), list_files(applied.iter())));
+    }
+    if !new_drift.is_empty() {
+        lines.push(format!("{} file(s) differ from the source: {}", new_drift.len(), list_files(new_drift.into_iter())));
+    }
+
+    let title = if applied.is_empty() { "Dotfiles drifted" } else { "Dotfiles updated" };
+    (!lines.is_empty()).then(|| (String::from(title), lines.join("\n")))
+}
+
+fn load_notified_drift(path: &Path) -> BTreeSet<PathBuf> {
+    fs::read_to_string(path).ok()
+        .and_then(|content| serde_yaml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// Sends a desktop notification about the files a daemon run changed or found drifted. Desktops without a
+// notification service only get a warning in the log, since the run itself went fine.
+pub fn notify_drift(before: &BTreeSet<PathBuf>, after: &BTreeSet<PathBuf>) -> Result<()> {
+    let state_path = get_data_dir()?.join(NOTIFIED_DRIFT_FILE);
+    let applied = before.difference(after).cloned().collect();
+
+    if let Some((title, body)) = drift_message(&applied, after, &load_notified_drift(&state_path))
+        && let Err(e) = Notification::new().appname(APP_NAME).summary(&title).body(&body).show() {
+        println!("{} {}", colorize::warning("Failed to send a desktop notification:"), e);
+    }
+
+    if let Some(parent) = state_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let content = serde_yaml::to_string(after)
+        .with_context(|| "Failed to serialize notified drift")?;
+    write_file_atomic(&state_path, content.as_bytes(), None)
+        .with_context(|| format!("Failed to write {}", state_path.display()))
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const DEFAULT_PAGER: &str = "less";
+
+// Passed to less unless LESS is already set: keep colors, exit when the output fits on one screen and
+// leave the output on the terminal afterwards, the same defaults git uses
+const DEFAULT_LESS_FLAGS: &str = "FRX";
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Whether standard output currently goes to a pager; prompts can't be answered while it does
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// $PAGER, or less. An empty PAGER or "cat" turns paging off.
+pub fn pager_command(pager_var: Option<&str>) -> Option<String> {
+    match pager_var.map(str::trim) {
+        Some("") | Some("cat") => None,
+        Some(command) => Some(command.to_string()),
+        None => Some(DEFAULT_PAGER.to_string()),
+    }
+}
+
+// Sends standard output through the pager until dropped
+#[cfg(unix)]
+pub struct Pager {
+    child: std::process::Child,
+    relay: Option<std::thread::JoinHandle<()>>,
+    saved_stdout: std::os::fd::OwnedFd,
+}
+
+#[cfg(not(unix))]
+pub struct Pager;
+
+// Starts the pager when standard output is a terminal. Returns None, leaving output unchanged, when
+// paging is off or the pager can't be started.
+pub fn start_pager(enabled: bool) -> Option<Pager> {
+    if !enabled || !io::stdout().is_terminal() {
+        return None;
+    }
+
+    let command = pager_command(env::var("PAGER").ok().as_deref())?;
+    let pager = spawn_pager(&command)?;
+    ACTIVE.store(true, Ordering::Relaxed);
+    Some(pager)
+}
+
+#[cfg(unix)]
+fn spawn_pager(command: &str) -> Option<Pager> {
+    use std::fs::File;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::process::{Command, Stdio};
+
+    let mut parts = command.split_whitespace();
+    let program = parts.next()?;
+
+    let mut child = Command::new(program)
+        .args(parts)
+        .env("LESS", env::var("LESS").unwrap_or_else(|_| DEFAULT_LESS_FLAGS.to_string()))
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut pager_input = child.stdin.take()?;
+
+    // Output goes through a pipe we own rather than straight to the pager, so quitting the pager early
+    // never turns later writes into broken pipe errors in the middle of a command
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        let _ = child.kill();
+        return None;
+    }
+    let (read_end, write_end) = unsafe { (File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    let _ = io::stdout().flush();
+    let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if saved_stdout < 0 || unsafe { libc::dup2(write_end.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        let _ = child.kill();
+        return None;
+    }
+    drop(write_end);
+
+    let relay = std::thread::spawn(move || {
+        let mut read_end = read_end;
+        if io::copy(&mut read_end, &mut pager_input).is_err() {
+            let _ = io::copy(&mut read_end, &mut io::sink());
+        }
+    });
+
+    Some(Pager {
+        child,
+        relay: Some(relay),
+        saved_stdout: unsafe { OwnedFd::from_raw_fd(saved_stdout) },
+    })
+}
+
+#[cfg(not(unix))]
+fn spawn_pager(_command: &str) -> Option<Pager> {
+    None
+}
+
+#[cfg(unix)]
+impl Drop for Pager {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        // Restoring the terminal closes the pipe, which ends the relay and lets the pager see the end of input
+        let _ = io::stdout().flush();
+        unsafe { libc::dup2(self.saved_stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+        if let Some(relay) = self.relay.take() {
+            let _ = relay.join();
+        }
+        let _ = self.child.wait();
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+// Sends standard output to standard error until dropped, so a command's JSON is the only thing on standard output
+#[cfg(unix)]
+pub struct StdoutToStderr {
+    saved_stdout: std::os::fd::OwnedFd,
+}
+
+#[cfg(not(unix))]
+pub struct StdoutToStderr;
+
+#[cfg(unix)]
+pub fn stdout_to_stderr() -> Option<StdoutToStderr> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let _ = io::stdout().flush();
+    let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if saved_stdout < 0 {
+        return None;
+    }
+    let saved_stdout = unsafe { OwnedFd::from_raw_fd(saved_stdout) };
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return None;
+    }
+
+    Some(StdoutToStderr { saved_stdout })
+}
+
+#[cfg(not(unix))]
+pub fn stdout_to_stderr() -> Option<StdoutToStderr> {
+    None
+}
+
+#[cfg(unix)]
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        let _ = io::stdout().flush();
+        unsafe { libc::dup2(self.saved_stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+
+pub const PATCH_EXTENSION: &str = "patch";
+
+// Source files ending in .patch are applied on top of the target instead of replacing it
+pub fn is_patch(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == PATCH_EXTENSION)
+}
+
+pub fn read_patch(path: &Path) -> Result<Patch> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read patch {}", path.display()))?;
+    Patch::parse(&text)
+        .with_context(|| format!("Invalid patch {}", path.display()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hunk {
+    // Zero-based lines the hunk starts at before and after the change
+    old_start: usize,
+    new_start: usize,
+    // Lines as they are before and after the change, each with its line ending
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+// A unified diff against a single file, as written by `diff -u` or `git diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchState {
+    // The target already holds the change
+    Applied,
+    // The change can be applied
+    Unapplied,
+    // Neither the change nor its reverse fits the target
+    Conflict,
+}
+
+// Zero-based start and length from a hunk range such as "-12,5" or "+3". An empty range names the
+// line after which lines are inserted, so it already is the zero-based start.
+fn parse_range(range: Option<&str>, sign: char) -> Option<(usize, usize)> {
+    let range = range?.strip_prefix(sign)?;
+    let (start, length) = match range.split_once(',') {
+        Some((start, length)) => (start.parse::<usize>().ok()?, length.parse().ok()?),
+        None => (range.parse().ok()?, 1),
+    };
+    Some((if length == 0 { start } else { start.saturating_sub(1) }, length))
+}
+
+impl Patch {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut hunks: Vec<Hunk> = Vec::new();
+        // Lines still expected on each side of the current hunk; anything past them is a header
+        let mut old_remaining = 0;
+        let mut new_remaining = 0;
+        // Which side the previous line belonged to, for "\ No newline at end of file"
+        let mut last_kind = ' ';
+
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            if line.starts_with('\\') {
+                // "\ No newline at end of file" applies to the line before it
+                if let Some(hunk) = hunks.last_mut() {
+                    if last_kind != '+' && let Some(last) = hunk.old.last_mut() {
+                        last.pop();
+                    }
+                    if last_kind != '-' && let Some(last) = hunk.new.last_mut() {
+                        last.pop();
+                    }
+                }
+                continue;
+            }
+
+            if old_remaining == 0 && new_remaining == 0 {
+                if let Some(header) = line.strip_prefix("@@ ") {
+                    let mut ranges = header.split_whitespace();
+                    let (Some((old_start, old_length)), Some((new_start, new_length))) =
+                        (parse_range(ranges.next(), '-'), parse_range(ranges.next(), '+')) else {
+                        return Err(anyhow!("line {}: malformed hunk header", index + 1));
+                    };
+                    hunks.push(Hunk { old_start, new_start, old: Vec::new(), new: Vec::new() });
+                    (old_remaining, new_remaining) = (old_length, new_length);
+                }
+                continue;
+            }
+
+            let Some(hunk) = hunks.last_mut() else {
+                continue;
+            };
+            // An empty context line whose leading space was trimmed by an editor
+            let (kind, content) = match line.chars().next() {
+                Some(kind @ (' ' | '-' | '+')) => (kind, &line[1..]),
+                Some('\n' | '\r') => (' ', line),
+                _ => return Err(anyhow!("line {}: unexpected line inside a hunk", index + 1)),
+            };
+
+            if kind != '+' {
+                hunk.old.push(content.to_string());
+                old_remaining = old_remaining.saturating_sub(1);
+            }
+            if kind != '-' {
+                hunk.new.push(content.to_string());
+                new_remaining = new_remaining.saturating_sub(1);
+            }
+            last_kind = kind;
+        }
+
+        if hunks.is_empty() {
+            return Err(anyhow!("no hunks found"));
+        }
+        if old_remaining > 0 || new_remaining > 0 {
+            return Err(anyhow!("the last hunk is cut short"));
+        }
+
+        Ok(Self { hunks })
+    }
+
+    // The patch that undoes this one
+    pub fn reversed(&self) -> Self {
+        let hunks = self.hunks.iter()
+            .map(|hunk| Hunk {
+                old_start: hunk.new_start,
+                new_start: hunk.old_start,
+                old: hunk.new.clone(),
+                new: hunk.old.clone(),
+            })
+            .collect();
+        Self { hunks }
+    }
+
+    // The text with every hunk applied. A hunk whose context has moved is found at the nearest offset,
+    // but its lines must match exactly.
+    pub fn apply(&self, text: &str) -> Result<String> {
+        let lines = text.split_inclusive('\n').collect::<Vec<_>>();
+        let mut output: Vec<&str> = Vec::new();
+        let mut position = 0;
+
+        for (index, hunk) in self.hunks.iter().enumerate() {
+            let start = find_hunk(&lines, &hunk.old, position, hunk.old_start)
+                .ok_or_else(|| anyhow!("hunk {} does not match", index + 1))?;
+
+            output.extend(&lines[position..start]);
+            output.extend(hunk.new.iter().map(String::as_str));
+            position = start + hunk.old.len();
+        }
+
+        output.extend(&lines[position..]);
+        Ok(output.concat())
+    }
+
+    pub fn state(&self, text: &str) -> PatchState {
+        // Checked first: a hunk that only adds lines still matches its context after it was applied
+        if self.reversed().apply(text).is_ok() {
+            PatchState::Applied
+        } else if self.apply(text).is_ok() {
+            PatchState::Unapplied
+        } else {
+            PatchState::Conflict
+        }
+    }
+}
+
+// Where `old` occurs in `lines` at or after `from`, preferring the match closest to `expected`
+fn find_hunk(lines: &[&str], old: &[String], from: usize, expected: usize) -> Option<usize> {
+    let matches_at = |start: usize| {
+        start + old.len() <= lines.len() && lines[start..start + old.len()].iter().zip(old).all(|(line, old)| *line == old)
+    };
+
+    (from..=lines.len().saturating_sub(old.len()))
+        .filter(|&start| matches_at(start))
+        .min_by_key(|&start| start.abs_diff(expected))
+}
@@ -0,0 +1,93 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+
+use crate::fs_utils::{file_mode, get_data_dir, PRIVATE_FILE_MODE};
+
+pub const DEFAULT_ESCALATION_COMMAND: &str = "sudo";
+
+// Targets outside the home directory, e.g. a directory config with `target: /etc/nginx`
+pub fn is_privileged(target_path: &Path, home_dir: &Path) -> bool {
+    !target_path.starts_with(home_dir)
+}
+
+fn run_escalated(escalation: &str, args: &[&str]) -> Result<()> {
+    // Commands such as "doas" or "pkexec --disable-internal-agent" are split like the editor command
+    let mut parts = escalation.split_whitespace();
+    let program = parts.next()
+        .ok_or_else(|| anyhow!("Escalation command is empty"))?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run '{}'", escalation))?;
+
+    if !status.success() {
+        return Err(anyhow!("'{} {}' exited with {}", escalation, args.join(" "), status));
+    }
+
+    Ok(())
+}
+
+// Writes a file as root. Only this write is escalated; rendered content is staged in the data directory first.
+pub fn escalated_write(escalation: &str, source_path: &Path, content: Option<&[u8]>, target_path: &Path, mode: Option<u32>) -> Result<()> {
+    let staged_path = content.map(stage_content).transpose()?;
+    let copy_from = staged_path.as_deref().unwrap_or(source_path);
+    // A new file would take the staged file's private mode from `cp`, so it gets its source's mode instead
+    let mode = match mode {
+        None if staged_path.is_some() && !target_path.exists() => file_mode(source_path),
+        mode => mode,
+    };
+
+    let target = target_path.to_string_lossy();
+    let result = (|| {
+        if let Some(parent) = target_path.parent() {
+            run_escalated(escalation, &["mkdir", "-p", &parent.to_string_lossy()])?;
+        }
+        run_escalated(escalation, &["cp", &copy_from.to_string_lossy(), &target])?;
+        if let Some(mode) = mode {
+            run_escalated(escalation, &["chmod", &format!("{:o}", mode), &target])?;
+        }
+        Ok(())
+    })();
+
+    if let Some(staged_path) = &staged_path {
+        let _ = fs::remove_file(staged_path);
+    }
+
+    result
+}
+
+// Writes content for an escalated write to a file of its own, readable only by the user. It is created new, so
+// nothing already at its path, such as a symlink, is written through.
+fn stage_content(content: &[u8]) -> Result<PathBuf> {
+    let data_dir = get_data_dir()?;
+    fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create directory {}", data_dir.display()))?;
+
+    for counter in 0.. {
+        let staged_path = data_dir.join(format!("privileged.{}.{}.staged", std::process::id(), counter));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(PRIVATE_FILE_MODE);
+        }
+
+        let mut file = match options.open(&staged_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to create {}", staged_path.display())),
+        };
+        if let Err(e) = file.write_all(content) {
+            let _ = fs::remove_file(&staged_path);
+            return Err(e).with_context(|| format!("Failed to write {}", staged_path.display()));
+        }
+        return Ok(staged_path);
+    }
+    unreachable!("some counter is free")
+}
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use anyhow::{anyhow, Result};
+use dialoguer::{Confirm, FuzzySelect, Input, MultiSelect, Select};
+
+use crate::pager;
+
+// How questions without an injected answer are answered
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AnswerMode {
+    // Ask at the terminal
+    #[default]
+    Ask,
+    // --assume-yes: go ahead with every action
+    AssumeYes,
+    // --assume-no: leave everything as it is
+    AssumeNo,
+}
+
+#[derive(Debug, Default)]
+struct Answers {
+    mode: AnswerMode,
+    // Answers from --answers, by prompt key
+    given: BTreeMap<String, String>,
+}
+
+thread_local! {
+    static ANSWERS: RefCell<Answers> = RefCell::new(Answers::default());
+}
+
+// Sets how the prompts of this run are answered; tests use it to drive prompts without a terminal
+pub fn set_answers(mode: AnswerMode, given: &[(String, String)]) {
+    ANSWERS.with(|answers| {
+        *answers.borrow_mut() = Answers { mode, given: given.iter().cloned().collect() };
+    });
+}
+
+fn given_answer(key: &str) -> Option<String> {
+    ANSWERS.with(|answers| answers.borrow().given.get(key).cloned())
+}
+
+fn answer_mode() -> AnswerMode {
+    ANSWERS.with(|answers| answers.borrow().mode)
+}
+
+fn can_ask() -> bool {
+    io::stdin().is_terminal() && !pager::is_active()
+}
+
+fn unanswered(key: &str) -> anyhow::Error {
+    anyhow!("No answer for the '{}' prompt without a terminal; pass --answers {}=<answer>, --assume-yes or --assume-no", key, key)
+}
+
+fn parse_yes_no(key: &str, answer: &str) -> Result<bool> {
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" | "true" => Ok(true),
+        "n" | "no" | "false" => Ok(false),
+        _ => Err(anyhow!("'{}' is not a yes/no answer for the '{}' prompt", answer, key)),
+    }
+}
+
+// A yes/no question. `key` names it for --answers, e.g. --answers reset=yes.
+pub fn confirm(key: &str, question: &str, default: bool) -> Result<bool> {
+    if let Some(answer) = given_answer(key) {
+        return parse_yes_no(key, &answer);
+    }
+
+    match answer_mode() {
+        AnswerMode::AssumeYes => Ok(true),
+        AnswerMode::AssumeNo => Ok(false),
+        AnswerMode::Ask if can_ask() => Confirm::new()
+            .with_prompt(question)
+            .default(default)
+            .interact()
+            .map_err(|e| anyhow!("Failed to read an answer: {}", e)),
+        AnswerMode::Ask => Err(unanswered(key)),
+    }
+}
+
+// Picks one of `choices`. The first choice is the one --assume-yes takes and `default` the one
+// --assume-no takes, so the first should go ahead and the default should leave things alone.
+// An injected answer names a choice or its first letter.
+pub fn choose(key: &str, question: &str, choices: &[&str], default: usize) -> Result<usize> {
+    if let Some(answer) = given_answer(key) {
+        let answer = answer.trim().to_lowercase();
+        return choices.iter()
+            .position(|choice| *choice == answer)
+            .or_else(|| choices.iter().position(|choice| choice.chars().next().is_some_and(|first| answer == first.to_string())))
+            .ok_or_else(|| anyhow!("'{}' is not one of {} for the '{}' prompt", answer, choices.join(", "), key));
+    }
+
+    match answer_mode() {
+        AnswerMode::AssumeYes => Ok(0),
+        AnswerMode::AssumeNo => Ok(default),
+        AnswerMode::Ask if can_ask() => Select::new()
+            .with_prompt(question)
+            .items(choices)
+            .default(default)
+            .interact()
+            .map_err(|e| anyhow!("Failed to read an answer: {}", e)),
+        AnswerMode::Ask => Err(unanswered(key)),
+    }
+}
+
+// A free-form answer. --assume-yes and --assume-no can't supply one, so it comes from --answers or the terminal.
+pub fn input(key: &str, question: &str) -> Result<String> {
+    if let Some(answer) = given_answer(key) {
+        return Ok(answer);
+    }
+    if !can_ask() {
+        return Err(unanswered(key));
+    }
+
+    Input::<String>::new()
+        .with_prompt(question)
+        .allow_empty(true)
+        .interact_text()
+        .map_err(|e| anyhow!("Failed to read an answer: {}", e))
+}
+
+// Fuzzy-finds one of `items` at the terminal, for commands run without naming a file. None when there is
+// no terminal or the answers are assumed, so the command goes on as it would in a script.
+pub fn pick(question: &str, items: &[String]) -> Result<Option<usize>> {
+    if answer_mode() != AnswerMode::Ask || !can_ask() || items.is_empty() {
+        return Ok(None);
+    }
+
+    FuzzySelect::new()
+        .with_prompt(question)
+        .items(items)
+        .default(0)
+        .interact_opt()
+        .map_err(|e| anyhow!("Failed to read an answer: {}", e))?
+        .map(Some)
+        .ok_or_else(|| anyhow!("Nothing was picked"))
+}
+
+// A checklist of `items`, all ticked to begin with, returning the indices left ticked. None like `pick`.
+pub fn checklist(question: &str, items: &[String]) -> Result<Option<Vec<usize>>> {
+    if answer_mode() != AnswerMode::Ask || !can_ask() || items.is_empty() {
+        return Ok(None);
+    }
+
+    MultiSelect::new()
+        .with_prompt(format!("{} (space toggles, enter confirms)", question))
+        .items(items)
+        .defaults(&vec![true; items.len()])
+        .interact_opt()
+        .map_err(|e| anyhow!("Failed to read an answer: {}", e))?
+        .map(Some)
+        .ok_or_else(|| anyhow!("Nothing was picked"))
+}
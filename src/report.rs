@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::colorize;
+use crate::commands::PartialFailure;
+use crate::summary::{format_bytes, DryRunSummary};
+
+// What install did with one source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallOutcome {
+    // Written to its target, or would be in a dry run
+    Copied,
+    // The target already matches the source
+    SkippedIdentical,
+    // The target differs from the source and was left alone, e.g. without --force
+    SkippedConflict,
+    // Not installed for another reason, such as a target outside the home directory without --apply-system
+    Skipped,
+    // The target was copied into the source with --adopt
+    Adopted,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstallEntry {
+    // Path of the source file, relative to the source directory
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub outcome: InstallOutcome,
+    // The previous target was backed up first
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub backed_up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// Everything an install did, for the CLI to print or serialize once it is over
+#[derive(Debug, Default, Serialize)]
+pub struct InstallReport {
+    pub dry_run: bool,
+    pub files: Vec<InstallEntry>,
+    // Bytes written to targets; nothing is written in a dry run
+    pub bytes_written: u64,
+    // The table of planned actions a dry run ends with
+    #[serde(skip)]
+    pub summary: Option<DryRunSummary>,
+}
+
+impl InstallReport {
+    pub fn new(dry_run: bool) -> Self {
+        Self { dry_run, ..Default::default() }
+    }
+
+    pub fn add(&mut self, source: &Path, target: &Path, outcome: InstallOutcome) -> &mut InstallEntry {
+        self.files.push(InstallEntry { source: source.to_path_buf(), target: target.to_path_buf(), outcome, backed_up: false, reason: None });
+        self.files.last_mut().expect("an entry was just added")
+    }
+
+    pub fn skip(&mut self, source: &Path, target: &Path, outcome: InstallOutcome, reason: &str) {
+        self.add(source, target, outcome).reason = Some(reason.to_string());
+    }
+
+    pub fn count(&self, outcome: InstallOutcome) -> usize {
+        self.files.iter().filter(|entry| entry.outcome == outcome).count()
+    }
+
+    pub fn backed_up(&self) -> usize {
+        self.files.iter().filter(|entry| entry.backed_up).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(InstallOutcome::Failed)
+    }
+
+    // Fails with PartialFailure when --keep-going set files aside
+    pub fn check(&self) -> Result<()> {
+        match self.failed() {
+            0 => Ok(()),
+            failed => Err(PartialFailure { failed }.into()),
+        }
+    }
+
+    fn print_entry(&self, entry: &InstallEntry, verbose: bool) {
+        let path = colorize::path(entry.source.display());
+        let reason = entry.reason.as_deref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+
+        match entry.outcome {
+            InstallOutcome::Copied if self.dry_run => println!("  {} {}", colorize::dry_run("[Dry run] Would copy:"), path),
+            InstallOutcome::Copied => println!("  {} {}", colorize::success("Copied:"), path),
+            InstallOutcome::SkippedIdentical if verbose => println!("  {} {}", colorize::info("Unchanged:"), path),
+            InstallOutcome::SkippedIdentical => {},
+            InstallOutcome::SkippedConflict | InstallOutcome::Skipped => println!("  {} {}{}", colorize::warning("Skipped:"), path, reason),
+            InstallOutcome::Adopted if self.dry_run => println!("  {} {}", colorize::dry_run("[Dry run] Would adopt into source:"), path),
+            InstallOutcome::Adopted => println!("  {} {}", colorize::success("Adopted into source:"), path),
+            // Failures are listed together at the end
+            InstallOutcome::Failed => {},
+        }
+    }
+
+    pub fn print(&self, verbose: bool) {
+        for entry in &self.files {
+            self.print_entry(entry, verbose);
+        }
+
+        if let Some(summary) = &self.summary {
+            summary.print();
+        }
+
+        let failed = self.files.iter().filter(|entry| entry.outcome == InstallOutcome::Failed).collect::<Vec<_>>();
+        if !failed.is_empty() {
+            println!("\n{} {} file(s) could not be installed:", colorize::error("Failed:"), colorize::highlight(failed.len()));
+            for entry in failed {
+                println!("  {} {} ({})", colorize::error(colorize::cross()), colorize::path(entry.source.display()), entry.reason.as_deref().unwrap_or_default());
+            }
+            return;
+        }
+        if self.dry_run {
+            return;
+        }
+
+        println!("{} {} copied, {} unchanged, {} skipped, {} backed up ({} written)",
+            colorize::success("Installation complete:"),
+            self.count(InstallOutcome::Copied),
+            self.count(InstallOutcome::SkippedIdentical),
+            self.count(InstallOutcome::SkippedConflict) + self.count(InstallOutcome::Skipped),
+            self.backed_up(),
+            format_bytes(self.bytes_written));
+        println!("{}", colorize::info("You can now run 'restore' to revert to original files at any time."));
+    }
+}
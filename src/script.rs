@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::colorize;
+use crate::commands::configured_files;
+use crate::config::{current_hostname, Config, Layout};
+use crate::fs_utils::get_home_dir;
+use crate::template::{expected_content, load_template_data};
+
+// Hook and helper scripts live here inside the source directory; it is never installed
+pub const SCRIPTS_DIR: &str = ".dotfiles-rust";
+// Script whose functions without parameters become template variables
+const HELPERS_SCRIPT: &str = "helpers";
+// Hooks that can be configured under `hooks`
+pub const HOOKS: &[&str] = &["pre-install", "post-install"];
+
+// How long helpers.rhai may take to compute the template variables
+const HELPERS_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How often a command started by run() is checked for having exited or run out of time
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Limits for a hook and the commands it starts with run()
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    // Seconds the hook may take, commands included, before it is stopped and counted as failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    // Directory commands start in: relative to the source directory, or ~/... for home. The current directory without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    // Environment variables commands inherit, all of them when empty; a trailing * matches by prefix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_allow: Vec<String>,
+    // Environment variables commands never inherit, even when allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_deny: Vec<String>,
+}
+
+fn env_pattern_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+// Whether a command started by a hook with this config inherits the environment variable `name`
+pub fn inherits_env(hook: &HookConfig, name: &str) -> bool {
+    (hook.env_allow.is_empty() || hook.env_allow.iter().any(|pattern| env_pattern_matches(pattern, name)))
+        && !hook.env_deny.iter().any(|pattern| env_pattern_matches(pattern, name))
+}
+
+// How commands started by run() are set up
+#[derive(Debug, Clone, Default)]
+struct CommandSettings {
+    hook: HookConfig,
+    working_dir: Option<PathBuf>,
+    deadline: Option<Instant>,
+    // Everything the script printed and every command it ran, to show when a hook fails
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl CommandSettings {
+    fn log(&self, line: String) {
+        if let Ok(mut log) = self.log.lock() {
+            log.push(line);
+        }
+    }
+}
+
+fn resolve_working_dir(working_dir: &str, source_dir: &Path) -> Result<PathBuf> {
+    Ok(match working_dir.strip_prefix("~/") {
+        Some(rest) => get_home_dir()?.join(rest),
+        None if working_dir == "~" => get_home_dir()?,
+        None => source_dir.join(working_dir),
+    })
+}
+
+pub fn script_path(source_dir: &Path, name: &str) -> PathBuf {
+    source_dir.join(SCRIPTS_DIR).join(format!("{}.rhai", name))
+}
+
+fn config_map(config: &Config) -> Map {
+    let mut map = Map::new();
+    let layout = match config.layout {
+        Layout::Plain => "plain",
+        Layout::Stow => "stow",
+    };
+    let template_vars = config.template_vars.iter()
+        .map(|(name, value)| (name.into(), Dynamic::from(value.clone())))
+        .collect::<Map>();
+
+    map.insert("source_dir".into(), config.source_dir.clone().into());
+    map.insert("layout".into(), layout.into());
+    map.insert("hostname".into(), current_hostname().into());
+    map.insert("ignore".into(), config.ignore.iter().cloned().map(Dynamic::from).collect::<Array>().into());
+    map.insert("template_vars".into(), template_vars.into());
+    map
+}
+
+// One entry per managed file with its home-relative path and "installed", "modified" or "missing".
+// Templates are rendered with saved answers and config variables only, since helpers may call this.
+fn status_array(config: &Config) -> Result<Array> {
+    let home_dir = get_home_dir()?;
+    let mut vars = load_template_data()?;
+    vars.extend(config.template_vars.clone());
+
+    let mut statuses = Array::new();
+    for file in configured_files(config, &home_dir)? {
+        let state = if !file.target.exists() {
+            "missing"
+        } else if expected_content(&file.source, &vars).ok() == fs::read(&file.target).ok() {
+            "installed"
+        } else {
+            "modified"
+        };
+
+        let mut entry = Map::new();
+        entry.insert("path".into(), file.relative_path.to_string_lossy().into_owned().into());
+        entry.insert("state".into(), state.into());
+        statuses.push(entry.into());
+    }
+
+    Ok(statuses)
+}
+
+// Collects a command's output on a thread of its own, so what was read so far can be taken without waiting
+// for the stream to close
+struct OutputReader {
+    output: Arc<Mutex<Vec<u8>>>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl OutputReader {
+    fn spawn<R: Read + Send + 'static>(stream: Option<R>) -> Self {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let buffer = Arc::clone(&output);
+        let thread = thread::spawn(move || {
+            let Some(mut stream) = stream else {
+                return;
+            };
+            let mut chunk = [0; 8192];
+            while let Ok(read @ 1..) = stream.read(&mut chunk) {
+                buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).extend_from_slice(&chunk[..read]);
+            }
+        });
+        Self { output, thread }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    fn take(&self) -> String {
+        String::from_utf8_lossy(&self.output.lock().unwrap_or_else(|poisoned| poisoned.into_inner())).into_owned()
+    }
+}
+
+// Waits until the readers have reached the end of their streams, or until `deadline`; false when it passed first
+fn wait_for_readers(readers: &[&OutputReader], deadline: Option<Instant>) -> bool {
+    while !readers.iter().all(|reader| reader.is_finished()) {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return false;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
+
+// Commands get a process group of their own, so a timeout also stops whatever they started
+#[cfg(unix)]
+fn in_own_process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn in_own_process_group(_command: &mut Command) {}
+
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) {
+    let _ = child.kill();
+}
+
+// Runs a shell command to completion, or kills it when the hook's time is up
+fn run_command(command: &str, settings: &CommandSettings) -> Map {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    shell.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    in_own_process_group(&mut shell);
+    if let Some(working_dir) = &settings.working_dir {
+        shell.current_dir(working_dir);
+    }
+    if !settings.hook.env_allow.is_empty() || !settings.hook.env_deny.is_empty() {
+        shell.env_clear();
+        shell.envs(env::vars_os().filter(|(name, _)| inherits_env(&settings.hook, &name.to_string_lossy())));
+    }
+
+    let (code, stdout, stderr) = match shell.spawn() {
+        Ok(mut child) => {
+            let stdout = OutputReader::spawn(child.stdout.take());
+            let stderr = OutputReader::spawn(child.stderr.take());
+            let mut timed_out = false;
+            let status = loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => break status.code().unwrap_or(-1),
+                    Ok(None) if settings.deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                        kill_process_group(&mut child);
+                        let _ = child.wait();
+                        timed_out = true;
+                        break -1;
+                    },
+                    Ok(None) => thread::sleep(POLL_INTERVAL),
+                    Err(_) => break -1,
+                }
+            };
+            // Something the command started in the background can keep its output open after it exits
+            if !wait_for_readers(&[&stdout, &stderr], settings.deadline) {
+                kill_process_group(&mut child);
+                timed_out = true;
+                // Whatever is still in the pipes once the group is gone
+                wait_for_readers(&[&stdout, &stderr], Some(Instant::now() + POLL_INTERVAL * 10));
+            }
+            let stdout = stdout.take();
+            let mut stderr = stderr.take();
+            if timed_out {
+                stderr.push_str("killed: the hook ran out of time\n");
+            }
+            (status, stdout, stderr)
+        },
+        Err(e) => (-1, String::new(), e.to_string()),
+    };
+
+    settings.log(format!("$ {} (exit code {})\n{}{}", command, code, stdout, stderr).trim_end().to_string());
+
+    let mut result = Map::new();
+    result.insert("code".into(), Dynamic::from(code as i64));
+    result.insert("stdout".into(), stdout.into());
+    result.insert("stderr".into(), stderr.into());
+    result
+}
+
+// A scripting engine exposing config(), status() and run(command) to hooks
+fn engine_with(config: &Config, settings: CommandSettings) -> Engine {
+    let mut engine = engine_without_commands(config, settings.deadline);
+
+    let settings_for_print = settings.clone();
+    engine.on_print(move |text| settings_for_print.log(text.to_string()));
+    engine.register_fn("run", move |command: &str| run_command(command, &settings));
+
+    engine
+}
+
+// An engine with config() and status() but no run(), stopped once `deadline` passes
+fn engine_without_commands(config: &Config, deadline: Option<Instant>) -> Engine {
+    let mut engine = Engine::new();
+
+    let config_for_map = config.clone();
+    engine.register_fn("config", move || config_map(&config_for_map));
+
+    let config_for_status = config.clone();
+    engine.register_fn("status", move || -> Result<Array, Box<rhai::EvalAltResult>> {
+        status_array(&config_for_status).map_err(|e| format!("{:#}", e).into())
+    });
+
+    if let Some(deadline) = deadline {
+        engine.on_progress(move |_| (Instant::now() >= deadline).then(|| Dynamic::from("timeout")));
+    }
+
+    engine
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST> {
+    engine.compile_file(path.to_path_buf())
+        .map_err(|e| anyhow!("{}", e))
+        .with_context(|| format!("Failed to compile {}", path.display()))
+}
+
+// Runs `<source>/.dotfiles-rust/<name>.rhai` if it exists, e.g. the pre-install and post-install hooks.
+// What the hook prints is shown once it finishes, along with the output of its commands if it fails.
+pub fn run_hook(config: &Config, name: &str, dry_run: bool) -> Result<()> {
+    let source_dir = Path::new(&config.source_dir);
+    let path = script_path(source_dir, name);
+
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("{} {}", colorize::dry_run("[Dry run] Would run hook"), colorize::path(path.display()));
+        return Ok(());
+    }
+
+    println!("{} {}", colorize::info("Running hook"), colorize::path(path.display()));
+    let hook = config.hooks.get(name).cloned().unwrap_or_default();
+    let settings = CommandSettings {
+        working_dir: hook.working_dir.as_deref().map(|dir| resolve_working_dir(dir, source_dir)).transpose()?,
+        deadline: hook.timeout.map(|seconds| Instant::now() + Duration::from_secs(seconds)),
+        hook: hook.clone(),
+        log: Arc::default(),
+    };
+    let log = settings.log.clone();
+    let engine = engine_with(config, settings);
+    let ast = compile(&engine, &path)?;
+
+    let result = engine.run_ast(&ast);
+    let log = log.lock().map(|log| log.clone()).unwrap_or_default();
+
+    match result {
+        Ok(()) => {
+            for line in log.iter().filter(|line| !line.starts_with("$ ")) {
+                println!("{}", line);
+            }
+            Ok(())
+        },
+        Err(e) => {
+            let lines = log.iter().flat_map(|line| line.lines()).collect::<Vec<_>>();
+            if !lines.is_empty() {
+                println!("{}", colorize::error(format!("Output of hook {}:", name)));
+                for line in lines {
+                    println!("  | {}", line);
+                }
+            }
+            match *e {
+                EvalAltResult::ErrorTerminated(..) => Err(anyhow!("Hook {} timed out after {}s", path.display(), hook.timeout.unwrap_or_default())),
+                e => Err(anyhow!("{}", e)).with_context(|| format!("Hook {} failed", path.display())),
+            }
+        },
+    }
+}
+
+// Values of the functions without parameters defined in helpers.rhai, for use as template variables.
+// Helpers can't start commands with run() and are stopped after HELPERS_TIMEOUT.
+pub fn helper_values(config: &Config) -> Result<BTreeMap<String, String>> {
+    let path = script_path(Path::new(&config.source_dir), HELPERS_SCRIPT);
+
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+
+    let engine = engine_without_commands(config, Some(Instant::now() + HELPERS_TIMEOUT));
+    let ast = compile(&engine, &path)?;
+    let names = ast.iter_functions()
+        .filter(|function| function.params.is_empty())
+        .map(|function| function.name.to_string())
+        .collect::<Vec<_>>();
+
+    let mut values = BTreeMap::new();
+    for name in names {
+        let value = engine.call_fn::<Dynamic>(&mut Scope::new(), &ast, &name, ())
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Template helper {}() in {} failed", name, path.display()))?;
+        values.insert(name, value.to_string());
+    }
+
+    Ok(values)
+}
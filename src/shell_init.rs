@@ -0,0 +1,47 @@
+use clap::ValueEnum;
+
+const BIN: &str = env!("CARGO_PKG_NAME");
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const ALIASES: &[(&str, &str)] = &[
+    ("dfi", "install"),
+    ("dfs", "status"),
+    ("dfe", "edit"),
+    ("dfv", "verify"),
+];
+
+// A snippet to source from the shell's startup file: completions, optional aliases and an optional drift check
+pub fn render_shell_init(shell: InitShell, check: bool, aliases: bool) -> String {
+    let mut lines = vec![format!("# {} shell integration", BIN)];
+
+    lines.push(match shell {
+        InitShell::Bash => format!("source <({} completions bash)", BIN),
+        InitShell::Zsh => format!("(( $+functions[compdef] )) && source <({} completions zsh)", BIN),
+        InitShell::Fish => format!("{} completions fish | source", BIN),
+    });
+
+    if aliases {
+        for (alias, command) in ALIASES {
+            lines.push(match shell {
+                InitShell::Fish => format!("alias {} '{} {}'", alias, BIN, command),
+                _ => format!("alias {}='{} {}'", alias, BIN, command),
+            });
+        }
+    }
+
+    if check {
+        let warning = format!("{}: installed dotfiles differ from the source, run '{} status'", BIN, BIN);
+        lines.push(match shell {
+            InitShell::Fish => format!("{} verify >/dev/null 2>&1; or echo \"{}\" >&2", BIN, warning),
+            _ => format!("{} verify >/dev/null 2>&1 || echo \"{}\" >&2", BIN, warning),
+        });
+    }
+
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use walkdir::WalkDir;
+
+use crate::colorize;
+use crate::config::{read_config, Config};
+use crate::fleet::STATE_DIR;
+use crate::fs_utils::{get_data_dir, write_file_atomic};
+use crate::update::sha256_hex;
+use crate::verify::parse_checksums;
+
+// Checksums of every file in the source tree and their ed25519 signature, kept at the root of the source directory
+pub const SIGNED_MANIFEST_FILE: &str = ".dotfiles-rust.sums";
+pub const SIGNATURE_FILE: &str = ".dotfiles-rust.sums.sig";
+
+// The secret half of the signing key, in the data directory of the machine that signs
+const SIGNING_KEY_FILE: &str = "signing.key";
+
+// Top-level entries that change without the dotfiles changing, so they aren't signed
+const UNSIGNED: &[&str] = &[".git", STATE_DIR, SIGNED_MANIFEST_FILE, SIGNATURE_FILE];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeProblem {
+    // No manifest or signature in the source directory
+    Unsigned,
+    // The manifest wasn't signed by the trusted key, or was changed after signing
+    BadSignature,
+    Missing(PathBuf),
+    Modified(PathBuf),
+    // A file that isn't in the signed manifest, such as one added in transit
+    Unexpected(PathBuf),
+}
+
+impl fmt::Display for TreeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeProblem::Unsigned => write!(f, "the source tree is not signed (no {} and {})", SIGNED_MANIFEST_FILE, SIGNATURE_FILE),
+            TreeProblem::BadSignature => write!(f, "{} does not match its signature from the trusted key", SIGNED_MANIFEST_FILE),
+            TreeProblem::Missing(path) => write!(f, "{} is missing", path.display()),
+            TreeProblem::Modified(path) => write!(f, "{} was modified", path.display()),
+            TreeProblem::Unexpected(path) => write!(f, "{} is not in the signed manifest", path.display()),
+        }
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len()).step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey> {
+    let bytes = decode_hex(text)
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| anyhow!("'{}' is not a hex-encoded ed25519 public key", text.trim()))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| anyhow!("'{}' is not a valid ed25519 public key: {}", text.trim(), e))
+}
+
+pub fn public_key_hex(key: &VerifyingKey) -> String {
+    encode_hex(key.as_bytes())
+}
+
+// Whether `signature`, hex-encoded, is `key`'s signature of `message`
+pub fn signature_matches(message: &[u8], signature: &str, key: &VerifyingKey) -> bool {
+    decode_hex(signature)
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .is_some_and(|signature| key.verify(message, &signature).is_ok())
+}
+
+#[cfg(unix)]
+fn private_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn private_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+// This machine's signing key, created on first use
+pub fn load_signing_key() -> Result<SigningKey> {
+    let key_path = get_data_dir()?.join(SIGNING_KEY_FILE);
+
+    if key_path.exists() {
+        let content = fs::read_to_string(&key_path)
+            .with_context(|| format!("Failed to read signing key at {}", key_path.display()))?;
+        let seed = decode_hex(&content)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| anyhow!("Signing key at {} is corrupt", key_path.display()))?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("Failed to generate a signing key: {}", e))?;
+
+    if let Some(parent) = key_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    write_file_atomic(&key_path, encode_hex(&seed).as_bytes(), private_permissions())
+        .with_context(|| format!("Failed to write signing key at {}", key_path.display()))?;
+    println!("{} {}", colorize::info("Created a signing key at"), colorize::path(key_path.display()));
+
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+// SHA-256 of every file in the source tree by source-relative path; symlinks are hashed by where they point
+pub fn tree_checksums(source_dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let mut checksums = BTreeMap::new();
+    let walker = WalkDir::new(source_dir).min_depth(1).sort_by_file_name().into_iter()
+        .filter_entry(|entry| entry.depth() > 1 || !UNSIGNED.iter().any(|name| entry.file_name() == *name));
+
+    for entry in walker {
+        let entry = entry.with_context(|| format!("Failed to walk {}", source_dir.display()))?;
+        let relative_path = entry.path().strip_prefix(source_dir)?.to_path_buf();
+
+        let digest = if entry.file_type().is_symlink() {
+            let link = fs::read_link(entry.path())
+                .with_context(|| format!("Failed to read symlink {}", entry.path().display()))?;
+            sha256_hex(link.to_string_lossy().as_bytes())
+        } else if entry.file_type().is_file() {
+            sha256_hex(&fs::read(entry.path()).with_context(|| format!("Failed to read {}", entry.path().display()))?)
+        } else {
+            continue;
+        };
+
+        checksums.insert(relative_path, digest);
+    }
+
+    Ok(checksums)
+}
+
+// `sha256sum`-style lines, one per file in path order
+pub fn render_manifest(checksums: &BTreeMap<PathBuf, String>) -> String {
+    checksums.iter()
+        .map(|(path, digest)| format!("{}  {}\n", digest, path.display()))
+        .collect()
+}
+
+// Writes the manifest of the source tree and its signature, returning how many files were signed
+pub fn sign_tree(source_dir: &Path, key: &SigningKey) -> Result<usize> {
+    let checksums = tree_checksums(source_dir)?;
+    let manifest = render_manifest(&checksums);
+    let signature = key.sign(manifest.as_bytes());
+
+    let manifest_path = source_dir.join(SIGNED_MANIFEST_FILE);
+    fs::write(&manifest_path, &manifest)
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+    let signature_path = source_dir.join(SIGNATURE_FILE);
+    fs::write(&signature_path, format!("{}\n", encode_hex(&signature.to_bytes())))
+        .with_context(|| format!("Failed to write {}", signature_path.display()))?;
+
+    Ok(checksums.len())
+}
+
+// Everything wrong with the source tree compared to the manifest signed by `key`; empty when it is intact
+pub fn verify_tree(source_dir: &Path, key: &VerifyingKey) -> Result<Vec<TreeProblem>> {
+    let manifest_path = source_dir.join(SIGNED_MANIFEST_FILE);
+    let signature_path = source_dir.join(SIGNATURE_FILE);
+    if !manifest_path.is_file() || !signature_path.is_file() {
+        return Ok(vec![TreeProblem::Unsigned]);
+    }
+
+    let manifest = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let signature = fs::read_to_string(&signature_path)
+        .with_context(|| format!("Failed to read {}", signature_path.display()))?;
+
+    if !signature_matches(manifest.as_bytes(), &signature, key) {
+        return Ok(vec![TreeProblem::BadSignature]);
+    }
+
+    let signed = parse_checksums(&manifest)
+        .with_context(|| format!("Invalid manifest {}", manifest_path.display()))?
+        .into_iter()
+        .map(|(digest, path)| (path, digest))
+        .collect::<BTreeMap<_, _>>();
+    let mut actual = tree_checksums(source_dir)?;
+    let mut problems = Vec::new();
+
+    for (path, digest) in signed {
+        match actual.remove(&path) {
+            None => problems.push(TreeProblem::Missing(path)),
+            Some(actual_digest) if actual_digest != digest => problems.push(TreeProblem::Modified(path)),
+            Some(_) => {},
+        }
+    }
+    problems.extend(actual.into_keys().map(TreeProblem::Unexpected));
+
+    Ok(problems)
+}
+
+fn trusted_key(config: &Config, key: Option<&str>) -> Result<VerifyingKey> {
+    let key = key.or(config.trusted_key.as_deref())
+        .ok_or_else(|| anyhow!("No trusted key: set `trusted_key` in the config or pass --key"))?;
+    parse_public_key(key)
+}
+
+// Fails unless the source tree matches a manifest signed by the configured trusted key
+pub fn ensure_signed(source_dir: &Path, config: &Config) -> Result<()> {
+    let problems = verify_tree(source_dir, &trusted_key(config, None)?)?;
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("  {} {}", colorize::error(colorize::cross()), problem);
+    }
+    Err(anyhow!("Refusing to install from {}: the source tree does not match its signed manifest", source_dir.display()))
+}
+
+pub fn sign_source() -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let key = load_signing_key()?;
+    let count = sign_tree(source_dir, &key)?;
+    let public_key = public_key_hex(&key.verifying_key());
+
+    println!("{} {} file(s) in {}", colorize::success("Signed"), colorize::highlight(count), colorize::path(source_dir.display()));
+    println!("{} {}", colorize::info("Public key:"), colorize::highlight(&public_key));
+    println!("{}", colorize::info(format!("Set `trusted_key: {}` in the config of machines that should check it", public_key)));
+
+    Ok(())
+}
+
+pub fn verify_source(key: Option<&str>) -> Result<()> {
+    let config = read_config()?;
+    let source_dir = Path::new(&config.source_dir);
+    if !source_dir.exists() {
+        return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+    }
+
+    let problems = verify_tree(source_dir, &trusted_key(&config, key)?)?;
+    println!("{} {}", colorize::header("Verifying the signed manifest of"), colorize::path(source_dir.display()));
+
+    if problems.is_empty() {
+        println!("{}", colorize::success("The source tree matches its signed manifest"));
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("  {} {}", colorize::error(colorize::cross()), problem);
+    }
+    Err(anyhow!("Verification failed: {} problem(s)", problems.len()))
+}
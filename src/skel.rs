@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+
+use crate::colorize;
+use crate::commands::{install_dotfiles, InstallOptions, PartialFailure};
+use crate::fs_utils::WritingAs;
+
+// New accounts get a copy of this directory as their home
+pub const SKEL_DIR: &str = "/etc/skel";
+
+// Login shells of accounts nobody logs into
+const NO_LOGIN_SHELLS: &[&str] = &["nologin", "false"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserHome {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    pub shell: PathBuf,
+}
+
+// Regular accounts from /etc/passwd content: a uid of 1000 or more, other than nobody, with a login shell
+pub fn parse_passwd(content: &str) -> Vec<UserHome> {
+    content.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let fields = line.split(':').collect::<Vec<_>>();
+            let [name, _, uid, gid, _, home, shell] = fields.as_slice() else {
+                return None;
+            };
+            let (uid, gid) = (uid.parse::<u32>().ok()?, gid.parse::<u32>().ok()?);
+            let shell_name = Path::new(shell).file_name().unwrap_or_default();
+
+            (uid >= 1000 && uid != 65534 && !NO_LOGIN_SHELLS.iter().any(|no_login| shell_name == *no_login))
+                .then(|| UserHome { name: name.to_string(), uid, gid, home: PathBuf::from(home), shell: PathBuf::from(shell) })
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+// Installs into one home and prints what happened, returning how many files --keep-going set aside
+fn install_into(options: &InstallOptions) -> Result<usize> {
+    let report = install_dotfiles(options)?;
+    report.print(options.verbose);
+    Ok(report.failed())
+}
+
+// Installs into the home of each user that has one, returning how many files --keep-going set aside
+pub fn install_for_users(options: &InstallOptions, users: &[UserHome]) -> Result<usize> {
+    let mut failed = 0;
+
+    for user in users.iter().filter(|user| user.home.is_dir()) {
+        println!("\n{} {} ({})", colorize::header("Installing for"), colorize::highlight(&user.name), colorize::path(user.home.display()));
+        // Written as the user, so what is created belongs to them even when the install stops partway, and a
+        // symlink in their home can't point root's writes at files they couldn't change themselves
+        let _writing_as = WritingAs::owner(user.uid, user.gid, &user.home);
+        failed += install_into(&InstallOptions { target: Some(user.home.clone()), user: Some(user.clone()), ..options.clone() })?;
+    }
+
+    Ok(failed)
+}
+
+// Installs the managed tree into /etc/skel for accounts created from now on, and with `all_users` into the
+// home of every existing regular account too
+pub fn install_skel(options: &InstallOptions, all_users: bool) -> Result<()> {
+    if !is_root() {
+        return Err(anyhow!("install --skel writes to {} and other users' homes, so it must run as root", SKEL_DIR));
+    }
+
+    println!("{} {}", colorize::header("Installing into"), colorize::path(SKEL_DIR));
+    // With --keep-going a home with failed files doesn't stop the others
+    let mut failed = install_into(&InstallOptions { target: Some(PathBuf::from(SKEL_DIR)), ..options.clone() })?;
+
+    if all_users {
+        let passwd = fs::read_to_string("/etc/passwd")
+            .with_context(|| "Failed to read /etc/passwd")?;
+        failed += install_for_users(options, &parse_passwd(&passwd))?;
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(PartialFailure { failed }.into()),
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::colorize;
+use crate::table::{Column, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlannedAction {
+    Copy,
+    Overwrite,
+    Adopt,
+    BackUp,
+    Restore,
+    Remove,
+    Skip,
+}
+
+impl PlannedAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlannedAction::Copy => "would copy",
+            PlannedAction::Overwrite => "would overwrite",
+            PlannedAction::Adopt => "would adopt",
+            PlannedAction::BackUp => "would back up",
+            PlannedAction::Restore => "would restore",
+            PlannedAction::Remove => "would remove",
+            PlannedAction::Skip => "would skip",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ActionTotals {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+// Tallies what a dry run would do so it can be reviewed at a glance instead of line by line
+#[derive(Debug, Default)]
+pub struct DryRunSummary {
+    totals: BTreeMap<PlannedAction, ActionTotals>,
+}
+
+impl DryRunSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, action: PlannedAction, bytes: u64) {
+        let totals = self.totals.entry(action).or_default();
+        totals.files += 1;
+        totals.bytes += bytes;
+    }
+
+    // Counts a file using its current size on disk; unreadable files count as zero bytes
+    pub fn add_file(&mut self, action: PlannedAction, path: &Path) {
+        let bytes = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        self.add(action, bytes);
+    }
+
+    pub fn render(&self) -> String {
+        let mut table = Table::new(vec![Column::left("Action"), Column::right("Files"), Column::right("Size")]);
+
+        for (action, totals) in &self.totals {
+            table.row(vec![action.label().into(), totals.files.to_string().into(), format_bytes(totals.bytes).into()]);
+        }
+
+        // Nothing here can be cut short, so the terminal's width doesn't matter
+        table.render(None)
+    }
+
+    pub fn print(&self) {
+        println!("\n{}", colorize::header("Dry run summary:"));
+
+        if self.totals.is_empty() {
+            println!("  {}", colorize::info("Nothing to do"));
+            return;
+        }
+
+        print!("{}", self.render());
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,233 @@
+use std::env;
+use std::fmt::{Display, Write};
+
+use crate::colorize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+// Colors are applied after padding, so escape codes never count towards a column's width
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Style {
+    #[default]
+    Plain,
+    Path,
+    Info,
+    Version,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Style {
+    fn paint(self, text: &str) -> String {
+        match self {
+            Style::Plain => text.to_string(),
+            Style::Path => colorize::path(text).to_string(),
+            Style::Info => colorize::info(text).to_string(),
+            Style::Version => colorize::version(text).to_string(),
+            Style::Success => colorize::success(text).to_string(),
+            Style::Warning => colorize::warning(text).to_string(),
+            Style::Error => colorize::error(text).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    pub fn new<T: Display>(style: Style, text: T) -> Self {
+        Self { text: text.to_string(), style }
+    }
+
+    fn width(&self) -> usize {
+        self.text.chars().count()
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Self::new(Style::Plain, text)
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Self::new(Style::Plain, text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    header: String,
+    align: Align,
+    // Whether this column gives up width when the table is wider than the terminal
+    shrink: bool,
+}
+
+impl Column {
+    pub fn left(header: &str) -> Self {
+        Self { header: header.to_string(), align: Align::Left, shrink: false }
+    }
+
+    pub fn right(header: &str) -> Self {
+        Self { header: header.to_string(), align: Align::Right, shrink: false }
+    }
+
+    pub fn shrink(self) -> Self {
+        Self { shrink: true, ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    Cells(Vec<Cell>),
+    // A heading between rows, printed unindented and left out of the column widths
+    Section(Cell),
+}
+
+// Narrowest a shrinking column gets, so a truncated path still says something
+const MIN_SHRUNK_WIDTH: usize = 12;
+
+const GAP: &str = "  ";
+
+// Rows of aligned columns. The header row is only printed when some column has a header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Row>,
+    indent: usize,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self { columns, rows: Vec::new(), indent: 2 }
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        self.rows.push(Row::Cells(cells));
+    }
+
+    pub fn section(&mut self, heading: Cell) {
+        self.rows.push(Row::Section(heading));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn widths(&self, max_width: Option<usize>) -> Vec<usize> {
+        let mut widths = self.columns.iter()
+            .map(|column| column.header.chars().count())
+            .collect::<Vec<_>>();
+        for row in &self.rows {
+            if let Row::Cells(cells) = row {
+                for (width, cell) in widths.iter_mut().zip(cells) {
+                    *width = (*width).max(cell.width());
+                }
+            }
+        }
+
+        let total = self.indent + widths.iter().sum::<usize>() + GAP.len() * widths.len().saturating_sub(1);
+        if let Some(max_width) = max_width && total > max_width {
+            let mut excess = total - max_width;
+            for (width, _) in widths.iter_mut().zip(&self.columns).filter(|(_, column)| column.shrink) {
+                let shrunk = (*width).saturating_sub(excess).max(MIN_SHRUNK_WIDTH.min(*width));
+                excess -= *width - shrunk;
+                *width = shrunk;
+            }
+        }
+
+        widths
+    }
+
+    // The table as text, with columns that shrink cut down so lines fit in `max_width` characters
+    pub fn render(&self, max_width: Option<usize>) -> String {
+        let widths = self.widths(max_width);
+        let indent = " ".repeat(self.indent);
+        let mut output = String::new();
+
+        if self.columns.iter().any(|column| !column.header.is_empty()) {
+            let headers = self.columns.iter().map(|column| Cell::from(column.header.as_str())).collect::<Vec<_>>();
+            let _ = writeln!(output, "{}{}", indent, self.render_cells(&headers, &widths));
+        }
+
+        for row in &self.rows {
+            match row {
+                Row::Cells(cells) => {
+                    let _ = writeln!(output, "{}{}", indent, self.render_cells(cells, &widths));
+                },
+                Row::Section(heading) => {
+                    let _ = writeln!(output, "{}", heading.style.paint(&heading.text));
+                },
+            }
+        }
+
+        output
+    }
+
+    fn render_cells(&self, cells: &[Cell], widths: &[usize]) -> String {
+        let last = cells.len().min(widths.len()).saturating_sub(1);
+
+        let line = cells.iter().zip(widths).zip(&self.columns).enumerate()
+            .map(|(index, ((cell, &width), column))| {
+                let text = truncate(&cell.text, width);
+                let padding = " ".repeat(width.saturating_sub(text.chars().count()));
+                match column.align {
+                    Align::Right => format!("{}{}", padding, cell.style.paint(&text)),
+                    // The last column isn't padded, so lines don't end in spaces
+                    Align::Left if index == last => cell.style.paint(&text),
+                    Align::Left => format!("{}{}", cell.style.paint(&text), padding),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(GAP);
+
+        line.trim_end().to_string()
+    }
+
+    pub fn print(&self) {
+        print!("{}", self.render(terminal_width()));
+    }
+}
+
+// Keeps the end of text that is too wide, since that is the file name of a path
+pub fn truncate(text: &str, width: usize) -> String {
+    let length = text.chars().count();
+    if length <= width {
+        return text.to_string();
+    }
+
+    let ellipsis = if colorize::is_plain() { "..." } else { "…" };
+    let kept = width.saturating_sub(ellipsis.chars().count());
+    format!("{}{}", ellipsis, text.chars().skip(length - kept).collect::<String>())
+}
+
+#[cfg(unix)]
+fn terminal_columns() -> Option<usize> {
+    // Standard output goes to the pager when paging, so the other streams are asked too
+    [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO].into_iter().find_map(|fd| {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+        (result == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+    })
+}
+
+#[cfg(not(unix))]
+fn terminal_columns() -> Option<usize> {
+    None
+}
+
+// Width to fit tables in: $COLUMNS when set, otherwise the terminal's; None when there is no terminal
+pub fn terminal_width() -> Option<usize> {
+    env::var("COLUMNS").ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(terminal_columns)
+}
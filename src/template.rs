@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::{current_hostname, Config};
+use crate::fragments;
+use crate::fs_utils::{get_data_dir, get_home_dir, write_file_atomic};
+use crate::patch::is_patch;
+use crate::prompt;
+use crate::script::helper_values;
+use crate::skel::UserHome;
+
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+pub fn is_template(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == TEMPLATE_EXTENSION)
+}
+
+// Sources installed as generated content rather than copied: templates and directories assembled from fragments
+pub fn is_generated(path: &Path) -> bool {
+    is_template(path) || path.is_dir()
+}
+
+// Templates and patches install without their .tmpl or .patch suffix
+pub fn target_file_path(path: &Path) -> PathBuf {
+    if is_template(path) || is_patch(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+// Splits a template into literal text and `{{ name }}` references; anything else between braces is kept as text
+fn parse(content: &str) -> Vec<(&str, Option<&str>)> {
+    let mut parts = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+        let end = start + 2 + length + 2;
+
+        if is_variable_name(name) {
+            parts.push((&rest[..start], Some(name)));
+        } else {
+            parts.push((&rest[..end], None));
+        }
+        rest = &rest[end..];
+    }
+
+    parts.push((rest, None));
+    parts
+}
+
+// Variable names referenced by a template, in order of first use
+pub fn referenced_variables(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+
+    for (_, name) in parse(content) {
+        if let Some(name) = name
+            && !names.iter().any(|existing| existing == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    names
+}
+
+pub fn undefined_variables(content: &str, vars: &BTreeMap<String, String>) -> Vec<String> {
+    referenced_variables(content).into_iter()
+        .filter(|name| !vars.contains_key(name))
+        .collect()
+}
+
+pub fn render(content: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let undefined = undefined_variables(content, vars);
+    if !undefined.is_empty() {
+        return Err(anyhow!("undefined template variable(s): {}", undefined.join(", ")));
+    }
+
+    let mut output = String::with_capacity(content.len());
+    for (text, name) in parse(content) {
+        output.push_str(text);
+        if let Some(name) = name {
+            output.push_str(&vars[name]);
+        }
+    }
+
+    Ok(output)
+}
+
+// Variables a generated source needs that `vars` doesn't define
+pub fn missing_variables(source_path: &Path, vars: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    if source_path.is_dir() {
+        return fragments::undefined_variables(source_path, vars);
+    }
+
+    let content = fs::read_to_string(source_path)
+        .with_context(|| format!("Failed to read template {}", source_path.display()))?;
+    Ok(undefined_variables(&content, vars))
+}
+
+// The bytes a source file installs as: rendered for templates, assembled for fragment directories, unchanged otherwise
+pub fn expected_content(source_path: &Path, vars: &BTreeMap<String, String>) -> Result<Vec<u8>> {
+    if source_path.is_dir() {
+        return fragments::assemble(source_path, vars)
+            .with_context(|| format!("Failed to assemble {}", source_path.display()));
+    }
+
+    if !is_template(source_path) {
+        return fs::read(source_path)
+            .with_context(|| format!("Failed to read {}", source_path.display()));
+    }
+
+    let content = fs::read_to_string(source_path)
+        .with_context(|| format!("Failed to read template {}", source_path.display()))?;
+    let rendered = render(&content, vars)
+        .with_context(|| format!("Failed to render {}", source_path.display()))?;
+
+    Ok(rendered.into_bytes())
+}
+
+pub fn get_template_data_path() -> Result<PathBuf> {
+    Ok(get_data_dir()?.join("template_data.yaml"))
+}
+
+// Values answered on this machine; kept out of the source repository
+pub fn load_template_data() -> Result<BTreeMap<String, String>> {
+    let data_path = get_template_data_path()?;
+
+    if !data_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let content = fs::read_to_string(&data_path)
+        .with_context(|| format!("Failed to read template data at {}", data_path.display()))?;
+    let data = serde_yaml::from_str::<Option<BTreeMap<String, String>>>(&content)
+        .with_context(|| format!("Failed to parse template data at {}", data_path.display()))?;
+
+    Ok(data.unwrap_or_default())
+}
+
+fn get_helper_values_path() -> Result<PathBuf> {
+    Ok(get_data_dir()?.join("helper_values.yaml"))
+}
+
+// Helper values computed by the last install
+fn load_helper_values() -> Result<BTreeMap<String, String>> {
+    let path = get_helper_values_path()?;
+
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read helper values at {}", path.display()))?;
+    let values = serde_yaml::from_str::<Option<BTreeMap<String, String>>>(&content)
+        .with_context(|| format!("Failed to parse helper values at {}", path.display()))?;
+
+    Ok(values.unwrap_or_default())
+}
+
+fn save_helper_values(values: &BTreeMap<String, String>) -> Result<()> {
+    let path = get_helper_values_path()?;
+    if values.is_empty() && !path.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let content = serde_yaml::to_string(values)
+        .with_context(|| "Failed to serialize helper values")?;
+    write_file_atomic(&path, content.as_bytes(), None)
+        .with_context(|| format!("Failed to write helper values at {}", path.display()))
+}
+
+pub fn save_template_data(data: &BTreeMap<String, String>) -> Result<()> {
+    let data_path = get_template_data_path()?;
+
+    if let Some(parent) = data_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    let content = serde_yaml::to_string(data)
+        .with_context(|| "Failed to serialize template data")?;
+    fs::write(&data_path, content)
+        .with_context(|| format!("Failed to write template data at {}", data_path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn current_uid() -> Option<u32> {
+    Some(unsafe { libc::getuid() })
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> Option<u32> {
+    None
+}
+
+fn shell_name(shell: &Path) -> Option<String> {
+    shell.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+// Who and where files are rendered for: the current user on this machine, or `user` when installing
+// into another account's home. Values that can't be found out are left undefined.
+pub fn builtin_vars(user: Option<&UserHome>) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+    vars.insert(String::from("hostname"), current_hostname());
+    vars.insert(String::from("os"), String::from(env::consts::OS));
+    vars.insert(String::from("arch"), String::from(env::consts::ARCH));
+
+    let (username, uid, home, shell) = match user {
+        Some(user) => (Some(user.name.clone()), Some(user.uid), Some(user.home.clone()), shell_name(&user.shell)),
+        None => (
+            ["USER", "LOGNAME", "USERNAME"].iter().find_map(|name| env::var(name).ok().filter(|value| !value.is_empty())),
+            current_uid(),
+            get_home_dir().ok(),
+            env::var_os("SHELL").and_then(|shell| shell_name(Path::new(&shell))),
+        ),
+    };
+
+    let user_vars = [
+        ("username", username),
+        ("uid", uid.map(|uid| uid.to_string())),
+        ("home", home.map(|home| home.to_string_lossy().into_owned())),
+        ("shell", shell),
+    ];
+    for (name, value) in user_vars {
+        if let Some(value) = value {
+            vars.insert(String::from(name), value);
+        }
+    }
+
+    vars
+}
+
+// Variables available to templates: built-ins, then script helpers, then saved answers, then the config,
+// then explicit overrides. The helpers' values are the ones the last install computed, so looking at files
+// never runs code from the source tree.
+pub fn template_vars(config: &Config, overrides: &[(String, String)]) -> Result<BTreeMap<String, String>> {
+    template_vars_for(config, overrides, None, false)
+}
+
+// Like template_vars, with the built-ins describing `user` instead of the current user. With `run_helpers`,
+// as when installing for real, helpers.rhai is evaluated and its values are kept for the commands that don't.
+pub fn template_vars_for(config: &Config, overrides: &[(String, String)], user: Option<&UserHome>, run_helpers: bool) -> Result<BTreeMap<String, String>> {
+    let mut vars = builtin_vars(user);
+    if run_helpers {
+        let helpers = helper_values(config)?;
+        save_helper_values(&helpers)?;
+        vars.extend(helpers);
+    } else {
+        vars.extend(load_helper_values()?);
+    }
+    vars.extend(load_template_data()?);
+    vars.extend(config.template_vars.clone());
+    vars.extend(overrides.iter().cloned());
+    Ok(vars)
+}
+
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((name, value)) if is_variable_name(name.trim()) => Ok((name.trim().to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{}'", assignment)),
+    }
+}
+
+// Asks for an undefined variable; `--answers <name>=<value>` answers it without a terminal
+pub fn prompt_for_variable(name: &str) -> Result<String> {
+    prompt::input(name, &format!("Value for template variable '{}'", name))
+        .with_context(|| format!("Template variable '{}' is not defined; pass --set {}=<value> or add it to template_vars", name, name))
+}
+
+// Writes rendered output to the target, carrying over the template's permissions like a copy would.
+// Assembled files take the permissions of their first fragment.
+pub fn write_rendered(source_path: &Path, target_path: &Path, content: &[u8]) -> Result<()> {
+    let permissions_source = if source_path.is_dir() {
+        fragments::permissions_source(source_path)
+    } else {
+        Some(source_path.to_path_buf())
+    };
+    let permissions = match permissions_source {
+        Some(path) => Some(fs::metadata(&path)
+            .with_context(|| format!("Failed to read permissions of {}", path.display()))?
+            .permissions()),
+        None => None,
+    };
+    write_file_atomic(target_path, content, permissions)
+        .with_context(|| format!("Failed to write {}", target_path.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::net::TcpListener;
+use anyhow::Result;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use tempfile::tempdir;
+
+use crate::archive::{is_archive_url, unpack_into_cache, ArchiveKind};
+use crate::commands::{install_dotfiles, InstallOptions};
+use crate::config::{write_config, Config};
+use crate::fs_utils::{set_test_backup_dir, set_test_home_dir, set_test_id, clear_test_id};
+
+fn tar_gz(entries: &[(&str, &str)]) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    for (path, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, content.as_bytes())?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn zip(entries: &[(&str, &str)]) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (path, content) in entries {
+        writer.start_file(*path, zip::write::SimpleFileOptions::default())?;
+        writer.write_all(content.as_bytes())?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+// Serves `body` to every request on a local port, returning the URL it is served at
+fn serve(body: Vec<u8>, path: &str) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/{}", listener.local_addr()?, path);
+
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().filter_map(|stream| stream.ok()) {
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+            let _ = stream.write_all(header.as_bytes()).and_then(|_| stream.write_all(&body));
+        }
+    });
+
+    Ok(url)
+}
+
+#[test]
+fn test_archive_kind_from_url() {
+    assert_eq!(ArchiveKind::from_url("https://example.com/dotfiles.tar.gz"), Some(ArchiveKind::TarGz));
+    assert_eq!(ArchiveKind::from_url("https://example.com/dotfiles.TGZ?token=abc"), Some(ArchiveKind::TarGz));
+    assert_eq!(ArchiveKind::from_url("https://example.com/dotfiles.zip"), Some(ArchiveKind::Zip));
+    assert_eq!(ArchiveKind::from_url("https://example.com/dotfiles.tar"), Some(ArchiveKind::Tar));
+    assert_eq!(ArchiveKind::from_url("https://example.com/dotfiles.git"), None);
+
+    assert!(is_archive_url("https://github.com/me/dotfiles/archive/refs/heads/main.tar.gz"));
+    assert!(!is_archive_url("/home/me/dotfiles.zip"), "Local paths are plain source directories");
+}
+
+#[test]
+fn test_unpack_into_cache() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let cache_dir = temp_dir.path().join("sources/abc");
+
+    let archive = tar_gz(&[("dotfiles-main/.bashrc", "export EDITOR=vim"), ("dotfiles-main/.config/nvim/init.vim", "set number")])?;
+    unpack_into_cache(&archive, ArchiveKind::TarGz, &cache_dir)?;
+
+    assert_eq!(fs::read_to_string(cache_dir.join(".bashrc"))?, "export EDITOR=vim", "A single top-level directory is stripped");
+    assert_eq!(fs::read_to_string(cache_dir.join(".config/nvim/init.vim"))?, "set number");
+
+    let archive = zip(&[(".vimrc", "set nocompatible"), (".zshrc", "export ZSH=1")])?;
+    unpack_into_cache(&archive, ArchiveKind::Zip, &cache_dir)?;
+
+    assert_eq!(fs::read_to_string(cache_dir.join(".vimrc"))?, "set nocompatible");
+    assert!(!cache_dir.join(".bashrc").exists(), "A refresh replaces the previous contents");
+    assert_eq!(fs::read_dir(temp_dir.path().join("sources"))?.count(), 1, "Staging directories are cleaned up");
+
+    assert!(unpack_into_cache(b"not an archive", ArchiveKind::Zip, &cache_dir).is_err());
+    assert!(cache_dir.join(".vimrc").exists(), "A failed extraction keeps the previous copy");
+
+    let archive = tar_gz(&[(".config/nvim/init.vim", "set number")])?;
+    unpack_into_cache(&archive, ArchiveKind::TarGz, &cache_dir)?;
+    assert_eq!(fs::read_to_string(cache_dir.join(".config/nvim/init.vim"))?, "set number",
+        "A single dot directory is part of the dotfiles, not a wrapper");
+
+    Ok(())
+}
+
+#[test]
+fn test_install_from_archive_uses_its_repo_config() -> Result<()> {
+    let test_id = set_test_id();
+    let temp_dir = tempdir()?;
+    let home = temp_dir.path().join(format!("home_{}", test_id));
+    let backup_dir = temp_dir.path().join(format!("backup_{}", test_id));
+    fs::create_dir_all(&home)?;
+    fs::create_dir_all(&backup_dir)?;
+    set_test_home_dir(Some(home.clone()));
+    set_test_backup_dir(Some(backup_dir));
+
+    let archive = tar_gz(&[
+        ("dotfiles-main/.dotfiles-rust.yaml", "ignore:\n  - .secret\n"),
+        ("dotfiles-main/.bashrc", "export EDITOR=vim"),
+        ("dotfiles-main/.secret", "token"),
+    ])?;
+    write_config(&Config {
+        source_dir: serve(archive, "dotfiles.tar.gz")?,
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(home.join(".bashrc").exists());
+    assert!(!home.join(".secret").exists(), "The repository config in a freshly downloaded archive applies on the first install");
+
+    set_test_home_dir(None);
+    set_test_backup_dir(None);
+    clear_test_id();
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use std::fs;
-use std::path::{PathBuf};
-use tempfile;
+use std::path::{Path, PathBuf};
 
 use crate::backup::*;
+use crate::config::Config;
 use crate::fs_utils::{set_test_home_dir, set_test_backup_dir, set_test_id, clear_test_id};
 
 fn setup_test_dirs() -> (tempfile::TempDir, PathBuf, PathBuf) {
@@ -25,6 +25,11 @@ fn setup_test_dirs() -> (tempfile::TempDir, PathBuf, PathBuf) {
     (temp_dir, test_home, backup_dir)
 }
 
+// Backups in the directory with the default settings
+fn backups(backup_dir: &Path) -> Backups {
+    Backups::new(backup_dir.to_path_buf(), &Config::default()).unwrap()
+}
+
 // Clean up after tests
 fn cleanup_test_dirs() {
     set_test_home_dir(None);
@@ -44,7 +49,7 @@ fn test_backup_file() {
     assert!(file_path.exists(), "Test file should exist");
     assert!(backup_dir.exists(), "Backup directory should exist");
     
-    backup_file(&file_path, &backup_dir, false).unwrap();
+    backup_file(&file_path, &backups(&backup_dir), false).unwrap();
     
     let entries = fs::read_dir(&backup_dir).unwrap()
         .filter_map(|e| e.ok())
@@ -55,7 +60,7 @@ fn test_backup_file() {
     let backup_file_path = entries.iter()
         .map(|e| e.path())
         .find(|p| {
-            p.file_name().map_or(false, |name| 
+            p.file_name().is_some_and(|name| 
                 name.to_string_lossy().starts_with("test_file.txt."))
         });
     
@@ -65,7 +70,7 @@ fn test_backup_file() {
     fs::create_dir_all(&dry_run_dir).unwrap();
     
     let file_count_before = fs::read_dir(&dry_run_dir).unwrap().count();
-    backup_file(&file_path, &dry_run_dir, true).unwrap();
+    backup_file(&file_path, &backups(&dry_run_dir), true).unwrap();
     let file_count_after = fs::read_dir(&dry_run_dir).unwrap().count();
     
     assert_eq!(file_count_before, file_count_after, "Dry run should not create new files");
@@ -167,9 +172,224 @@ fn test_find_all_backup_versions() {
     
     assert_eq!(versions.len(), 3, "Should find 3 backup versions");
     
-    assert_eq!(versions[0], (1678886400, backup_file_1), "First element should be oldest backup");
-    assert_eq!(versions[1], (1678972800, backup_file_2), "Second element should be middle backup");
-    assert_eq!(versions[2], (1679059200, backup_file_3), "Third element should be newest backup");
+    assert_eq!(versions[0], (BackupVersion::parse("1678886400").unwrap(), backup_file_1), "First element should be oldest backup");
+    assert_eq!(versions[1], (BackupVersion::parse("1678972800").unwrap(), backup_file_2), "Second element should be middle backup");
+    assert_eq!(versions[2], (BackupVersion::parse("1679059200").unwrap(), backup_file_3), "Third element should be newest backup");
     
     cleanup_test_dirs();
-} 
\ No newline at end of file
+} 
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("500MB").unwrap(), 500 * 1024 * 1024);
+    assert_eq!(parse_size("1.5 GiB").unwrap(), 1536 * 1024 * 1024);
+    assert_eq!(parse_size("2k").unwrap(), 2048);
+    assert_eq!(parse_size("300").unwrap(), 300);
+    assert!(parse_size("lots").is_err());
+    assert!(parse_size("5 parsecs").is_err());
+}
+
+#[test]
+fn test_prune_to_quota() {
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    fs::write(backup_dir.join(".bashrc.100"), vec![b'a'; 400]).unwrap();
+    fs::write(backup_dir.join(".bashrc.200"), vec![b'b'; 400]).unwrap();
+    fs::write(backup_dir.join(".bashrc.300"), vec![b'c'; 400]).unwrap();
+    fs::write(backup_dir.join(".vimrc.150"), vec![b'd'; 400]).unwrap();
+
+    let pruning = prune_to_quota(&backup_dir, 1000).unwrap();
+    assert_eq!(pruning, QuotaPruning { removed: 2, reclaimed: 800, remaining: 800 });
+    assert!(!backup_dir.join(".bashrc.100").exists(), "The oldest versions go first");
+    assert!(!backup_dir.join(".bashrc.200").exists());
+    assert!(backup_dir.join(".vimrc.150").exists(), "The only version of a file is kept");
+
+    // Only the latest version of each file is left, so nothing more can go
+    let pruning = prune_to_quota(&backup_dir, 100).unwrap();
+    assert_eq!(pruning.removed, 0);
+    assert_eq!(pruning.remaining, 800);
+
+    // Files with the same name in different directories each keep their latest version
+    for directory in ["a", "b"] {
+        let file_path = test_home.join(directory).join("init.lua");
+        fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        fs::write(&file_path, vec![b'e'; 400]).unwrap();
+        backup_file(&file_path, &backups(&backup_dir), false).unwrap();
+    }
+    let pruning = prune_to_quota(&backup_dir, 100).unwrap();
+    assert_eq!(pruning.removed, 0);
+    assert_eq!(find_all_backup_versions("init.lua", &backup_dir).unwrap().len(), 2);
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_format_age() {
+    assert_eq!(format_age(30), "just now");
+    assert_eq!(format_age(60), "1 minute ago");
+    assert_eq!(format_age(3 * 3600 + 59), "3 hours ago");
+    assert_eq!(format_age(45 * 86400), "1 month ago");
+    assert_eq!(format_age(800 * 86400), "2 years ago");
+}
+
+#[test]
+fn test_backup_versions_by_file() {
+    let (_temp_dir, _test_home, backup_dir) = setup_test_dirs();
+
+    fs::write(backup_dir.join(".bashrc.200"), "new").unwrap();
+    fs::write(backup_dir.join(".bashrc.100"), "old").unwrap();
+    fs::write(backup_dir.join("init.lua.150"), "").unwrap();
+    fs::write(backup_dir.join("notes.txt"), "").unwrap();
+
+    let versions = backup_versions_by_file(&backup_dir).unwrap();
+    assert_eq!(versions.keys().collect::<Vec<_>>(), vec![".bashrc", "init.lua"]);
+    assert_eq!(versions[".bashrc"].iter().map(|(version, _)| version.seconds()).collect::<Vec<_>>(), vec![100, 200]);
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_trim_versions() {
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    for version in [100, 200, 300] {
+        fs::write(backup_dir.join(format!(".bashrc.{}", version)), "old").unwrap();
+    }
+    fs::write(backup_dir.join(".vimrc.100"), "other file").unwrap();
+
+    assert_eq!(trim_versions(&test_home.join(".bashrc"), &backup_dir, 2).unwrap(), 1);
+    assert!(!backup_dir.join(".bashrc.100").exists(), "The oldest version goes first");
+    assert!(backup_dir.join(".bashrc.300").exists());
+    assert!(backup_dir.join(".vimrc.100").exists(), "Other files' backups are left alone");
+    assert_eq!(trim_versions(&test_home.join(".bashrc"), &backup_dir, 0).unwrap(), 1, "The newest version is always kept");
+
+    let keep_one = Backups::new(backup_dir.clone(), &Config {
+        keep_versions_per_file: Some(1),
+        ..Default::default()
+    }).unwrap();
+    fs::write(test_home.join(".vimrc"), "set number").unwrap();
+    backup_file(&test_home.join(".vimrc"), &keep_one, false).unwrap();
+
+    let versions = find_all_backup_versions(".vimrc", &backup_dir).unwrap();
+    assert_eq!(versions.len(), 1, "Backing up trims the file's versions to keep_versions_per_file");
+    assert_eq!(fs::read_to_string(&versions[0].1).unwrap(), "set number");
+
+    // A file with the same name elsewhere has versions of its own
+    fs::create_dir_all(test_home.join("other")).unwrap();
+    fs::write(test_home.join("other/.vimrc"), "set list").unwrap();
+    backup_file(&test_home.join("other/.vimrc"), &keep_one, false).unwrap();
+    let versions = find_all_backup_versions(".vimrc", &backup_dir).unwrap();
+    assert_eq!(versions.len(), 2, "Backups of another .vimrc don't count towards this one's versions");
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_backup_version() {
+    assert_eq!(BackupVersion::parse("1700000000123"), Some(BackupVersion { millis: 1700000000123, counter: 0 }));
+    assert_eq!(BackupVersion::parse("1700000000123-2"), Some(BackupVersion { millis: 1700000000123, counter: 2 }));
+    assert_eq!(BackupVersion::parse("1700000000"), Some(BackupVersion { millis: 1700000000000, counter: 0 }),
+        "Legacy names in whole seconds are still read");
+    assert_eq!(BackupVersion::parse("bak"), None);
+    assert_eq!(BackupVersion::parse("-1"), None);
+
+    assert_eq!(BackupVersion { millis: 1700000000123, counter: 2 }.to_string(), "1700000000123-2");
+    assert!(BackupVersion::parse("1700000000").unwrap() < BackupVersion::parse("1700000000001").unwrap());
+    assert!(BackupVersion::parse("1700000000123").unwrap() < BackupVersion::parse("1700000000123-1").unwrap());
+}
+
+#[test]
+fn test_rapid_backups_are_kept() {
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    let file_path = test_home.join(".bashrc");
+    for content in ["one", "two", "three"] {
+        fs::write(&file_path, content).unwrap();
+        backup_file(&file_path, &backups(&backup_dir), false).unwrap();
+    }
+
+    let versions = find_all_backup_versions(".bashrc", &backup_dir).unwrap();
+    let contents = versions.iter().map(|(_, path)| fs::read_to_string(path).unwrap()).collect::<Vec<_>>();
+    assert_eq!(contents, ["one", "two", "three"], "Backups within the same millisecond don't replace each other");
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_sensitive_backups() {
+    use std::os::unix::fs::PermissionsExt;
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    let file_path = test_home.join(".aws/credentials");
+    fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+    fs::write(&file_path, "secret").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+    let mode = |backups: &Backups| {
+        backup_file(&file_path, backups, false).unwrap();
+        let (_, backup_path) = find_all_backup_versions("credentials", &backup_dir).unwrap().pop().unwrap();
+        fs::metadata(backup_path).unwrap().permissions().mode() & 0o777
+    };
+    assert_eq!(mode(&backups(&backup_dir)), 0o644, "Only the default sensitive paths are private");
+    let aws = Backups::new(backup_dir.clone(), &Config {
+        sensitive: vec![String::from(".aws/**")],
+        ..Default::default()
+    }).unwrap();
+    assert_eq!(mode(&aws), 0o600, "The caller's sensitive paths apply");
+
+    let invalid = Config {
+        sensitive: vec![String::from("[")],
+        ..Default::default()
+    };
+    assert!(Backups::new(backup_dir.clone(), &invalid).is_err(), "An invalid pattern is an error, not a public backup");
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_backup_deleted_file() {
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    let file_path = test_home.join(".bashrc");
+    fs::write(&file_path, "overwritten").unwrap();
+    backup_file(&file_path, &backups(&backup_dir), false).unwrap();
+    fs::write(&file_path, "deleted").unwrap();
+    backup_deleted_file(&file_path, &backups(&backup_dir)).unwrap();
+
+    let index = BackupIndex::load().unwrap();
+    let versions = find_all_backup_versions(".bashrc", &backup_dir).unwrap();
+    let tombstones = versions.iter().map(|(_, path)| index.is_tombstone(path)).collect::<Vec<_>>();
+    assert_eq!(tombstones, [false, true], "Only the backup taken before deleting is a tombstone");
+    assert_eq!(index.original_path(&versions[1].1), Some(std::path::absolute(&file_path).unwrap().as_path()));
+
+    cleanup_test_dirs();
+}
+
+#[test]
+fn test_concurrent_backups() {
+    let (_temp_dir, test_home, backup_dir) = setup_test_dirs();
+
+    let files = (0..8).map(|i| test_home.join(format!("file{}", i % 2))).collect::<Vec<_>>();
+    for file in &files {
+        fs::write(file, "content").unwrap();
+    }
+
+    let start = std::sync::Barrier::new(files.len());
+    std::thread::scope(|scope| {
+        for file in &files {
+            let (start, test_home, backup_dir) = (&start, test_home.clone(), backup_dir.clone());
+            scope.spawn(move || {
+                set_test_home_dir(Some(test_home));
+                set_test_backup_dir(Some(backup_dir.clone()));
+                start.wait();
+                backup_file(file, &backups(&backup_dir), false).unwrap();
+            });
+        }
+    });
+
+    let backups = fs::read_dir(&backup_dir).unwrap().count();
+    assert_eq!(backups, files.len(), "Backups made in the same millisecond each get their own name");
+    let index = BackupIndex::load().unwrap();
+    assert_eq!(index.backups.len(), files.len(), "No thread's index record is lost");
+
+    cleanup_test_dirs();
+}
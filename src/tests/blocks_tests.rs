@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::blocks::{apply_block, block_state, remove_block, BlockConfig, BlockState};
+
+fn block(name: Option<&str>) -> BlockConfig {
+    BlockConfig {
+        target: String::from("~/.bashrc"),
+        name: name.map(String::from),
+        content: Some(String::from("source ~/.aliases")),
+        source: None,
+        comment: String::from("#"),
+    }
+}
+
+#[test]
+fn test_block_markers() {
+    assert_eq!(block(None).markers(), (String::from("# BEGIN dotfiles-rust"), String::from("# END dotfiles-rust")));
+    assert_eq!(block(Some("aliases")).markers().0, "# BEGIN dotfiles-rust aliases");
+    assert_eq!(block(None).target_path(), Path::new(".bashrc"));
+}
+
+#[test]
+fn test_apply_block() {
+    let block = block(None);
+    let original = "export EDITOR=vim\n";
+
+    let applied = apply_block(original, &block, "source ~/.aliases");
+    assert_eq!(applied, "export EDITOR=vim\n\n# BEGIN dotfiles-rust\nsource ~/.aliases\n# END dotfiles-rust\n");
+    assert_eq!(apply_block(&applied, &block, "source ~/.aliases"), applied, "Applying again changes nothing");
+
+    let edited = format!("{}alias ll='ls -l'\n", applied);
+    let updated = apply_block(&edited, &block, "source ~/.aliases\nsource ~/.functions");
+    assert_eq!(updated, "export EDITOR=vim\n\n# BEGIN dotfiles-rust\nsource ~/.aliases\nsource ~/.functions\n# END dotfiles-rust\nalias ll='ls -l'\n",
+        "Only the lines between the markers are replaced");
+
+    assert_eq!(apply_block("", &block, "source ~/.aliases"), "# BEGIN dotfiles-rust\nsource ~/.aliases\n# END dotfiles-rust\n");
+}
+
+#[test]
+fn test_remove_block() {
+    let block = block(Some("aliases"));
+    let original = "export EDITOR=vim\n";
+    let applied = apply_block(original, &block, "source ~/.aliases");
+
+    assert_eq!(remove_block(&applied, &block).as_deref(), Some(original));
+    assert_eq!(remove_block(original, &block), None);
+}
+
+#[test]
+fn test_block_state() {
+    let block = block(None);
+    let applied = apply_block("", &block, "source ~/.aliases");
+
+    assert_eq!(block_state(Some(&applied), &block, "source ~/.aliases"), BlockState::InSync);
+    assert_eq!(block_state(Some(&applied), &block, "source ~/.functions"), BlockState::Outdated);
+    assert_eq!(block_state(Some("export EDITOR=vim\n"), &block, "source ~/.aliases"), BlockState::Missing);
+    assert_eq!(block_state(None, &block, "source ~/.aliases"), BlockState::Missing);
+}
@@ -1,12 +1,19 @@
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::Write;
 use anyhow::Result;
 use tempfile::{tempdir, TempDir};
+use clap_complete::Shell;
 
-use crate::commands::{install_dotfiles, list_backups, clear_backups, uninstall_dotfiles};
-use crate::fs_utils::{set_test_home_dir, set_test_backup_dir, set_test_id, clear_test_id};
-use crate::config::{Config, write_config};
+use crate::commands::{source_entries, is_blacklisted, install_dotfiles, InstallOptions, PartialFailure, StatusOptions, StatusCounts, status_group, find_extra_files, fix_differences_with, FixAction, restore_backups_with, RestoreChoice, RestoreOptions, list_backups, clear_backups, prune_redundant_backups, uninstall_dotfiles, generate_completions, home_relative_path, find_source_path, find_target_path, which_dotfile, managed_files, list_managed_files, find_broken_symlinks, clean_broken_symlinks, backed_up_files, rendered_dotfile, backup_content};
+use crate::completion::managed_paths;
+use crate::fs_utils::{set_test_home_dir, DirModes, set_test_backup_dir, set_test_id, clear_test_id};
+use crate::config::{Config, Layout, write_config};
+use crate::manifest::Manifest;
+use crate::backup::{find_latest_backup_of, Backups};
+use crate::prompt::{set_answers, AnswerMode};
+use crate::report::InstallOutcome;
 
 fn setup_test_env() -> Result<(TempDir, PathBuf, PathBuf)> {
     let test_id = set_test_id();
@@ -33,6 +40,11 @@ fn cleanup_test_env() {
     clear_test_id();
 }
 
+// Backups in the directory with the default settings
+fn backups(backup_dir: &Path) -> Backups {
+    Backups::new(backup_dir.to_path_buf(), &Config::default()).unwrap()
+}
+
 fn create_test_file(path: &Path, content: &str) -> Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -60,6 +72,7 @@ fn test_install_dotfiles() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
     };
 
     write_config(&config)?;
@@ -68,7 +81,7 @@ fn test_install_dotfiles() -> Result<()> {
     create_test_file(&source_dir.join(".config/fish/config.fish"), "set -x PATH $PATH")?;
     
     println!("Installing dotfiles from {} to {}", source_dir.display(), temp_home.display());
-    install_dotfiles(false, false, false, false)?;
+    install_dotfiles(&InstallOptions::default())?;
     
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should be installed on first run");
     assert!(temp_home.join(".config/fish/config.fish").exists(), "config.fish should be installed on first run");
@@ -79,14 +92,14 @@ fn test_install_dotfiles() -> Result<()> {
     create_test_file(&source_dir.join(".bashrc"), "export PATH=$PATH:/usr/local/bin")?;
     create_test_file(&temp_home.join(".bashrc"), "# existing bashrc content")?;
     
-    install_dotfiles(false, true, true, false)?;
+    install_dotfiles(&InstallOptions { force: true, backup: true, ..Default::default() })?;
     
     let bashrc_content = fs::read_to_string(temp_home.join(".bashrc"))?;
     assert_eq!(bashrc_content, "export PATH=$PATH:/usr/local/bin", ".bashrc should be overwritten with force");
     
     create_test_file(&source_dir.join(".zshrc"), "export ZSH=$HOME/.oh-my-zsh")?;
     
-    install_dotfiles(true, false, false, false)?;
+    install_dotfiles(&InstallOptions { dry_run: true, ..Default::default() })?;
     
     assert!(!temp_home.join(".zshrc").exists(), ".zshrc should not be installed in dry run");
     
@@ -95,6 +108,60 @@ fn test_install_dotfiles() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_install_keep_going() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".config/fish/config.fish"), "set -x PATH $PATH")?;
+    create_test_file(&source_dir.join(".vimrc"), "set nocompatible")?;
+    write_config(&Config { source_dir: source_dir.to_string_lossy().into_owned(), ..Default::default() })?;
+    // A file where a directory should be, so config.fish can't be written
+    create_test_file(&temp_home.join(".config"), "not a directory")?;
+
+    assert!(install_dotfiles(&InstallOptions::default()).is_err());
+    assert!(!temp_home.join(".vimrc").exists(), "Without --keep-going the install stops at the first failure");
+
+    let report = install_dotfiles(&InstallOptions { keep_going: true, ..Default::default() })?;
+    assert_eq!(report.failed(), 1);
+    assert_eq!(report.files.iter().find(|entry| entry.outcome == InstallOutcome::Failed).map(|entry| entry.source.clone()), Some(PathBuf::from(".config/fish/config.fish")));
+    let error = report.check().unwrap_err();
+    assert_eq!(error.downcast_ref::<PartialFailure>().map(|partial| partial.failed), Some(1));
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set nocompatible", "Files after the failure are still installed");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_report() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".vimrc"), "set nocompatible")?;
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_config(&Config { source_dir: source_dir.to_string_lossy().into_owned(), ..Default::default() })?;
+    create_test_file(&temp_home.join(".bashrc"), "local bashrc")?;
+
+    let report = install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(report.count(InstallOutcome::Copied), 1);
+    assert_eq!(report.count(InstallOutcome::SkippedConflict), 1);
+    assert_eq!(report.bytes_written, "set nocompatible".len() as u64);
+
+    let report = install_dotfiles(&InstallOptions { force: true, backup: true, ..Default::default() })?;
+    assert_eq!(report.count(InstallOutcome::SkippedIdentical), 1);
+    assert_eq!(report.count(InstallOutcome::Copied), 1);
+    assert_eq!(report.backed_up(), 1, "The local .bashrc is backed up before it is overwritten");
+
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(json["files"][0]["source"], ".bashrc");
+    assert_eq!(json["files"][0]["outcome"], "copied");
+    assert_eq!(json["files"][0]["backed_up"], true);
+
+    cleanup_test_env();
+    Ok(())
+}
+
 #[test]
 fn test_install_dotfiles_blacklist() -> Result<()> {
     let (temp_dir, temp_home, _) = setup_test_env()?;
@@ -104,6 +171,7 @@ fn test_install_dotfiles_blacklist() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
     };
     write_config(&config)?;
     
@@ -113,7 +181,7 @@ fn test_install_dotfiles_blacklist() -> Result<()> {
     create_test_file(&source_dir.join(".DS_Store"), "binary data")?;
     create_test_file(&source_dir.join(".config/fish/config.fish"), "set -x PATH $PATH")?;
 
-    install_dotfiles(false, false, false, false)?;
+    install_dotfiles(&InstallOptions::default())?;
     
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should be installed");
     assert!(temp_home.join(".config/fish/config.fish").exists(), "config.fish should be installed");
@@ -172,6 +240,24 @@ fn test_clear_backups() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_clear_backups_answered() -> Result<()> {
+    let (_temp_dir, _, backup_dir) = setup_test_env()?;
+    std::fs::write(backup_dir.join("file1.txt.123"), "backup 1")?;
+
+    set_answers(AnswerMode::Ask, &[(String::from("reset"), String::from("no"))]);
+    clear_backups(false)?;
+    assert!(backup_dir.exists(), "Answering no should keep the backups");
+
+    set_answers(AnswerMode::Ask, &[(String::from("reset"), String::from("yes"))]);
+    clear_backups(false)?;
+    assert!(!backup_dir.exists(), "Answering yes should clear the backups");
+
+    set_answers(AnswerMode::Ask, &[]);
+    cleanup_test_env();
+    Ok(())
+}
+
 #[test]
 fn test_install_dotfiles_identical_files() -> Result<()> {
     let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
@@ -181,6 +267,7 @@ fn test_install_dotfiles_identical_files() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
     };
     write_config(&config)?;
     
@@ -198,7 +285,7 @@ fn test_install_dotfiles_identical_files() -> Result<()> {
         .map(|entries| entries.count())
         .unwrap_or(0);
     
-    install_dotfiles(false, true, true, false)?;
+    install_dotfiles(&InstallOptions { force: true, backup: true, ..Default::default() })?;
     
     let vimrc_mtime_after = temp_home.join(".vimrc").metadata()?.modified()?;
     assert_eq!(vimrc_mtime_before, vimrc_mtime_after, "Identical file should not be modified");
@@ -230,6 +317,7 @@ fn test_status_dotfiles() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
     };
     write_config(&config)?;
     
@@ -248,18 +336,43 @@ fn test_status_dotfiles() -> Result<()> {
     create_test_file(&source_dir.join(".git/config"), "git config")?;
     
     // Run status with basic output
-    let result = crate::commands::status_dotfiles(false);
+    let result = crate::commands::status_dotfiles(&StatusOptions::default());
     assert!(result.is_ok(), "Status command should run without errors");
     
     // Run status with verbose output
-    let verbose_result = crate::commands::status_dotfiles(true);
+    let verbose_result = crate::commands::status_dotfiles(&StatusOptions { verbose: true, extras: true, ..Default::default() });
     assert!(verbose_result.is_ok(), "Verbose status command should run without errors");
+
+    crate::commands::status_dotfiles(&StatusOptions { changed_only: true, ..Default::default() })?;
+
+    let metrics_path = temp_dir.path().join("dotfiles.prom");
+    crate::commands::status_dotfiles(&StatusOptions { metrics: Some(metrics_path.clone()), ..Default::default() })?;
+    let metrics = fs::read_to_string(&metrics_path)?;
+    assert!(metrics.contains("dotfiles_rust_managed_files 3\n"), "Unexpected metrics: {}", metrics);
+    assert!(metrics.contains("dotfiles_rust_drifted_files 2\n"), "Unexpected metrics: {}", metrics);
+
+    let json_path = temp_dir.path().join("dotfiles.json");
+    crate::commands::status_dotfiles(&StatusOptions { metrics: Some(json_path.clone()), ..Default::default() })?;
+    let json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&json_path)?)?;
+    assert_eq!(json["modified_files"], 1);
+    assert_eq!(json["missing_files"], 1);
     
     cleanup_test_env();
     
     Ok(())
 }
 
+#[test]
+fn test_status_groups_and_counts() {
+    assert_eq!(status_group(Path::new(".bashrc")), PathBuf::new());
+    assert_eq!(status_group(Path::new(".config/nvim/init.vim")), PathBuf::from(".config"));
+    assert_eq!(status_group(Path::new("/etc/nginx/nginx.conf")), PathBuf::from("/etc"));
+    assert_eq!(status_group(Path::new("/etc/hosts")), PathBuf::from("/etc"));
+
+    let counts = StatusCounts { ok: 142, modified: 3, missing: 1, extra: 2 };
+    assert_eq!(counts.to_string(), "142 ok, 3 modified, 1 missing, 2 extra");
+}
+
 #[test]
 fn test_uninstall_dotfiles() -> Result<()> {
     let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
@@ -269,6 +382,7 @@ fn test_uninstall_dotfiles() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
     };
     write_config(&config)?;
     
@@ -279,7 +393,7 @@ fn test_uninstall_dotfiles() -> Result<()> {
     create_test_file(&source_dir.join(".config/fish/config.fish"), "fish config content")?;
     
     // Install files to home
-    install_dotfiles(false, false, true, false)?;
+    install_dotfiles(&InstallOptions { backup: true, ..Default::default() })?;
     
     // Verify files were installed
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should be installed");
@@ -298,7 +412,7 @@ fn test_uninstall_dotfiles() -> Result<()> {
     create_test_file(&temp_home.join(".zshrc"), "modified zshrc content")?;
     
     // Uninstall dotfiles without force flag
-    uninstall_dotfiles(false, false, false)?;
+    uninstall_dotfiles(false, false, false, false)?;
     
     // Check files with backups were replaced with backup content
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should exist (replaced with backup)");
@@ -317,6 +431,8 @@ fn test_uninstall_dotfiles() -> Result<()> {
     
     // Check that fish config was uninstalled (no backup)
     assert!(!temp_home.join(".config/fish/config.fish").exists(), "fish config should be removed (no backup)");
+    assert!(fs::read_dir(&backup_dir)?.flatten().any(|entry| entry.file_name().to_string_lossy().starts_with("config.fish.")),
+        "fish config should be backed up before it is removed");
     
     // Check that backups were removed
     assert!(!backup_vimrc.exists(), "vimrc backup should be deleted");
@@ -325,7 +441,7 @@ fn test_uninstall_dotfiles() -> Result<()> {
     // Test uninstall with force flag
     
     // First reinstall everything
-    install_dotfiles(false, true, true, false)?;
+    install_dotfiles(&InstallOptions { force: true, backup: true, ..Default::default() })?;
     
     // Create new backups with higher timestamps to ensure they're chosen as latest
     let new_backup_vimrc = backup_dir.join(".vimrc.2000000000");
@@ -340,7 +456,7 @@ fn test_uninstall_dotfiles() -> Result<()> {
     create_test_file(&temp_home.join(".zshrc"), "modified zshrc content again")?;
     
     // Uninstall with force flag
-    uninstall_dotfiles(false, true, false)?;
+    uninstall_dotfiles(false, true, false, false)?;
     
     // Check that files with backups were restored from backup
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should exist (replaced with backup)");
@@ -362,14 +478,14 @@ fn test_uninstall_dotfiles() -> Result<()> {
     // Test dry run
     
     // First reinstall everything
-    install_dotfiles(false, true, false, false)?;
+    install_dotfiles(&InstallOptions { force: true, ..Default::default() })?;
     
     // Create new backups - use timestamp 3000000000 to ensure it's selected as the latest
     let newest_backup_vimrc = backup_dir.join(".vimrc.3000000000");
     create_test_file(&newest_backup_vimrc, "newest backup vimrc content")?;
     
     // Uninstall with dry run
-    uninstall_dotfiles(true, true, false)?;
+    uninstall_dotfiles(true, true, false, false)?;
     
     // Check that no files were actually removed or changed
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should still exist after dry run");
@@ -394,11 +510,15 @@ fn test_uninstall_with_blacklist() -> Result<()> {
     
     let config = Config {
         source_dir: source_dir.to_str().unwrap().to_string(),
+        ignore: vec![".zshrc".to_string()],
+        ..Default::default()
     };
     write_config(&config)?;
     
-    // Create source files including blacklisted ones
+    // Create source files including blacklisted and ignored ones
     create_test_file(&source_dir.join(".vimrc"), "vimrc content")?;
+    create_test_file(&source_dir.join(".zshrc"), "zshrc content")?;
+    create_test_file(&temp_home.join(".zshrc"), "zshrc content")?;
     create_test_file(&source_dir.join(".git/config"), "[core]")?;
     create_test_file(&source_dir.join("node_modules/some_package/index.js"), "console.log('hello')")?;
     create_test_file(&source_dir.join(".DS_Store"), "binary data")?;
@@ -409,13 +529,13 @@ fn test_uninstall_with_blacklist() -> Result<()> {
     create_test_file(&temp_home.join(".DS_Store"), "modified binary data")?;
     
     // Install vimrc to home
-    install_dotfiles(false, false, false, false)?;
+    install_dotfiles(&InstallOptions::default())?;
     
     // Verify vimrc was installed
     assert!(temp_home.join(".vimrc").exists(), ".vimrc should be installed");
     
     // Run uninstall
-    uninstall_dotfiles(false, true, false)?;
+    uninstall_dotfiles(false, true, false, false)?;
     
     // Check that vimrc was removed
     assert!(!temp_home.join(".vimrc").exists(), ".vimrc should be removed");
@@ -424,8 +544,1621 @@ fn test_uninstall_with_blacklist() -> Result<()> {
     assert!(temp_home.join(".git/config").exists(), ".git/config should not be removed (blacklisted)");
     assert!(temp_home.join("node_modules/some_package/index.js").exists(), "node_modules file should not be removed (blacklisted)");
     assert!(temp_home.join(".DS_Store").exists(), ".DS_Store should not be removed (blacklisted)");
+    assert!(temp_home.join(".zshrc").exists(), ".zshrc should not be removed (ignored in the config)");
     
     cleanup_test_env();
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_generate_completions() -> Result<()> {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+        generate_completions(shell)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_completion_candidates() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "bashrc")?;
+    create_test_file(&source_dir.join(".config/nvim/init.vim"), "init")?;
+    let config = Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+
+    let paths = managed_paths(&config, &temp_home)?;
+    assert_eq!(paths.into_iter().collect::<Vec<_>>(), [".bashrc", ".config", ".config/nvim", ".config/nvim/init.vim"].map(PathBuf::from));
+
+    let fish = temp_home.join(".config/fish/config.fish");
+    create_test_file(&fish, "old fish")?;
+    crate::backup::backup_file(&fish, &backups(&backup_dir), false)?;
+    assert_eq!(backed_up_files(&temp_home, &backup_dir).into_iter().collect::<Vec<_>>(), [PathBuf::from(".config/fish/config.fish")]);
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_stow_layout() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&source_dir)?;
+
+    let config = Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        layout: Layout::Stow,
+        ..Default::default()
+    };
+    write_config(&config)?;
+
+    create_test_file(&source_dir.join("vim/.vimrc"), "set number")?;
+    create_test_file(&source_dir.join("fish/.config/fish/config.fish"), "set -x EDITOR vim")?;
+    create_test_file(&source_dir.join("README.md"), "not a package")?;
+
+    install_dotfiles(&InstallOptions::default())?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set number", "Package contents should map to home");
+    assert!(temp_home.join(".config/fish/config.fish").exists());
+    assert!(!temp_home.join("vim").exists(), "Package directories should not be created in home");
+    assert!(!temp_home.join("README.md").exists());
+
+    crate::commands::status_dotfiles(&StatusOptions::default())?;
+
+    uninstall_dotfiles(false, false, false, false)?;
+    assert!(!temp_home.join(".vimrc").exists(), "Uninstall should follow the stow layout");
+    assert!(!temp_home.join(".config/fish/config.fish").exists());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_home_relative_path() -> Result<()> {
+    let home = Path::new("/home/user");
+
+    assert_eq!(home_relative_path("~/.config/nvim/init.vim", home)?, PathBuf::from(".config/nvim/init.vim"));
+    assert_eq!(home_relative_path("/home/user/.bashrc", home)?, PathBuf::from(".bashrc"));
+    assert_eq!(home_relative_path(".zshrc", home)?, PathBuf::from(".zshrc"));
+    assert!(home_relative_path("/etc/hosts", home).is_err(), "Paths outside home should be rejected");
+
+    Ok(())
+}
+
+#[test]
+fn test_find_source_path() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    create_test_file(&source_dir.join("fish/.config/fish/config.fish"), "set -x EDITOR vim")?;
+
+    assert_eq!(find_source_path(source_dir, Layout::Plain, Path::new(".vimrc"))?, Some(source_dir.join(".vimrc")));
+    assert_eq!(find_source_path(source_dir, Layout::Plain, Path::new(".missing"))?, None);
+    assert_eq!(find_source_path(source_dir, Layout::Stow, Path::new(".config/fish/config.fish"))?,
+        Some(source_dir.join("fish/.config/fish/config.fish")));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_dotfile_reinstalls() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&source_dir)?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".vimrc"), "set nocompatible")?;
+    create_test_file(&temp_home.join(".vimrc"), "local edits")?;
+
+    // Stand-in editor that rewrites the file it is given
+    let editor = temp_dir.path().join("fake-editor");
+    create_test_file(&editor, "#!/bin/sh\nprintf 'set number' > \"$1\"\n")?;
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755))?;
+
+    crate::commands::edit_dotfile_with("~/.vimrc", editor.to_str().unwrap(), true)?;
+
+    assert_eq!(fs::read_to_string(source_dir.join(".vimrc"))?, "set number", "Source file should be edited");
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set number", "Edited file should be reinstalled");
+    assert_eq!(fs::read_dir(&backup_dir)?.count(), 1, "Replaced home file should be backed up");
+
+    assert!(crate::commands::edit_dotfile_with("~/.missing", editor.to_str().unwrap(), true).is_err());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_find_target_path() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path().join("source");
+    let home = Path::new("/home/user");
+
+    create_test_file(&source_dir.join(".config/nvim/init.vim"), "set number")?;
+    create_test_file(&source_dir.join(".git/config"), "[core]")?;
+    create_test_file(&source_dir.join("vim/.vimrc"), "set number")?;
+
+    assert_eq!(find_target_path(&source_dir, Layout::Plain, home, Path::new(".config/nvim/init.vim"))?,
+        home.join(".config/nvim/init.vim"));
+    assert_eq!(find_target_path(&source_dir, Layout::Plain, home, &source_dir.join(".config/nvim/init.vim"))?,
+        home.join(".config/nvim/init.vim"));
+    assert_eq!(find_target_path(&source_dir, Layout::Stow, home, Path::new("vim/.vimrc"))?, home.join(".vimrc"));
+    assert!(find_target_path(&source_dir, Layout::Plain, home, Path::new(".git/config")).is_err(), "Blacklisted files are not installed");
+    assert!(find_target_path(&source_dir, Layout::Plain, home, Path::new(".missing")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_which_dotfile() -> Result<()> {
+    let (temp_dir, _, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    which_dotfile("~/.bashrc", false)?;
+    which_dotfile(".bashrc", true)?;
+    assert!(which_dotfile("~/.unmanaged", false).is_err(), "Unmanaged paths should be reported as errors");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_rendered_dotfile() -> Result<()> {
+    let (temp_dir, _, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".gitconfig.tmpl"), "email = {{ email }}\neditor = {{ editor }}\n")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        template_vars: [(String::from("email"), String::from("me@example.com"))].into(),
+        ..Default::default()
+    })?;
+
+    let overrides = [(String::from("editor"), String::from("vim"))];
+    assert_eq!(rendered_dotfile("~/.gitconfig", &overrides)?, b"email = me@example.com\neditor = vim\n");
+    let source_file = source_dir.join(".gitconfig.tmpl");
+    assert_eq!(rendered_dotfile(source_file.to_str().unwrap(), &overrides)?, b"email = me@example.com\neditor = vim\n");
+
+    let err = rendered_dotfile("~/.gitconfig", &[]).expect_err("An undefined variable can't be rendered");
+    assert!(format!("{:#}", err).contains("editor"), "Unexpected error: {:#}", err);
+    assert!(rendered_dotfile("~/.unmanaged", &[]).is_err(), "Unmanaged paths should be reported as errors");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_managed_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path().join("source");
+    let home = Path::new("/home/user");
+
+    create_test_file(&source_dir.join(".zshrc"), "export ZSH=1")?;
+    create_test_file(&source_dir.join(".config/fish/config.fish"), "set -x PATH $PATH")?;
+    create_test_file(&source_dir.join(".git/config"), "[core]")?;
+    create_test_file(&source_dir.join("README.md"), "readme")?;
+
+    let files = managed_files(&source_dir, Layout::Plain, home)?;
+    let relative_paths: Vec<_> = files.iter().map(|f| f.relative_path.clone()).collect();
+
+    assert_eq!(relative_paths, vec![PathBuf::from(".config/fish/config.fish"), PathBuf::from(".zshrc")],
+        "Blacklisted files are excluded and results are sorted");
+    assert_eq!(files[1].source, source_dir.join(".zshrc"));
+    assert_eq!(files[1].target, home.join(".zshrc"));
+
+    let stow_files = managed_files(&source_dir, Layout::Stow, home)?;
+    assert_eq!(stow_files.len(), 1, "Only files inside packages are managed under the stow layout");
+    assert_eq!(stow_files[0].relative_path, PathBuf::from("fish/config.fish"));
+
+    Ok(())
+}
+
+#[test]
+fn test_source_entries_prunes_blacklisted_dirs() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path().join("source");
+
+    create_test_file(&source_dir.join(".config/nvim/init.lua"), "vim.o.number = true")?;
+    create_test_file(&source_dir.join(".config/nvim/node_modules/plugin/index.js"), "module.exports = {}")?;
+
+    let entries: Vec<PathBuf> = source_entries(&source_dir, false, is_blacklisted)
+        .map(|e| e.path().strip_prefix(&source_dir).unwrap().to_path_buf())
+        .collect();
+
+    assert!(entries.contains(&PathBuf::from(".config/nvim/init.lua")));
+    assert!(entries.contains(&PathBuf::from(".config/nvim/node_modules")), "The pruned directory itself is still listed");
+    assert!(!entries.iter().any(|path| path.starts_with(".config/nvim/node_modules/plugin")), "Nothing below it is read");
+
+    Ok(())
+}
+
+#[test]
+fn test_source_entries_sorted() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path().join("source");
+
+    for path in ["zz", ".config/b.toml", "aa", ".config/a.toml", "mm/z", "mm/a"] {
+        create_test_file(&source_dir.join(path), path)?;
+    }
+
+    let entries: Vec<PathBuf> = source_entries(&source_dir, false, is_blacklisted)
+        .map(|e| e.path().strip_prefix(&source_dir).unwrap().to_path_buf())
+        .collect();
+
+    let expected = [".config", ".config/a.toml", ".config/b.toml", "aa", "mm", "mm/a", "mm/z", "zz"];
+    assert_eq!(entries, expected.map(PathBuf::from), "Entries come in name order whatever the filesystem's order");
+
+    Ok(())
+}
+
+#[test]
+fn test_list_managed_files() -> Result<()> {
+    let (temp_dir, _, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    list_managed_files(false)?;
+    list_managed_files(true)?;
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_respects_gitignore() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".gitignore"), "*.zwc\n")?;
+    create_test_file(&source_dir.join(".zshrc"), "autoload -U compinit")?;
+    create_test_file(&source_dir.join(".zshrc.zwc"), "compiled")?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(temp_home.join(".zshrc.zwc").exists(), "The .gitignore is only used when asked to");
+
+    fs::remove_file(temp_home.join(".zshrc.zwc"))?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        respect_gitignore: true,
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions { force: true, ..Default::default() })?;
+    assert!(temp_home.join(".zshrc").exists());
+    assert!(!temp_home.join(".zshrc.zwc").exists(), "Files the repository ignores aren't installed");
+    assert!(managed_files(&source_dir, Layout::Plain, &temp_home)?.iter().all(|file| file.relative_path != Path::new(".zshrc.zwc")));
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_walk_limits() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    for path in [".bashrc", ".config/nvim/init.lua", ".config/nvim/pack/vendor/start/plugin/init.lua", ".config/nvim/.github/workflow.yml", ".config/nvim/.luarc.json"] {
+        create_test_file(&source_dir.join(path), path)?;
+    }
+    let managed = |source_dir: &Path| -> Result<Vec<PathBuf>> {
+        Ok(managed_files(source_dir, Layout::Plain, &temp_home)?.into_iter().map(|file| file.relative_path).collect())
+    };
+
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        max_depth: Some(3),
+        skip_hidden_dirs: true,
+        ..Default::default()
+    })?;
+    assert_eq!(managed(&source_dir)?, [".bashrc", ".config/nvim/.luarc.json", ".config/nvim/init.lua"].map(PathBuf::from),
+        "Deeper trees and nested hidden directories are left out, hidden files and top-level hidden directories are not");
+
+    #[cfg(unix)]
+    {
+        let shared = temp_dir.path().join("shared");
+        create_test_file(&shared.join("aliases"), "alias ll='ls -l'")?;
+        std::os::unix::fs::symlink(&shared, source_dir.join("shared"))?;
+        std::os::unix::fs::symlink(&source_dir, shared.join("loop"))?;
+        write_config(&Config {
+            source_dir: source_dir.to_string_lossy().into_owned(),
+            follow_symlinks: true,
+            ..Default::default()
+        })?;
+
+        let files = managed(&source_dir)?;
+        assert!(files.contains(&PathBuf::from("shared/aliases")), "Symlinked directories are walked");
+        assert!(!files.iter().any(|path| path.starts_with("shared/loop")), "A symlink back to the source directory is skipped");
+    }
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_require_signed() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "alias ll='ls -l'")?;
+    let key = crate::signing::load_signing_key()?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        trusted_key: Some(crate::signing::public_key_hex(&key.verifying_key())),
+        ..Default::default()
+    })?;
+
+    assert!(install_dotfiles(&InstallOptions { require_signed: true, ..Default::default() }).is_err(), "An unsigned tree is refused");
+    assert!(!temp_home.join(".bashrc").exists());
+
+    crate::signing::sign_tree(&source_dir, &key)?;
+    install_dotfiles(&InstallOptions { require_signed: true, ..Default::default() })?;
+    assert!(temp_home.join(".bashrc").exists());
+    assert!(!temp_home.join(crate::signing::SIGNED_MANIFEST_FILE).exists(), "The manifest itself is never installed");
+
+    create_test_file(&source_dir.join(".bashrc"), "tampered")?;
+    assert!(install_dotfiles(&InstallOptions { require_signed: true, force: true, ..Default::default() }).is_err(), "A tampered tree is refused");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "alias ll='ls -l'");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_prune() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".config/old.conf"), "stale=true")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(Manifest::load()?.files.contains_key(Path::new(".config/old.conf")));
+
+    fs::remove_file(source_dir.join(".config/old.conf"))?;
+
+    install_dotfiles(&InstallOptions { prune: true, dry_run: true, ..Default::default() })?;
+    assert!(temp_home.join(".config/old.conf").exists(), "Dry run should not remove stale files");
+
+    install_dotfiles(&InstallOptions { prune: true, force: true, ..Default::default() })?;
+    assert!(!temp_home.join(".config/old.conf").exists(), "Stale file should be pruned");
+    assert!(temp_home.join(".bashrc").exists(), "Managed files should be kept");
+    let backed_up = fs::read_dir(&backup_dir)?
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("old.conf."));
+    assert!(backed_up, "Pruned file should be backed up");
+
+    let manifest = Manifest::load()?;
+    assert!(!manifest.files.contains_key(Path::new(".config/old.conf")));
+    assert!(manifest.files.contains_key(Path::new(".bashrc")));
+
+    restore_backups_with(&RestoreOptions { ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".config/old.conf"))?, "stale=true", "Pruned file comes back from its tombstone");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_clean_broken_symlinks() -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&temp_dir.path().join("elsewhere/kept"), "unrelated")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    fs::create_dir_all(temp_home.join(".config"))?;
+    symlink(source_dir.join(".bashrc"), temp_home.join(".bashrc"))?;
+    symlink(source_dir.join(".vimrc"), temp_home.join(".vimrc"))?;
+    symlink("../../source/.config/moved.conf", temp_home.join(".config/moved.conf"))?;
+    symlink(temp_dir.path().join("elsewhere/missing"), temp_home.join(".unrelated"))?;
+
+    let broken = find_broken_symlinks(&temp_home, &source_dir)?;
+    let links: Vec<_> = broken.iter().map(|b| b.link.strip_prefix(&temp_home).unwrap().to_path_buf()).collect();
+    assert_eq!(links, vec![PathBuf::from(".config/moved.conf"), PathBuf::from(".vimrc")],
+        "Only dangling links into the source directory are reported");
+
+    clean_broken_symlinks(true)?;
+    assert!(temp_home.join(".vimrc").symlink_metadata().is_ok(), "Dry run should not remove symlinks");
+
+    clean_broken_symlinks(false)?;
+    assert!(temp_home.join(".vimrc").symlink_metadata().is_err());
+    assert!(temp_home.join(".config/moved.conf").symlink_metadata().is_err());
+    assert!(temp_home.join(".bashrc").exists(), "Working symlinks should be kept");
+    assert!(temp_home.join(".unrelated").symlink_metadata().is_ok(), "Links outside the source directory should be kept");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_selected_paths() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".config/nvim/init.vim"), "set number")?;
+    create_test_file(&source_dir.join(".config/nvim/lua/plugins.lua"), "return {}")?;
+    create_test_file(&source_dir.join(".config/fish/config.fish"), "set -x EDITOR vim")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions {
+        paths: vec![String::from(".config/nvim"), String::from("~/.bashrc")],
+        ..Default::default()
+    })?;
+
+    assert!(temp_home.join(".config/nvim/init.vim").exists());
+    assert!(temp_home.join(".config/nvim/lua/plugins.lua").exists(), "Subtrees should be installed recursively");
+    assert!(temp_home.join(".bashrc").exists());
+    assert!(!temp_home.join(".config/fish/config.fish").exists(), "Paths outside the selection should be left alone");
+
+    install_dotfiles(&InstallOptions::default())?;
+    fs::remove_file(source_dir.join(".bashrc"))?;
+
+    install_dotfiles(&InstallOptions {
+        paths: vec![String::from(".config/nvim")],
+        prune: true,
+        force: true,
+        ..Default::default()
+    })?;
+    assert!(temp_home.join(".bashrc").exists(), "Pruning should stay within the selected paths");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_excludes() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".bashrc.bak"), "old")?;
+    create_test_file(&source_dir.join(".ssh/config"), "Host *")?;
+    create_test_file(&source_dir.join(".inputrc"), "set editing-mode vi")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ignore: vec![String::from(".inputrc")],
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions {
+        excludes: vec![String::from(".ssh/"), String::from("*.bak")],
+        ..Default::default()
+    })?;
+
+    assert!(temp_home.join(".bashrc").exists());
+    assert!(!temp_home.join(".bashrc.bak").exists(), "Files matching a glob should be excluded");
+    assert!(!temp_home.join(".ssh/config").exists(), "Excluding a directory should exclude its contents");
+    assert!(!temp_home.join(".inputrc").exists(), "Patterns from the config ignore list should apply too");
+
+    assert!(install_dotfiles(&InstallOptions {
+        excludes: vec![String::from("[")],
+        ..Default::default()
+    }).is_err(), "Invalid patterns should be rejected");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_uninstall_backs_up_replaced_file() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".vimrc"), "source vimrc")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    create_test_file(&backup_dir.join(".vimrc.1000000000"), "original vimrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "local edits")?;
+
+    uninstall_dotfiles(false, true, false, false)?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "original vimrc");
+    assert!(!backup_dir.join(".vimrc.1000000000").exists(), "Restored backup should be consumed");
+
+    let saved = crate::backup::find_latest_backup(".vimrc", &backup_dir)?;
+    assert_eq!(fs::read_to_string(saved)?, "local edits", "The replaced file should be backed up before restoring");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_find_extra_files() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".config/nvim/init.vim"), "set number")?;
+    create_test_file(&source_dir.join(".config/nvim/lua/plugins.lua"), "return {}")?;
+
+    create_test_file(&temp_home.join(".config/nvim/init.vim"), "set number")?;
+    create_test_file(&temp_home.join(".config/nvim/lua/plugins.lua"), "return {}")?;
+    create_test_file(&temp_home.join(".config/nvim/lua/local.lua"), "-- forgot to add")?;
+    create_test_file(&temp_home.join(".config/nvim/after/ftplugin/rust.vim"), "setlocal tw=100")?;
+    create_test_file(&temp_home.join(".config/other/settings.ini"), "unrelated")?;
+    create_test_file(&temp_home.join(".profile"), "unrelated")?;
+
+    let files = managed_files(&source_dir, Layout::Plain, &temp_home)?;
+    let extras = find_extra_files(&files, &temp_home)?;
+
+    assert_eq!(extras, vec![PathBuf::from(".config/nvim/after"), PathBuf::from(".config/nvim/lua/local.lua")],
+        "Only entries inside directories holding managed files are reported");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_fix_differences() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "source bashrc")?;
+    create_test_file(&source_dir.join(".vimrc"), "source vimrc")?;
+    create_test_file(&source_dir.join(".zshrc"), "source zshrc")?;
+    create_test_file(&source_dir.join(".inputrc"), "source inputrc")?;
+    create_test_file(&source_dir.join(".gitconfig"), "same")?;
+
+    create_test_file(&temp_home.join(".bashrc"), "local bashrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "local vimrc")?;
+    create_test_file(&temp_home.join(".inputrc"), "local inputrc")?;
+    create_test_file(&temp_home.join(".gitconfig"), "same")?;
+
+    let files = managed_files(&source_dir, Layout::Plain, &temp_home)?;
+    let mut prompted = Vec::new();
+
+    fix_differences_with(&files, &source_dir, &backups(&backup_dir), &DirModes::default(), &Default::default(), None, |file, missing| {
+        prompted.push(file.relative_path.clone());
+        Ok(match file.relative_path.to_str().unwrap() {
+            ".bashrc" => FixAction::Reinstall,
+            ".inputrc" => FixAction::Ignore,
+            ".vimrc" if prompted.iter().filter(|p| p.as_path() == Path::new(".vimrc")).count() == 1 => FixAction::ShowDiff,
+            ".vimrc" => FixAction::Adopt,
+            ".zshrc" => {
+                assert!(missing, "Missing files should be reported as such");
+                FixAction::Reinstall
+            },
+            other => panic!("Unexpected prompt for {}", other),
+        })
+    })?;
+
+    assert_eq!(prompted.len(), 5, "Identical files are skipped and the diff view re-prompts");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "source bashrc");
+    assert_eq!(fs::read_to_string(source_dir.join(".vimrc"))?, "local vimrc", "Adopt copies the local file into the source");
+    assert_eq!(fs::read_to_string(temp_home.join(".zshrc"))?, "source zshrc");
+    assert_eq!(fs::read_to_string(temp_home.join(".inputrc"))?, "local inputrc");
+    assert!(crate::backup::find_latest_backup(".bashrc", &backup_dir).is_ok(), "Reinstalling over a local change backs it up");
+
+    let mut calls = 0;
+    create_test_file(&temp_home.join(".bashrc"), "changed again")?;
+    fix_differences_with(&files, &source_dir, &backups(&backup_dir), &DirModes::default(), &Default::default(), None, |_, _| {
+        calls += 1;
+        Ok(FixAction::Quit)
+    })?;
+    assert_eq!(calls, 1, "Quit stops the walk");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_changed_only() -> Result<()> {
+    use crate::git::{run_git, head_commit};
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    // Ignoring files in the config doesn't make every install partial
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ignore: vec!["*.swp".to_string()],
+        ..Default::default()
+    })?;
+
+    let commit_all = |message: &str| -> Result<()> {
+        run_git(&source_dir, &["add", "-A"])?;
+        run_git(&source_dir, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", message])?;
+        Ok(())
+    };
+    run_git(&source_dir, &["init", "-q"])?;
+    commit_all("initial")?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(Manifest::load()?.commit, Some(head_commit(&source_dir)?), "The installed commit should be recorded");
+
+    fs::remove_file(temp_home.join(".bashrc"))?;
+    create_test_file(&source_dir.join(".vimrc"), "set relativenumber")?;
+    commit_all("update vimrc")?;
+
+    install_dotfiles(&InstallOptions { changed_only: true, force: true, ..Default::default() })?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set relativenumber");
+    assert!(!temp_home.join(".bashrc").exists(), "Files unchanged since the last install should be left alone");
+    assert_eq!(Manifest::load()?.commit, Some(head_commit(&source_dir)?));
+
+    crate::commands::status_dotfiles(&StatusOptions::default())?;
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_since() -> Result<()> {
+    use crate::git::run_git;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    create_test_file(&source_dir.join(".config/nvim/init.lua"), "vim.o.number = true")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    let commit_all = |message: &str| -> Result<()> {
+        run_git(&source_dir, &["add", "-A"])?;
+        run_git(&source_dir, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", message])?;
+        Ok(())
+    };
+    run_git(&source_dir, &["init", "-q"])?;
+    commit_all("initial")?;
+    run_git(&source_dir, &["tag", "v1"])?;
+
+    create_test_file(&source_dir.join(".config/nvim/init.lua"), "vim.o.number = false")?;
+    commit_all("update nvim")?;
+
+    install_dotfiles(&InstallOptions { since: Some(String::from("v1")), ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".config/nvim/init.lua"))?, "vim.o.number = false");
+    assert!(!temp_home.join(".bashrc").exists(), "Files unchanged since the ref should be left alone");
+    assert_eq!(Manifest::load()?.commit, None, "Installing only some changes doesn't make the install complete");
+
+    install_dotfiles(&InstallOptions { changed_only: true, ..Default::default() })?;
+    assert!(temp_home.join(".bashrc").exists(), "--changed-only should still install what --since left out");
+
+    assert!(install_dotfiles(&InstallOptions { since: Some(String::from("missing")), ..Default::default() }).is_err(),
+        "An unknown ref is an error rather than a full install");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_ref() -> Result<()> {
+    use crate::git::run_git;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    let commit_all = |message: &str| -> Result<()> {
+        run_git(&source_dir, &["add", "-A"])?;
+        run_git(&source_dir, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", message])?;
+        Ok(())
+    };
+    run_git(&source_dir, &["init", "-q"])?;
+    commit_all("initial")?;
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=nvim")?;
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    commit_all("switch editor")?;
+    create_test_file(&source_dir.join(".bashrc"), "half-edited")?;
+    // Repository settings come from the ref too, not from the working copy
+    create_test_file(&source_dir.join(".dotfiles-rust.yaml"), "ignore: ['.bashrc']")?;
+
+    install_dotfiles(&InstallOptions { git_ref: Some(String::from("HEAD~1")), ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "export EDITOR=vim");
+    assert!(!temp_home.join(".vimrc").exists(), "Only files in the ref are installed");
+    assert_eq!(fs::read_to_string(source_dir.join(".bashrc"))?, "half-edited", "The working copy is left alone");
+    assert_eq!(Manifest::load()?.commit, Some(run_git(&source_dir, &["rev-parse", "HEAD~1"])?));
+    assert!(!temp_home.join(".local/share/dotfiles-rust").read_dir()?.flatten()
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("checkout-")), "The checkout is removed afterwards");
+
+    assert!(install_dotfiles(&InstallOptions { git_ref: Some(String::from("missing")), ..Default::default() }).is_err());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_clean_install() -> Result<()> {
+    use crate::git::run_git;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        clean_install: true,
+        ..Default::default()
+    })?;
+
+    run_git(&source_dir, &["init", "-q"])?;
+    run_git(&source_dir, &["add", "-A"])?;
+    run_git(&source_dir, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", "initial"])?;
+    create_test_file(&source_dir.join(".bashrc"), "half-edited")?;
+    create_test_file(&source_dir.join(".vimrc"), "not committed yet")?;
+    create_test_file(&source_dir.join(".dotfiles-rust.yaml"), "ignore: ['.bashrc']")?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "export EDITOR=vim", "Uncommitted edits aren't installed");
+    assert!(!temp_home.join(".vimrc").exists(), "Untracked files aren't installed");
+    assert!(Manifest::load()?.files.keys().any(|path| path == Path::new(".bashrc")), "An uncommitted repository config doesn't apply");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_push_source() -> Result<()> {
+    use crate::git::run_git;
+
+    let (temp_dir, _, _) = setup_test_env()?;
+
+    let origin = temp_dir.path().join("origin.git");
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&origin)?;
+    run_git(&origin, &["init", "-q", "--bare"])?;
+    run_git(temp_dir.path(), &["clone", "-q", origin.to_str().unwrap(), source_dir.to_str().unwrap()])?;
+    run_git(&source_dir, &["config", "user.name", "Test"])?;
+    run_git(&source_dir, &["config", "user.email", "test@example.com"])?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+
+    crate::commands::push_source(None, true)?;
+    assert!(run_git(&source_dir, &["log"]).is_err(), "Dry run should not commit");
+
+    crate::commands::push_source(None, false)?;
+    assert_eq!(run_git(&origin, &["log", "-1", "--format=%s"])?, "Update .bashrc");
+
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    crate::commands::push_source(Some("Add vim config"), false)?;
+    assert_eq!(run_git(&origin, &["log", "-1", "--format=%s"])?, "Add vim config");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_templates() -> Result<()> {
+    use crate::template::load_template_data;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".hgrc.tmpl"), "[ui]\nusername = {{ name }} <{{ email }}>\n")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        template_vars: [(String::from("email"), String::from("me@example.com"))].into(),
+        ..Default::default()
+    })?;
+
+    assert!(install_dotfiles(&InstallOptions::default()).is_err(),
+        "Undefined variables fail the install when there is no terminal to prompt on");
+    assert!(!temp_home.join(".hgrc").exists());
+
+    install_dotfiles(&InstallOptions {
+        set_vars: vec![(String::from("name"), String::from("Jane"))],
+        ..Default::default()
+    })?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".hgrc"))?, "[ui]\nusername = Jane <me@example.com>\n");
+    assert!(!temp_home.join(".hgrc.tmpl").exists(), "Templates install without their suffix");
+    assert_eq!(load_template_data()?.get("name").map(String::as_str), Some("Jane"), "Answers are saved for later runs");
+    assert_eq!(find_source_path(&source_dir, Layout::Plain, Path::new(".hgrc"))?, Some(source_dir.join(".hgrc.tmpl")));
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(fs::read_to_string(temp_home.join(".hgrc"))?, "[ui]\nusername = Jane <me@example.com>\n",
+        "Saved answers are reused and the rendered file counts as unchanged");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_assembled_fragments() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".zshrc.d/.dotfiles.yaml"), "assemble: true\n")?;
+    create_test_file(&source_dir.join(".zshrc.d/20-aliases.zsh"), "alias ll='ls -l'\n")?;
+    create_test_file(&source_dir.join(".zshrc.d/10-env.zsh.tmpl"), "export EDITOR={{ editor }}")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        template_vars: [(String::from("editor"), String::from("nvim"))].into_iter().collect(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+
+    let installed = temp_home.join(".zshrc");
+    assert_eq!(fs::read_to_string(&installed)?, "export EDITOR=nvim\nalias ll='ls -l'\n", "Fragments are joined in name order");
+    assert!(!temp_home.join(".zshrc.d").exists(), "Fragments aren't installed on their own");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&installed)?.permissions().mode() & 0o111, 0, "The directory's mode isn't copied");
+    }
+
+    let config = crate::config::read_config()?;
+    let vars = crate::template::template_vars(&config, &[])?;
+    let files = crate::commands::configured_files(&config, &temp_home)?;
+    assert_eq!(files.len(), 1);
+    assert!(crate::verify::verify_against_source(&files, &vars)?.is_empty());
+
+    create_test_file(&source_dir.join(".zshrc.d/30-path.zsh"), "export PATH=~/bin:$PATH\n")?;
+    assert_eq!(crate::verify::verify_against_source(&files, &vars)?.len(), 1, "A new fragment is drift in the assembled file");
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(fs::read_to_string(&installed)?.ends_with("export PATH=~/bin:$PATH\n"),
+        "The untouched assembled file is updated without --force");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_and_uninstall_patches() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".config/app/settings.ini.patch"), "@@ -1,2 +1,2 @@\n [ui]\n-theme = light\n+theme = dark\n")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(!temp_home.join(".config/app/settings.ini").exists(), "Nothing is patched until the file exists");
+
+    let settings = temp_home.join(".config/app/settings.ini");
+    create_test_file(&settings, "[ui]\ntheme = light\nfont = mono\n")?;
+    install_dotfiles(&InstallOptions { backup: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(&settings)?, "[ui]\ntheme = dark\nfont = mono\n");
+    assert!(!temp_home.join(".config/app/settings.ini.patch").exists(), "The patch itself isn't installed");
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(fs::read_to_string(&settings)?, "[ui]\ntheme = dark\nfont = mono\n", "Patches are applied once");
+
+    uninstall_dotfiles(false, false, false, false)?;
+    assert_eq!(fs::read_to_string(&settings)?, "[ui]\ntheme = light\nfont = mono\n", "Uninstall reverses the patch");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_with_dir_config() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join("bin/.dotfiles.yaml"), "target: .local/bin\nmode: \"755\"\nignore: [\"*.bak\"]\n")?;
+    create_test_file(&source_dir.join("bin/backup.sh"), "#!/bin/sh")?;
+    create_test_file(&source_dir.join("bin/backup.sh.bak"), "#!/bin/sh")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+
+    let installed = temp_home.join(".local/bin/backup.sh");
+    assert!(installed.exists(), "The subtree installs to its configured target");
+    assert!(!temp_home.join("bin").exists());
+    assert!(!temp_home.join(".local/bin/backup.sh.bak").exists(), "Directory ignore patterns are applied");
+    assert!(!temp_home.join(".local/bin/.dotfiles.yaml").exists(), "Directory configs are never installed");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&installed)?.permissions().mode() & 0o777, 0o755);
+    }
+
+    assert_eq!(find_source_path(&source_dir, Layout::Plain, Path::new(".local/bin/backup.sh"))?, Some(source_dir.join("bin/backup.sh")));
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_runs_hooks() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    create_test_file(&source_dir.join(".dotfiles-rust/post-install.rhai"), r#"
+        for file in status() {
+            if file.state != "installed" { throw file.path + " is " + file.state; }
+        }
+    "#)?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".dotfiles-rust/pre-install.rhai"), r#"throw "abort";"#)?;
+    assert!(install_dotfiles(&InstallOptions::default()).is_err(), "A failing pre-install hook stops the install");
+    assert!(!temp_home.join(".vimrc").exists());
+
+    fs::remove_file(source_dir.join(".dotfiles-rust/pre-install.rhai"))?;
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(temp_home.join(".vimrc").exists());
+    assert!(!temp_home.join(".dotfiles-rust").exists(), "Scripts are never installed");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_install_makes_bin_files_executable() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".local/bin/backup"), "#!/bin/sh")?;
+    create_test_file(&source_dir.join("scripts/sync"), "#!/bin/sh")?;
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    for file in [".local/bin/backup", "scripts/sync", ".vimrc"] {
+        fs::set_permissions(source_dir.join(file), fs::Permissions::from_mode(0o644))?;
+    }
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        bin_dirs: vec![String::from("scripts")],
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+
+    let mode = |path: &str| -> Result<u32> { Ok(fs::metadata(temp_home.join(path))?.permissions().mode() & 0o777) };
+    assert_eq!(mode(".local/bin/backup")?, 0o755);
+    assert_eq!(mode("scripts/sync")?, 0o755, "Configured bin directories are handled too");
+    assert_eq!(mode(".vimrc")?, 0o644);
+
+    let files = crate::commands::configured_files(&crate::config::read_config()?, &temp_home)?;
+    assert!(crate::verify::verify_against_source(&files, &Default::default())?.is_empty(),
+        "Executable bits added on install aren't reported as permission drift");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_install_keeps_sensitive_files_private() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".ssh/config"), "Host *")?;
+    create_test_file(&source_dir.join(".netrc"), "machine example.com")?;
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    for file in [".ssh/config", ".netrc", ".vimrc"] {
+        fs::set_permissions(source_dir.join(file), fs::Permissions::from_mode(0o644))?;
+    }
+    write_config(&Config { source_dir: source_dir.to_str().unwrap().to_string(), ..Default::default() })?;
+    fs::set_permissions(&temp_home, fs::Permissions::from_mode(0o755))?;
+
+    install_dotfiles(&InstallOptions::default())?;
+
+    let mode = |path: &Path| -> Result<u32> { Ok(fs::metadata(path)?.permissions().mode() & 0o777) };
+    assert_eq!(mode(&temp_home.join(".ssh/config"))?, 0o600);
+    assert_eq!(mode(&temp_home.join(".ssh"))?, 0o700);
+    assert_eq!(mode(&temp_home.join(".netrc"))?, 0o600);
+    assert_eq!(mode(&temp_home.join(".vimrc"))?, 0o644);
+    assert_eq!(mode(&temp_home)?, 0o755, "The home directory itself is left alone");
+
+    let files = crate::commands::configured_files(&crate::config::read_config()?, &temp_home)?;
+    assert!(crate::verify::verify_against_source(&files, &Default::default())?.is_empty(),
+        "The private mode isn't reported as permission drift");
+
+    // Loosened by hand since the last install
+    fs::set_permissions(temp_home.join(".netrc"), fs::Permissions::from_mode(0o644))?;
+    create_test_file(&temp_home.join(".ssh/config"), "Host local")?;
+    fs::set_permissions(temp_home.join(".ssh/config"), fs::Permissions::from_mode(0o644))?;
+    install_dotfiles(&InstallOptions { force_local: true, backup: true, ..Default::default() })?;
+
+    assert_eq!(mode(&temp_home.join(".netrc"))?, 0o600, "Unchanged files are tightened too");
+    let backup = fs::read_dir(&backup_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("config.")))
+        .expect("the local .ssh/config is backed up");
+    assert_eq!(mode(&backup)?, 0o600);
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_skips_privileged_targets_without_apply_system() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    let system_dir = temp_dir.path().join("etc");
+    create_test_file(&source_dir.join("etc/.dotfiles.yaml"), &format!("target: {}\n", system_dir.display()))?;
+    create_test_file(&source_dir.join("etc/hosts"), "127.0.0.1 localhost")?;
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        ..Default::default()
+    })?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(temp_home.join(".vimrc").exists());
+    assert!(!system_dir.join("hosts").exists(), "Targets outside home need --apply-system");
+
+    install_dotfiles(&InstallOptions { apply_system: true, dry_run: true, ..Default::default() })?;
+    assert!(!system_dir.join("hosts").exists());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_apply_system_files() -> Result<()> {
+    let (temp_dir, _, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    let system_dir = temp_dir.path().join("etc");
+    create_test_file(&source_dir.join("etc/.dotfiles.yaml"), &format!("target: {}\n", system_dir.display()))?;
+    create_test_file(&source_dir.join("etc/zsh/zshenv"), "export ZDOTDIR=~/.zsh")?;
+    create_test_file(&system_dir.join("zsh/zshenv"), "# system default")?;
+    // `env` runs the escalated commands as they are, so the test needs no root
+    write_config(&Config {
+        source_dir: source_dir.to_str().unwrap().to_string(),
+        escalation_command: Some(String::from("env")),
+        ..Default::default()
+    })?;
+    let options = InstallOptions { apply_system: true, backup: true, ..Default::default() };
+
+    set_answers(AnswerMode::Ask, &[(String::from("sudo"), String::from("no"))]);
+    let report = install_dotfiles(&options)?;
+    assert_eq!(report.count(InstallOutcome::Skipped), 1, "Nothing is written without confirmation");
+    assert_eq!(fs::read_to_string(system_dir.join("zsh/zshenv"))?, "# system default");
+
+    set_answers(AnswerMode::Ask, &[(String::from("sudo"), String::from("yes"))]);
+    let report = install_dotfiles(&options)?;
+    assert_eq!(report.count(InstallOutcome::Copied), 1);
+    assert_eq!(fs::read_to_string(system_dir.join("zsh/zshenv"))?, "export ZDOTDIR=~/.zsh");
+
+    set_answers(AnswerMode::Ask, &[]);
+    let report = install_dotfiles(&options)?;
+    assert_eq!(report.count(InstallOutcome::SkippedIdentical), 1, "Unchanged system files aren't confirmed again");
+
+    assert!(fs::read_dir(&backup_dir)?.count() > 0);
+    restore_backups_with(&RestoreOptions { ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(system_dir.join("zsh/zshenv"))?, "export ZDOTDIR=~/.zsh", "Restoring everything leaves system files alone");
+
+    set_answers(AnswerMode::Ask, &[(String::from("sudo"), String::from("yes"))]);
+    restore_backups_with(&RestoreOptions { apply_system: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(system_dir.join("zsh/zshenv"))?, "# system default");
+
+    set_answers(AnswerMode::Ask, &[]);
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_selected_files() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config { source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(), ..Default::default() })?;
+
+    create_test_file(&temp_home.join(".bashrc"), "current bashrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "current vimrc")?;
+    create_test_file(&backup_dir.join(".bashrc.100"), "old bashrc")?;
+    create_test_file(&backup_dir.join(".vimrc.100"), "old vimrc")?;
+
+    let selected = BTreeSet::from([temp_home.join(".vimrc")]);
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, selected: Some(selected), ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "old vimrc");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "current bashrc", "Files left unticked aren't restored");
+
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into()], keep_backups: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "old bashrc");
+    assert_eq!(fs::read_to_string(backup_dir.join(".bashrc.100"))?, "old bashrc", "A kept backup stays where it was");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_asks_before_overwriting_newer_edits() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config { source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(), ..Default::default() })?;
+
+    // The backup is from 1970, so the current file was edited after it was taken
+    create_test_file(&temp_home.join(".bashrc"), "this morning's bashrc")?;
+    create_test_file(&backup_dir.join(".bashrc.100"), "old bashrc")?;
+
+    set_answers(AnswerMode::Ask, &[(String::from("overwrite-newer"), String::from("no"))]);
+    restore_backups_with(&RestoreOptions { files: vec![".bashrc".into()], ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "this morning's bashrc", "Newer edits aren't overwritten without confirmation");
+
+    set_answers(AnswerMode::Ask, &[]);
+    restore_backups_with(&RestoreOptions { files: vec![".bashrc".into()], overwrite_newer: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "old bashrc");
+
+    // A file still holding what install wrote is restored without asking, however recent
+    let source_dir = temp_home.join("dotfiles");
+    create_test_file(&source_dir.join(".vimrc"), "installed vimrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "original vimrc")?;
+    install_dotfiles(&InstallOptions { force: true, backup: true, ..Default::default() })?;
+    restore_backups_with(&RestoreOptions { files: vec![".vimrc".into()], ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "original vimrc");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_backups_with_preview() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config {
+        source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&temp_home.join(".bashrc"), "current bashrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "current vimrc")?;
+    create_test_file(&backup_dir.join(".bashrc.100"), "old bashrc")?;
+    create_test_file(&backup_dir.join(".vimrc.100"), "old vimrc")?;
+
+    let mut prompted = Vec::new();
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, preview: true, ..Default::default() }, |target| {
+        prompted.push(target.file_name().unwrap().to_string_lossy().into_owned());
+        Ok(if target.ends_with(".bashrc") { RestoreChoice::Restore } else { RestoreChoice::Skip })
+    })?;
+
+    assert_eq!(prompted, vec![".bashrc", ".vimrc"], "Every file is confirmed separately");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "old bashrc");
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "current vimrc", "Declined files are left alone");
+    assert_eq!(fs::read_to_string(crate::backup::find_latest_backup(".bashrc", &backup_dir)?)?, "current bashrc",
+        "The replaced file is backed up");
+
+    // Without --preview nothing is asked
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".vimrc".into()], version: Some("100".into()), ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "old vimrc");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_backup_content() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config { source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(), ..Default::default() })?;
+
+    create_test_file(&temp_home.join(".vimrc"), "current vimrc")?;
+    create_test_file(&backup_dir.join(".vimrc.100"), "older vimrc")?;
+    create_test_file(&backup_dir.join(".vimrc.200"), "old vimrc")?;
+
+    assert_eq!(backup_content(".vimrc", "100")?, b"older vimrc");
+    assert_eq!(backup_content("~/.vimrc", "latest")?, b"old vimrc");
+    assert!(backup_content(".vimrc", "300").is_err());
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "current vimrc", "Nothing is restored");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_several_files() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config { source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(), ..Default::default() })?;
+
+    create_test_file(&temp_home.join(".bashrc"), "current bashrc")?;
+    create_test_file(&temp_home.join(".vimrc"), "current vimrc")?;
+    create_test_file(&backup_dir.join(".bashrc.100"), "old bashrc")?;
+    create_test_file(&backup_dir.join(".vimrc.100"), "old vimrc")?;
+
+    set_answers(AnswerMode::Ask, &[(String::from("restore-files"), String::from("no"))]);
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into(), ".vimrc".into()], ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "current bashrc", "Nothing is restored without confirmation");
+
+    assert!(restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into(), ".vimrc".into()], version: Some("100".into()), ..Default::default() }, |_| panic!("Should not prompt")).is_err(),
+        "A version only names the backup of one file");
+    assert!(restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into(), ".zshrc".into()], ..Default::default() }, |_| panic!("Should not prompt")).is_err());
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "current bashrc", "Every backup is found before anything changes");
+
+    // .config is a file, so the last restore fails and the first is undone
+    create_test_file(&temp_home.join(".config"), "not a directory")?;
+    create_test_file(&backup_dir.join("app.conf.100"), "old app.conf")?;
+    set_answers(AnswerMode::Ask, &[(String::from("restore-files"), String::from("yes"))]);
+    assert!(restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into(), ".config/app.conf".into()], ..Default::default() }, |_| panic!("Should not prompt")).is_err());
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "current bashrc");
+    assert_eq!(fs::read_to_string(backup_dir.join(".bashrc.100"))?, "old bashrc", "The backup is put back too");
+
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".bashrc".into(), ".vimrc".into()], ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "old bashrc");
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "old vimrc");
+
+    set_answers(AnswerMode::Ask, &[]);
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_rollback_keeps_backup_records() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config { source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(), ..Default::default() })?;
+
+    // Two files with the same name, told apart only by the index
+    let fish = temp_home.join(".config/fish/config.fish");
+    let other = temp_home.join(".config/other/config.fish");
+    create_test_file(&other, "old other")?;
+    crate::backup::backup_file(&other, &backups(&backup_dir), false)?;
+    let other_backup = find_latest_backup_of(&other, &backup_dir)?;
+    create_test_file(&fish, "old fish")?;
+    crate::backup::backup_file(&fish, &backups(&backup_dir), false)?;
+    let fish_backup = find_latest_backup_of(&fish, &backup_dir)?;
+    create_test_file(&fish, "current fish")?;
+    create_test_file(&temp_home.join(".bashrc"), "current bashrc")?;
+    create_test_file(&backup_dir.join(".bashrc.100"), "old bashrc")?;
+
+    // .vim is a file, so the last restore fails and the others are undone. Restoring .bashrc saves the backup
+    // index after the restored fish backup is gone.
+    create_test_file(&temp_home.join(".vim"), "not a directory")?;
+    create_test_file(&backup_dir.join("app.conf.100"), "old app.conf")?;
+    set_answers(AnswerMode::Ask, &[(String::from("restore-files"), String::from("yes"))]);
+    let files = vec![".config/fish/config.fish".into(), ".bashrc".into(), ".vim/app.conf".into()];
+    assert!(restore_backups_with(&RestoreOptions { overwrite_newer: true, files, ..Default::default() }, |_| panic!("Should not prompt")).is_err());
+
+    assert_eq!(fs::read_to_string(&fish)?, "current fish");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "current bashrc");
+    assert_eq!(find_latest_backup_of(&fish, &backup_dir)?, fish_backup, "The put back backup is found again");
+    assert_eq!(find_latest_backup_of(&other, &backup_dir)?, other_backup,
+        "The put back backup still belongs to its own file, not to every file with its name");
+
+    set_answers(AnswerMode::Ask, &[]);
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_uses_recorded_locations() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+    write_config(&Config {
+        source_dir: temp_home.join("dotfiles").to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    let fish = temp_home.join(".config/fish/config.fish");
+    let other = temp_home.join(".config/other/config.fish");
+    create_test_file(&fish, "old fish")?;
+    create_test_file(&other, "old other")?;
+    crate::backup::backup_file(&fish, &backups(&backup_dir), false)?;
+    crate::backup::backup_file(&other, &backups(&backup_dir), false)?;
+    create_test_file(&fish, "new fish")?;
+    create_test_file(&other, "new other")?;
+
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, files: vec![".config/fish/config.fish".into()], ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(&fish)?, "old fish", "The backup taken from that path is restored, not the newest of the name");
+
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(&other)?, "old other", "Nested files go back where they came from");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_dry_run_changes_nothing() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let fish = temp_home.join(".config/fish/config.fish");
+    let bashrc = temp_home.join(".bashrc");
+    create_test_file(&fish, "old fish")?;
+    create_test_file(&bashrc, "old bashrc")?;
+    crate::backup::backup_file(&fish, &backups(&backup_dir), false)?;
+    crate::backup::backup_file(&bashrc, &backups(&backup_dir), false)?;
+    fs::remove_dir_all(temp_home.join(".config"))?;
+    create_test_file(&bashrc, "new bashrc")?;
+    let backups = fs::read_dir(&backup_dir)?.count();
+
+    restore_backups_with(&RestoreOptions { dry_run: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert!(!temp_home.join(".config").exists(), "Missing directories are not created");
+    assert_eq!(fs::read_to_string(&bashrc)?, "new bashrc");
+    assert_eq!(fs::read_dir(&backup_dir)?.count(), backups, "Backups are neither taken nor used up");
+
+    restore_backups_with(&RestoreOptions { overwrite_newer: true, ..Default::default() }, |_| panic!("Should not prompt"))?;
+    assert_eq!(fs::read_to_string(&fish)?, "old fish");
+    assert_eq!(fs::read_to_string(&bashrc)?, "old bashrc");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_restore_suggests_backed_up_file() -> Result<()> {
+    let (_temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let fish = temp_home.join(".config/fish/config.fish");
+    create_test_file(&fish, "old fish")?;
+    crate::backup::backup_file(&fish, &backups(&backup_dir), false)?;
+
+    let err = restore_backups_with(&RestoreOptions { files: vec!["~/.config/fish/config.fsh".into()], ..Default::default() }, |_| panic!("Should not prompt"))
+        .expect_err("A file without backups can't be restored");
+    assert!(err.to_string().contains("did you mean `~/.config/fish/config.fish`?"), "Unexpected error: {}", err);
+
+    let err = restore_backups_with(&RestoreOptions { files: vec!["~/.gitconfig".into()], ..Default::default() }, |_| panic!("Should not prompt"))
+        .expect_err("A file without backups can't be restored");
+    assert!(!err.to_string().contains("did you mean"), "Nothing is close enough to suggest: {}", err);
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_prune_redundant_backups() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    create_test_file(&source_dir.join(".bashrc"), "from source")?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    let bashrc = temp_home.join(".bashrc");
+    for content in ["unique", "from source", "installed"] {
+        create_test_file(&bashrc, content)?;
+        crate::backup::backup_file(&bashrc, &backups(&backup_dir), false)?;
+    }
+    list_backups(None)?;
+
+    prune_redundant_backups(true)?;
+    assert_eq!(fs::read_dir(&backup_dir)?.count(), 3, "Dry run should not remove backups");
+
+    prune_redundant_backups(false)?;
+    let remaining = fs::read_dir(&backup_dir)?
+        .map(|entry| fs::read_to_string(entry?.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(remaining, ["unique"], "Backups matching the installed file or the source are redundant");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_dotfiles_into_target() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    let skel_dir = temp_dir.path().join("skel");
+    fs::create_dir_all(&skel_dir)?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".bashrc"), "alias ll='ls -l'")?;
+    create_test_file(&source_dir.join(".config/git/config"), "[user]")?;
+    create_test_file(&source_dir.join("etc/.dotfiles.yaml"), "target: /etc")?;
+    create_test_file(&source_dir.join("etc/hosts"), "127.0.0.1 localhost")?;
+
+    install_dotfiles(&InstallOptions { target: Some(skel_dir.clone()), ..Default::default() })?;
+
+    assert_eq!(fs::read_to_string(skel_dir.join(".bashrc"))?, "alias ll='ls -l'");
+    assert!(skel_dir.join(".config/git/config").exists());
+    assert!(!skel_dir.join("etc/hosts").exists(), "Files outside the home directory are skipped");
+    assert!(!temp_home.join(".bashrc").exists(), "The home directory is left alone");
+    assert!(Manifest::load()?.files.is_empty(), "Installs elsewhere aren't recorded in the manifest");
+
+    assert!(install_dotfiles(&InstallOptions { target: Some(temp_dir.path().join("missing")), ..Default::default() }).is_err());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_install_dotfiles_read_only_target() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".gitconfig"), "[user]\n    name = Repo")?;
+    create_test_file(&temp_home.join(".gitconfig"), "[user]\n    name = Local")?;
+    fs::set_permissions(temp_home.join(".gitconfig"), fs::Permissions::from_mode(0o444))?;
+
+    install_dotfiles(&InstallOptions { force: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".gitconfig"))?, "[user]\n    name = Local", "Read-only files are skipped");
+
+    install_dotfiles(&InstallOptions { force: true, force_readonly: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".gitconfig"))?, "[user]\n    name = Repo");
+    assert_eq!(fs::metadata(temp_home.join(".gitconfig"))?.permissions().mode() & 0o777, 0o444, "The file stays read-only");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_dotfiles_frozen() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        frozen: vec![String::from(".gitconfig.local"), String::from(".config/work/*")],
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".gitconfig.local"), "[user]\n    email = repo@example.com")?;
+    create_test_file(&source_dir.join(".config/work/env"), "REPO=1")?;
+    create_test_file(&source_dir.join(".bashrc"), "repo bashrc")?;
+    create_test_file(&temp_home.join(".gitconfig.local"), "[user]\n    email = me@work.example.com")?;
+    create_test_file(&temp_home.join(".bashrc"), "local bashrc")?;
+
+    install_dotfiles(&InstallOptions { force: true, ..Default::default() })?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".gitconfig.local"))?, "[user]\n    email = me@work.example.com",
+        "Frozen files are never overwritten, even with --force");
+    assert_eq!(fs::read_to_string(temp_home.join(".config/work/env"))?, "REPO=1", "Missing frozen files are still installed");
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "repo bashrc");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_dotfiles_adopt() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        template_vars: [(String::from("name"), String::from("Me"))].into(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".vimrc"), "set number")?;
+    create_test_file(&source_dir.join(".hgrc.tmpl"), "username = {{ name }}")?;
+    create_test_file(&source_dir.join(".inputrc"), "set bell-style none")?;
+    create_test_file(&temp_home.join(".vimrc"), "set number\nset relativenumber")?;
+    create_test_file(&temp_home.join(".hgrc"), "username = Someone else")?;
+
+    install_dotfiles(&InstallOptions { adopt: true, dry_run: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(source_dir.join(".vimrc"))?, "set number", "A dry run adopts nothing");
+
+    install_dotfiles(&InstallOptions { adopt: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(source_dir.join(".vimrc"))?, "set number\nset relativenumber", "Local changes are copied into the source");
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set number\nset relativenumber");
+    assert_eq!(fs::read_to_string(source_dir.join(".hgrc.tmpl"))?, "username = {{ name }}", "Templates are not adopted");
+    assert_eq!(fs::read_to_string(temp_home.join(".inputrc"))?, "set bell-style none", "Missing files are installed as usual");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_dotfiles_local_changes() -> Result<()> {
+    let (temp_dir, temp_home, backup_dir) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        ..Default::default()
+    })?;
+
+    create_test_file(&source_dir.join(".bashrc"), "version 1")?;
+    create_test_file(&source_dir.join(".vimrc"), "version 1")?;
+    install_dotfiles(&InstallOptions::default())?;
+    assert!(Manifest::load()?.files[Path::new(".bashrc")].hash.is_some(), "The installed content is hashed");
+
+    // .bashrc is only behind the source, .vimrc was edited in place
+    create_test_file(&source_dir.join(".bashrc"), "version 2")?;
+    create_test_file(&source_dir.join(".vimrc"), "version 2")?;
+    create_test_file(&temp_home.join(".vimrc"), "local edit")?;
+
+    install_dotfiles(&InstallOptions::default())?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "version 2", "Untouched files update without --force");
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "local edit");
+
+    install_dotfiles(&InstallOptions { force: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "local edit", "--force doesn't overwrite local edits");
+
+    install_dotfiles(&InstallOptions { force_local: true, backup: true, ..Default::default() })?;
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "version 2");
+    assert_eq!(fs::read_to_string(crate::backup::find_latest_backup(".vimrc", &backup_dir)?)?, "local edit");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_and_uninstall_blocks() -> Result<()> {
+    let (temp_dir, temp_home, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&source_dir)?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        blocks: vec![crate::blocks::BlockConfig {
+            target: String::from(".bashrc"),
+            name: None,
+            content: Some(String::from("source ~/.aliases")),
+            source: None,
+            comment: String::from("#"),
+        }],
+        ..Default::default()
+    })?;
+    create_test_file(&temp_home.join(".bashrc"), "export EDITOR=vim\n")?;
+
+    install_dotfiles(&InstallOptions { backup: true, ..Default::default() })?;
+    let bashrc = fs::read_to_string(temp_home.join(".bashrc"))?;
+    assert!(bashrc.starts_with("export EDITOR=vim\n"), "The rest of the file is kept");
+    assert!(bashrc.contains("# BEGIN dotfiles-rust\nsource ~/.aliases\n# END dotfiles-rust\n"), "{}", bashrc);
+
+    uninstall_dotfiles(false, false, false, false)?;
+    assert_eq!(fs::read_to_string(temp_home.join(".bashrc"))?, "export EDITOR=vim\n", "Uninstall takes the block out again");
+
+    cleanup_test_env();
+    Ok(())
+}
@@ -1,10 +1,11 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 use tempfile::tempdir;
 
-use crate::config::{Config, read_config, write_config, get_config_path, initialize_config};
-use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+use crate::config::{Config, read_config, write_config, get_config_path, initialize_config, check_config, parse_config, migrate_config, CONFIG_VERSION, MIGRATIONS, Layout, current_hostname, REPO_CONFIG_FILE, deep_merge};
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id, IoOptions};
 
 fn setup_test_env() -> Result<(tempfile::TempDir, PathBuf)> {
     let test_id = set_test_id();
@@ -29,6 +30,7 @@ fn test_config_read_write() -> Result<()> {
     
     let config = Config {
         source_dir: String::from("/path/to/dotfiles"),
+        ..Default::default()
     };
     
     write_config(&config)?;
@@ -143,4 +145,309 @@ fn test_migrate_json_to_yaml() -> Result<()> {
     
     cleanup_test_env();
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_check_config_valid() {
+    let issues = check_config("source_dir: /path/to/dotfiles\n");
+    assert!(issues.is_empty(), "Valid config should have no issues: {:?}", issues);
+}
+
+#[test]
+fn test_check_config_unknown_key() {
+    let issues = check_config("source_dir: /path/to/dotfiles\nsoruce_dirr: /other\n");
+
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key_path.as_deref(), Some("soruce_dirr"));
+    assert_eq!(issues[0].line, Some(2));
+    assert_eq!(issues[0].suggestion.as_deref(), Some("source_dir"));
+    assert!(issues[0].to_string().contains("did you mean `source_dir`?"));
+}
+
+#[test]
+fn test_check_config_wrong_type() {
+    let issues = check_config("\nsource_dir: [1, 2]\n");
+
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].line, Some(2));
+    assert!(issues[0].message.contains("source_dir"), "Message should name the key: {}", issues[0].message);
+    assert!(!issues[0].message.contains("at line"), "Location should not be duplicated in the message");
+}
+
+#[test]
+fn test_check_config_invalid_patterns() {
+    let issues = check_config("source_dir: /dotfiles\nignore:\n  - '*.swp'\n  - '[abc'\nfrozen: ['{a,b']\nmachines:\n  laptop:\n    ignore: ['[x']\n");
+
+    assert_eq!(issues.len(), 3, "{:?}", issues);
+    assert_eq!(issues[0].key_path.as_deref(), Some("ignore"));
+    assert_eq!(issues[0].line, Some(4), "The line of the bad pattern should be reported");
+    assert!(issues[0].message.contains("[abc"), "Message should name the pattern: {}", issues[0].message);
+    assert_eq!(issues[1].key_path.as_deref(), Some("frozen"));
+    assert_eq!(issues[1].line, Some(5));
+    assert_eq!(issues[2].key_path.as_deref(), Some("machines.laptop.ignore"));
+    assert_eq!(issues[2].line, Some(8));
+}
+
+#[test]
+fn test_read_config_rejects_invalid_file() -> Result<()> {
+    let (_temp_dir, home_path) = setup_test_env()?;
+
+    fs::write(home_path.join(".dotfiles-rustrc.yaml"), "source_dir: /dotfiles\nsource_dri: /typo\n")?;
+
+    let error = read_config().expect_err("Unknown keys should be rejected");
+    let message = format!("{:#}", error);
+    assert!(message.contains("source_dri"), "Error should mention the unknown key: {}", message);
+    assert!(parse_config("source_dir: /dotfiles\n").is_ok());
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_migrations_cover_every_version() {
+    for version in 0..CONFIG_VERSION {
+        assert!(MIGRATIONS.iter().any(|migration| migration.from == version),
+            "Missing migration from config version {}", version);
+    }
+}
+
+#[test]
+fn test_migrate_config() -> Result<()> {
+    let value: serde_yaml::Value = serde_yaml::from_str("source_dir: /dotfiles\n")?;
+
+    let (migrated, applied) = migrate_config(value)?;
+
+    assert_eq!(applied.len(), CONFIG_VERSION as usize);
+    assert_eq!(migrated.get("version").and_then(|v| v.as_u64()), Some(CONFIG_VERSION as u64));
+    assert_eq!(migrated.get("source_dir").and_then(|v| v.as_str()), Some("/dotfiles"));
+
+    let future: serde_yaml::Value = serde_yaml::from_str(&format!("version: {}\nsource_dir: /dotfiles\n", CONFIG_VERSION + 1))?;
+    assert!(migrate_config(future).is_err(), "Configs from newer versions should be rejected");
+
+    Ok(())
+}
+
+#[test]
+fn test_read_config_upgrades_unversioned_file() -> Result<()> {
+    let (_temp_dir, home_path) = setup_test_env()?;
+
+    let config_path = home_path.join(".dotfiles-rustrc.yaml");
+    fs::write(&config_path, "source_dir: /unversioned/path\n")?;
+
+    let config = read_config()?;
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.source_dir, "/unversioned/path");
+
+    let content = fs::read_to_string(&config_path)?;
+    assert!(content.contains(&format!("version: {}", CONFIG_VERSION)), "Upgraded file should be stamped: {}", content);
+
+    let backup_path = home_path.join(".dotfiles-rustrc.yaml.v0");
+    assert_eq!(fs::read_to_string(backup_path)?, "source_dir: /unversioned/path\n", "Original file should be kept");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_layout_target_relative_path() {
+    assert_eq!(Layout::Plain.target_relative_path(Path::new(".config/nvim/init.vim")), Some(Path::new(".config/nvim/init.vim")));
+    assert_eq!(Layout::Stow.target_relative_path(Path::new("nvim/.config/nvim/init.vim")), Some(Path::new(".config/nvim/init.vim")));
+    assert_eq!(Layout::Stow.target_relative_path(Path::new("README.md")), None, "Loose files are not part of a package");
+
+    let config: Config = serde_yaml::from_str("source_dir: /dotfiles\nlayout: stow\n").unwrap();
+    assert_eq!(config.layout, Layout::Stow);
+}
+
+#[test]
+fn test_machine_overrides() -> Result<()> {
+    let content = r#"
+source_dir: ~/dotfiles
+ignore: [".ssh"]
+template_vars:
+  email: me@example.com
+  editor: vim
+machines:
+  work-laptop:
+    layout: stow
+    ignore: [".config/games"]
+    template_vars:
+      email: me@work.example.com
+"#;
+
+    let mut config = parse_config(content)?;
+    config.apply_machine_overrides("home-desktop");
+    assert_eq!(config.layout, Layout::Plain, "Sections for other machines are ignored");
+    assert_eq!(config.ignore, vec![String::from(".ssh")]);
+
+    config.apply_machine_overrides("work-laptop");
+    assert_eq!(config.layout, Layout::Stow);
+    assert_eq!(config.source_dir, "~/dotfiles");
+    assert_eq!(config.ignore, vec![String::from(".ssh"), String::from(".config/games")]);
+    assert_eq!(config.template_vars["email"], "me@work.example.com");
+    assert_eq!(config.template_vars["editor"], "vim");
+
+    assert!(!check_config("source_dir: .\nmachines:\n  laptop:\n    sourcedir: /tmp\n").is_empty(),
+        "Unknown keys inside a machine section are reported");
+
+    Ok(())
+}
+
+#[test]
+fn test_read_config_applies_current_machine() -> Result<()> {
+    let (_temp_dir, home_path) = setup_test_env()?;
+
+    let content = format!("source_dir: /base\nmachines:\n  {}:\n    source_dir: /machine\n", current_hostname());
+    fs::write(home_path.join(".dotfiles-rustrc.yaml"), content)?;
+
+    assert_eq!(read_config()?.source_dir, "/machine");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+
+#[test]
+fn test_read_config_merges_repo_config() -> Result<()> {
+    let (temp_dir, home_path) = setup_test_env()?;
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&source_dir)?;
+
+    let content = format!("source_dir: {}\nignore: [\".ssh\"]\ntemplate_vars:\n  email: me@example.com\n", source_dir.display());
+    fs::write(home_path.join(".dotfiles-rustrc.yaml"), content)?;
+    fs::write(source_dir.join(REPO_CONFIG_FILE), r#"
+layout: stow
+ignore: ["*.bak"]
+template_vars:
+  email: shared@example.com
+  editor: nvim
+"#)?;
+
+    let config = read_config()?;
+    assert_eq!(config.layout, Layout::Stow, "The repository layout applies when the local config doesn't set one");
+    assert_eq!(config.ignore, vec![String::from("*.bak"), String::from(".ssh")]);
+    assert_eq!(config.template_vars["email"], "me@example.com", "Local values win over repository defaults");
+    assert_eq!(config.template_vars["editor"], "nvim");
+
+    fs::write(source_dir.join(REPO_CONFIG_FILE), "ignores: [\"*.bak\"]\n")?;
+    assert!(read_config().is_err(), "Unknown keys in the repository config are rejected");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_read_config_resolves_archive_url() -> Result<()> {
+    let (_temp_dir, home_path) = setup_test_env()?;
+
+    let url = "https://example.com/dotfiles.tar.gz";
+    fs::write(home_path.join(".dotfiles-rustrc.yaml"), format!("source_dir: {}\n", url))?;
+
+    let config = read_config()?;
+    assert_eq!(config.source_url.as_deref(), Some(url));
+    assert!(Path::new(&config.source_dir).starts_with(home_path.join(".local/share/dotfiles-rust/sources")),
+        "Archive sources resolve to their extracted copy under the data directory");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_deep_merge() -> Result<()> {
+    let mut base: serde_yaml::Value = serde_yaml::from_str("source_dir: ~/dotfiles\nignore: [\".ssh\"]\ntemplate_vars:\n  email: me@example.com\n  editor: vim\n")?;
+    let overlay: serde_yaml::Value = serde_yaml::from_str("ignore: [\"*.bak\"]\ntemplate_vars:\n  editor: nvim\ndaemon_interval: 60\n")?;
+
+    deep_merge(&mut base, overlay);
+    let config = parse_config(&serde_yaml::to_string(&base)?)?;
+
+    assert_eq!(config.source_dir, "~/dotfiles");
+    assert_eq!(config.ignore, vec![String::from(".ssh"), String::from("*.bak")], "Lists are concatenated");
+    assert_eq!(config.template_vars["email"], "me@example.com", "Nested mappings are merged key by key");
+    assert_eq!(config.template_vars["editor"], "nvim");
+    assert_eq!(config.daemon_interval, Some(60));
+
+    Ok(())
+}
+
+#[test]
+fn test_read_config_merges_includes() -> Result<()> {
+    let (_temp_dir, home_path) = setup_test_env()?;
+
+    fs::create_dir_all(home_path.join("dotfiles"))?;
+    fs::write(home_path.join(".dotfiles-rustrc.yaml"),
+        "source_dir: /shared\ninclude: [\"dotfiles/base.yaml\", \"~/.dotfiles-local.yaml\", \"missing.yaml\"]\n")?;
+    fs::write(home_path.join("dotfiles/base.yaml"), "ignore: [\".ssh\"]\ninclude: [\"extra.yaml\"]\n")?;
+    fs::write(home_path.join("dotfiles/extra.yaml"), "template_vars:\n  editor: vim\n")?;
+    fs::write(home_path.join(".dotfiles-local.yaml"), "source_dir: /local\ntemplate_vars:\n  email: me@example.com\n")?;
+
+    let config = read_config()?;
+    assert_eq!(config.source_dir, "/local", "Includes are merged over the including file in order");
+    assert_eq!(config.ignore, vec![String::from(".ssh")]);
+    assert_eq!(config.template_vars["editor"], "vim", "Includes are resolved relative to the including file");
+    assert_eq!(config.template_vars["email"], "me@example.com");
+
+    fs::write(home_path.join("dotfiles/extra.yaml"), "include: [\"base.yaml\"]\n")?;
+    assert!(read_config().is_err(), "Include cycles are reported");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_io_options() {
+    let config = Config { jobs: Some(3), copy_buffer_size: Some(String::from("64K")), fsync: true, ..Default::default() };
+    assert_eq!(config.io_options(None), IoOptions { jobs: 3, copy_buffer_size: Some(64 * 1024), fsync: true });
+    assert_eq!(config.io_options(Some(8)).jobs, 8, "--jobs overrides the config");
+    assert_eq!(Config::default().io_options(None), IoOptions::default());
+
+    let issues = check_config("source_dir: ~/dotfiles\njobs: 0\ncopy_buffer_size: lots\n");
+    assert_eq!(issues.len(), 2);
+    assert!(issues.iter().any(|issue| issue.key_path.as_deref() == Some("jobs")));
+    assert!(issues.iter().any(|issue| issue.key_path.as_deref() == Some("copy_buffer_size")));
+}
+
+#[test]
+fn test_sensitive() -> Result<()> {
+    let sensitive = Config::default().sensitive_paths()?;
+    assert!(sensitive.is_sensitive(Path::new(".ssh/id_ed25519")));
+    assert!(sensitive.is_sensitive(Path::new(".gnupg/private-keys-v1.d/key")));
+    assert!(sensitive.is_sensitive(Path::new(".netrc")));
+    assert!(!sensitive.is_sensitive(Path::new(".config/ssh")));
+
+    let config = Config { sensitive: vec![String::from("*.pem")], ..Default::default() };
+    assert!(serde_yaml::to_string(&config)?.contains("sensitive"));
+    assert!(!serde_yaml::to_string(&Config::default())?.contains("sensitive"), "The defaults aren't written out");
+    assert!(!config.sensitive_paths()?.is_sensitive(Path::new(".netrc")), "A list of its own replaces the defaults");
+
+    let issues = check_config("source_dir: ~/dotfiles\nsensitive:\n  - \".ssh/[\"\n");
+    assert_eq!(issues.iter().map(|issue| issue.key_path.as_deref()).collect::<Vec<_>>(), [Some("sensitive")]);
+    Ok(())
+}
+
+#[test]
+fn test_flag_defaults() -> Result<()> {
+    let config = parse_config("source_dir: ~/dotfiles\ndefaults:\n  keep_backups: true\n  backup: false\n")?;
+    assert!(config.defaults.keep_backups);
+    assert_eq!(config.defaults.backup, Some(false));
+    assert!(!config.defaults.force);
+    assert!(!serde_yaml::to_string(&Config::default())?.contains("defaults"));
+
+    let issues = check_config("source_dir: ~/dotfiles\ndefaults:\n  keep_backup: true\n");
+    assert_eq!(issues.len(), 1, "Unknown flags are reported");
+    Ok(())
+}
+
+#[test]
+fn test_dir_modes() {
+    let config = Config {
+        dir_mode: Some(String::from("750")),
+        dir_modes: BTreeMap::from([(String::from(".ssh/"), String::from("0700"))]),
+        ..Default::default()
+    };
+    let modes = config.dir_modes(Path::new("/home/user"));
+    assert_eq!(modes.mode_for(Path::new("/home/user/.ssh")), Some(0o700));
+    assert_eq!(modes.mode_for(Path::new("/home/user/.config")), Some(0o750));
+
+    let issues = check_config("source_dir: ~/dotfiles\ndir_mode: rwx\ndir_modes:\n  .gnupg: \"999\"\n");
+    assert_eq!(issues.iter().map(|issue| issue.key_path.as_deref()).collect::<Vec<_>>(), [Some("dir_mode"), Some("dir_modes..gnupg")]);
+    assert_eq!(issues[1].line, Some(3));
+}
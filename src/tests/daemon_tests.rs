@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::{tempdir, TempDir};
+
+use crate::config::{Config, write_config};
+use crate::daemon::{render_service_unit, render_timer_unit, resolve_interval, run_once, install_unit, DEFAULT_INTERVAL};
+use crate::fs_utils::{set_test_home_dir, set_test_backup_dir, set_test_id, clear_test_id};
+
+fn setup_test_env() -> Result<(TempDir, PathBuf, PathBuf)> {
+    let test_id = set_test_id();
+
+    let temp_dir = tempdir()?;
+    let temp_home = temp_dir.path().join(format!("home_{}", test_id));
+    let backup_dir = temp_dir.path().join(format!("backup_{}", test_id));
+    let source_dir = temp_dir.path().join("source");
+
+    fs::create_dir_all(&temp_home)?;
+    fs::create_dir_all(&backup_dir)?;
+    fs::create_dir_all(&source_dir)?;
+
+    set_test_home_dir(Some(temp_home.clone()));
+    set_test_backup_dir(Some(backup_dir));
+
+    Ok((temp_dir, temp_home, source_dir))
+}
+
+fn cleanup_test_env() {
+    set_test_home_dir(None);
+    set_test_backup_dir(None);
+    clear_test_id();
+}
+
+#[test]
+fn test_render_units() {
+    let service = render_service_unit(Path::new("/usr/local/bin/dotfiles-rust"), true);
+    assert!(service.contains("Type=oneshot"));
+    assert!(service.contains("ExecStart=\"/usr/local/bin/dotfiles-rust\" daemon --once --force"));
+
+    let service = render_service_unit(Path::new("/usr/local/bin/dotfiles-rust"), false);
+    assert!(service.contains("ExecStart=\"/usr/local/bin/dotfiles-rust\" daemon --once\n"));
+
+    let service = render_service_unit(Path::new("/home/me/My Tools/100%/dotfiles-rust"), false);
+    assert!(service.contains("ExecStart=\"/home/me/My Tools/100%%/dotfiles-rust\" daemon --once\n"),
+        "A path with a space stays one word, and % isn't read as a specifier: {}", service);
+    let service = render_service_unit(Path::new("/opt/a\"b\\c/$HOME/dotfiles-rust"), false);
+    assert!(service.contains(r#"ExecStart="/opt/a\"b\\c/$$HOME/dotfiles-rust" daemon"#), "{}", service);
+
+    let timer = render_timer_unit(900);
+    assert!(timer.contains("OnUnitActiveSec=900s"));
+    assert!(timer.contains("WantedBy=timers.target"));
+}
+
+#[test]
+fn test_resolve_interval() -> Result<()> {
+    let (_temp_dir, _, source_dir) = setup_test_env()?;
+
+    assert_eq!(resolve_interval(Some(60))?, 60);
+    assert!(resolve_interval(Some(0)).is_err(), "Zero interval should be rejected");
+    assert_eq!(resolve_interval(None)?, DEFAULT_INTERVAL);
+
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().to_string(),
+        daemon_interval: Some(300),
+        ..Default::default()
+    })?;
+    assert_eq!(resolve_interval(None)?, 300);
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_run_once_installs_dotfiles() -> Result<()> {
+    let (_temp_dir, temp_home, source_dir) = setup_test_env()?;
+
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().to_string(),
+        ..Default::default()
+    })?;
+    fs::write(source_dir.join(".vimrc"), "set nocompatible")?;
+
+    run_once(false)?;
+
+    assert_eq!(fs::read_to_string(temp_home.join(".vimrc"))?, "set nocompatible");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_install_unit() -> Result<()> {
+    let (_temp_dir, temp_home, _) = setup_test_env()?;
+    let unit_dir = temp_home.join(".config/systemd/user");
+
+    install_unit(Some(600), false, true)?;
+    assert!(!unit_dir.exists(), "Dry run should not write unit files");
+
+    install_unit(Some(600), false, false)?;
+    assert!(unit_dir.join("dotfiles-rust.service").exists());
+    assert!(fs::read_to_string(unit_dir.join("dotfiles-rust.timer"))?.contains("OnUnitActiveSec=600s"));
+
+    cleanup_test_env();
+    Ok(())
+}
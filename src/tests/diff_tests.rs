@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::diff::{diff_stat, format_size, has_binary_extension, is_binary, same_file, same_file_content, unified_diff, CompareMode, DiffStat, MMAP_THRESHOLD};
+
+#[test]
+fn test_diff_stat_text() {
+    let source = b"set number\nset ruler\nsyntax on\n";
+    let target = b"set number\nsyntax on\nset mouse=a\nset hlsearch\n";
+    let path = Path::new(".vimrc");
+
+    assert_eq!(diff_stat(path, source, target), DiffStat::Text { added: 2, removed: 1 });
+    assert_eq!(diff_stat(path, source, source), DiffStat::Text { added: 0, removed: 0 });
+    assert_eq!(diff_stat(path, source, target).to_string(), "+2 -1 lines");
+}
+
+#[test]
+fn test_diff_stat_binary() {
+    let source = b"\x00\x01\x02";
+    let target = b"\x00\x01";
+    let path = Path::new("data.bin");
+
+    assert!(is_binary(source));
+    assert!(!is_binary(b"plain text"));
+    assert_eq!(diff_stat(path, source, target), DiffStat::Binary { old_size: 3, new_size: 2 });
+    assert_eq!(diff_stat(path, b"text", b"text\xff\xfe").to_string(), "binary file differs (4 B → 6 B)");
+
+    let icon = Path::new(".local/share/icons/app.PNG");
+    assert!(has_binary_extension(icon));
+    assert!(!has_binary_extension(Path::new(".bashrc")));
+    assert_eq!(diff_stat(icon, &[b'a'; 12 * 1024], &[b'b'; 14 * 1024]).to_string(), "binary file differs (12 KiB → 14 KiB)",
+        "Known binary formats are never diffed line by line");
+}
+
+#[test]
+fn test_format_size() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1024), "1 KiB");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(14 * 1024), "14 KiB");
+    assert_eq!(format_size(3 * 1024 * 1024), "3 MiB");
+}
+
+#[test]
+fn test_same_file_content() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let a = temp_dir.path().join("a.png");
+    let b = temp_dir.path().join("b.png");
+
+    fs::write(&a, b"\x89PNG\x00\x01")?;
+    fs::write(&b, b"\x89PNG\x00\x01")?;
+    assert!(same_file_content(&a, &b)?);
+
+    fs::write(&b, b"\x89PNG\x00\x02")?;
+    assert!(!same_file_content(&a, &b)?, "Files of the same size are compared by hash");
+
+    fs::write(&b, b"\x89PNG")?;
+    assert!(!same_file_content(&a, &b)?);
+
+    Ok(())
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[test]
+fn test_unified_diff() {
+    let source = "set number\nset ruler\nsyntax on\n";
+    let target = "set number\nset rulerformat=%l\nsyntax on\nset mouse=a";
+
+    let diff = strip_ansi(&unified_diff(source, target, "source/.vimrc", "~/.vimrc"));
+    assert_eq!(diff, "\
+--- source/.vimrc
++++ ~/.vimrc
+@@ -1,3 +1,4 @@
+ set number
+-set ruler
++set rulerformat=%l
+ syntax on
++set mouse=a
+\\ No newline at end of file
+");
+
+    assert_eq!(unified_diff(source, source, "a", "b"), "", "Identical inputs produce no output");
+}
+
+#[test]
+fn test_same_file_content_mapped() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let a = temp_dir.path().join("a.ttf");
+    let b = temp_dir.path().join("b.ttf");
+
+    let mut content = vec![7u8; MMAP_THRESHOLD as usize + 10];
+    fs::write(&a, &content)?;
+    fs::write(&b, &content)?;
+    assert!(same_file_content(&a, &b)?, "Large files are compared through memory maps");
+
+    let last = content.len() - 1;
+    content[last] = 8;
+    fs::write(&b, &content)?;
+    assert!(!same_file_content(&a, &b)?, "A difference in the last chunk is found");
+
+    Ok(())
+}
+
+#[test]
+fn test_same_file_metadata_only() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source = temp_dir.path().join("source.otf");
+    let target = temp_dir.path().join("target.otf");
+
+    fs::write(&source, b"glyphs")?;
+    fs::write(&target, b"GLYPHS")?;
+    assert!(same_file(&source, &target, CompareMode::MetadataOnly)?, "Content is not read");
+    assert!(!same_file(&source, &target, CompareMode::Content)?);
+
+    fs::write(&target, b"more glyphs")?;
+    assert!(!same_file(&source, &target, CompareMode::MetadataOnly)?, "Sizes still have to match");
+
+    fs::write(&target, b"GLYPHS")?;
+    fs::File::options().write(true).open(&source)?.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    assert!(!same_file(&source, &target, CompareMode::MetadataOnly)?, "A source changed after the target was written differs");
+
+    Ok(())
+}
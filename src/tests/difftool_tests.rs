@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::{Config, DifftoolMode};
+use crate::difftool::{is_available, run_difftool, DiffPair, Difftool};
+use crate::fs_utils::{get_data_dir, set_test_home_dir, set_test_id, clear_test_id};
+
+#[test]
+fn test_difftool_from_config() {
+    assert!(is_available("sh"));
+    assert!(!is_available("dotfiles-rust-no-such-difftool --side-by-side"));
+
+    let config = Config { difftool: Some(String::from("dotfiles-rust-no-such-difftool")), ..Default::default() };
+    assert_eq!(Difftool::from_config(&config), None, "A missing tool falls back to the built-in diff");
+
+    let config = Config { difftool: Some(String::from("sh")), difftool_mode: DifftoolMode::Dir, ..Default::default() };
+    assert_eq!(Difftool::from_config(&config), Some(Difftool { command: String::from("sh"), mode: DifftoolMode::Dir }));
+
+    assert_eq!(Difftool::from_config(&Config::default()), None);
+}
+
+// A stand-in difftool that records what it was given
+#[cfg(unix)]
+fn fake_difftool(dir: &Path) -> Result<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = dir.join("fake-difftool");
+    let output = dir.join("difftool.out");
+    fs::write(&script, format!(
+        "#!/bin/sh\nif [ -d \"$1\" ]; then ls -A \"$1\" \"$2\"; else cat \"$1\" \"$2\"; fi >> '{}'\nexit 1\n",
+        output.display()))?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+    Ok(script.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_difftool() -> Result<()> {
+    set_test_id();
+    let temp_dir = tempdir()?;
+    set_test_home_dir(Some(temp_dir.path().join("home")));
+
+    let command = fake_difftool(temp_dir.path())?;
+    let target = temp_dir.path().join(".bashrc");
+    fs::write(&target, "installed\n")?;
+    let pairs = [DiffPair { relative_path: Path::new(".bashrc"), expected: b"rendered\n", target: &target }];
+
+    run_difftool(&Difftool { command: command.clone(), mode: DifftoolMode::File }, &pairs)?;
+    assert_eq!(fs::read_to_string(temp_dir.path().join("difftool.out"))?, "rendered\ninstalled\n",
+        "The rendered source and installed file are passed in order; a non-zero exit is not an error");
+
+    fs::remove_file(temp_dir.path().join("difftool.out"))?;
+    run_difftool(&Difftool { command, mode: DifftoolMode::Dir }, &pairs)?;
+    let listing = fs::read_to_string(temp_dir.path().join("difftool.out"))?;
+    assert_eq!(listing.matches(".bashrc").count(), 2, "Both staged directories hold the file: {}", listing);
+    assert!(!get_data_dir()?.join("difftool").exists(), "Staged copies are removed afterwards");
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
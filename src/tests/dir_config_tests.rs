@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::Layout;
+use crate::diff::CompareMode;
+use crate::dir_config::{parse_mode, DirConfigs, DIR_CONFIG_FILE};
+
+#[test]
+fn test_parse_mode() {
+    assert_eq!(parse_mode("755").unwrap(), 0o755);
+    assert_eq!(parse_mode("0600").unwrap(), 0o600);
+    assert_eq!(parse_mode("0o644").unwrap(), 0o644);
+    assert!(parse_mode("rwx").is_err());
+    assert!(parse_mode("99").is_err());
+}
+
+#[test]
+fn test_dir_configs() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    fs::create_dir_all(source_dir.join("bin/private"))?;
+    fs::write(source_dir.join("bin").join(DIR_CONFIG_FILE), "target: .local/bin\nmode: \"755\"\nignore: [\"*.bak\"]\n")?;
+    fs::write(source_dir.join("bin/private").join(DIR_CONFIG_FILE), "mode: \"700\"\n")?;
+
+    let dirs = DirConfigs::load(source_dir)?;
+
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new("bin/backup.sh")), Some(PathBuf::from(".local/bin/backup.sh")));
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new("bin/private/token.sh.tmpl")), Some(PathBuf::from(".local/bin/private/token.sh")));
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new(".bashrc")), Some(PathBuf::from(".bashrc")), "Other paths follow the layout");
+
+    assert_eq!(dirs.mode_for(Path::new("bin/backup.sh")), Some(0o755));
+    assert_eq!(dirs.mode_for(Path::new("bin/private/token.sh")), Some(0o700), "The innermost config wins");
+    assert_eq!(dirs.mode_for(Path::new("binaries/tool")), None, "Sibling directories sharing a prefix are unaffected");
+
+    assert!(dirs.is_ignored(Path::new("bin/old.bak")));
+    assert!(dirs.is_ignored(Path::new("bin/private/old.bak")), "Ignore patterns apply to the whole subtree");
+    assert!(!dirs.is_ignored(Path::new("old.bak")));
+
+    fs::create_dir_all(source_dir.join(".zshrc.d"))?;
+    fs::write(source_dir.join(".zshrc.d").join(DIR_CONFIG_FILE), "assemble: true\n")?;
+    let dirs = DirConfigs::load(source_dir)?;
+
+    assert!(dirs.is_assembled(Path::new(".zshrc.d")));
+    assert!(!dirs.is_assembled(Path::new("bin")));
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new(".zshrc.d")), Some(PathBuf::from(".zshrc")), "The .d suffix is dropped");
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new(".zshrc.d/10-env.zsh")), Some(PathBuf::from(".zshrc")),
+        "Fragments belong to the assembled file");
+
+    fs::write(source_dir.join(".zshrc.d").join(DIR_CONFIG_FILE), "assemble: true\ntarget: .config/zsh/.zshrc\n")?;
+    let dirs = DirConfigs::load(source_dir)?;
+    assert_eq!(dirs.target_for(Layout::Plain, Path::new(".zshrc.d")), Some(PathBuf::from(".config/zsh/.zshrc")));
+
+    fs::create_dir_all(source_dir.join(".local/share/fonts"))?;
+    fs::write(source_dir.join(".local/share/fonts").join(DIR_CONFIG_FILE), "compare: metadata-only\n")?;
+    let dirs = DirConfigs::load(source_dir)?;
+    assert_eq!(dirs.compare_for(Path::new(".local/share/fonts/Iosevka.ttf")), CompareMode::MetadataOnly);
+    assert_eq!(dirs.compare_for(Path::new(".bashrc")), CompareMode::Content);
+
+    fs::write(source_dir.join("bin").join(DIR_CONFIG_FILE), "strategy: symlink\n")?;
+    assert!(DirConfigs::load(source_dir).is_err(), "Unknown keys are rejected");
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::{write_config, Config};
+use crate::export::{chezmoi_path, export_dotfiles, stow_package, ExportFormat};
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+
+#[test]
+fn test_chezmoi_path() {
+    assert_eq!(chezmoi_path(Path::new(".bashrc"), Some(0o644)), PathBuf::from("dot_bashrc"));
+    assert_eq!(chezmoi_path(Path::new(".config/nvim/init.lua"), None), PathBuf::from("dot_config/nvim/init.lua"));
+    assert_eq!(chezmoi_path(Path::new(".local/bin/ll"), Some(0o755)), PathBuf::from("dot_local/bin/executable_ll"));
+    assert_eq!(chezmoi_path(Path::new(".ssh/config"), Some(0o600)), PathBuf::from("dot_ssh/private_config"));
+}
+
+#[test]
+fn test_stow_package() {
+    assert_eq!(stow_package(Path::new(".bashrc")), "bashrc");
+    assert_eq!(stow_package(Path::new(".config/nvim/init.lua")), "nvim");
+    assert_eq!(stow_package(Path::new(".config/starship.toml")), "config");
+    assert_eq!(stow_package(Path::new(".local/share/fonts/mono.ttf")), "fonts");
+    assert_eq!(stow_package(Path::new(".local/bin/ll")), "bin");
+}
+
+#[test]
+fn test_export_dotfiles() -> Result<()> {
+    set_test_id();
+    let temp_dir = tempdir()?;
+    let home_dir = temp_dir.path().join("home");
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(&home_dir)?;
+    set_test_home_dir(Some(home_dir));
+
+    fs::create_dir_all(source_dir.join(".config/nvim"))?;
+    fs::write(source_dir.join(".hgrc.tmpl"), "username = {{ name }}")?;
+    fs::write(source_dir.join(".config/nvim/init.lua"), "vim.o.number = true")?;
+    write_config(&Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        template_vars: [(String::from("name"), String::from("Me"))].into(),
+        ..Default::default()
+    })?;
+
+    let stow_dir = temp_dir.path().join("stow");
+    export_dotfiles(ExportFormat::Stow, &stow_dir, false, false)?;
+    assert_eq!(fs::read_to_string(stow_dir.join("hgrc/.hgrc"))?, "username = Me", "Templates are exported rendered");
+    assert!(stow_dir.join("nvim/.config/nvim/init.lua").exists());
+
+    let dotbot_dir = temp_dir.path().join("dotbot");
+    export_dotfiles(ExportFormat::Dotbot, &dotbot_dir, false, false)?;
+    let install_config = fs::read_to_string(dotbot_dir.join("install.conf.yaml"))?;
+    assert!(install_config.contains("'~/.config/nvim/init.lua': '.config/nvim/init.lua'"), "{}", install_config);
+    assert!(dotbot_dir.join(".hgrc").exists());
+
+    let chezmoi_dir = temp_dir.path().join("chezmoi");
+    export_dotfiles(ExportFormat::Chezmoi, &chezmoi_dir, true, false)?;
+    assert!(!chezmoi_dir.exists(), "A dry run writes nothing");
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::commands::is_blacklisted;
+use crate::config::{current_hostname, write_config, Config};
+use crate::fleet::{load_states, record_install, show_fleet, state_path, sync_status, MachineState, SyncStatus};
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+use crate::git::{head_commit, run_git};
+
+fn write_state(source_dir: &Path, hostname: &str, content: &str) -> Result<()> {
+    let path = state_path(source_dir, hostname);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_record_install() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    record_install(source_dir, None, 3)?;
+    record_install(source_dir, Some("0123456789abcdef"), 4)?;
+
+    let states = load_states(source_dir)?;
+    assert_eq!(states.len(), 1);
+    assert_eq!(states[0].hostname, current_hostname());
+    assert_eq!(states[0].generation, 2, "Each install counts up the generation");
+    assert_eq!(states[0].commit.as_deref(), Some("0123456789abcdef"));
+    assert_eq!(states[0].files, 4);
+    assert!(state_path(source_dir, &current_hostname()).exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_load_states() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+    assert!(load_states(source_dir)?.is_empty());
+
+    for (hostname, installed_at) in [("desktop", 100), ("laptop", 300), ("server", 200)] {
+        let state = MachineState { hostname: hostname.into(), installed_at, commit: None, generation: 1, files: 0 };
+        let path = state_path(source_dir, hostname);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, serde_yaml::to_string(&state)?)?;
+    }
+    fs::write(state_path(source_dir, "broken"), "not: [valid")?;
+
+    let hostnames = load_states(source_dir)?.into_iter().map(|state| state.hostname).collect::<Vec<_>>();
+    assert_eq!(hostnames, ["laptop", "server", "desktop"], "Most recent first, unreadable files skipped");
+
+    assert!(is_blacklisted(Path::new(".state/laptop.yaml")), "State files are never installed");
+
+    Ok(())
+}
+
+#[test]
+fn test_load_states_skips_what_it_cannot_read() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    // Written by an older version, without the commit and file count
+    write_state(source_dir, "old", "hostname: old\ninstalled_at: 100\ngeneration: 7\n")?;
+    // Cut off halfway through a write, and a directory where a file should be
+    write_state(source_dir, "partial", "hostname: partial\ninstalled_at: 200\n")?;
+    fs::create_dir_all(state_path(source_dir, "unreadable"))?;
+    fs::write(source_dir.join(".state/notes.txt"), "not a state file")?;
+
+    let states = load_states(source_dir)?;
+    assert_eq!(states, [MachineState { hostname: String::from("old"), installed_at: 100, commit: None, generation: 7, files: 0 }],
+        "Missing optional fields get defaults; files that can't be read are skipped");
+
+    // A state directory that isn't a directory has no states rather than failing
+    let other_dir = temp_dir.path().join("other");
+    fs::create_dir_all(&other_dir)?;
+    fs::write(other_dir.join(".state"), "")?;
+    assert!(load_states(&other_dir)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_record_install_over_broken_state() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    write_state(source_dir, &current_hostname(), "generation: [")?;
+    record_install(source_dir, None, 2)?;
+
+    let states = load_states(source_dir)?;
+    assert_eq!(states.len(), 1, "A broken state file is replaced");
+    assert_eq!(states[0].generation, 1, "Counting starts over when the previous generation can't be read");
+    assert_eq!(states[0].commit, None, "An install that failed for some files records no commit");
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_status() {
+    let state = |commit: Option<&str>| MachineState { hostname: String::from("laptop"), installed_at: 0, commit: commit.map(String::from), generation: 1, files: 0 };
+
+    assert_eq!(sync_status(&state(Some("abc")), Some("abc")), SyncStatus::UpToDate);
+    assert_eq!(sync_status(&state(Some("abc")), Some("def")), SyncStatus::Behind);
+    assert_eq!(sync_status(&state(None), Some("def")), SyncStatus::Unknown, "A partial install has no commit to compare");
+    assert_eq!(sync_status(&state(Some("abc")), None), SyncStatus::Unknown, "Nothing to compare with outside a git repository");
+}
+
+#[test]
+fn test_show_fleet() -> Result<()> {
+    let test_id = set_test_id();
+    let temp_dir = tempdir()?;
+    let home = temp_dir.path().join(format!("home_{}", test_id));
+    let source_dir = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&home)?;
+    fs::create_dir_all(&source_dir)?;
+    set_test_home_dir(Some(home));
+    write_config(&Config { source_dir: source_dir.to_string_lossy().into_owned(), ..Default::default() })?;
+
+    show_fleet()?;
+
+    run_git(&source_dir, &["init", "-q"])?;
+    fs::write(source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    run_git(&source_dir, &["add", "-A"])?;
+    run_git(&source_dir, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", "bashrc"])?;
+    let head = head_commit(&source_dir)?;
+    record_install(&source_dir, Some(&head), 1)?;
+    write_state(&source_dir, "server", "hostname: server\ninstalled_at: 4102444800\ncommit: 0123456\ngeneration: 3\n")?;
+    write_state(&source_dir, "broken", "hostname: [")?;
+    show_fleet()?;
+
+    fs::remove_dir_all(&source_dir)?;
+    assert!(show_fleet().is_err(), "A missing source directory is an error");
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
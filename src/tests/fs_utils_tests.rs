@@ -1,7 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tempfile::tempdir;
 
-use crate::fs_utils::{get_home_dir, get_backup_dir, ensure_parent_dirs, set_test_home_dir, set_test_backup_dir, set_test_id, clear_test_id};
+use crate::fs_utils::{get_home_dir, get_backup_dir, ensure_parent_dirs, create_dirs, DirModes, normalize_path, copy_file, copy_file_with_mode, write_file_atomic, with_executable_bits, is_on_path, parallel_map, set_io_options, IoOptions, set_test_home_dir, set_test_backup_dir, set_test_id, clear_test_id};
 
 // Set up a test environment with unique test ID
 fn setup_test_dirs() -> (tempfile::TempDir, PathBuf, PathBuf) {
@@ -74,7 +74,7 @@ fn test_ensure_parent_dirs() {
     let parent_dir = file_path.parent().unwrap();
 
     assert!(!parent_dir.exists());
-    ensure_parent_dirs(&file_path, false).unwrap();
+    ensure_parent_dirs(&file_path, &DirModes::default(), false).unwrap();
     assert!(parent_dir.exists());
 
     let temp_dir_dry_run = tempdir().unwrap();
@@ -82,8 +82,197 @@ fn test_ensure_parent_dirs() {
     let parent_dir_dry_run = file_path_dry_run.parent().unwrap();
 
     assert!(!parent_dir_dry_run.exists());
-    ensure_parent_dirs(&file_path_dry_run, true).unwrap();
+    ensure_parent_dirs(&file_path_dry_run, &DirModes::default(), true).unwrap();
     assert!(!parent_dir_dry_run.exists());
     
     cleanup_test_dirs();
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_dir_modes() {
+    let home = Path::new("/home/user");
+    let modes = DirModes::new(home, Some(0o750), vec![(PathBuf::from(".ssh"), 0o700), (PathBuf::from(".ssh/public"), 0o755)]);
+
+    assert_eq!(modes.mode_for(&home.join(".config/nvim")), Some(0o750));
+    assert_eq!(modes.mode_for(&home.join(".ssh")), Some(0o700));
+    assert_eq!(modes.mode_for(&home.join(".ssh/config.d")), Some(0o700), "Directories below a configured one get its mode");
+    assert_eq!(modes.mode_for(&home.join(".ssh/public/keys")), Some(0o755), "The innermost configured directory wins");
+    assert_eq!(modes.mode_for(Path::new("/etc/nginx")), None, "Directories outside the home directory keep the umask's mode");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_create_dirs_with_modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir().unwrap();
+    let home = temp_dir.path();
+    std::fs::create_dir(home.join(".config")).unwrap();
+    std::fs::set_permissions(home.join(".config"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let modes = DirModes::new(home, Some(0o750), vec![(PathBuf::from(".gnupg"), 0o700)]);
+
+    create_dirs(&home.join(".gnupg/private-keys-v1.d"), &modes).unwrap();
+    create_dirs(&home.join(".config/fish"), &modes).unwrap();
+
+    let mode = |path: &str| std::fs::metadata(home.join(path)).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(".gnupg"), 0o700);
+    assert_eq!(mode(".gnupg/private-keys-v1.d"), 0o700);
+    assert_eq!(mode(".config/fish"), 0o750);
+    assert_eq!(mode(".config"), 0o755, "Existing directories keep their mode");
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(normalize_path(Path::new("/home/user/./dotfiles/../.bashrc")), PathBuf::from("/home/user/.bashrc"));
+    assert_eq!(normalize_path(Path::new("/home/user/.config/../../other")), PathBuf::from("/home/other"));
+}
+
+#[test]
+fn test_copy_file() {
+    let temp_dir = tempdir().unwrap();
+    let source = temp_dir.path().join("font.ttf");
+    let target = temp_dir.path().join("copy.ttf");
+    std::fs::write(&source, b"\x00\x01glyphs").unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    copy_file(&source, &target).unwrap();
+    assert_eq!(std::fs::read(&target).unwrap(), b"\x00\x01glyphs");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o755, "Permissions are carried over");
+    }
+
+    std::fs::write(&source, b"updated").unwrap();
+    copy_file(&source, &target).unwrap();
+    assert_eq!(std::fs::read(&target).unwrap(), b"updated", "Existing targets are overwritten");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        copy_file_with_mode(&source, &target, Some(0o600)).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"updated");
+        assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600, "The given mode replaces the source's");
+    }
+}
+
+#[test]
+fn test_copy_file_with_io_options() {
+    let temp_dir = tempdir().unwrap();
+    let source = temp_dir.path().join("large.bin");
+    let target = temp_dir.path().join("copy.bin");
+    let content = (0..100_000u32).flat_map(|n| n.to_le_bytes()).collect::<Vec<_>>();
+    std::fs::write(&source, &content).unwrap();
+
+    set_io_options(IoOptions { jobs: 1, copy_buffer_size: Some(4096), fsync: true });
+    copy_file(&source, &target).unwrap();
+    write_file_atomic(&temp_dir.path().join("written"), b"synced", None).unwrap();
+    set_io_options(IoOptions::default());
+
+    assert_eq!(std::fs::read(&target).unwrap(), content, "Buffered copies carry the whole file");
+    assert_eq!(std::fs::read(temp_dir.path().join("written")).unwrap(), b"synced");
+}
+
+#[test]
+fn test_parallel_map() {
+    let items = (0..100).collect::<Vec<u64>>();
+
+    set_io_options(IoOptions { jobs: 8, ..IoOptions::default() });
+    let squares = parallel_map(&items, |n| n * n);
+    let empty = parallel_map(&[] as &[u64], |n| *n);
+    set_io_options(IoOptions::default());
+
+    assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>(), "Results keep the order of the items");
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_write_file_atomic() {
+    let temp_dir = tempdir().unwrap();
+    let target = temp_dir.path().join(".zshrc");
+    std::fs::write(&target, "old").unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    write_file_atomic(&target, b"new", None).unwrap();
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1, "No temporary file is left behind");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600, "The replaced file's permissions are kept");
+
+        let link = temp_dir.path().join(".zshrc.link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_file_atomic(&link, b"through the link", None).unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink(), "Symlinks are written through, not replaced");
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "through the link");
+    }
+}
+
+#[test]
+fn test_with_executable_bits() {
+    assert_eq!(with_executable_bits(0o644), 0o755);
+    assert_eq!(with_executable_bits(0o600), 0o700);
+    assert_eq!(with_executable_bits(0o640), 0o750);
+    assert_eq!(with_executable_bits(0o755), 0o755);
+}
+
+#[test]
+fn test_is_on_path() {
+    let temp_dir = tempdir().unwrap();
+    assert!(!is_on_path(temp_dir.path()));
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let first = std::env::split_paths(&path).next();
+    if let Some(first) = first {
+        assert!(is_on_path(&first));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_writing_as_home_owner() -> anyhow::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use crate::fs_utils::WritingAs;
+
+    // Switching ids needs root
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+    const NOBODY: u32 = 65534;
+
+    let temp_dir = tempdir()?;
+    std::fs::set_permissions(temp_dir.path(), std::fs::Permissions::from_mode(0o755))?;
+    let home = temp_dir.path().join("home");
+    let outside = temp_dir.path().join("shadow");
+    std::fs::create_dir(&home)?;
+    std::os::unix::fs::chown(&home, Some(NOBODY), Some(NOBODY))?;
+    std::fs::write(&outside, "root only")?;
+    std::os::unix::fs::symlink(&outside, home.join(".vimrc"))?;
+
+    {
+        let _writing_as = WritingAs::owner(NOBODY, NOBODY, &home);
+        assert!(write_file_atomic(&home.join(".vimrc"), b"planted", None).is_err(), "A symlink out of the home leads nowhere");
+        write_file_atomic(&home.join(".bashrc"), b"export EDITOR=vim", None)?;
+        create_dirs(&home.join(".config/nvim"), &DirModes::default())?;
+    }
+    assert_eq!(std::fs::read_to_string(&outside)?, "root only");
+    assert_eq!(std::fs::metadata(home.join(".bashrc"))?.uid(), NOBODY, "New files belong to the home's owner");
+    assert_eq!(std::fs::metadata(home.join(".config"))?.uid(), NOBODY);
+
+    write_file_atomic(&outside, b"still root", None)?;
+    assert_eq!(std::fs::read_to_string(&outside)?, "still root", "Root's own ids are back once the guard is dropped");
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::git::{is_git_repo, repo_status, run_git, head_commit, commits_since, changed_files_since, short_commit, generate_commit_message, Worktree};
+
+fn commit_file(repo: &Path, name: &str, content: &str) -> Result<()> {
+    fs::write(repo.join(name), content)?;
+    run_git(repo, &["add", name])?;
+    run_git(repo, &["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "-q", "-m", name])?;
+    Ok(())
+}
+
+#[test]
+fn test_repo_status() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let origin = temp_dir.path().join("origin");
+    let local = temp_dir.path().join("local");
+
+    fs::create_dir_all(&origin)?;
+    run_git(&origin, &["init", "-q", "-b", "main"])?;
+    commit_file(&origin, ".bashrc", "export EDITOR=vim")?;
+
+    run_git(temp_dir.path(), &["clone", "-q", origin.to_str().unwrap(), local.to_str().unwrap()])?;
+    assert!(is_git_repo(&local));
+
+    let status = repo_status(&local)?;
+    assert_eq!(status.branch, "main");
+    assert_eq!(status.ahead_behind, Some((0, 0)));
+    assert!(status.uncommitted.is_empty());
+
+    commit_file(&origin, ".vimrc", "set number")?;
+    run_git(&local, &["fetch", "-q"])?;
+    commit_file(&local, ".zshrc", "export ZSH=1")?;
+    fs::write(local.join(".bashrc"), "export EDITOR=nvim")?;
+
+    let status = repo_status(&local)?;
+    assert_eq!(status.ahead_behind, Some((1, 1)));
+    assert_eq!(status.uncommitted, vec![String::from(".bashrc")]);
+
+    run_git(&local, &["checkout", "-q", "-b", "topic"])?;
+    assert_eq!(repo_status(&local)?.ahead_behind, None, "Branches without an upstream report no counts");
+
+    Ok(())
+}
+
+#[test]
+fn test_changes_since_commit() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path();
+
+    run_git(repo, &["init", "-q"])?;
+    commit_file(repo, ".bashrc", "export EDITOR=vim")?;
+    let installed = head_commit(repo)?;
+    assert_eq!(short_commit(&installed).len(), 7);
+
+    commit_file(repo, ".vimrc", "set number")?;
+    commit_file(repo, ".zshrc", "export ZSH=1")?;
+    fs::write(repo.join(".inputrc"), "set editing-mode vi")?;
+
+    assert_eq!(commits_since(repo, &installed)?, 2);
+
+    let mut changed = changed_files_since(repo, &installed)?;
+    changed.sort();
+    assert_eq!(changed, vec![PathBuf::from(".inputrc"), PathBuf::from(".vimrc"), PathBuf::from(".zshrc")],
+        "Committed, uncommitted and untracked changes are all included");
+
+    Ok(())
+}
+
+#[test]
+fn test_worktree() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path().join("repo");
+    let checkout = temp_dir.path().join("checkout");
+
+    fs::create_dir_all(repo.join("home"))?;
+    run_git(&repo, &["init", "-q"])?;
+    commit_file(&repo, "home/.bashrc", "export EDITOR=vim")?;
+    run_git(&repo, &["tag", "v1"])?;
+    commit_file(&repo, "home/.bashrc", "export EDITOR=nvim")?;
+    fs::write(repo.join("home/.bashrc"), "half-edited")?;
+
+    {
+        let worktree = Worktree::add(&repo.join("home"), "v1", &checkout)?;
+        assert_eq!(worktree.dir(), checkout.join("home"), "A source directory inside the repository is found in the checkout");
+        assert_eq!(fs::read_to_string(worktree.dir().join(".bashrc"))?, "export EDITOR=vim");
+        assert_eq!(fs::read_to_string(repo.join("home/.bashrc"))?, "half-edited", "The working copy is left alone");
+    }
+    assert!(!checkout.exists(), "The checkout is removed when dropped");
+
+    assert!(Worktree::add(&repo, "missing", &checkout).is_err());
+    assert!(Worktree::add(&repo, "--orphan", &checkout).is_err());
+    assert!(Worktree::add(temp_dir.path(), "v1", &checkout).is_err(), "Not a repository");
+    Ok(())
+}
+
+#[test]
+fn test_generate_commit_message() {
+    let paths = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(generate_commit_message(&[]), "Update dotfiles");
+    assert_eq!(generate_commit_message(&paths(&[".bashrc"])), "Update .bashrc");
+    assert_eq!(generate_commit_message(&paths(&[".bashrc", ".vimrc", ".old -> .zshrc"])), "Update .bashrc, .vimrc, .zshrc");
+    assert_eq!(generate_commit_message(&paths(&["a", "b", "c", "d", "e"])), "Update a, b and 3 other files");
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::gitignore::GitIgnore;
+
+#[test]
+fn test_gitignore_rules() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    fs::create_dir_all(source_dir.join(".config/nvim/plugin"))?;
+    fs::create_dir_all(source_dir.join("build"))?;
+    fs::write(source_dir.join(".gitignore"), "build/\n*.log\n")?;
+    fs::write(source_dir.join(".config/nvim/.gitignore"), "plugin/\n!keep.log\n")?;
+
+    let gitignore = GitIgnore::load(source_dir);
+    assert!(gitignore.is_ignored(Path::new("build")));
+    assert!(gitignore.is_ignored(Path::new("build/output.txt")), "Files inside an ignored directory are ignored");
+    assert!(gitignore.is_ignored(Path::new("debug.log")));
+    assert!(gitignore.is_ignored(Path::new(".config/nvim/plugin/packer_compiled.lua")), "Nested .gitignore files apply below their directory");
+    assert!(!gitignore.is_ignored(Path::new(".config/nvim/keep.log")), "A nested negation wins over the parent's rule");
+    assert!(!gitignore.is_ignored(Path::new(".bashrc")));
+
+    assert!(!GitIgnore::default().is_ignored(Path::new("debug.log")));
+
+    Ok(())
+}
+
+#[test]
+fn test_gitignore_pattern_forms() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+
+    fs::create_dir_all(source_dir.join("docs/cache"))?;
+    fs::create_dir_all(source_dir.join(".config/app/logs"))?;
+    fs::create_dir_all(source_dir.join(".git/info"))?;
+    fs::write(source_dir.join("tmp"), "a file, not a directory")?;
+    fs::write(source_dir.join(".gitignore"), "\
+# comments and blank lines are not patterns
+
+/cache
+tmp/
+**/logs/**
+\\#notes
+a[
+*.swp
+")?;
+    fs::write(source_dir.join("docs/.gitignore"), "*.md\n")?;
+    fs::write(source_dir.join(".git/.gitignore"), ".bashrc\n")?;
+
+    let gitignore = GitIgnore::load(source_dir);
+    assert!(gitignore.is_ignored(Path::new("cache")));
+    assert!(!gitignore.is_ignored(Path::new("docs/cache")), "A leading slash anchors the pattern to its directory");
+    assert!(!gitignore.is_ignored(Path::new("tmp")), "A trailing slash only matches directories");
+    assert!(gitignore.is_ignored(Path::new(".config/app/logs/today.txt")));
+    assert!(gitignore.is_ignored(Path::new("#notes")), "An escaped # is a pattern, not a comment");
+    assert!(!gitignore.is_ignored(Path::new("# comments and blank lines are not patterns")));
+    assert!(gitignore.is_ignored(Path::new(".vimrc.swp")), "Lines after one git can't parse still apply");
+    assert!(gitignore.is_ignored(Path::new("docs/guide.md")));
+    assert!(!gitignore.is_ignored(Path::new("README.md")), "A nested .gitignore doesn't apply to its parent");
+    assert!(!gitignore.is_ignored(Path::new(".bashrc")), "Files inside .git aren't read");
+
+    Ok(())
+}
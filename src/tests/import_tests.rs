@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::{tempdir, TempDir};
+
+use crate::config::{read_config, Config};
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+use crate::script::{run_hook, script_path};
+use crate::import::{dotbot_hook_script, import_dotfiles, parse_chezmoi_name, parse_dotbot_config, ChezmoiAttributes, ChezmoiIgnore, DotbotCommand, DotbotConfig, DotbotLink, ImportFormat};
+
+fn setup_test_env() -> Result<(TempDir, PathBuf)> {
+    let test_id = set_test_id();
+
+    let temp_dir = tempdir()?;
+    let temp_home = temp_dir.path().join(format!("home_{}", test_id));
+    fs::create_dir_all(&temp_home)?;
+    set_test_home_dir(Some(temp_home.clone()));
+
+    Ok((temp_dir, temp_home))
+}
+
+fn cleanup_test_env() {
+    set_test_home_dir(None);
+    clear_test_id();
+}
+
+fn create_test_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_chezmoi_name() {
+    assert_eq!(parse_chezmoi_name("dot_bashrc").unwrap(), (".bashrc".to_string(), ChezmoiAttributes::default()));
+    assert_eq!(parse_chezmoi_name("config.fish").unwrap().0, "config.fish");
+    assert_eq!(parse_chezmoi_name("literal_dot_keep").unwrap().0, "dot_keep");
+
+    let (name, attributes) = parse_chezmoi_name("private_executable_dot_script").unwrap();
+    assert_eq!(name, ".script");
+    assert!(attributes.private && attributes.executable && !attributes.template);
+
+    let (name, attributes) = parse_chezmoi_name("dot_gitconfig.tmpl").unwrap();
+    assert_eq!(name, ".gitconfig");
+    assert!(attributes.template);
+
+    assert!(parse_chezmoi_name("run_once_install.sh").is_err());
+    assert!(parse_chezmoi_name("symlink_dot_vim").is_err());
+}
+
+#[test]
+fn test_chezmoi_ignore() -> Result<()> {
+    let ignore = ChezmoiIgnore::parse("# comment\nREADME.md\n.config/secret\n*.log\n!keep.log\n{{ if ne .chezmoi.os \"darwin\" }}\n")?;
+
+    assert!(ignore.is_ignored(Path::new("README.md")));
+    assert!(ignore.is_ignored(Path::new(".config/secret/token")), "Files below an ignored directory are ignored");
+    assert!(ignore.is_ignored(Path::new("debug.log")));
+    assert!(!ignore.is_ignored(Path::new("keep.log")), "Negated patterns are kept");
+    assert!(!ignore.is_ignored(Path::new(".bashrc")));
+    assert_eq!(ignore.templated_lines, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_chezmoi() -> Result<()> {
+    let (temp_dir, temp_home) = setup_test_env()?;
+
+    let chezmoi_dir = temp_dir.path().join("chezmoi");
+    let output_dir = temp_dir.path().join("dotfiles");
+
+    create_test_file(&chezmoi_dir.join("dot_bashrc"), "export EDITOR=vim")?;
+    create_test_file(&chezmoi_dir.join("dot_config/nvim/init.vim"), "set number")?;
+    create_test_file(&chezmoi_dir.join("private_dot_ssh/config"), "Host *")?;
+    create_test_file(&chezmoi_dir.join("dot_config/private_gh/private_tokens/hosts.yml"), "github.com:")?;
+    create_test_file(&chezmoi_dir.join("dot_local/bin/executable_sync"), "#!/bin/sh")?;
+    create_test_file(&chezmoi_dir.join("dot_gitconfig.tmpl"), "[user]\n  name = {{ .name }}")?;
+    create_test_file(&chezmoi_dir.join("run_once_install.sh"), "#!/bin/sh")?;
+    create_test_file(&chezmoi_dir.join("README.md"), "my dotfiles")?;
+    create_test_file(&chezmoi_dir.join(".chezmoiignore"), "README.md\n")?;
+    create_test_file(&chezmoi_dir.join(".chezmoiscripts/run_setup.sh"), "#!/bin/sh")?;
+
+    import_dotfiles(ImportFormat::Chezmoi, &chezmoi_dir, &output_dir, true, false)?;
+    assert!(!output_dir.exists(), "Dry run should not write anything");
+
+    import_dotfiles(ImportFormat::Chezmoi, &chezmoi_dir, &output_dir, false, false)?;
+
+    assert_eq!(fs::read_to_string(output_dir.join(".bashrc"))?, "export EDITOR=vim");
+    assert!(output_dir.join(".config/nvim/init.vim").exists());
+    assert!(output_dir.join(".ssh/config").exists());
+    assert!(output_dir.join(".local/bin/sync").exists());
+    assert!(!output_dir.join(".gitconfig").exists(), "Templates should not be imported");
+    assert!(!output_dir.join("run_once_install.sh").exists(), "Scripts should not be imported");
+    assert!(!output_dir.join("README.md").exists(), "Ignored files should not be imported");
+    assert!(!output_dir.join(".chezmoiignore").exists());
+    assert!(!output_dir.join(".chezmoiscripts").exists());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(output_dir.join(".local/bin/sync"))?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o755, "Executable attribute should set the mode");
+        let mode = |path: &str| -> Result<u32> { Ok(fs::metadata(output_dir.join(path))?.permissions().mode() & 0o777) };
+        assert_eq!(mode(".config/gh")?, 0o700, "A nested private_ directory keeps its attribute");
+        assert_eq!(mode(".config/gh/tokens")?, 0o700);
+        assert_ne!(mode(".config")?, 0o700);
+    }
+
+    assert!(temp_home.join(".dotfiles-rustrc.yaml").exists(), "Config should be created when missing");
+    let config = read_config()?;
+    assert_eq!(config.source_dir, output_dir.canonicalize()?.to_string_lossy());
+    assert_eq!(config.dir_modes.into_iter().collect::<Vec<_>>(), [
+        (String::from(".config/gh"), String::from("700")),
+        (String::from(".config/gh/tokens"), String::from("700")),
+        (String::from(".ssh"), String::from("700")),
+    ], "Private directories are created with mode 700 on install");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_import_stow() -> Result<()> {
+    let (temp_dir, _) = setup_test_env()?;
+
+    let stow_dir = temp_dir.path().join("stow");
+    let output_dir = temp_dir.path().join("dotfiles");
+
+    create_test_file(&stow_dir.join("vim/.vimrc"), "set number")?;
+    create_test_file(&stow_dir.join("vim/README.md"), "vim package")?;
+    create_test_file(&stow_dir.join("fish/.config/fish/config.fish"), "set -x EDITOR vim")?;
+    create_test_file(&stow_dir.join("fish/.stow-local-ignore"), "README.*")?;
+    create_test_file(&stow_dir.join("other/.vimrc"), "set nonumber")?;
+    create_test_file(&stow_dir.join("README.md"), "top-level readme")?;
+    create_test_file(&stow_dir.join(".git/config"), "[core]")?;
+
+    import_dotfiles(ImportFormat::Stow, &stow_dir, &output_dir, false, false)?;
+
+    assert!(output_dir.join(".config/fish/config.fish").exists());
+    assert!(!output_dir.join("README.md").exists(), "Package READMEs and loose files are not imported");
+    assert!(!output_dir.join(".stow-local-ignore").exists());
+    assert!(!output_dir.join("config").exists(), "Hidden directories are not packages");
+    assert_eq!(fs::read_to_string(output_dir.join(".vimrc"))?, "set nonumber",
+        "Packages are processed in name order and the first provider wins");
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_parse_dotbot_config() -> Result<()> {
+    let config = parse_dotbot_config(r#"
+- defaults:
+    link:
+      relink: true
+- clean: ['~']
+- link:
+    ~/.vimrc:
+    ~/.zshrc: zsh/zshrc
+    ~/.config/:
+      glob: true
+      path: config/*
+      if: '[ `uname` = Darwin ]'
+- create:
+    ~/.ssh:
+      mode: 0700
+- shell:
+  - git submodule update --init
+  - [./setup.sh, Running setup]
+"#)?;
+
+    assert_eq!(config.links, vec![
+        DotbotLink { target: String::from("~/.vimrc"), path: String::from("vimrc"), glob: false, condition: None },
+        DotbotLink { target: String::from("~/.zshrc"), path: String::from("zsh/zshrc"), glob: false, condition: None },
+        DotbotLink { target: String::from("~/.config/"), path: String::from("config/*"), glob: true, condition: Some(String::from("[ `uname` = Darwin ]")) },
+    ]);
+    assert_eq!(config.create, vec![(String::from("~/.ssh"), Some(0o700))]);
+    assert_eq!(config.shell, vec![
+        DotbotCommand { command: String::from("git submodule update --init"), description: None },
+        DotbotCommand { command: String::from("./setup.sh"), description: Some(String::from("Running setup")) },
+    ]);
+    assert_eq!(config.unsupported, vec![String::from("clean")]);
+
+    let script = dotbot_hook_script(&config).unwrap();
+    assert!(script.contains(r#"mkdir -p \"$HOME\"/'.ssh' && chmod 700 \"$HOME\"/'.ssh'"#), "{}", script);
+    assert!(script.contains("// Running setup"));
+    assert_eq!(dotbot_hook_script(&DotbotConfig::default()), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_dotbot() -> Result<()> {
+    let (temp_dir, _) = setup_test_env()?;
+
+    let dotbot_dir = temp_dir.path().join("dotbot");
+    let output_dir = temp_dir.path().join("dotfiles");
+
+    create_test_file(&dotbot_dir.join("install.conf.yaml"), r#"
+- link:
+    ~/.vimrc:
+    ~/.config/fish: fish
+    ~/.local/bin/:
+      glob: true
+      path: bin/*
+    /etc/hosts.d/work: hosts
+    ~/.config/../../etc/passwd: vimrc
+    .local/./share/../state/vim: vimrc
+- shell:
+  - echo done
+"#)?;
+    create_test_file(&dotbot_dir.join("vimrc"), "set number")?;
+    create_test_file(&dotbot_dir.join("fish/config.fish"), "set -x EDITOR vim")?;
+    create_test_file(&dotbot_dir.join("bin/ll"), "ls -l")?;
+    create_test_file(&dotbot_dir.join("hosts"), "10.0.0.1 work")?;
+
+    import_dotfiles(ImportFormat::Dotbot, &dotbot_dir.join("install.conf.yaml"), &output_dir, false, false)?;
+
+    assert_eq!(fs::read_to_string(output_dir.join(".vimrc"))?, "set number", "A link without a path uses the target name without its dot");
+    assert!(output_dir.join(".config/fish/config.fish").exists(), "Linked directories are copied whole");
+    assert!(output_dir.join(".local/bin/ll").exists(), "Glob matches are placed inside the target directory");
+    assert!(output_dir.join("root/etc/hosts.d/work").exists());
+    assert!(output_dir.join(".local/state/vim").exists(), "Relative targets are normalized");
+    assert!(!temp_dir.path().join("etc/passwd").exists() && !output_dir.join("etc/passwd").exists(),
+        "A target leading out of the home directory is skipped");
+    assert!(fs::read_to_string(output_dir.join("root/.dotfiles.yaml"))?.contains("target: /"));
+    assert!(fs::read_to_string(output_dir.join(".dotfiles-rust/post-install.rhai"))?.contains("echo done"));
+
+    cleanup_test_env();
+    Ok(())
+}
+
+#[test]
+fn test_dotbot_hook_quotes_source_dir() -> Result<()> {
+    let (temp_dir, _) = setup_test_env()?;
+
+    let source_dir = temp_dir.path().join("it's $HOME");
+    let config = DotbotConfig {
+        shell: vec![DotbotCommand { command: String::from("touch ran"), description: None }],
+        ..Default::default()
+    };
+    create_test_file(&script_path(&source_dir, "post-install"), &dotbot_hook_script(&config).unwrap())?;
+
+    let config = Config {
+        source_dir: source_dir.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    run_hook(&config, "post-install", false)?;
+    assert!(source_dir.join("ran").exists(), "Commands run in a source directory with quotes in its name");
+
+    cleanup_test_env();
+    Ok(())
+}
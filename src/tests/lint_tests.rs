@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::{Config, Layout};
+use crate::lint::{lint_source, LintIssue, LintProblem};
+
+fn create_file(path: &Path, content: &str) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_lint_clean_source() -> Result<()> {
+    let temp_dir = tempdir()?;
+    create_file(&temp_dir.path().join(".bashrc"), "export EDITOR=vim")?;
+    create_file(&temp_dir.path().join(".gitconfig.tmpl"), "email = {{ email }}")?;
+
+    let config = Config { source_dir: temp_dir.path().to_string_lossy().into_owned(), ignore: vec![String::from(".bashrc")], ..Default::default() };
+    let vars = BTreeMap::from([(String::from("email"), String::from("me@example.com"))]);
+    assert_eq!(lint_source(&config, &vars)?, []);
+
+    Ok(())
+}
+
+#[test]
+fn test_lint_problems() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+    create_file(&source_dir.join("bash/.bashrc"), "export EDITOR=vim")?;
+    create_file(&source_dir.join("work/.bashrc"), "export EDITOR=code")?;
+    create_file(&source_dir.join("git/.gitconfig.tmpl"), "email = {{ email }}\nname = {{ name }}")?;
+    create_file(&source_dir.join("nvim/.config"), "a file in the way")?;
+    create_file(&source_dir.join("vim/.config/nvim/init.vim"), "set number")?;
+
+    let config = Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        layout: Layout::Stow,
+        ignore: vec![String::from(".zshrc")],
+        ..Default::default()
+    };
+    let vars = BTreeMap::from([(String::from("name"), String::from("Me"))]);
+    let problems = lint_source(&config, &vars)?;
+
+    assert_eq!(problems, [
+        LintProblem { path: PathBuf::from("bash/.bashrc"), issue: LintIssue::SameTarget { target: PathBuf::from(".bashrc"), sources: vec![PathBuf::from("bash/.bashrc"), PathBuf::from("work/.bashrc")] } },
+        LintProblem { path: PathBuf::from("config"), issue: LintIssue::UnusedIgnore { pattern: String::from(".zshrc") } },
+        LintProblem { path: PathBuf::from("git/.gitconfig.tmpl"), issue: LintIssue::UndefinedVariables { names: vec![String::from("email")] } },
+        LintProblem { path: PathBuf::from("vim/.config/nvim/init.vim"), issue: LintIssue::TargetInsideFile { target: PathBuf::from(".config/nvim/init.vim"), file: PathBuf::from(".config") } },
+    ]);
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::path::Path;
+use anyhow::Result;
+
+use crate::commands::is_blacklisted;
+use crate::matcher::PathMatcher;
+
+#[test]
+fn test_name_patterns_match_at_any_depth() -> Result<()> {
+    let matcher = PathMatcher::new(&["*.swp", "cache/"])?;
+
+    assert!(matcher.is_match(Path::new(".vimrc.swp")));
+    assert!(matcher.is_match(Path::new(".config/nvim/.init.lua.swp")));
+    assert!(matcher.is_match(Path::new(".config/app/cache/state.json")), "A matching directory matches everything below it");
+    assert!(!matcher.is_match(Path::new(".config/app/cached.json")));
+
+    Ok(())
+}
+
+#[test]
+fn test_patterns_with_a_slash_are_anchored() -> Result<()> {
+    let matcher = PathMatcher::new(&["/.gitignore", ".config/*/cache"])?;
+
+    assert!(matcher.is_match(Path::new(".gitignore")));
+    assert!(!matcher.is_match(Path::new(".config/git/.gitignore")));
+    assert!(matcher.is_match(Path::new(".config/app/cache/state.json")));
+    assert!(!matcher.is_match(Path::new(".config/app/sub/cache/state.json")), "* does not cross directories");
+    assert!(!matcher.is_match(Path::new("backup/.config/app/cache")));
+
+    assert!(PathMatcher::new(&["[unclosed"]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_blacklist_matches_whole_names() {
+    assert!(is_blacklisted(Path::new(".git/config")));
+    assert!(is_blacklisted(Path::new("docs/README.md")));
+    assert!(is_blacklisted(Path::new(".config/nvim/node_modules/plugin/index.js")));
+    assert!(is_blacklisted(Path::new(".gitignore")));
+
+    assert!(!is_blacklisted(Path::new(".gitconfig")), "Names that merely contain a blacklisted name are installed");
+    assert!(!is_blacklisted(Path::new(".config/gitconfig.d/work")));
+    assert!(!is_blacklisted(Path::new(".config/git/.gitignore")));
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeSet;
+use std::fs;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::commands::StatusCounts;
+use crate::metrics::{render_prometheus, write_metrics, Metrics};
+
+#[test]
+fn test_render_prometheus() {
+    let metrics = Metrics {
+        managed_files: 4,
+        ok_files: 2,
+        modified_files: 1,
+        missing_files: 1,
+        extra_files: 0,
+        last_install_timestamp: Some(1760601600),
+        backup_files: 3,
+        backup_bytes: 2048,
+    };
+    let output = render_prometheus(&metrics);
+
+    assert!(output.starts_with(concat!(
+        "# HELP dotfiles_rust_managed_files Files, blocks and patches managed by dotfiles-rust.\n",
+        "# TYPE dotfiles_rust_managed_files gauge\n",
+        "dotfiles_rust_managed_files 4\n",
+    )));
+    assert!(output.contains("dotfiles_rust_files{state=\"modified\"} 1\n"));
+    assert!(output.contains("dotfiles_rust_drifted_files 2\n"));
+    assert!(output.contains("dotfiles_rust_last_install_timestamp_seconds 1760601600\n"));
+    assert!(output.contains("dotfiles_rust_backup_bytes 2048\n"));
+
+    let never_installed = render_prometheus(&Metrics { last_install_timestamp: None, ..metrics });
+    assert!(!never_installed.contains("last_install"), "No timestamp is made up before the first install");
+}
+
+#[test]
+fn test_metrics_from_counts() {
+    let counts = StatusCounts { ok: 5, modified: 2, missing: 1, extra: 4 };
+    let metrics = Metrics::new(&counts, Some(1760601600), (3, 2048));
+
+    assert_eq!(metrics, Metrics {
+        managed_files: 8,
+        ok_files: 5,
+        modified_files: 2,
+        missing_files: 1,
+        extra_files: 4,
+        last_install_timestamp: Some(1760601600),
+        backup_files: 3,
+        backup_bytes: 2048,
+    }, "Extra files aren't managed");
+}
+
+#[test]
+fn test_prometheus_format() {
+    let output = render_prometheus(&Metrics::new(&StatusCounts { ok: 1, modified: 0, missing: 2, extra: 0 }, Some(1760601600), (0, 0)));
+
+    // Every sample is `name{labels} value` and comes after the HELP and TYPE lines of its metric
+    let mut described = BTreeSet::new();
+    for line in output.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let mut words = comment.split_whitespace();
+            let (kind, name) = (words.next().unwrap(), words.next().unwrap());
+            match kind {
+                "HELP" => assert!(described.insert(name.to_string()), "{} is described twice", name),
+                "TYPE" => assert_eq!(words.next(), Some("gauge")),
+                other => panic!("Unexpected comment {}", other),
+            }
+            continue;
+        }
+
+        let (series, value) = line.rsplit_once(' ').unwrap();
+        let name = series.split('{').next().unwrap();
+        assert!(name.starts_with("dotfiles_rust_") && name.bytes().all(|b| b.is_ascii_lowercase() || b == b'_'), "Bad metric name in {}", line);
+        assert!(described.contains(name), "{} has no HELP line", name);
+        if let Some(labels) = series.strip_prefix(name).filter(|labels| !labels.is_empty()) {
+            assert!(labels.starts_with('{') && labels.ends_with('}') && labels.contains("=\""), "Bad labels in {}", line);
+        }
+        assert!(value.parse::<f64>().is_ok(), "Bad value in {}", line);
+    }
+    assert!(output.contains("dotfiles_rust_files{state=\"missing\"} 2\n"));
+    assert!(output.ends_with('\n'));
+}
+
+#[test]
+fn test_write_metrics() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let metrics = Metrics { managed_files: 2, ok_files: 2, ..Default::default() };
+
+    let json_path = temp_dir.path().join("dotfiles.json");
+    write_metrics(&json_path, &metrics)?;
+    let json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&json_path)?)?;
+    assert_eq!(json["managed_files"], 2);
+    assert!(json["last_install_timestamp"].is_null(), "A missing timestamp is null in JSON");
+
+    let prom_path = temp_dir.path().join("dotfiles.prom");
+    fs::write(&prom_path, "stale")?;
+    write_metrics(&prom_path, &metrics)?;
+    assert_eq!(fs::read_to_string(&prom_path)?, render_prometheus(&metrics), "The previous file is replaced");
+
+    assert!(write_metrics(&temp_dir.path().join("missing/dotfiles.prom"), &metrics).is_err());
+    assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2, "No temporary files are left behind");
+
+    Ok(())
+}
@@ -1,4 +1,35 @@
 mod fs_utils_tests;
 mod backup_tests;
+mod blocks_tests;
 mod commands_tests;
-mod config_tests; 
\ No newline at end of file
+mod config_tests;
+mod update_tests;
+mod suggest_tests;
+mod daemon_tests;
+mod import_tests;
+mod summary_tests;
+mod verify_tests;
+mod diff_tests;
+mod git_tests;
+mod template_tests;
+mod archive_tests;
+mod dir_config_tests;
+mod script_tests;
+mod shell_init_tests;
+mod privileged_tests;
+mod difftool_tests;
+mod pager_tests;
+mod matcher_tests;
+mod export_tests;
+mod wizard_tests;
+mod patch_tests;
+mod fleet_tests;
+mod gitignore_tests;
+mod signing_tests;
+mod skel_tests;
+mod prompt_tests;
+mod table_tests;
+mod notification_tests;
+mod webhook_tests;
+mod metrics_tests;
+mod lint_tests;
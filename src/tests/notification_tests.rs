@@ -0,0 +1,28 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::notification::drift_message;
+
+fn paths(names: &[&str]) -> BTreeSet<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_drift_message() {
+    let (title, body) = drift_message(&paths(&[".bashrc"]), &paths(&[".vimrc"]), &paths(&[])).unwrap();
+    assert_eq!(title, "Dotfiles updated");
+    assert_eq!(body, "Installed 1 file(s): .bashrc\n1 file(s) differ from the source: .vimrc");
+
+    let (title, body) = drift_message(&paths(&[]), &paths(&[".vimrc", ".zshrc"]), &paths(&[".vimrc"])).unwrap();
+    assert_eq!(title, "Dotfiles drifted");
+    assert_eq!(body, "1 file(s) differ from the source: .zshrc", "Drift that was already reported isn't repeated");
+
+    assert_eq!(drift_message(&paths(&[]), &paths(&[".vimrc"]), &paths(&[".vimrc"])), None);
+}
+
+#[test]
+fn test_drift_message_lists_a_few_files() {
+    let drifted = paths(&["a", "b", "c", "d", "e", "f", "g"]);
+    let (_, body) = drift_message(&paths(&[]), &drifted, &paths(&[])).unwrap();
+    assert_eq!(body, "7 file(s) differ from the source: a, b, c, d, e, and 2 more");
+}
@@ -0,0 +1,15 @@
+use crate::pager::{is_active, pager_command, start_pager, DEFAULT_PAGER};
+
+#[test]
+fn test_pager_command() {
+    assert_eq!(pager_command(None).as_deref(), Some(DEFAULT_PAGER));
+    assert_eq!(pager_command(Some("most -s")).as_deref(), Some("most -s"));
+    assert_eq!(pager_command(Some("cat")), None, "cat turns paging off");
+    assert_eq!(pager_command(Some("  ")), None);
+}
+
+#[test]
+fn test_pager_disabled() {
+    assert!(start_pager(false).is_none());
+    assert!(!is_active());
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::patch::{is_patch, Patch, PatchState};
+
+const ORIGINAL: &str = "[ui]\ntheme = light\nfont = mono\n\n[editor]\ntabs = 4\n";
+const PATCH: &str = "\
+--- a/settings.ini
++++ b/settings.ini
+@@ -1,3 +1,3 @@
+ [ui]
+-theme = light
++theme = dark
+ font = mono
+@@ -5,2 +5,3 @@
+ [editor]
+ tabs = 4
++wrap = true
+";
+
+#[test]
+fn test_is_patch() {
+    assert!(is_patch(Path::new(".config/app/settings.ini.patch")));
+    assert!(!is_patch(Path::new(".config/app/settings.ini")));
+}
+
+#[test]
+fn test_apply_and_reverse() -> anyhow::Result<()> {
+    let patch = Patch::parse(PATCH)?;
+
+    let patched = patch.apply(ORIGINAL)?;
+    assert_eq!(patched, "[ui]\ntheme = dark\nfont = mono\n\n[editor]\ntabs = 4\nwrap = true\n");
+    assert_eq!(patch.reversed().apply(&patched)?, ORIGINAL);
+
+    let moved = format!("# added by the app\n{}", ORIGINAL);
+    assert_eq!(patch.apply(&moved)?, format!("# added by the app\n{}", patched), "Hunks are found when lines have moved");
+
+    Ok(())
+}
+
+#[test]
+fn test_patch_state() -> anyhow::Result<()> {
+    let patch = Patch::parse(PATCH)?;
+
+    assert_eq!(patch.state(ORIGINAL), PatchState::Unapplied);
+    assert_eq!(patch.state(&patch.apply(ORIGINAL)?), PatchState::Applied, "Adding lines isn't applied twice");
+    assert_eq!(patch.state("[ui]\ntheme = solarized\n"), PatchState::Conflict);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_patch() {
+    let patch = Patch::parse("@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n").unwrap();
+    assert_eq!(patch.apply("a").unwrap(), "b");
+
+    assert!(Patch::parse("just some text\n").is_err());
+    assert!(Patch::parse("@@ -1,3 +1,3 @@\n a\n").is_err(), "Truncated hunks are rejected");
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::fs_utils::{get_data_dir, set_test_home_dir, set_test_id, clear_test_id};
+use crate::privileged::{escalated_write, is_privileged};
+
+#[test]
+fn test_is_privileged() {
+    let home = Path::new("/home/me");
+
+    assert!(is_privileged(Path::new("/etc/nginx/nginx.conf"), home));
+    assert!(!is_privileged(Path::new("/home/me/.bashrc"), home));
+    assert!(is_privileged(Path::new("/home/meow/.bashrc"), home), "Paths are compared by component");
+}
+
+// `env` runs the commands unprivileged, which is enough to check what gets executed
+#[cfg(unix)]
+#[test]
+fn test_escalated_write() -> Result<()> {
+    set_test_id();
+    let temp_dir = tempdir()?;
+    set_test_home_dir(Some(temp_dir.path().join("home")));
+
+    let source = temp_dir.path().join("nginx.conf.tmpl");
+    let target = temp_dir.path().join("etc/nginx/nginx.conf");
+    fs::write(&source, "worker_processes {{ workers }};")?;
+
+    escalated_write("env", &source, None, &target, Some(0o640))?;
+    assert_eq!(fs::read_to_string(&target)?, "worker_processes {{ workers }};");
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&target)?.permissions().mode() & 0o777, 0o640);
+    }
+
+    escalated_write("env", &source, Some(b"worker_processes 4;"), &target, None)?;
+    assert_eq!(fs::read_to_string(&target)?, "worker_processes 4;", "Rendered content is written instead of the template");
+    let staged = fs::read_dir(get_data_dir()?)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("privileged."))
+        .count();
+    assert_eq!(staged, 0, "Staged content is removed afterwards");
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o644))?;
+        let new_target = temp_dir.path().join("etc/nginx/mime.types");
+        escalated_write("env", &source, Some(b"types {}"), &new_target, None)?;
+        assert_eq!(fs::metadata(&new_target)?.permissions().mode() & 0o777, 0o644, "A new file gets its source's mode, not the staged file's");
+    }
+
+    assert!(escalated_write("false", &source, None, &target, None).is_err(), "A failing escalation command is reported");
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
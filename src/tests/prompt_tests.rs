@@ -0,0 +1,53 @@
+use anyhow::Result;
+
+use crate::prompt::{choose, confirm, input, pick, set_answers, AnswerMode};
+
+fn answers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn test_injected_answers() -> Result<()> {
+    set_answers(AnswerMode::Ask, &answers(&[("reset", "yes"), ("prune", "n"), ("restore", "q"), ("fix", "adopt"), ("email", "me@example.com")]));
+
+    assert!(confirm("reset", "Reset?", false)?);
+    assert!(!confirm("prune", "Prune?", true)?);
+    assert_eq!(choose("restore", "Restore?", &["restore", "skip", "quit"], 1)?, 2);
+    assert_eq!(choose("fix", "Fix?", &["reinstall", "adopt", "diff", "ignore", "quit"], 3)?, 1);
+    assert_eq!(input("email", "Email?")?, "me@example.com");
+
+    set_answers(AnswerMode::Ask, &answers(&[("reset", "maybe"), ("restore", "later")]));
+    assert!(confirm("reset", "Reset?", false).is_err());
+    assert!(choose("restore", "Restore?", &["restore", "skip", "quit"], 1).is_err());
+
+    set_answers(AnswerMode::Ask, &[]);
+    Ok(())
+}
+
+#[test]
+fn test_assumed_answers() -> Result<()> {
+    set_answers(AnswerMode::AssumeYes, &answers(&[("prune", "no")]));
+    assert!(confirm("reset", "Reset?", false)?);
+    assert!(!confirm("prune", "Prune?", false)?, "An injected answer should win over --assume-yes");
+    assert_eq!(choose("restore", "Restore?", &["restore", "skip", "quit"], 1)?, 0);
+
+    set_answers(AnswerMode::AssumeNo, &[]);
+    assert!(!confirm("reset", "Reset?", true)?);
+    assert_eq!(choose("restore", "Restore?", &["restore", "skip", "quit"], 1)?, 1);
+
+    set_answers(AnswerMode::Ask, &[]);
+    Ok(())
+}
+
+#[test]
+fn test_pick_without_terminal() -> Result<()> {
+    let items = ["~/.bashrc".to_string(), "~/.vimrc".to_string()];
+
+    set_answers(AnswerMode::Ask, &[]);
+    assert_eq!(pick("File to edit", &items)?, None, "Nothing is picked without a terminal");
+    set_answers(AnswerMode::AssumeYes, &[]);
+    assert_eq!(pick("File to edit", &items)?, None, "Assumed answers never pick a file");
+
+    set_answers(AnswerMode::Ask, &[]);
+    Ok(())
+}
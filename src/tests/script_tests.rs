@@ -0,0 +1,142 @@
+use std::fs;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::Config;
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+use crate::script::{helper_values, inherits_env, run_hook, script_path, HookConfig};
+use crate::template::{template_vars, template_vars_for};
+
+fn config_for(source_dir: &std::path::Path) -> Config {
+    Config {
+        source_dir: source_dir.to_string_lossy().into_owned(),
+        template_vars: [(String::from("email"), String::from("me@example.com"))].into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_helper_values() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let config = config_for(temp_dir.path());
+
+    assert!(helper_values(&config)?.is_empty(), "No helpers script means no helper values");
+
+    let helpers = script_path(temp_dir.path(), "helpers");
+    fs::create_dir_all(helpers.parent().unwrap())?;
+    fs::write(&helpers, r#"
+        fn signature() { "-- " + config().template_vars.email }
+        fn answer() { 40 + 2 }
+        fn greet(name) { "hello " + name }
+    "#)?;
+
+    let values = helper_values(&config)?;
+    assert_eq!(values.get("signature").map(String::as_str), Some("-- me@example.com"));
+    assert_eq!(values.get("answer").map(String::as_str), Some("42"));
+    assert!(!values.contains_key("greet"), "Functions taking parameters aren't template variables");
+
+    fs::write(&helpers, r#"fn user() { run("whoami").stdout }"#)?;
+    assert!(helper_values(&config).is_err(), "Helpers can't run commands");
+
+    Ok(())
+}
+
+#[test]
+fn test_helper_values_only_computed_by_installs() -> Result<()> {
+    let temp_dir = tempdir()?;
+    set_test_id();
+    set_test_home_dir(Some(temp_dir.path().join("home")));
+    let config = config_for(temp_dir.path());
+
+    let helpers = script_path(temp_dir.path(), "helpers");
+    fs::create_dir_all(helpers.parent().unwrap())?;
+    fs::write(&helpers, r#"fn theme() { "dark" }"#)?;
+
+    assert!(!template_vars(&config, &[])?.contains_key("theme"), "Looking at files doesn't evaluate helpers");
+    assert_eq!(template_vars_for(&config, &[], None, true)?.get("theme").map(String::as_str), Some("dark"));
+
+    fs::write(&helpers, r#"fn theme() { "light" }"#)?;
+    assert_eq!(template_vars(&config, &[])?.get("theme").map(String::as_str), Some("dark"), "The last install's values are used");
+
+    set_test_home_dir(None);
+    clear_test_id();
+
+    Ok(())
+}
+
+#[test]
+fn test_run_hook() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let config = config_for(temp_dir.path());
+    let hook = script_path(temp_dir.path(), "pre-install");
+
+    run_hook(&config, "pre-install", false)?;
+
+    fs::create_dir_all(hook.parent().unwrap())?;
+    fs::write(&hook, r#"
+        let result = run("echo hooked");
+        if result.code != 0 || !result.stdout.contains("hooked") { throw "run() returned " + result; }
+    "#)?;
+    run_hook(&config, "pre-install", false)?;
+
+    fs::write(&hook, r#"throw "not today";"#)?;
+    assert!(run_hook(&config, "pre-install", true).is_ok(), "Dry runs don't execute hooks");
+    let error = run_hook(&config, "pre-install", false).unwrap_err();
+    assert!(format!("{:#}", error).contains("not today"));
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_config() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let mut config = config_for(temp_dir.path());
+    let hook = script_path(temp_dir.path(), "post-install");
+    fs::create_dir_all(hook.parent().unwrap())?;
+    fs::create_dir_all(temp_dir.path().join("scripts"))?;
+
+    config.hooks.insert(String::from("post-install"), HookConfig {
+        working_dir: Some(String::from("scripts")),
+        env_deny: vec![String::from("HOME")],
+        ..Default::default()
+    });
+    fs::write(&hook, r#"
+        let result = run("pwd; echo \"home=$HOME\"");
+        if !result.stdout.contains("scripts") { throw "ran in " + result.stdout; }
+        if !result.stdout.contains("home=\n") { throw "inherited " + result.stdout; }
+    "#)?;
+    run_hook(&config, "post-install", false)?;
+
+    config.hooks.insert(String::from("post-install"), HookConfig { timeout: Some(1), ..Default::default() });
+    fs::write(&hook, r#"run("sleep 30"); loop {}"#)?;
+    let started = Instant::now();
+    let error = run_hook(&config, "post-install", false).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(10), "A hung hook should be stopped");
+    assert!(error.to_string().contains("timed out"), "Unexpected error: {:#}", error);
+
+    // A process that leaves the command's group keeps its output open after the command exits
+    if cfg!(target_os = "linux") {
+        fs::write(&hook, r#"run("setsid sleep 30 &"); loop {}"#)?;
+        let started = Instant::now();
+        assert!(run_hook(&config, "post-install", false).is_err());
+        assert!(started.elapsed() < Duration::from_secs(10), "Reading the output should stop at the timeout too");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_inherits_env() {
+    let hook = HookConfig {
+        env_allow: vec![String::from("PATH"), String::from("LC_*")],
+        env_deny: vec![String::from("LC_ALL")],
+        ..Default::default()
+    };
+
+    assert!(inherits_env(&hook, "PATH"));
+    assert!(inherits_env(&hook, "LC_CTYPE"));
+    assert!(!inherits_env(&hook, "LC_ALL"), "Denied variables aren't inherited even when allowed");
+    assert!(!inherits_env(&hook, "AWS_SECRET_ACCESS_KEY"));
+    assert!(inherits_env(&HookConfig::default(), "AWS_SECRET_ACCESS_KEY"), "Everything is inherited by default");
+}
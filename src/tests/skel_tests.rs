@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::skel::{parse_passwd, UserHome};
+
+#[test]
+fn test_parse_passwd() {
+    let passwd = "\
+root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+# a comment
+alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+svc:x:1001:1001::/srv/svc:/bin/false
+broken line
+bob:x:1002:100::/home/bob:/usr/bin/fish
+";
+
+    assert_eq!(parse_passwd(passwd), [
+        UserHome { name: String::from("alice"), uid: 1000, gid: 1000, home: PathBuf::from("/home/alice"), shell: PathBuf::from("/bin/zsh") },
+        UserHome { name: String::from("bob"), uid: 1002, gid: 100, home: PathBuf::from("/home/bob"), shell: PathBuf::from("/usr/bin/fish") },
+    ], "System accounts and accounts without a login shell are left out");
+}
+
+#[test]
+fn test_parse_passwd_edge_cases() {
+    let passwd = "\
+carol:x:1003:1003::/home/carol:/bin/bash\r
+\x20\x20# an indented comment
+
+sys:x:999:999::/home/sys:/bin/bash
+dave:x:1000:1000::/home/dave:
+guest:x:65534:65534::/home/guest:/bin/sh
+erin:x:1004:1004::/home/erin:/bin/sh:extra
+frank:x:abc:1005::/home/frank:/bin/sh
+gina:x:1006::::/home/gina:/bin/sh
+huge:x:4294967296:1007::/home/huge:/bin/sh
+ivan:x:1008:1008::/home/ivan:/usr/local/bin/nologin-wrapper
+";
+
+    let names = parse_passwd(passwd).into_iter().map(|user| user.name).collect::<Vec<_>>();
+    assert_eq!(names, ["carol", "dave", "ivan"],
+        "CRLF line ends are fine, an empty shell is the default shell, and malformed or out of range fields are skipped");
+    assert_eq!(parse_passwd(passwd)[0].shell, PathBuf::from("/bin/bash"));
+    assert!(parse_passwd("").is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_install_for_users() -> anyhow::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use crate::commands::InstallOptions;
+    use crate::config::{write_config, Config};
+    use crate::fs_utils::{set_test_backup_dir, set_test_home_dir, set_test_id, clear_test_id};
+    use crate::skel::install_for_users;
+
+    // Writing as another user needs root
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+    const NOBODY: u32 = 65534;
+
+    let test_id = set_test_id();
+    let temp_dir = tempfile::tempdir()?;
+    fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o755))?;
+    let root_home = temp_dir.path().join(format!("root_{}", test_id));
+    let source_dir = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&root_home)?;
+    fs::create_dir_all(source_dir.join(".config/nvim"))?;
+    fs::create_dir_all(temp_dir.path().join("backup"))?;
+    set_test_home_dir(Some(root_home));
+    set_test_backup_dir(Some(temp_dir.path().join("backup")));
+    fs::write(source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    fs::write(source_dir.join(".vimrc"), "set number")?;
+    fs::write(source_dir.join(".config/nvim/init.vim"), "set list")?;
+    write_config(&Config { source_dir: source_dir.to_string_lossy().into_owned(), ..Default::default() })?;
+
+    // The account planted a symlink to a file only root may change
+    let home = temp_dir.path().join("alice");
+    let shadow = temp_dir.path().join("shadow");
+    fs::create_dir(&home)?;
+    std::os::unix::fs::chown(&home, Some(NOBODY), Some(NOBODY))?;
+    fs::write(&shadow, "root only")?;
+    std::os::unix::fs::symlink(&shadow, home.join(".vimrc"))?;
+
+    let user = |name: &str, home: PathBuf| UserHome { name: name.into(), uid: NOBODY, gid: NOBODY, home, shell: PathBuf::from("/bin/sh") };
+    let users = [user("alice", home.clone()), user("gone", temp_dir.path().join("gone"))];
+    let failed = install_for_users(&InstallOptions { force: true, keep_going: true, ..Default::default() }, &users)?;
+
+    assert_eq!(failed, 1, "Only the write through the symlink fails");
+    assert_eq!(fs::read_to_string(&shadow)?, "root only", "The symlink doesn't lead root's writes out of the home");
+    assert_eq!(fs::metadata(home.join(".bashrc"))?.uid(), NOBODY, "Installed files belong to the account");
+    assert_eq!(fs::metadata(home.join(".config/nvim"))?.uid(), NOBODY, "So do the directories created for them");
+    assert!(!temp_dir.path().join("gone").exists(), "Accounts without a home are skipped");
+
+    set_test_home_dir(None);
+    set_test_backup_dir(None);
+    clear_test_id();
+    Ok(())
+}
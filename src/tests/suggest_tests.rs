@@ -0,0 +1,19 @@
+use crate::suggest::{levenshtein, closest_match};
+
+#[test]
+fn test_levenshtein() {
+    assert_eq!(levenshtein("", ""), 0);
+    assert_eq!(levenshtein("abc", ""), 3);
+    assert_eq!(levenshtein("kitten", "sitting"), 3);
+    assert_eq!(levenshtein("source_dir", "source_dir"), 0);
+    assert_eq!(levenshtein("soruce_dir", "source_dir"), 2);
+}
+
+#[test]
+fn test_closest_match() {
+    let candidates = ["source_dir", "ignore", "machines"];
+
+    assert_eq!(closest_match("sourcedir", candidates), Some("source_dir"));
+    assert_eq!(closest_match("ignroe", candidates), Some("ignore"));
+    assert_eq!(closest_match("completely_different", candidates), None);
+}
@@ -0,0 +1,27 @@
+use crate::summary::{format_bytes, DryRunSummary, PlannedAction};
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(0), "0 B");
+    assert_eq!(format_bytes(1023), "1023 B");
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+    assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MiB");
+}
+
+#[test]
+fn test_dry_run_summary_render() {
+    let mut summary = DryRunSummary::new();
+    summary.add(PlannedAction::Skip, 10);
+    summary.add(PlannedAction::Copy, 100);
+    summary.add(PlannedAction::Copy, 2048);
+    summary.add(PlannedAction::BackUp, 50);
+
+    let table = summary.render();
+    let lines: Vec<_> = table.lines().collect();
+
+    assert_eq!(lines.len(), 4, "Header plus one row per action");
+    assert!(lines[0].contains("Action") && lines[0].contains("Files") && lines[0].contains("Size"));
+    assert!(lines[1].contains("would copy") && lines[1].contains(" 2 ") && lines[1].contains("2.1 KiB"));
+    assert!(lines[2].contains("would back up") && lines[2].contains("50 B"));
+    assert!(lines[3].contains("would skip"), "Rows follow a fixed order regardless of insertion order");
+}
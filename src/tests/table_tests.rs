@@ -0,0 +1,43 @@
+use crate::table::{truncate, Cell, Column, Style, Table};
+
+#[test]
+fn test_table_alignment() {
+    let mut table = Table::new(vec![Column::left("File"), Column::left("State"), Column::right("Size")]);
+    table.row(vec![".bashrc".into(), "installed".into(), "1.2 KiB".into()]);
+    table.row(vec![".config/nvim/init.lua".into(), "missing".into(), "12 B".into()]);
+
+    assert_eq!(table.render(None), concat!(
+        "  File                   State         Size\n",
+        "  .bashrc                installed  1.2 KiB\n",
+        "  .config/nvim/init.lua  missing       12 B\n",
+    ));
+}
+
+#[test]
+fn test_table_sections_and_trailing_space() {
+    let mut table = Table::new(vec![Column::left(""), Column::left("")]);
+    table.section(Cell::new(Style::Plain, "~/.a-rather-long-section-heading"));
+    table.row(vec!["x".into(), "".into()]);
+    table.row(vec!["yy".into(), "note".into()]);
+
+    assert_eq!(table.render(None), "~/.a-rather-long-section-heading\n  x\n  yy  note\n",
+        "Sections don't widen columns and lines don't end in spaces");
+}
+
+#[test]
+fn test_table_shrinks_to_width() {
+    let mut table = Table::new(vec![Column::left("").shrink(), Column::left("")]);
+    table.row(vec![".config/some/deeply/nested/file.toml".into(), "Modified".into()]);
+
+    let rendered = table.render(Some(30));
+    assert_eq!(rendered, "  …/nested/file.toml  Modified\n");
+    assert_eq!(rendered.trim_end().chars().count(), 30);
+
+    assert_eq!(table.render(Some(200)), "  .config/some/deeply/nested/file.toml  Modified\n");
+}
+
+#[test]
+fn test_truncate() {
+    assert_eq!(truncate("short", 10), "short");
+    assert_eq!(truncate("abcdefghij", 5), "…ghij");
+}
@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::skel::UserHome;
+use crate::template::{builtin_vars, parse_assignment, referenced_variables, render, target_file_path, undefined_variables};
+
+fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn test_render() {
+    let template = "[user]\n  name = {{ name }}\n  email = {{email}}\n  signing = {{ name }}\n";
+    let rendered = render(template, &vars(&[("name", "Jane"), ("email", "jane@example.com")])).unwrap();
+
+    assert_eq!(rendered, "[user]\n  name = Jane\n  email = jane@example.com\n  signing = Jane\n");
+    assert_eq!(render("{{ not a variable }} and {{ unclosed", &vars(&[])).unwrap(), "{{ not a variable }} and {{ unclosed",
+        "Text that isn't a variable reference is kept as is");
+
+    let error = render("{{ name }} {{ email }}", &vars(&[("name", "Jane")])).unwrap_err();
+    assert!(error.to_string().contains("email"), "Undefined variables should be named in the error");
+}
+
+#[test]
+fn test_referenced_and_undefined_variables() {
+    let template = "{{ email }} {{ name }} {{ email }} {{ host.name }}";
+
+    assert_eq!(referenced_variables(template), vec!["email", "name", "host.name"]);
+    assert_eq!(undefined_variables(template, &vars(&[("name", "Jane")])), vec!["email", "host.name"]);
+}
+
+#[test]
+fn test_parse_assignment() {
+    assert_eq!(parse_assignment("email=me@example.com"), Ok((String::from("email"), String::from("me@example.com"))));
+    assert_eq!(parse_assignment("theme="), Ok((String::from("theme"), String::new())));
+    assert_eq!(parse_assignment("args=a=b"), Ok((String::from("args"), String::from("a=b"))));
+    assert!(parse_assignment("email").is_err());
+    assert!(parse_assignment("=value").is_err());
+}
+
+#[test]
+fn test_target_file_path() {
+    assert_eq!(target_file_path(Path::new(".gitconfig.tmpl")), PathBuf::from(".gitconfig"));
+    assert_eq!(target_file_path(Path::new(".config/git/config")), PathBuf::from(".config/git/config"));
+    assert_eq!(target_file_path(Path::new(".tmpl")), PathBuf::from(".tmpl"), "A bare .tmpl file is not a template");
+}
+
+#[test]
+fn test_builtin_vars() {
+    let current = builtin_vars(None);
+    assert_eq!(current.get("os").map(String::as_str), Some(std::env::consts::OS));
+    assert_eq!(current.get("arch").map(String::as_str), Some(std::env::consts::ARCH));
+    assert!(current.contains_key("hostname"));
+
+    let user = UserHome {
+        name: String::from("alice"),
+        uid: 1000,
+        gid: 1000,
+        home: PathBuf::from("/home/alice"),
+        shell: PathBuf::from("/usr/bin/fish"),
+    };
+    let for_user = builtin_vars(Some(&user));
+    let rendered = render("{{ username }} {{ uid }} {{ home }} {{ shell }}", &for_user).unwrap();
+    assert_eq!(rendered, "alice 1000 /home/alice fish", "Installing for another account describes that account");
+    assert_eq!(for_user.get("hostname"), current.get("hostname"));
+}
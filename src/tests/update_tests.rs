@@ -0,0 +1,80 @@
+use std::fs;
+use tempfile::tempdir;
+
+use crate::update::{parse_version, is_newer_version, find_checksum, sha256_hex, platform_asset_name, replace_executable, verify_checksums};
+
+#[test]
+fn test_parse_version() {
+    assert_eq!(parse_version("0.1.0").unwrap(), (0, 1, 0));
+    assert_eq!(parse_version("v1.12.3").unwrap(), (1, 12, 3));
+    assert_eq!(parse_version("v2.0.0-rc.1").unwrap(), (2, 0, 0));
+    assert!(parse_version("v1.2").is_err());
+    assert!(parse_version("latest").is_err());
+}
+
+#[test]
+fn test_is_newer_version() {
+    assert!(is_newer_version("0.1.0", "v0.2.0").unwrap());
+    assert!(is_newer_version("0.9.9", "v1.0.0").unwrap());
+    assert!(!is_newer_version("0.2.0", "v0.2.0").unwrap());
+    assert!(!is_newer_version("1.0.0", "v0.9.0").unwrap());
+}
+
+#[test]
+fn test_find_checksum() {
+    let checksums = "\
+0123abcd  dotfiles-rust-x86_64-linux
+DEADBEEF *dotfiles-rust-aarch64-macos
+";
+
+    assert_eq!(find_checksum(checksums, "dotfiles-rust-x86_64-linux"), Some("0123abcd".to_string()));
+    assert_eq!(find_checksum(checksums, "dotfiles-rust-aarch64-macos"), Some("deadbeef".to_string()));
+    assert_eq!(find_checksum(checksums, "dotfiles-rust-x86_64-windows.exe"), None);
+}
+
+#[test]
+fn test_sha256_hex() {
+    assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+#[test]
+fn test_platform_asset_name() {
+    let name = platform_asset_name();
+    assert!(name.starts_with("dotfiles-rust-"));
+    assert!(name.contains(std::env::consts::ARCH));
+    assert!(name.contains(std::env::consts::OS));
+}
+
+#[test]
+fn test_replace_executable() {
+    let temp_dir = tempdir().unwrap();
+    let exe_path = temp_dir.path().join("dotfiles-rust");
+    fs::write(&exe_path, "old binary").unwrap();
+
+    replace_executable(&exe_path, b"new binary").unwrap();
+
+    assert_eq!(fs::read_to_string(&exe_path).unwrap(), "new binary");
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1, "Temp file should be renamed into place");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&exe_path).unwrap().permissions().mode() & 0o777, 0o755);
+    }
+}
+
+#[test]
+fn test_verify_checksums() {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = crate::signing::public_key_hex(&key.verifying_key());
+    let checksums = b"abc123  dotfiles-rust-x86_64-linux\n";
+    let signature = key.sign(checksums).to_bytes().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+
+    assert!(verify_checksums(checksums, &signature, &public_key).is_ok());
+    assert!(verify_checksums(b"def456  dotfiles-rust-x86_64-linux\n", &signature, &public_key).is_err(),
+        "Checksums changed after signing are rejected");
+    let other_key = crate::signing::public_key_hex(&SigningKey::from_bytes(&[8; 32]).verifying_key());
+    assert!(verify_checksums(checksums, &signature, &other_key).is_err(), "A signature by another key is rejected");
+    assert!(verify_checksums(checksums, "not hex", &public_key).is_err());
+}
@@ -0,0 +1,93 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::commands::managed_files;
+use crate::config::Layout;
+use crate::update::sha256_hex;
+use crate::verify::{parse_checksums, verify_against_checksums, verify_against_source, VerifyIssue, VerifyProblem};
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_checksums() -> Result<()> {
+    let digest = sha256_hex(b"hello");
+    let content = format!("# generated\n{}  .bashrc\n{} *./.config/nvim/init.vim\n\n", digest, digest.to_uppercase());
+
+    let checksums = parse_checksums(&content)?;
+    assert_eq!(checksums, vec![
+        (digest.clone(), PathBuf::from(".bashrc")),
+        (digest, PathBuf::from(".config/nvim/init.vim")),
+    ]);
+
+    assert!(parse_checksums("abc .bashrc").is_err(), "Short digests should be rejected");
+    assert!(parse_checksums(&sha256_hex(b"x")).is_err(), "Lines without a path should be rejected");
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_against_source() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let source_dir = temp_dir.path().join("source");
+    let home_dir = temp_dir.path().join("home");
+
+    write_file(&source_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_file(&source_dir.join(".vimrc"), "set number")?;
+    write_file(&source_dir.join(".zshrc"), "export ZSH=1")?;
+    write_file(&home_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_file(&home_dir.join(".vimrc"), "set nonumber")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(source_dir.join(".bashrc"), fs::Permissions::from_mode(0o644))?;
+        fs::set_permissions(home_dir.join(".bashrc"), fs::Permissions::from_mode(0o600))?;
+        fs::set_permissions(source_dir.join(".vimrc"), fs::Permissions::from_mode(0o644))?;
+        fs::set_permissions(home_dir.join(".vimrc"), fs::Permissions::from_mode(0o644))?;
+    }
+
+    let files = managed_files(&source_dir, Layout::Plain, &home_dir)?;
+    let problems = verify_against_source(&files, &Default::default())?;
+
+    let mut expected = vec![
+        VerifyProblem { relative_path: PathBuf::from(".vimrc"), issue: VerifyIssue::Mismatch },
+        VerifyProblem { relative_path: PathBuf::from(".zshrc"), issue: VerifyIssue::Missing },
+    ];
+    if cfg!(unix) {
+        expected.insert(0, VerifyProblem {
+            relative_path: PathBuf::from(".bashrc"),
+            issue: VerifyIssue::Permissions { expected: 0o644, actual: 0o600 },
+        });
+    }
+    assert_eq!(problems, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_against_checksums() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let home_dir = temp_dir.path();
+
+    write_file(&home_dir.join(".bashrc"), "export EDITOR=vim")?;
+    write_file(&home_dir.join(".vimrc"), "set nonumber")?;
+
+    let checksums = format!("{}  .bashrc\n{}  .vimrc\n{}  .zshrc\n",
+        sha256_hex(b"export EDITOR=vim"), sha256_hex(b"set number"), sha256_hex(b"export ZSH=1"));
+
+    let problems = verify_against_checksums(&checksums, home_dir)?;
+    assert_eq!(problems, vec![
+        VerifyProblem { relative_path: PathBuf::from(".vimrc"), issue: VerifyIssue::Mismatch },
+        VerifyProblem { relative_path: PathBuf::from(".zshrc"), issue: VerifyIssue::Missing },
+    ]);
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use anyhow::{anyhow, Result};
+use tempfile::tempdir;
+
+use crate::webhook::{run_webhook_command, RunReport};
+
+#[test]
+fn test_run_report() -> Result<()> {
+    let temp_dir = tempdir()?;
+
+    let report = RunReport::new("install", temp_dir.path(), &Err(anyhow!("disk full")));
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(json["event"], "install");
+    assert_eq!(json["success"], false);
+    assert_eq!(json["error"], "disk full");
+    assert!(json["commit"].is_null(), "A source directory that isn't a repository has no commit");
+
+    let json = serde_json::to_value(RunReport::new("restore", temp_dir.path(), &Ok(())))?;
+    assert_eq!(json["success"], true);
+    assert!(json.get("error").is_none(), "Successful runs have no error field");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_webhook_command() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let received = temp_dir.path().join("received.json");
+
+    run_webhook_command(&format!("cat > '{}'", received.display()), "{\"event\":\"install\"}")?;
+    assert_eq!(fs::read_to_string(&received)?, "{\"event\":\"install\"}", "The report arrives on standard input");
+
+    assert!(run_webhook_command("exit 3", "{}").is_err(), "A failing command is reported");
+    Ok(())
+}
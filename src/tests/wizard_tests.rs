@@ -0,0 +1,102 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::config::{read_config, write_config, Config, Layout};
+use crate::fs_utils::{set_test_home_dir, set_test_id, clear_test_id};
+use crate::wizard::{adoption_candidates, is_git_url, run_wizard_with, suggest_layout};
+
+#[test]
+fn test_is_git_url() {
+    assert!(is_git_url("git@github.com:me/dotfiles.git"));
+    assert!(is_git_url("https://github.com/me/dotfiles"));
+    assert!(is_git_url("ssh://git@example.com/dotfiles.git"));
+    assert!(!is_git_url("https://example.com/dotfiles.tar.gz"), "Archive URLs are downloaded, not cloned");
+    assert!(!is_git_url("~/dotfiles"));
+}
+
+#[test]
+fn test_suggest_layout() -> Result<()> {
+    let temp_dir = tempdir()?;
+
+    fs::create_dir_all(temp_dir.path().join("nvim/.config/nvim"))?;
+    fs::create_dir_all(temp_dir.path().join("zsh"))?;
+    fs::create_dir_all(temp_dir.path().join(".git"))?;
+    fs::write(temp_dir.path().join("README.md"), "# dotfiles")?;
+    assert_eq!(suggest_layout(temp_dir.path()), Layout::Stow);
+
+    fs::write(temp_dir.path().join(".bashrc"), "")?;
+    assert_eq!(suggest_layout(temp_dir.path()), Layout::Plain);
+    assert_eq!(suggest_layout(&temp_dir.path().join("missing")), Layout::Plain);
+
+    Ok(())
+}
+
+#[test]
+fn test_adoption_candidates() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let home_dir = temp_dir.path().join("home");
+    let source_dir = temp_dir.path().join("source");
+
+    fs::create_dir_all(home_dir.join(".config/nvim"))?;
+    fs::create_dir_all(source_dir.join("zshrc"))?;
+    fs::write(home_dir.join(".bashrc"), "")?;
+    fs::write(home_dir.join(".zshrc"), "")?;
+    fs::write(home_dir.join(".config/nvim/init.lua"), "")?;
+    fs::write(source_dir.join("zshrc/.zshrc"), "")?;
+
+    assert_eq!(adoption_candidates(&home_dir, &source_dir, Layout::Stow),
+        vec![PathBuf::from(".bashrc"), PathBuf::from(".config/nvim/init.lua")]);
+    assert_eq!(adoption_candidates(&home_dir, &source_dir, Layout::Plain).len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_run_wizard() -> Result<()> {
+    set_test_id();
+    let temp_dir = tempdir()?;
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&home_dir)?;
+    set_test_home_dir(Some(home_dir.clone()));
+
+    fs::write(home_dir.join(".bashrc"), "alias ll='ls -l'")?;
+    fs::write(home_dir.join(".vimrc"), "set number")?;
+
+    // Default repository path, create it, plain layout, choose: adopt .bashrc only, skip the install
+    let mut input = Cursor::new("\ny\np\nc\ny\nn\nn\n");
+    run_wizard_with(&mut input)?;
+
+    let source_dir = home_dir.join("dotfiles");
+    let config = read_config()?;
+    assert_eq!(Path::new(&config.source_dir), source_dir);
+    assert_eq!(config.layout, Layout::Plain);
+    assert_eq!(fs::read_to_string(source_dir.join(".bashrc"))?, "alias ll='ls -l'");
+    assert!(!source_dir.join(".vimrc").exists());
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
+
+#[test]
+fn test_run_wizard_keeps_existing_config() -> Result<()> {
+    set_test_id();
+    let temp_dir = tempdir()?;
+    set_test_home_dir(Some(temp_dir.path().to_path_buf()));
+
+    write_config(&Config {
+        source_dir: String::from("/srv/dotfiles"),
+        ..Default::default()
+    })?;
+
+    let mut input = Cursor::new("\n");
+    run_wizard_with(&mut input)?;
+    assert_eq!(read_config()?.source_dir, "/srv/dotfiles");
+
+    set_test_home_dir(None);
+    clear_test_id();
+    Ok(())
+}
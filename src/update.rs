@@ -0,0 +1,228 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::colorize;
+use crate::signing::{parse_public_key, signature_matches};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/ff3rno/dotfiles-rust/releases/latest";
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+// Hex-encoded ed25519 signature of CHECKSUMS_ASSET, made with the release key
+const SIGNATURE_ASSET: &str = "SHA256SUMS.sig";
+// Public half of the release key, hex-encoded, set when release binaries are built; builds without it can't
+// update themselves, since a checksum from the same release as the binary proves nothing about who made it
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("DOTFILES_RUST_RELEASE_KEY");
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    fn find_asset(&self, name: &str) -> Result<&ReleaseAsset> {
+        self.assets.iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("Release {} has no asset named {}", self.tag_name, name))
+    }
+}
+
+pub fn platform_asset_name() -> String {
+    let extension = if cfg!(windows) { ".exe" } else { "" };
+    format!("{}-{}-{}{}", env!("CARGO_PKG_NAME"), env::consts::ARCH, env::consts::OS, extension)
+}
+
+pub fn parse_version(version: &str) -> Result<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next().unwrap_or(version);
+    let parts = core.split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid version '{}'", version))?;
+
+    match parts.as_slice() {
+        [major, minor, patch] => Ok((*major, *minor, *patch)),
+        _ => Err(anyhow!("Invalid version '{}'", version)),
+    }
+}
+
+pub fn is_newer_version(current: &str, candidate: &str) -> Result<bool> {
+    Ok(parse_version(candidate)? > parse_version(current)?)
+}
+
+pub fn find_checksum(checksums: &str, asset_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let digest = parts.next()?;
+        let name = parts.next()?.trim_start_matches('*');
+
+        if name == asset_name {
+            Some(digest.to_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+// Fails unless `signature` is the release key's signature of the checksums file
+pub fn verify_checksums(checksums: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    let key = parse_public_key(public_key)
+        .with_context(|| "Invalid release key")?;
+    if !signature_matches(checksums, signature, &key) {
+        return Err(anyhow!("{} is not signed by the release key; refusing to update", CHECKSUMS_ASSET));
+    }
+    Ok(())
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn fetch_latest_release() -> Result<Release> {
+    let release: Release = ureq::get(RELEASES_URL)
+        .set("User-Agent", concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .with_context(|| format!("Failed to query {}", RELEASES_URL))?
+        .into_json()
+        .with_context(|| "Failed to parse GitHub release information")?;
+
+    Ok(release)
+}
+
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    ureq::get(url)
+        .set("User-Agent", concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .with_context(|| format!("Failed to download {}", url))?
+        .into_reader()
+        .read_to_end(&mut data)
+        .with_context(|| format!("Failed to read response from {}", url))?;
+
+    Ok(data)
+}
+
+pub fn replace_executable(exe_path: &Path, new_binary: &[u8]) -> Result<()> {
+    let file_name = exe_path.file_name()
+        .ok_or_else(|| anyhow!("Could not get executable filename"))?
+        .to_string_lossy();
+    // Written next to the executable and renamed, so a failed write never leaves a truncated binary. The file is
+    // created new and only writable by us, so nothing already at its path, such as a symlink, is written through
+    // and nobody can change the binary between the write and the rename.
+    let (temp_path, mut file) = (0..)
+        .map(|counter| exe_path.with_file_name(format!(".{}.update-{}-{}", file_name, std::process::id(), counter)))
+        .find_map(|temp_path| match create_exclusive(&temp_path) {
+            Ok(file) => Some(Ok((temp_path, file))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => None,
+            Err(e) => Some(Err(e).with_context(|| format!("Failed to create {}", temp_path.display()))),
+        })
+        .expect("some counter is free")?;
+
+    let result = file.write_all(new_binary)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to write {}", temp_path.display()))
+        .and_then(|_| make_executable(&temp_path))
+        .and_then(|_| fs::rename(&temp_path, exe_path)
+            .with_context(|| format!("Failed to replace {}", exe_path.display())));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn create_exclusive(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o700).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_exclusive(path: &Path) -> std::io::Result<fs::File> {
+    fs::OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+// Gives the staged binary the usual mode of an executable once nothing more is written to it
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+pub fn self_update(check_only: bool, force: bool) -> Result<()> {
+    let current_version = env!("CARGO_PKG_VERSION");
+
+    println!("{}", colorize::info("Checking for updates..."));
+    let release = fetch_latest_release()?;
+
+    if !force && !is_newer_version(current_version, &release.tag_name)? {
+        println!("{} {}",
+            colorize::success("Already up to date:"),
+            colorize::version(current_version));
+        return Ok(());
+    }
+
+    println!("{} {} (current: {})",
+        colorize::info("New version available:"),
+        colorize::version(&release.tag_name),
+        colorize::highlight(current_version));
+
+    if check_only {
+        return Ok(());
+    }
+
+    let public_key = RELEASE_PUBLIC_KEY
+        .ok_or_else(|| anyhow!("This build has no release key to verify updates with; update it the way it was installed, e.g. with cargo"))?;
+
+    let asset_name = platform_asset_name();
+    let binary_asset = release.find_asset(&asset_name)?;
+    let checksums_asset = release.find_asset(CHECKSUMS_ASSET)?;
+
+    let signature_asset = release.find_asset(SIGNATURE_ASSET)?;
+
+    let checksums = download(&checksums_asset.browser_download_url)?;
+    let signature = String::from_utf8(download(&signature_asset.browser_download_url)?)
+        .with_context(|| format!("{} is not valid UTF-8", SIGNATURE_ASSET))?;
+    verify_checksums(&checksums, &signature, public_key)?;
+    let checksums = String::from_utf8(checksums)
+        .with_context(|| format!("{} is not valid UTF-8", CHECKSUMS_ASSET))?;
+    let expected = find_checksum(&checksums, &asset_name)
+        .ok_or_else(|| anyhow!("No checksum listed for {} in {}", asset_name, CHECKSUMS_ASSET))?;
+
+    println!("{} {}", colorize::info("Downloading"), colorize::path(&asset_name));
+    let binary = download(&binary_asset.browser_download_url)?;
+
+    let actual = sha256_hex(&binary);
+    if actual != expected {
+        return Err(anyhow!("Checksum mismatch for {}: expected {}, got {}", asset_name, expected, actual));
+    }
+
+    let exe_path = env::current_exe()
+        .with_context(|| "Failed to locate the running executable")?;
+    replace_executable(&exe_path, &binary)?;
+
+    println!("{} {}",
+        colorize::success("Updated to"),
+        colorize::version(&release.tag_name));
+
+    Ok(())
+}
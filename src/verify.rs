@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+
+use crate::colorize;
+use crate::commands::{configured_files, ManagedFile};
+use crate::config::read_config;
+use crate::fs_utils::{file_mode, get_home_dir};
+use crate::template::{self, expected_content};
+use crate::update::sha256_hex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyIssue {
+    Missing,
+    Mismatch,
+    // Unix permission bits differ from the source file, or from the mode its directory config sets
+    Permissions { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::Missing => write!(f, "missing"),
+            VerifyIssue::Mismatch => write!(f, "content differs"),
+            VerifyIssue::Permissions { expected, actual } => {
+                write!(f, "permissions {:03o}, expected {:03o}", actual, expected)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyProblem {
+    pub relative_path: PathBuf,
+    pub issue: VerifyIssue,
+}
+
+pub fn verify_against_source(files: &[ManagedFile], vars: &BTreeMap<String, String>) -> Result<Vec<VerifyProblem>> {
+    let mut problems = Vec::new();
+
+    for file in files {
+        let problem = |issue| VerifyProblem { relative_path: file.relative_path.clone(), issue };
+
+        if !file.target.is_file() {
+            problems.push(problem(VerifyIssue::Missing));
+            continue;
+        }
+
+        let source_content = expected_content(&file.source, vars)?;
+        let target_content = fs::read(&file.target)
+            .with_context(|| format!("Failed to read {}", file.target.display()))?;
+
+        if source_content != target_content {
+            problems.push(problem(VerifyIssue::Mismatch));
+        }
+
+        if let (Some(expected), Some(actual)) = (file.mode.or_else(|| file_mode(&file.source)), file_mode(&file.target))
+            && expected != actual {
+            problems.push(problem(VerifyIssue::Permissions { expected, actual }));
+        }
+    }
+
+    Ok(problems)
+}
+
+// Parses `sha256sum` output: a hex digest, whitespace, then a path relative to home
+pub fn parse_checksums(content: &str) -> Result<Vec<(String, PathBuf)>> {
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| {
+            let (digest, path) = line.trim().split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("line {}: expected '<sha256> <path>'", index + 1))?;
+            let path = path.trim_start().trim_start_matches('*');
+
+            if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow!("line {}: '{}' is not a SHA-256 digest", index + 1, digest));
+            }
+
+            Ok((digest.to_lowercase(), PathBuf::from(path.trim_start_matches("./"))))
+        })
+        .collect()
+}
+
+pub fn verify_against_checksums(checksums: &str, home_dir: &Path) -> Result<Vec<VerifyProblem>> {
+    let mut problems = Vec::new();
+
+    for (digest, relative_path) in parse_checksums(checksums)? {
+        let target = home_dir.join(&relative_path);
+
+        let issue = if !target.is_file() {
+            VerifyIssue::Missing
+        } else {
+            let content = fs::read(&target)
+                .with_context(|| format!("Failed to read {}", target.display()))?;
+            if sha256_hex(&content) == digest {
+                continue;
+            }
+            VerifyIssue::Mismatch
+        };
+
+        problems.push(VerifyProblem { relative_path, issue });
+    }
+
+    Ok(problems)
+}
+
+pub fn verify_dotfiles(checksums: Option<&Path>) -> Result<()> {
+    let home_dir = get_home_dir()?;
+
+    let (problems, checked) = match checksums {
+        Some(checksums_path) => {
+            let content = fs::read_to_string(checksums_path)
+                .with_context(|| format!("Failed to read checksums from {}", checksums_path.display()))?;
+            let checked = parse_checksums(&content)
+                .with_context(|| format!("Invalid checksums file {}", checksums_path.display()))?
+                .len();
+
+            println!("{} {}", colorize::header("Verifying installed files against"), colorize::path(checksums_path.display()));
+            (verify_against_checksums(&content, &home_dir)?, checked)
+        },
+        None => {
+            let config = read_config()?;
+            let source_dir = Path::new(&config.source_dir);
+
+            if !source_dir.exists() {
+                return Err(anyhow!("Source directory '{}' does not exist", source_dir.display()));
+            }
+
+            let files = configured_files(&config, &home_dir)?;
+            let vars = template::template_vars(&config, &[])?;
+
+            println!("{} {}", colorize::header("Verifying installed files against"), colorize::path(source_dir.display()));
+            (verify_against_source(&files, &vars)?, files.len())
+        },
+    };
+
+    for problem in &problems {
+        println!("  {} {} ({})",
+            colorize::error(colorize::cross()),
+            colorize::path(problem.relative_path.display()),
+            problem.issue);
+    }
+
+    if problems.is_empty() {
+        println!("{} {}", colorize::success("All files verified:"), colorize::highlight(checked));
+        return Ok(());
+    }
+
+    Err(anyhow!("Verification failed: {} problem(s) in {} file(s) checked", problems.len(), checked))
+}
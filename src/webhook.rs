@@ -0,0 +1,100 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::colorize;
+use crate::config::{current_hostname, read_config};
+use crate::git;
+
+// A webhook that doesn't answer in time is given up on, so an install never hangs on monitoring
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What the webhook and webhook command are sent after a run, as JSON
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RunReport {
+    // "install", "restore" or "daemon"
+    pub event: String,
+    pub hostname: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub source_dir: String,
+    // The source repository's HEAD, when it is a git repository
+    pub commit: Option<String>,
+    // Unix time the run finished
+    pub timestamp: i64,
+}
+
+impl RunReport {
+    pub fn new(event: &str, source_dir: &Path, result: &Result<()>) -> Self {
+        Self {
+            event: String::from(event),
+            hostname: current_hostname(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            source_dir: source_dir.display().to_string(),
+            commit: git::is_git_repo(source_dir).then(|| git::head_commit(source_dir).ok()).flatten(),
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+fn post_webhook(url: &str, payload: &str) -> Result<()> {
+    ureq::AgentBuilder::new()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .post(url)
+        .set("User-Agent", concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .set("Content-Type", "application/json")
+        .send_string(payload)
+        .with_context(|| format!("Failed to post to {}", url))?;
+    Ok(())
+}
+
+// Runs `command` through the shell with the payload on standard input
+pub fn run_webhook_command(command: &str, payload: &str) -> Result<()> {
+    let mut child = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).stdin(Stdio::piped()).spawn()
+    } else {
+        Command::new("sh").args(["-c", command]).stdin(Stdio::piped()).spawn()
+    }.with_context(|| format!("Failed to run '{}'", command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read its input closes the pipe early, which is fine
+        let _ = stdin.write_all(payload.as_bytes());
+    }
+
+    let status = child.wait().with_context(|| format!("Failed to run '{}'", command))?;
+    if !status.success() {
+        return Err(anyhow!("'{}' exited with {}", command, status));
+    }
+    Ok(())
+}
+
+// Sends the report of a finished run to webhook_url and webhook_command from the config. Delivery
+// problems are only warned about, so a monitoring outage never fails a run.
+pub fn report_run(event: &str, result: &Result<()>) {
+    let Ok(config) = read_config() else {
+        return;
+    };
+    if config.webhook_url.is_none() && config.webhook_command.is_none() {
+        return;
+    }
+
+    let report = RunReport::new(event, Path::new(&config.source_dir), result);
+    let payload = match serde_json::to_string(&report) {
+        Ok(payload) => payload,
+        Err(e) => return println!("{} {}", colorize::warning("Failed to serialize the run report:"), e),
+    };
+
+    let deliveries = [
+        config.webhook_url.as_deref().map(|url| post_webhook(url, &payload)),
+        config.webhook_command.as_deref().map(|command| run_webhook_command(command, &payload)),
+    ];
+    for e in deliveries.into_iter().flatten().filter_map(Result::err) {
+        println!("{} {:#}", colorize::warning("Failed to report the run:"), e);
+    }
+}
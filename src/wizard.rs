@@ -0,0 +1,266 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+use crate::archive::is_archive_url;
+use crate::colorize;
+use crate::commands::{install_and_report, is_blacklisted, InstallOptions};
+use crate::config::{get_config_path, write_config, Config, Layout};
+use crate::export::stow_package;
+use crate::fs_utils::{copy_file, get_home_dir};
+use crate::git;
+
+// Files commonly kept in a dotfiles repository, offered for adoption when they exist in the home directory
+const ADOPTION_CANDIDATES: &[&str] = &[
+    ".bashrc",
+    ".bash_profile",
+    ".profile",
+    ".zshrc",
+    ".zprofile",
+    ".vimrc",
+    ".gitconfig",
+    ".gitignore_global",
+    ".tmux.conf",
+    ".inputrc",
+    ".editorconfig",
+    ".config/nvim/init.lua",
+    ".config/nvim/init.vim",
+    ".config/fish/config.fish",
+    ".config/alacritty/alacritty.toml",
+    ".config/kitty/kitty.conf",
+    ".config/starship.toml",
+    ".config/git/config",
+    ".ssh/config",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InstallChoice {
+    Install,
+    DryRun,
+    Skip,
+}
+
+// git@host:repo, ssh:// and git:// URLs, and http(s) URLs that aren't archive downloads
+pub fn is_git_url(source: &str) -> bool {
+    source.starts_with("git@")
+        || source.starts_with("ssh://")
+        || source.starts_with("git://")
+        || ((source.starts_with("https://") || source.starts_with("http://")) && !is_archive_url(source))
+}
+
+fn expand_home(path: &str, home_dir: &Path) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some("") => home_dir.to_path_buf(),
+        Some(rest) if rest.starts_with('/') => home_dir.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Stow when every top-level entry is a package directory rather than a dotfile
+pub fn suggest_layout(source_dir: &Path) -> Layout {
+    let Ok(entries) = fs::read_dir(source_dir) else {
+        return Layout::Plain;
+    };
+
+    let entries = entries.flatten()
+        .filter(|entry| !is_blacklisted(Path::new(&entry.file_name())))
+        .collect::<Vec<_>>();
+
+    let is_package = |entry: &fs::DirEntry| entry.path().is_dir() && !entry.file_name().to_string_lossy().starts_with('.');
+    if !entries.is_empty() && entries.iter().all(is_package) {
+        Layout::Stow
+    } else {
+        Layout::Plain
+    }
+}
+
+// Where a home-relative file goes in the source directory for a layout
+fn source_path(source_dir: &Path, relative_path: &Path, layout: Layout) -> PathBuf {
+    match layout {
+        Layout::Plain => source_dir.join(relative_path),
+        Layout::Stow => source_dir.join(stow_package(relative_path)).join(relative_path),
+    }
+}
+
+// Common dotfiles present in the home directory that the source directory doesn't have yet
+pub fn adoption_candidates(home_dir: &Path, source_dir: &Path, layout: Layout) -> Vec<PathBuf> {
+    ADOPTION_CANDIDATES.iter()
+        .map(PathBuf::from)
+        .filter(|relative_path| home_dir.join(relative_path).is_file())
+        .filter(|relative_path| !source_path(source_dir, relative_path, layout).exists())
+        .collect()
+}
+
+// Prints a question and reads the answer; an empty answer or end of input gives the default
+fn ask(input: &mut impl BufRead, question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{} ", colorize::info(format!("{}:", question)));
+    } else {
+        print!("{} [{}] ", colorize::info(format!("{}:", question)), colorize::highlight(default));
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+}
+
+fn ask_yes_no(input: &mut impl BufRead, question: &str, default: bool) -> Result<bool> {
+    loop {
+        let answer = ask(input, &format!("{} (y/n)", question), if default { "y" } else { "n" })?;
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("  {}", colorize::warning("Please answer y or n")),
+        }
+    }
+}
+
+fn ask_layout(input: &mut impl BufRead, suggested: Layout) -> Result<Layout> {
+    let default = match suggested {
+        Layout::Plain => "plain",
+        Layout::Stow => "stow",
+    };
+
+    loop {
+        match ask(input, "Layout, [p]lain (mirrors the home directory) or [s]tow (one directory per package)", default)?.to_lowercase().as_str() {
+            "p" | "plain" => return Ok(Layout::Plain),
+            "s" | "stow" => return Ok(Layout::Stow),
+            _ => println!("  {}", colorize::warning("Please answer plain or stow")),
+        }
+    }
+}
+
+fn ask_install(input: &mut impl BufRead) -> Result<InstallChoice> {
+    loop {
+        match ask(input, "Install now? [y]es, [d]ry run, [n]o", "d")?.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(InstallChoice::Install),
+            "d" | "dry run" => return Ok(InstallChoice::DryRun),
+            "n" | "no" => return Ok(InstallChoice::Skip),
+            _ => println!("  {}", colorize::warning("Please answer y, d or n")),
+        }
+    }
+}
+
+// Works out the source directory from the answer, cloning or creating it when asked to. Returns the
+// value for `source_dir` and the local directory, which is None for archive URLs.
+fn resolve_source(input: &mut impl BufRead, answer: &str, home_dir: &Path) -> Result<(String, Option<PathBuf>)> {
+    if is_archive_url(answer) {
+        println!("  {}", colorize::info("The archive is downloaded on each install"));
+        return Ok((answer.to_string(), None));
+    }
+
+    if is_git_url(answer) {
+        let destination = ask(input, "Clone into", &home_dir.join("dotfiles").to_string_lossy())?;
+        let destination = expand_home(&destination, home_dir);
+
+        if destination.exists() {
+            println!("  {} {} already exists; using it as is", colorize::warning("Note:"), colorize::path(destination.display()));
+        } else if ask_yes_no(input, &format!("Clone {} now?", answer), true)? {
+            git::clone(answer, &destination)?;
+        }
+        return Ok((destination.to_string_lossy().into_owned(), Some(destination)));
+    }
+
+    let path = expand_home(answer, home_dir);
+    if !path.exists() && ask_yes_no(input, &format!("{} does not exist. Create it?", path.display()), true)? {
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create directory {}", path.display()))?;
+    }
+
+    Ok((path.to_string_lossy().into_owned(), Some(path)))
+}
+
+fn adopt_files(input: &mut impl BufRead, home_dir: &Path, source_dir: &Path, layout: Layout) -> Result<()> {
+    let candidates = adoption_candidates(home_dir, source_dir, layout);
+    if candidates.is_empty() {
+        return Ok(());
+    }
+
+    println!("{}", colorize::header("Found dotfiles in your home directory that the repository doesn't have:"));
+    for candidate in &candidates {
+        println!("  {}", colorize::path(candidate.display()));
+    }
+
+    let selected = loop {
+        match ask(input, "Adopt them into the repository? [a]ll, [c]hoose, [n]one", "a")?.to_lowercase().as_str() {
+            "a" | "all" => break candidates,
+            "n" | "none" => break Vec::new(),
+            "c" | "choose" => {
+                let mut selected = Vec::new();
+                for candidate in candidates {
+                    if ask_yes_no(input, &format!("  Adopt {}?", candidate.display()), true)? {
+                        selected.push(candidate);
+                    }
+                }
+                break selected;
+            },
+            _ => println!("  {}", colorize::warning("Please answer a, c or n")),
+        }
+    };
+
+    for relative_path in selected {
+        let target_path = source_path(source_dir, &relative_path, layout);
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        copy_file(&home_dir.join(&relative_path), &target_path)
+            .with_context(|| format!("Failed to copy {} into {}", relative_path.display(), target_path.display()))?;
+        println!("  {} {}", colorize::success("Adopted:"), colorize::path(relative_path.display()));
+    }
+
+    Ok(())
+}
+
+pub fn run_wizard() -> Result<()> {
+    run_wizard_with(&mut io::stdin().lock())
+}
+
+// The interactive `init`, reading answers from `input`
+pub fn run_wizard_with(input: &mut impl BufRead) -> Result<()> {
+    let home_dir = get_home_dir()?;
+    let config_path = get_config_path()?;
+
+    println!("{}", colorize::header("Setting up dotfiles-rust"));
+
+    if config_path.exists() && !ask_yes_no(input, &format!("{} already exists. Replace it?", config_path.display()), false)? {
+        println!("{} {}", colorize::info("Kept the existing config at"), colorize::path(config_path.display()));
+        return Ok(());
+    }
+
+    let answer = ask(input, "Dotfiles repository (path or git URL)", &home_dir.join("dotfiles").to_string_lossy())?;
+    let (source_value, local_dir) = resolve_source(input, &answer, &home_dir)?;
+
+    let suggested = local_dir.as_deref().map(suggest_layout).unwrap_or_default();
+    let layout = ask_layout(input, suggested)?;
+
+    if let Some(local_dir) = local_dir.as_deref().filter(|dir| dir.is_dir()) {
+        adopt_files(input, &home_dir, local_dir, layout)?;
+    }
+
+    write_config(&Config {
+        source_dir: source_value,
+        layout,
+        ..Default::default()
+    })?;
+    println!("{} {}", colorize::success("Configuration file created at"), colorize::path(config_path.display()));
+
+    let dry_run = match ask_install(input)? {
+        InstallChoice::Install => false,
+        InstallChoice::DryRun => true,
+        InstallChoice::Skip => {
+            println!("{}", colorize::info("Run `dotfiles-rust install` when you're ready"));
+            return Ok(());
+        },
+    };
+
+    install_and_report(&InstallOptions {
+        dry_run,
+        backup: true,
+        ..Default::default()
+    })
+}